@@ -0,0 +1,765 @@
+//! Assembler - turns CHIP-8 assembly source into a ROM, in two passes :
+//! the first pass reads the source (and its includes) and gives every label an address,
+//! the second pass encodes the instructions and data with all labels known.
+//!
+//! The syntax follows Cowgod's CHIP-8 technical reference, which is also what the disassembler writes :
+//!
+//! ```text
+//! ; comments start with a semicolon
+//! SPEED equ 2                 ; constants, which may use labels defined further down
+//!         include "sprites.asm" ; includes, relative to the including file
+//! start:  LD I, ball          ; labels end with a colon
+//!         LD V0, #10          ; numbers are decimal, 0x1F, #1F, $1F or 0b0101
+//!         ADD V0, SPEED + 1   ; operands may add and subtract numbers, constants and labels
+//!         DRW V0, V1, 4
+//!         JP start
+//! ball:   db 0x60, 0xF0, 0xF0, 0x60
+//! table:  dw start, ball      ; 16-bit words, big endian
+//! ```
+//!
+//! The SUPER-CHIP and XO-CHIP instructions use the mnemonics of the disassembler as well, e.g. `SCD 4`, `LD HF, V0`,
+//! `LD [I], V2-V5` or `LD I, LONG table` (4 bytes long).
+//! Mnemonics, registers and directives are case insensitive, labels and constants are not.
+
+use crate::drivers::max_rom_size;
+use crate::vm::{Instruction, PROGRAM_START, XO_CHIP_MEMORY_SIZE};
+use log::debug;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Includes may be nested up to `MAX_INCLUDE_DEPTH` levels, which stops a file from including itself forever.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// `AssemblyError` : what went wrong, and where.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssemblyError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl Error for AssemblyError {}
+
+/// `Location` : the file and the line a statement comes from.
+#[derive(Debug, Clone)]
+struct Location {
+    file: String,
+    line: usize,
+}
+
+impl Location {
+    fn error(&self, message: String) -> AssemblyError {
+        AssemblyError {
+            file: self.file.clone(),
+            line: self.line,
+            message,
+        }
+    }
+}
+
+/// `Statement` : a line of source which emits bytes.
+enum Statement {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<String>,
+    },
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+}
+
+/// `Assembler` : the symbols (labels and constants) and the statements found by the first pass.
+/// The constants are evaluated once every label is known, so they may use labels defined after them.
+struct Assembler {
+    symbols: HashMap<String, i64>,
+    constants: Vec<(Location, String, String)>,
+    statements: Vec<(Location, Statement)>,
+    address: usize,
+}
+
+/// `assemble()` will return the ROM assembled from `source`. Includes are read relative to the current directory.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssemblyError> {
+    let mut assembler = Assembler::new();
+    assembler.read(source, "<source>", Path::new("."), 0)?;
+    assembler.resolve()?;
+    assembler.emit()
+}
+
+/// `assemble_file()` will return the ROM assembled from the source file `filename`.
+pub fn assemble_file(filename: &str) -> Result<Vec<u8>, AssemblyError> {
+    let mut assembler = Assembler::new();
+    assembler.include(Path::new(filename), None, 0)?;
+    assembler.resolve()?;
+    assembler.emit()
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        Assembler {
+            symbols: HashMap::new(),
+            constants: vec![],
+            statements: vec![],
+            address: PROGRAM_START as usize,
+        }
+    }
+
+    /// `Assembler.include()` reads the source file at `path`. `location` is the include statement, if any.
+    fn include(
+        &mut self,
+        path: &Path,
+        location: Option<&Location>,
+        depth: usize,
+    ) -> Result<(), AssemblyError> {
+        let file = path.display().to_string();
+        let error = |message: String| match location {
+            Some(location) => location.error(message),
+            None => AssemblyError {
+                file: file.clone(),
+                line: 0,
+                message,
+            },
+        };
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(error(format!(
+                "includes are nested deeper than {} levels",
+                MAX_INCLUDE_DEPTH
+            )));
+        }
+        let source = fs::read_to_string(path)
+            .map_err(|io_error| error(format!("could not read {} : {}", file, io_error)))?;
+        let directory = path.parent().unwrap_or_else(|| Path::new("."));
+        self.read(&source, &file, directory, depth)
+    }
+
+    /// `Assembler.read()` is the first pass : it defines the labels and constants and collects the statements.
+    fn read(
+        &mut self,
+        source: &str,
+        file: &str,
+        directory: &Path,
+        depth: usize,
+    ) -> Result<(), AssemblyError> {
+        debug!("[Assembler.read()] Reading {}.", file);
+        for (index, text) in source.lines().enumerate() {
+            let location = Location {
+                file: String::from(file),
+                line: index + 1,
+            };
+            let mut text = text.split(';').next().unwrap_or("").trim();
+            //labels, possibly followed by a statement on the same line
+            while let Some(colon) = text.find(':') {
+                let label = text[..colon].trim();
+                if !is_symbol(label) {
+                    break;
+                }
+                self.define(label, self.address as i64, &location)?;
+                text = text[colon + 1..].trim();
+            }
+            if text.is_empty() {
+                continue;
+            }
+
+            let (word, rest) = split_word(text);
+            let (second, value) = split_word(rest);
+            if second.eq_ignore_ascii_case("equ") {
+                self.check_name(word, &location)?;
+                self.constants
+                    .push((location, String::from(word), String::from(value)));
+                continue;
+            }
+            let operands: Vec<String> = if rest.is_empty() {
+                vec![]
+            } else {
+                rest.split(',')
+                    .map(|operand| String::from(operand.trim()))
+                    .collect()
+            };
+            let (statement, size) = match word.to_ascii_lowercase().as_str() {
+                "include" => {
+                    let name = rest.trim_matches('"');
+                    self.include(&directory.join(name), Some(&location), depth + 1)?;
+                    continue;
+                }
+                "db" => (Statement::Bytes(operands.clone()), operands.len()),
+                "dw" => (Statement::Words(operands.clone()), 2 * operands.len()),
+                _ => {
+                    let mnemonic = word.to_ascii_uppercase();
+                    let size = match long_address(&mnemonic, &operands) {
+                        Some(_) => 4,
+                        None => 2,
+                    };
+                    (Statement::Instruction { mnemonic, operands }, size)
+                }
+            };
+            self.address += size;
+            //the program may use the 64 KB of XO-CHIP (`LD I, LONG`), the emulator checks it against its platform
+            let max_size = max_rom_size(XO_CHIP_MEMORY_SIZE, PROGRAM_START);
+            if self.address > PROGRAM_START as usize + max_size {
+                return Err(location.error(format!(
+                    "the program does not fit in memory ({} bytes at most)",
+                    max_size
+                )));
+            }
+            self.statements.push((location, statement));
+        }
+        Ok(())
+    }
+
+    /// `Assembler.define()` defines a label.
+    fn define(&mut self, name: &str, value: i64, location: &Location) -> Result<(), AssemblyError> {
+        self.check_name(name, location)?;
+        self.symbols.insert(String::from(name), value);
+        Ok(())
+    }
+
+    /// `Assembler.check_name()` checks that a new label or constant has a valid name, not defined before.
+    fn check_name(&self, name: &str, location: &Location) -> Result<(), AssemblyError> {
+        if !is_symbol(name) {
+            return Err(location.error(format!("`{}` is not a valid name", name)));
+        }
+        if self.symbols.contains_key(name)
+            || self
+                .constants
+                .iter()
+                .any(|(_, constant, _)| constant == name)
+        {
+            return Err(location.error(format!("`{}` is defined more than once", name)));
+        }
+        Ok(())
+    }
+
+    /// `Assembler.resolve()` evaluates the constants, once the first pass gave every label its address.
+    /// A constant may use constants defined after it : they are evaluated again and again, until all are known.
+    fn resolve(&mut self) -> Result<(), AssemblyError> {
+        let mut constants = std::mem::take(&mut self.constants);
+        while !constants.is_empty() {
+            let count = constants.len();
+            let mut first_error = None;
+            let mut unresolved = vec![];
+            for (location, name, expression) in constants {
+                match self.evaluate(&expression) {
+                    Ok(value) => {
+                        self.symbols.insert(name, value);
+                    }
+                    Err(message) => {
+                        first_error.get_or_insert_with(|| location.error(message));
+                        unresolved.push((location, name, expression));
+                    }
+                }
+            }
+            if unresolved.len() == count {
+                return Err(first_error.expect("a constant is not resolved"));
+            }
+            constants = unresolved;
+        }
+        Ok(())
+    }
+
+    /// `Assembler.emit()` is the second pass : it encodes every statement.
+    fn emit(&self) -> Result<Vec<u8>, AssemblyError> {
+        let mut rom = vec![];
+        for (location, statement) in &self.statements {
+            let error = |message: String| location.error(message);
+            match statement {
+                Statement::Instruction { mnemonic, operands } => {
+                    if let Some(address) = long_address(mnemonic, operands) {
+                        let address = self.value(address, 0, 0xFFFF).map_err(error)? as u16;
+                        rom.extend_from_slice(&Instruction::LoadIndexLong.encode().to_be_bytes());
+                        rom.extend_from_slice(&address.to_be_bytes());
+                        continue;
+                    }
+                    let operands: Vec<&str> = operands.iter().map(String::as_str).collect();
+                    let opcode = self
+                        .instruction(mnemonic, &operands)
+                        .map_err(error)?
+                        .encode();
+                    rom.extend_from_slice(&opcode.to_be_bytes());
+                }
+                Statement::Bytes(values) => {
+                    for value in values {
+                        rom.push(self.byte(value).map_err(error)?);
+                    }
+                }
+                Statement::Words(values) => {
+                    for value in values {
+                        let word = self.value(value, -0x8000, 0xFFFF).map_err(error)? as u16;
+                        rom.extend_from_slice(&word.to_be_bytes());
+                    }
+                }
+            }
+        }
+        debug!("[Assembler.emit()] Assembled {} bytes.", rom.len());
+        Ok(rom)
+    }
+
+    /// `Assembler.instruction()` returns the instruction of a mnemonic and its operands.
+    fn instruction(&self, mnemonic: &str, operands: &[&str]) -> Result<Instruction, String> {
+        let special = |operand: &str| operand.to_ascii_uppercase();
+        let instruction = match (mnemonic, operands) {
+            ("CLS", []) => Instruction::Cls,
+            ("RET", []) => Instruction::Ret,
+            ("SYS", [nnn]) => Instruction::Sys(self.address(nnn)?),
+            ("JP", [nnn]) => Instruction::Jump(self.address(nnn)?),
+            ("JP", [v0, nnn]) if register(v0) == Some(0) => {
+                Instruction::JumpOffset(self.address(nnn)?)
+            }
+            ("CALL", [nnn]) => Instruction::Call(self.address(nnn)?),
+            ("SE", [x, y]) => match register(y) {
+                Some(y) => Instruction::SkipEqual {
+                    x: self.register(x)?,
+                    y,
+                },
+                None => Instruction::SkipEqualByte {
+                    x: self.register(x)?,
+                    nn: self.byte(y)?,
+                },
+            },
+            ("SNE", [x, y]) => match register(y) {
+                Some(y) => Instruction::SkipNotEqual {
+                    x: self.register(x)?,
+                    y,
+                },
+                None => Instruction::SkipNotEqualByte {
+                    x: self.register(x)?,
+                    nn: self.byte(y)?,
+                },
+            },
+            ("LD", [a, b]) => match (special(a).as_str(), special(b).as_str()) {
+                ("I", _) => Instruction::LoadIndex(self.address(b)?),
+                ("HF", _) => Instruction::LoadBigFont(self.register(b)?),
+                ("R", _) => Instruction::StoreFlags(self.register(b)?),
+                ("PITCH", _) => Instruction::SetPitch(self.register(b)?),
+                (_, "R") => Instruction::LoadFlags(self.register(a)?),
+                ("[I]", _) if register_range(b).is_some() => {
+                    let (x, y) = self.register_range(b)?;
+                    Instruction::StoreRange { x, y }
+                }
+                (_, "[I]") if register_range(a).is_some() => {
+                    let (x, y) = self.register_range(a)?;
+                    Instruction::LoadRange { x, y }
+                }
+                ("DT", _) => Instruction::SetDelay(self.register(b)?),
+                ("ST", _) => Instruction::SetSound(self.register(b)?),
+                ("F", _) => Instruction::LoadFont(self.register(b)?),
+                ("B", _) => Instruction::StoreBcd(self.register(b)?),
+                ("[I]", _) => Instruction::StoreRegisters(self.register(b)?),
+                (_, "DT") => Instruction::LoadDelay(self.register(a)?),
+                (_, "K") => Instruction::WaitKey(self.register(a)?),
+                (_, "[I]") => Instruction::LoadRegisters(self.register(a)?),
+                _ => match register(b) {
+                    Some(y) => Instruction::Load {
+                        x: self.register(a)?,
+                        y,
+                    },
+                    None => Instruction::LoadByte {
+                        x: self.register(a)?,
+                        nn: self.byte(b)?,
+                    },
+                },
+            },
+            ("ADD", [a, b]) if special(a) == "I" => Instruction::AddIndex(self.register(b)?),
+            ("ADD", [x, y]) => match register(y) {
+                Some(y) => Instruction::Add {
+                    x: self.register(x)?,
+                    y,
+                },
+                None => Instruction::AddByte {
+                    x: self.register(x)?,
+                    nn: self.byte(y)?,
+                },
+            },
+            ("OR", [x, y]) => Instruction::Or {
+                x: self.register(x)?,
+                y: self.register(y)?,
+            },
+            ("AND", [x, y]) => Instruction::And {
+                x: self.register(x)?,
+                y: self.register(y)?,
+            },
+            ("XOR", [x, y]) => Instruction::Xor {
+                x: self.register(x)?,
+                y: self.register(y)?,
+            },
+            ("SUB", [x, y]) => Instruction::Sub {
+                x: self.register(x)?,
+                y: self.register(y)?,
+            },
+            ("SUBN", [x, y]) => Instruction::SubNegated {
+                x: self.register(x)?,
+                y: self.register(y)?,
+            },
+            //without VY, the register is shifted in place
+            ("SHR", [x]) => Instruction::ShiftRight {
+                x: self.register(x)?,
+                y: self.register(x)?,
+            },
+            ("SHR", [x, y]) => Instruction::ShiftRight {
+                x: self.register(x)?,
+                y: self.register(y)?,
+            },
+            ("SHL", [x]) => Instruction::ShiftLeft {
+                x: self.register(x)?,
+                y: self.register(x)?,
+            },
+            ("SHL", [x, y]) => Instruction::ShiftLeft {
+                x: self.register(x)?,
+                y: self.register(y)?,
+            },
+            ("RND", [x, nn]) => Instruction::Random {
+                x: self.register(x)?,
+                nn: self.byte(nn)?,
+            },
+            ("DRW", [x, y, n]) => Instruction::Draw {
+                x: self.register(x)?,
+                y: self.register(y)?,
+                n: self.value(n, 0, 0xF)? as u8,
+            },
+            ("SKP", [x]) => Instruction::SkipKey(self.register(x)?),
+            ("SKNP", [x]) => Instruction::SkipNotKey(self.register(x)?),
+            //SUPER-CHIP and XO-CHIP
+            ("SCD", [n]) => Instruction::ScrollDown(self.value(n, 0, 0xF)? as u8),
+            ("SCU", [n]) => Instruction::ScrollUp(self.value(n, 0, 0xF)? as u8),
+            ("SCR", []) => Instruction::ScrollRight,
+            ("SCL", []) => Instruction::ScrollLeft,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::LowRes,
+            ("HIGH", []) => Instruction::HighRes,
+            ("PLANE", [n]) => Instruction::Plane(self.value(n, 0, 0xF)? as u8),
+            ("AUDIO", []) => Instruction::Audio,
+            _ => {
+                return Err(format!(
+                    "unknown instruction `{}` with {} operand(s)",
+                    mnemonic,
+                    operands.len()
+                ))
+            }
+        };
+        Ok(instruction)
+    }
+
+    /// `Assembler.register()` reads a register operand, `V0` to `VF`.
+    fn register(&self, operand: &str) -> Result<usize, String> {
+        register(operand).ok_or_else(|| format!("`{}` is not a register", operand))
+    }
+
+    /// `Assembler.register_range()` reads a range of registers, e.g. `V2-V5`.
+    fn register_range(&self, operand: &str) -> Result<(usize, usize), String> {
+        register_range(operand).ok_or_else(|| format!("`{}` is not a range of registers", operand))
+    }
+
+    /// `Assembler.address()` reads a 12-bit address operand.
+    fn address(&self, operand: &str) -> Result<u16, String> {
+        Ok(self.value(operand, 0, 0xFFF)? as u16)
+    }
+
+    /// `Assembler.byte()` reads a byte operand. Negative bytes are written in two's complement.
+    fn byte(&self, operand: &str) -> Result<u8, String> {
+        Ok(self.value(operand, -0x80, 0xFF)? as u8)
+    }
+
+    /// `Assembler.value()` evaluates an operand and checks it is within `min..=max`.
+    fn value(&self, operand: &str, min: i64, max: i64) -> Result<i64, String> {
+        let value = self.evaluate(operand)?;
+        if value < min || value > max {
+            return Err(format!(
+                "`{}` ({}) is out of range {}..={}",
+                operand, value, min, max
+            ));
+        }
+        Ok(value)
+    }
+
+    /// `Assembler.evaluate()` adds and subtracts the numbers and symbols of an expression, e.g. `table + 2`.
+    /// Every operator must be followed by a term : `5 +` is not a value.
+    fn evaluate(&self, expression: &str) -> Result<i64, String> {
+        let mut total = 0;
+        let mut sign = 1;
+        let mut term = String::new();
+        for character in expression.chars() {
+            match character {
+                //a sign without a term before it, e.g. `-1` or `5 - -1`
+                '+' | '-' if term.trim().is_empty() => {
+                    if character == '-' {
+                        sign = -sign;
+                    }
+                }
+                '+' | '-' => {
+                    total += sign * self.term(term.trim())?;
+                    sign = if character == '-' { -1 } else { 1 };
+                    term.clear();
+                }
+                _ => term.push(character),
+            }
+        }
+        if term.trim().is_empty() {
+            return Err(format!("`{}` is not a value", expression));
+        }
+        Ok(total + sign * self.term(term.trim())?)
+    }
+
+    /// `Assembler.term()` returns the value of a number or a symbol.
+    fn term(&self, term: &str) -> Result<i64, String> {
+        match parse_number(term) {
+            Some(value) => Ok(value),
+            None => self
+                .symbols
+                .get(term)
+                .copied()
+                .ok_or_else(|| format!("`{}` is not defined", term)),
+        }
+    }
+}
+
+/// `register()` reads a register name, `V0` to `VF`.
+fn register(operand: &str) -> Option<usize> {
+    let mut characters = operand.chars();
+    match (characters.next(), characters.next(), characters.next()) {
+        (Some('V'), Some(digit), None) | (Some('v'), Some(digit), None) => {
+            digit.to_digit(16).map(|digit| digit as usize)
+        }
+        _ => None,
+    }
+}
+
+/// `register_range()` reads a range of registers, e.g. `V2-V5`.
+fn register_range(operand: &str) -> Option<(usize, usize)> {
+    let mut registers = operand.split('-').map(|name| register(name.trim()));
+    match (registers.next(), registers.next(), registers.next()) {
+        (Some(Some(x)), Some(Some(y)), None) => Some((x, y)),
+        _ => None,
+    }
+}
+
+/// `long_address()` returns the address of `LD I, LONG <address>`, the XO-CHIP instruction which is 4 bytes long.
+fn long_address<'a>(mnemonic: &str, operands: &'a [String]) -> Option<&'a str> {
+    match (mnemonic, operands) {
+        ("LD", [i, long]) if i.eq_ignore_ascii_case("I") => {
+            let (word, address) = split_word(long);
+            if word.eq_ignore_ascii_case("LONG") {
+                Some(address)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// `parse_number()` reads a decimal, hexadecimal (`0x`, `#` or `$`) or binary (`0b`) number.
+fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+    if let Some(digits) = lower
+        .strip_prefix("0x")
+        .or_else(|| lower.strip_prefix('#'))
+        .or_else(|| lower.strip_prefix('$'))
+    {
+        i64::from_str_radix(digits, 16).ok()
+    } else if let Some(digits) = lower.strip_prefix("0b") {
+        i64::from_str_radix(digits, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+/// `is_symbol()` tells if `name` can name a label or a constant : a letter or `_`, then letters, digits and `_`.
+/// Register names are reserved.
+fn is_symbol(name: &str) -> bool {
+    let mut characters = name.chars();
+    characters
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && characters.all(|character| character.is_ascii_alphanumeric() || character == '_')
+        && register(name).is_none()
+}
+
+/// `split_word()` splits the first word of `text` from the rest.
+fn split_word(text: &str) -> (&str, &str) {
+    match text.find(char::is_whitespace) {
+        Some(space) => (&text[..space], text[space..].trim()),
+        None => (text, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `opcodes()` will return the ROM as 16-bit words.
+    fn opcodes(rom: &[u8]) -> Vec<u16> {
+        rom.chunks(2)
+            .map(|word| u16::from_be_bytes([word[0], word[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn assembles_every_chip8_instruction() {
+        let source = "
+            start:  CLS
+                    RET
+                    SYS 0x123
+                    JP start
+                    JP V0, 0x300
+                    CALL 0x400
+                    SE V1, 0x22
+                    SNE V2, #33
+                    SE V3, V4
+                    LD V5, $44
+                    ADD V6, 0b101
+                    LD V7, V8
+                    OR V9, VA
+                    AND VB, VC
+                    XOR VD, VE
+                    ADD V0, V1
+                    SUB V2, V3
+                    SHR V4
+                    SUBN V5, V6
+                    SHL V7, V8
+                    SNE V9, VA
+                    LD I, 0x555
+                    RND VB, 0x0F
+                    DRW VC, VD, 5
+                    SKP VE
+                    SKNP VF
+                    LD V1, DT
+                    LD V2, K
+                    LD DT, V3
+                    LD ST, V4
+                    ADD I, V5
+                    LD F, V6
+                    LD B, V7
+                    LD [I], V8
+                    LD V9, [I]
+        ";
+        let rom = assemble(source).unwrap();
+        assert_eq!(
+            opcodes(&rom),
+            vec![
+                0x00E0, 0x00EE, 0x0123, 0x1200, 0xB300, 0x2400, 0x3122, 0x4233, 0x5340, 0x6544,
+                0x7605, 0x8780, 0x89A1, 0x8BC2, 0x8DE3, 0x8014, 0x8235, 0x8446, 0x8567, 0x878E,
+                0x99A0, 0xA555, 0xCB0F, 0xDCD5, 0xEE9E, 0xEFA1, 0xF107, 0xF20A, 0xF315, 0xF418,
+                0xF51E, 0xF629, 0xF733, 0xF855, 0xF965,
+            ]
+        );
+    }
+
+    #[test]
+    fn assembles_super_chip_and_xo_chip_instructions() {
+        let source = "
+            SCD 4
+            SCU 2
+            SCR
+            SCL
+            EXIT
+            LOW
+            HIGH
+            LD HF, V1
+            LD R, V2
+            LD V3, R
+            LD [I], V2-V5
+            LD V1-V3, [I]
+            PLANE 3
+            AUDIO
+            LD PITCH, V4
+            LD I, LONG data
+        data: db 1
+        ";
+        let rom = assemble(source).unwrap();
+        assert_eq!(
+            opcodes(&rom[..32]),
+            vec![
+                0x00C4, 0x00D2, 0x00FB, 0x00FC, 0x00FD, 0x00FE, 0x00FF, 0xF130, 0xF275, 0xF385,
+                0x5252, 0x5133, 0xF301, 0xF002, 0xF43A, 0xF000,
+            ]
+        );
+        assert_eq!(&rom[32..], &[0x02, 0x22, 0x01]);
+    }
+
+    #[test]
+    fn assembles_data_labels_and_constants() {
+        let source = "
+            SPEED equ 2
+            SIZE  equ end - sprite   ; a constant using labels defined further down
+            OFFSET equ SIZE + 1      ; and a constant defined before
+                    ld v0, SPEED + 1 ; case insensitive mnemonics
+                    ld v1, SIZE
+                    ld v2, OFFSET
+                    ld v3, -1
+                    jp loop - 2
+            loop:   LD I, sprite
+            sprite: db 0x60, 0xF0, 0xF0, 0x60
+            end:    dw start, sprite
+            start:
+        ";
+        let rom = assemble(source).unwrap();
+        assert_eq!(
+            opcodes(&rom[..12]),
+            vec![0x6003, 0x6104, 0x6205, 0x63FF, 0x1208, 0xA20C]
+        );
+        assert_eq!(&rom[12..16], &[0x60, 0xF0, 0xF0, 0x60]);
+        assert_eq!(&rom[16..], &[0x02, 0x14, 0x02, 0x0C]);
+    }
+
+    #[test]
+    fn reports_errors_with_their_line() {
+        let error = |source: &str| assemble(source).unwrap_err();
+        assert_eq!(
+            error("CLS\nLD V0, 5 +"),
+            AssemblyError {
+                file: String::from("<source>"),
+                line: 2,
+                message: String::from("`5 +` is not a value"),
+            }
+        );
+        assert_eq!(error("JP nowhere").message, "`nowhere` is not defined");
+        assert_eq!(error("A equ B\nB equ A").message, "`B` is not defined");
+        assert_eq!(
+            error("x: CLS\nx equ 1").message,
+            "`x` is defined more than once"
+        );
+        assert_eq!(
+            error("LD V0, 0x100").message,
+            "`0x100` (256) is out of range -128..=255"
+        );
+        assert_eq!(
+            error("MOV V0, V1").message,
+            "unknown instruction `MOV` with 2 operand(s)"
+        );
+        let too_large = "CLS\n".repeat(0x10000 / 2);
+        assert_eq!(
+            error(&too_large).message,
+            "the program does not fit in memory (65024 bytes at most)"
+        );
+    }
+
+    #[test]
+    fn reads_includes_relative_to_the_including_file() {
+        let directory = std::env::temp_dir().join(format!("chip8-asm-{}", std::process::id()));
+        fs::create_dir_all(directory.join("lib")).unwrap();
+        fs::write(
+            directory.join("main.asm"),
+            "CALL draw\ninclude \"lib/draw.asm\"\n",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("lib/draw.asm"),
+            "draw: include \"ret.asm\"\n",
+        )
+        .unwrap();
+        fs::write(directory.join("lib/ret.asm"), "RET\n").unwrap();
+        fs::write(directory.join("self.asm"), "include \"self.asm\"\n").unwrap();
+
+        let rom = assemble_file(directory.join("main.asm").to_str().unwrap());
+        let error = assemble_file(directory.join("self.asm").to_str().unwrap()).unwrap_err();
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(rom.unwrap(), vec![0x22, 0x02, 0x00, 0xEE]);
+        assert_eq!(error.message, "includes are nested deeper than 16 levels");
+    }
+}
@@ -0,0 +1,261 @@
+//! JSON - a small reader for the JSON files holding ROM settings (RFC 8259, without surrogate pairs).
+
+use std::collections::BTreeMap;
+
+/// `Value` : a JSON value. Object keys are sorted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(BTreeMap<String, Value>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Value>> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&BTreeMap<String, Value>> {
+        match self {
+            Value::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+}
+
+/// `parse()` will return the value of a JSON document, or the line and the reason it could not be read.
+pub fn parse(text: &str) -> Result<Value, (usize, String)> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        position: 0,
+    };
+    let value = parser.value().map_err(|message| (parser.line(), message))?;
+    parser.skip_whitespace();
+    match parser.peek() {
+        None => Ok(value),
+        Some(c) => Err((parser.line(), format!("unexpected `{}` after the value", c))),
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn line(&self) -> usize {
+        1 + self.chars[..self.position.min(self.chars.len())]
+            .iter()
+            .filter(|&&c| c == '\n')
+            .count()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Result<char, String> {
+        let c = self
+            .peek()
+            .ok_or_else(|| String::from("unexpected end of file"))?;
+        self.position += 1;
+        Ok(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.next()? {
+            c if c == expected => Ok(()),
+            c => Err(format!("expected `{}`, found `{}`", expected, c)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some('n') => self.keyword("null", Value::Null),
+            Some('-' | '0'..='9') => self.number(),
+            Some(c) => Err(format!("unexpected `{}`", c)),
+            None => Err(String::from("unexpected end of file")),
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        for expected in word.chars() {
+            if self.next()? != expected {
+                return Err(format!("expected `{}`", word));
+            }
+        }
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.position;
+        while let Some('-' | '+' | '.' | 'e' | 'E' | '0'..='9') = self.peek() {
+            self.position += 1;
+        }
+        let text: String = self.chars[start..self.position].iter().collect();
+        text.parse()
+            .map(Value::Number)
+            .map_err(|_| format!("`{}` is not a number", text))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Ok(text),
+                '\\' => {
+                    let c = match self.next()? {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'u' => {
+                            let digits: String =
+                                (0..4).map(|_| self.next()).collect::<Result<_, _>>()?;
+                            u32::from_str_radix(&digits, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| format!("`\\u{}` is not a character", digits))?
+                        }
+                        c => c,
+                    };
+                    text.push(c);
+                }
+                c => text.push(c),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut values = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => (),
+                ']' => return Ok(Value::Array(values)),
+                c => return Err(format!("expected `,` or `]`, found `{}`", c)),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut members = BTreeMap::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            let value = self.value()?;
+            members.insert(key, value);
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => (),
+                '}' => return Ok(Value::Object(members)),
+                c => return Err(format!("expected `,` or `}}`, found `{}`", c)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_values() {
+        let document = parse(
+            r#"{ "title": "Pong \"2\"\u0021", "tickrate": 20, "speed": -1.5e1,
+                 "keys": [1, 2], "quirks": { "shift": true, "jump": false }, "author": null }"#,
+        )
+        .unwrap();
+        let members = document.as_object().unwrap();
+        assert_eq!(members["title"].as_str(), Some("Pong \"2\"!"));
+        assert_eq!(members["tickrate"].as_f64(), Some(20.0));
+        assert_eq!(members["speed"].as_f64(), Some(-15.0));
+        assert_eq!(
+            members["keys"].as_array(),
+            Some(&vec![Value::Number(1.0), Value::Number(2.0)])
+        );
+        assert_eq!(
+            members["quirks"].as_object().unwrap()["shift"].as_bool(),
+            Some(true)
+        );
+        assert_eq!(members["author"], Value::Null);
+        assert_eq!(parse(" [] ").unwrap(), Value::Array(vec![]));
+    }
+
+    #[test]
+    fn reports_the_line_of_errors() {
+        assert_eq!(
+            parse("{\n  \"a\": 1,\n  \"b\" 2\n}"),
+            Err((3, String::from("expected `:`, found `2`")))
+        );
+        assert_eq!(parse("{} {}").unwrap_err().0, 1);
+        for text in [
+            "",
+            "{",
+            "[1,",
+            "\"text",
+            "tru",
+            "1e",
+            "{\"a\":1,}",
+            "\"\\u12\"",
+        ] {
+            assert!(parse(text).is_err(), "{}", text);
+        }
+    }
+}
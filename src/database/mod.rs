@@ -0,0 +1,558 @@
+//! ROM database - settings for well-known ROMs, recognized by the SHA-1 of their bytes, so they run right
+//! without any configuration. The database is a JSON object keyed by SHA-1 :
+//!
+//! ```text
+//! {
+//!   "0123456789abcdef0123456789abcdef01234567": {
+//!     "title": "Some Game",
+//!     "author": "Someone",
+//!     "platform": "schip",
+//!     "quirks": { "clip_sprites": false },
+//!     "keymap": { "5": "Up", "8": "Down" },
+//!     "tick_rate": 30,
+//!     "colors": { "background": "#000000", "foreground": "#FFCC00" }
+//!   }
+//! }
+//! ```
+//!
+//! Every setting is optional. `platform` is `chip8`, `schip` or `xochip`, `quirks` override the quirks of the platform,
+//! `keymap` binds CHIP-8 keys (0-F) to keyboard keys (SDL names), `tick_rate` is the number of instructions per frame
+//! (60 frames per second), `load_address` is where the ROM is loaded (0x200 by default), `entry_point` is where
+//! it starts (its load address by default) and `palette` is the background and foreground colors, as an array
+//! (the same as `colors`).
+//! The database embedded in the program is `roms.json`, local files can add to it. The `programs.json` file of
+//! the community CHIP-8 database can be given as a local database as it is (see `programs`). A local database can
+//! also be written in TOML, with a table per ROM :
+//!
+//! ```text
+//! ["0123456789abcdef0123456789abcdef01234567"]
+//! title = "Some Game"
+//! platform = "schip"
+//!
+//! ["0123456789abcdef0123456789abcdef01234567".quirks]
+//! clip_sprites = false
+//! ```
+//!
+//! The settings of a single ROM can also be kept next to it, in a sidecar file named after the ROM
+//! (`game.ch8.toml` or `game.ch8.json`) :
+//!
+//! ```text
+//! title = "Some Game"
+//! platform = "schip"
+//! load_address = 0x200
+//! tick_rate = 30
+//! palette = ["#000000", "#FFCC00"]
+//!
+//! [quirks]
+//! clip_sprites = false
+//!
+//! [keymap]
+//! 5 = "Up"
+//! ```
+
+mod crc32;
+pub(crate) mod json;
+mod programs;
+mod sha1;
+mod toml;
+
+pub use self::crc32::crc32;
+pub use self::sha1::sha1;
+
+use crate::vm::{Platform, Quirks};
+use json::Value;
+use log::debug;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// `DatabaseError` : why a database could not be read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatabaseError {
+    pub message: String,
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for DatabaseError {}
+
+/// `RomSettings` : what is known about a ROM, and how to run it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RomSettings {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub platform: Option<Platform>,
+    /// `quirks` holds the quirks to change, by name (e.g. `shift_vy`).
+    pub quirks: Vec<(String, bool)>,
+    /// `keymap` binds CHIP-8 keys to keyboard keys, by their SDL name.
+    pub keymap: Vec<(usize, String)>,
+    pub tick_rate: Option<u32>,
+    pub load_address: Option<u16>,
+    pub entry_point: Option<u16>,
+    pub background: Option<[u8; 3]>,
+    pub foreground: Option<[u8; 3]>,
+}
+
+impl RomSettings {
+    /// `RomSettings::load()` will return the settings of a sidecar file, in JSON when its name ends with `.json`
+    /// and in TOML otherwise.
+    pub fn load(filename: &str) -> Result<RomSettings, DatabaseError> {
+        let text = fs::read_to_string(filename).map_err(|error| DatabaseError {
+            message: format!("Could not read {} : {}", filename, error),
+        })?;
+        let document = match Path::new(filename).extension() {
+            Some(extension) if extension == "json" => json::parse(&text),
+            _ => toml::parse(&text),
+        };
+        let document = document.map_err(|(line, message)| DatabaseError {
+            message: format!("{}:{}: {}", filename, line, message),
+        })?;
+        parse_settings(&document).map_err(|message| DatabaseError {
+            message: format!("{}: {}", filename, message),
+        })
+    }
+
+    /// `RomSettings.apply_quirks()` changes `quirks` as the settings say.
+    pub fn apply_quirks(&self, quirks: &mut Quirks) {
+        for (name, value) in &self.quirks {
+            quirks.set(name, *value);
+        }
+    }
+
+    /// `RomSettings.merge()` adds the settings of `other`, which replace those given by both.
+    pub fn merge(&mut self, other: RomSettings) {
+        self.title = other.title.or(self.title.take());
+        self.author = other.author.or(self.author.take());
+        self.platform = other.platform.or(self.platform);
+        self.quirks.extend(other.quirks);
+        self.keymap.extend(other.keymap);
+        self.tick_rate = other.tick_rate.or(self.tick_rate);
+        self.load_address = other.load_address.or(self.load_address);
+        self.entry_point = other.entry_point.or(self.entry_point);
+        self.background = other.background.or(self.background);
+        self.foreground = other.foreground.or(self.foreground);
+    }
+
+    /// `RomSettings.to_toml()` will return the settings written as a TOML sidecar file.
+    pub fn to_toml(&self) -> String {
+        let mut text = String::new();
+        let color = |[r, g, b]: [u8; 3]| format!("\"#{:02X}{:02X}{:02X}\"", r, g, b);
+        if let Some(title) = self.title.as_ref() {
+            text.push_str(&format!("title = {:?}\n", title));
+        }
+        if let Some(author) = self.author.as_ref() {
+            text.push_str(&format!("author = {:?}\n", author));
+        }
+        if let Some(platform) = self.platform {
+            text.push_str(&format!("platform = \"{}\"\n", platform.name()));
+        }
+        if let Some(load_address) = self.load_address {
+            text.push_str(&format!("load_address = 0x{:03X}\n", load_address));
+        }
+        if let Some(entry_point) = self.entry_point {
+            text.push_str(&format!("entry_point = 0x{:03X}\n", entry_point));
+        }
+        if let Some(tick_rate) = self.tick_rate {
+            text.push_str(&format!("tick_rate = {}\n", tick_rate));
+        }
+        if self.background.is_some() || self.foreground.is_some() {
+            text.push_str(&format!(
+                "palette = [{}, {}]\n",
+                color(self.background.unwrap_or([0, 0, 0])),
+                color(self.foreground.unwrap_or([0, 255, 0]))
+            ));
+        }
+        if !self.quirks.is_empty() {
+            text.push_str("\n[quirks]\n");
+            for (name, value) in &self.quirks {
+                text.push_str(&format!("{} = {}\n", name, value));
+            }
+        }
+        if !self.keymap.is_empty() {
+            text.push_str("\n[keymap]\n");
+            for (chip8_key, key_name) in &self.keymap {
+                text.push_str(&format!("{:X} = {:?}\n", chip8_key, key_name));
+            }
+        }
+        text
+    }
+}
+
+/// `RomDatabase` : the settings of every known ROM, by the SHA-1 of the ROM.
+#[derive(Debug, Clone, Default)]
+pub struct RomDatabase {
+    pub roms: HashMap<String, RomSettings>,
+}
+
+impl RomDatabase {
+    /// `RomDatabase::embedded()` will return the database shipped with the program.
+    pub fn embedded() -> RomDatabase {
+        RomDatabase::parse(include_str!("roms.json"))
+            .expect("Error: The embedded ROM database is invalid.")
+    }
+
+    /// `RomDatabase::load()` will return the database read from a file, in TOML when its name ends with `.toml`
+    /// and in JSON otherwise.
+    pub fn load(filename: &str) -> Result<RomDatabase, DatabaseError> {
+        let text = fs::read_to_string(filename).map_err(|error| DatabaseError {
+            message: format!("Could not read {} : {}", filename, error),
+        })?;
+        let database = match Path::new(filename).extension() {
+            Some(extension) if extension == "toml" => RomDatabase::parse_toml(&text),
+            _ => RomDatabase::parse(&text),
+        };
+        database.map_err(|error| DatabaseError {
+            message: format!("{}:{}", filename, error.message),
+        })
+    }
+
+    /// `RomDatabase::parse()` will return the database read from JSON `text`.
+    pub fn parse(text: &str) -> Result<RomDatabase, DatabaseError> {
+        RomDatabase::from_document(json::parse(text))
+    }
+
+    /// `RomDatabase::parse_toml()` will return the database read from TOML `text`.
+    pub fn parse_toml(text: &str) -> Result<RomDatabase, DatabaseError> {
+        RomDatabase::from_document(toml::parse(text))
+    }
+
+    /// `RomDatabase::from_document()` will return the database read from a parsed document, or its parse error.
+    fn from_document(
+        document: Result<Value, (usize, String)>,
+    ) -> Result<RomDatabase, DatabaseError> {
+        let document = document.map_err(|(line, message)| DatabaseError {
+            message: format!("{}: {}", line, message),
+        })?;
+        //the programs of the community CHIP-8 database are an array
+        if let Some(programs) = document.as_array() {
+            let roms =
+                programs::read_programs(programs).map_err(|message| DatabaseError { message })?;
+            return RomDatabase::checked(roms);
+        }
+        let entries = document.as_object().ok_or_else(|| DatabaseError {
+            message: String::from("the database must be an object keyed by SHA-1"),
+        })?;
+        let mut roms = HashMap::new();
+        for (hash, entry) in entries {
+            let settings = parse_settings(entry).map_err(|message| DatabaseError {
+                message: format!("{}: {}", hash, message),
+            })?;
+            roms.insert(hash.to_lowercase(), settings);
+        }
+        RomDatabase::checked(roms)
+    }
+
+    /// `RomDatabase::checked()` will return the database of `roms`, if every ROM is keyed by a SHA-1.
+    fn checked(roms: HashMap<String, RomSettings>) -> Result<RomDatabase, DatabaseError> {
+        if let Some(hash) = roms
+            .keys()
+            .find(|hash| hash.len() != 40 || !hash.chars().all(|c| c.is_ascii_hexdigit()))
+        {
+            return Err(DatabaseError {
+                message: format!("`{}` is not a SHA-1", hash),
+            });
+        }
+        debug!("[RomDatabase::parse()] Read {} ROMs.", roms.len());
+        Ok(RomDatabase { roms })
+    }
+
+    /// `RomDatabase.extend()` adds the ROMs of `other`, replacing the settings of the ROMs known to both.
+    pub fn extend(&mut self, other: RomDatabase) {
+        self.roms.extend(other.roms);
+    }
+
+    /// `RomDatabase.lookup()` returns the settings of `rom`, if it is known.
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomSettings> {
+        self.roms.get(&sha1(rom))
+    }
+}
+
+/// `parse_settings()` reads the settings of one ROM.
+fn parse_settings(entry: &Value) -> Result<RomSettings, String> {
+    let members = entry
+        .as_object()
+        .ok_or_else(|| String::from("the settings must be an object"))?;
+    let mut settings = RomSettings::default();
+    for (key, value) in members {
+        let invalid = || format!("`{}` is invalid", key);
+        match key.as_str() {
+            "title" => settings.title = Some(value.as_str().ok_or_else(invalid)?.to_string()),
+            "author" => settings.author = Some(value.as_str().ok_or_else(invalid)?.to_string()),
+            "platform" => {
+                let name = value.as_str().ok_or_else(invalid)?;
+                settings.platform = Some(
+                    Platform::from_name(name)
+                        .ok_or_else(|| format!("`{}` is not a platform", name))?,
+                );
+            }
+            "quirks" => {
+                for (name, value) in value.as_object().ok_or_else(invalid)? {
+                    let value = value
+                        .as_bool()
+                        .ok_or_else(|| format!("quirk `{}` must be true or false", name))?;
+                    if !Quirks::new().set(name, value) {
+                        return Err(format!("`{}` is not a quirk", name));
+                    }
+                    settings.quirks.push((name.clone(), value));
+                }
+            }
+            "keymap" => {
+                for (chip8_key, key_name) in value.as_object().ok_or_else(invalid)? {
+                    let chip8_key = usize::from_str_radix(chip8_key, 16)
+                        .ok()
+                        .filter(|&chip8_key| chip8_key < 16)
+                        .ok_or_else(|| format!("`{}` is not a CHIP-8 key", chip8_key))?;
+                    let key_name = key_name.as_str().ok_or_else(invalid)?;
+                    settings.keymap.push((chip8_key, key_name.to_string()));
+                }
+            }
+            "tick_rate" => {
+                let tick_rate = value
+                    .as_f64()
+                    .filter(|&rate| rate >= 1.0)
+                    .ok_or_else(invalid)?;
+                settings.tick_rate = Some(tick_rate as u32);
+            }
+            "load_address" => {
+                settings.load_address = Some(value.as_f64().and_then(address).ok_or_else(invalid)?)
+            }
+            "entry_point" => {
+                settings.entry_point = Some(value.as_f64().and_then(address).ok_or_else(invalid)?)
+            }
+            "palette" => {
+                let colors = value
+                    .as_array()
+                    .filter(|colors| colors.len() == 2)
+                    .ok_or_else(|| {
+                        String::from("the palette must be the background and foreground colors")
+                    })?;
+                let colors = colors
+                    .iter()
+                    .map(|color| color.as_str().and_then(parse_color))
+                    .collect::<Option<Vec<[u8; 3]>>>()
+                    .ok_or_else(|| {
+                        String::from("the colors of the palette must be like #RRGGBB")
+                    })?;
+                settings.background = Some(colors[0]);
+                settings.foreground = Some(colors[1]);
+            }
+            "colors" => {
+                for (name, color) in value.as_object().ok_or_else(invalid)? {
+                    let color = color
+                        .as_str()
+                        .and_then(parse_color)
+                        .ok_or_else(|| format!("color `{}` must be like #RRGGBB", name))?;
+                    match name.as_str() {
+                        "background" => settings.background = Some(color),
+                        "foreground" => settings.foreground = Some(color),
+                        _ => return Err(format!("`{}` is not a color", name)),
+                    }
+                }
+            }
+            _ => return Err(format!("`{}` is not a setting", key)),
+        }
+    }
+    Ok(settings)
+}
+
+/// `address()` returns `number` as a memory address, when it is one.
+fn address(number: f64) -> Option<u16> {
+    Some(number as u16).filter(|_| (0.0..4096.0).contains(&number) && number.fract() == 0.0)
+}
+
+/// `parse_color()` reads a color written `#RRGGBB`.
+pub(crate) fn parse_color(text: &str) -> Option<[u8; 3]> {
+    let digits = text.strip_prefix('#').filter(|digits| digits.len() == 6)?;
+    let value = u32::from_str_radix(digits, 16).ok()?;
+    Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    const JSON: &str = r##"{
+        "0123456789ABCDEF0123456789abcdef01234567": {
+            "title": "Some Game",
+            "platform": "schip",
+            "quirks": { "clip_sprites": false },
+            "keymap": { "5": "Up" },
+            "tick_rate": 30,
+            "colors": { "background": "#000000", "foreground": "#FFCC00" }
+        }
+    }"##;
+
+    const TOML: &str = r##"
+        # the same database, in TOML
+        ["0123456789ABCDEF0123456789abcdef01234567"]
+        title = "Some Game"
+        platform = "schip"
+        tick_rate = 30
+        palette = ["#000000", "#FFCC00"]
+
+        ["0123456789ABCDEF0123456789abcdef01234567".quirks]
+        clip_sprites = false
+
+        ["0123456789ABCDEF0123456789abcdef01234567".keymap]
+        5 = "Up"
+    "##;
+
+    #[test]
+    fn json_and_toml_databases_hold_the_same_settings() {
+        let json = RomDatabase::parse(JSON).unwrap();
+        let toml = RomDatabase::parse_toml(TOML).unwrap();
+        let settings = &json.roms["0123456789abcdef0123456789abcdef01234567"];
+        assert_eq!(settings.title.as_deref(), Some("Some Game"));
+        assert_eq!(settings.platform, Some(Platform::SuperChip));
+        assert_eq!(settings.quirks, vec![(String::from("clip_sprites"), false)]);
+        assert_eq!(settings.keymap, vec![(5, String::from("Up"))]);
+        assert_eq!(settings.foreground, Some([0xFF, 0xCC, 0x00]));
+        assert_eq!(json.roms, toml.roms);
+    }
+
+    #[test]
+    fn load_reads_the_format_of_the_extension() {
+        let directory = std::env::temp_dir().join(format!("chip8-database-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        for (name, text) in [("roms.json", JSON), ("roms.toml", TOML)] {
+            let filename = directory.join(name);
+            fs::write(&filename, text).unwrap();
+            let database = RomDatabase::load(filename.to_str().unwrap()).unwrap();
+            assert_eq!(database.roms.len(), 1, "{}", name);
+        }
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn sidecars_written_are_read_back() {
+        let settings = RomSettings {
+            title: Some(String::from("Some \"Game\"")),
+            author: Some(String::from("Someone")),
+            platform: Some(Platform::XoChip),
+            quirks: vec![
+                (String::from("clip_sprites"), false),
+                (String::from("shift_vy"), true),
+            ],
+            keymap: vec![(5, String::from("Up")), (0xA, String::from("Space"))],
+            tick_rate: Some(200),
+            load_address: Some(0x200),
+            entry_point: Some(0x2C0),
+            background: Some([0x10, 0x20, 0x30]),
+            foreground: Some([0xFF, 0xCC, 0x00]),
+        };
+        let filename =
+            std::env::temp_dir().join(format!("chip8-sidecar-{}.ch8.toml", std::process::id()));
+        fs::write(&filename, settings.to_toml()).unwrap();
+        let loaded = RomSettings::load(filename.to_str().unwrap());
+        fs::remove_file(&filename).unwrap();
+        assert_eq!(loaded.unwrap(), settings);
+
+        //settings left out stay out
+        let settings = RomSettings {
+            tick_rate: Some(15),
+            ..RomSettings::default()
+        };
+        assert_eq!(settings.to_toml(), "tick_rate = 15\n");
+    }
+
+    #[test]
+    fn lookup_finds_a_rom_by_its_sha1() {
+        //the SHA-1 of "abc"
+        let database = RomDatabase::parse(
+            r#"{ "a9993e364706816aba3e25717850c26c9cd0d89d": { "title": "abc" } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            database
+                .lookup(b"abc")
+                .and_then(|settings| settings.title.as_deref()),
+            Some("abc")
+        );
+        assert!(database.lookup(b"abd").is_none());
+    }
+
+    /// A small ROM : clear the screen, draw the 0 at (12, 8) and loop, then the sprite of the 0.
+    pub const ROM: [u8; 17] = [
+        0x00, 0xE0, 0xA2, 0x0C, 0x60, 0x0C, 0x61, 0x08, 0xD0, 0x15, 0x12, 0x0A, 0xF0, 0x90, 0x90,
+        0x90, 0xF0,
+    ];
+
+    /// The programs of the community CHIP-8 database, with `ROM` and a ROM of a platform this machine lacks.
+    pub const PROGRAMS: &str = r##"[
+        {
+            "title": "Zero",
+            "authors": ["Someone", "Someone Else"],
+            "description": "Draws a 0.",
+            "roms": {
+                "35FD480CF16456C060C000FE214847D01F820E0C": {
+                    "file": "zero.ch8",
+                    "platforms": ["megachip8", "superchip", "xochip"],
+                    "quirkyPlatforms": { "superchip": { "shift": false, "wrap": true, "vblank": true } },
+                    "tickrate": 20,
+                    "startAddress": 512,
+                    "keys": { "up": 5, "a": 6, "select": 1 },
+                    "colors": { "pixels": ["#101010", "#F0C000"], "buzzer": "#FFFFFF" }
+                }
+            }
+        },
+        {
+            "title": "MegaChip only",
+            "roms": { "0123456789abcdef0123456789abcdef01234567": { "platforms": ["megachip8"] } }
+        }
+    ]"##;
+
+    #[test]
+    fn programs_of_the_chip8_database_are_read_as_settings() {
+        let database = RomDatabase::parse(PROGRAMS).unwrap();
+        assert_eq!(sha1(&ROM), "35fd480cf16456c060c000fe214847d01f820e0c");
+        let settings = database.lookup(&ROM).unwrap();
+        assert_eq!(settings.title.as_deref(), Some("Zero"));
+        assert_eq!(settings.author.as_deref(), Some("Someone, Someone Else"));
+        assert_eq!(settings.platform, Some(Platform::SuperChip));
+        assert_eq!(
+            settings.quirks,
+            vec![
+                (String::from("shift_vy"), true),
+                (String::from("clip_sprites"), false)
+            ]
+        );
+        assert_eq!(settings.tick_rate, Some(20));
+        assert_eq!(settings.load_address, Some(0x200));
+        assert_eq!(
+            settings.keymap,
+            vec![(6, String::from("Space")), (5, String::from("Up"))]
+        );
+        assert_eq!(
+            (settings.background, settings.foreground),
+            (Some([0x10, 0x10, 0x10]), Some([0xF0, 0xC0, 0x00]))
+        );
+        let megachip = &database.roms["0123456789abcdef0123456789abcdef01234567"];
+        assert_eq!(megachip.platform, None);
+    }
+
+    #[test]
+    fn invalid_databases_are_rejected() {
+        assert!(RomDatabase::parse(r#"{ "not a sha1": {} }"#).is_err());
+        assert!(RomDatabase::parse(r#"1"#).is_err());
+        assert!(RomDatabase::parse(r#"[1]"#).is_err());
+        assert!(RomDatabase::parse(r#"[{ "roms": { "not a sha1": {} } }]"#).is_err());
+        assert!(RomDatabase::parse_toml(
+            "[\"0123456789abcdef0123456789abcdef01234567\"]\nplatform = \"gameboy\""
+        )
+        .is_err());
+        assert!(RomDatabase::parse_toml("a = 1\n[a.b]").is_err());
+    }
+
+    #[test]
+    fn embedded_database_is_valid() {
+        for hash in RomDatabase::embedded().roms.keys() {
+            assert_eq!(hash.len(), 40);
+        }
+    }
+}
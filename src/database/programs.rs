@@ -0,0 +1,165 @@
+//! CHIP-8 database - the settings of the ROMs of the community CHIP-8 database (`programs.json`), read as
+//! ROM settings. The file is an array of programs, each with its ROMs keyed by SHA-1 :
+//!
+//! ```text
+//! [
+//!   {
+//!     "title": "Some Game",
+//!     "authors": ["Someone"],
+//!     "roms": {
+//!       "0123456789abcdef0123456789abcdef01234567": {
+//!         "platforms": ["superchip"],
+//!         "quirkyPlatforms": { "superchip": { "wrap": true } },
+//!         "tickrate": 30,
+//!         "startAddress": 512,
+//!         "keys": { "up": 5, "down": 8 },
+//!         "colors": { "pixels": ["#000000", "#FFCC00"] }
+//!       }
+//!     }
+//!   }
+//! ]
+//! ```
+//!
+//! The first platform this machine knows gives the platform of a ROM, its quirks are changed as `quirkyPlatforms`
+//! says for that platform. The other fields of the database (descriptions, images, ...) are left out.
+
+use super::json::Value;
+use super::{parse_color, RomSettings};
+use crate::vm::Platform;
+use std::collections::HashMap;
+
+/// `read_programs()` will return the settings of every ROM of the programs, by SHA-1.
+pub fn read_programs(programs: &[Value]) -> Result<HashMap<String, RomSettings>, String> {
+    let mut roms = HashMap::new();
+    for program in programs {
+        let program = program
+            .as_object()
+            .ok_or_else(|| String::from("the programs must be objects"))?;
+        let title = program.get("title").and_then(Value::as_str);
+        let authors = program
+            .get("authors")
+            .and_then(Value::as_array)
+            .map(|authors| {
+                authors
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            })
+            .filter(|authors| !authors.is_empty());
+        let entries = match program.get("roms").and_then(Value::as_object) {
+            Some(entries) => entries,
+            None => continue,
+        };
+        for (hash, entry) in entries {
+            let mut settings =
+                read_rom(entry).map_err(|message| format!("{}: {}", hash, message))?;
+            settings.title = title.map(String::from);
+            settings.author = authors.clone();
+            roms.insert(hash.to_lowercase(), settings);
+        }
+    }
+    Ok(roms)
+}
+
+/// `read_rom()` reads the settings of one ROM of a program.
+fn read_rom(entry: &Value) -> Result<RomSettings, String> {
+    let entry = entry
+        .as_object()
+        .ok_or_else(|| String::from("the ROM must be an object"))?;
+    let mut settings = RomSettings::default();
+    let platform = entry
+        .get("platforms")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .find_map(|name| platform(name).map(|platform| (name, platform)));
+    if let Some((name, platform)) = platform {
+        settings.platform = Some(platform);
+        let quirks = entry
+            .get("quirkyPlatforms")
+            .and_then(Value::as_object)
+            .and_then(|platforms| platforms.get(name))
+            .and_then(Value::as_object);
+        for (quirk, value) in quirks.into_iter().flatten() {
+            if let (Some((name, inverted)), Some(value)) = (quirk_name(quirk), value.as_bool()) {
+                settings
+                    .quirks
+                    .push((String::from(name), value != inverted));
+            }
+        }
+    }
+    if let Some(tick_rate) = entry.get("tickrate").and_then(Value::as_f64) {
+        settings.tick_rate = Some(tick_rate as u32).filter(|&rate| rate >= 1);
+    }
+    if let Some(start) = entry.get("startAddress").and_then(Value::as_f64) {
+        settings.load_address = Some(start as u16).filter(|_| (0.0..4096.0).contains(&start));
+    }
+    for (action, chip8_key) in entry
+        .get("keys")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+    {
+        if let (Some(key_name), Some(chip8_key)) = (key_name(action), chip8_key.as_f64()) {
+            if (0.0..16.0).contains(&chip8_key) {
+                settings
+                    .keymap
+                    .push((chip8_key as usize, String::from(key_name)));
+            }
+        }
+    }
+    let pixels = entry
+        .get("colors")
+        .and_then(Value::as_object)
+        .and_then(|colors| colors.get("pixels"))
+        .and_then(Value::as_array)
+        .map(|pixels| {
+            pixels
+                .iter()
+                .filter_map(|color| color.as_str().and_then(parse_color))
+                .collect::<Vec<[u8; 3]>>()
+        });
+    if let Some(pixels) = pixels.filter(|pixels| pixels.len() >= 2) {
+        settings.background = Some(pixels[0]);
+        settings.foreground = Some(pixels[1]);
+    }
+    Ok(settings)
+}
+
+/// `platform()` returns the platform which runs the ROMs of a platform of the database, if there is one.
+fn platform(name: &str) -> Option<Platform> {
+    match name {
+        "originalChip8" | "hybridVIP" | "modernChip8" | "chip8x" => Some(Platform::Chip8),
+        "chip48" | "superchip1" | "superchip" => Some(Platform::SuperChip),
+        "xochip" => Some(Platform::XoChip),
+        _ => None,
+    }
+}
+
+/// `quirk_name()` returns the name of the quirk of the database, as `Quirks.set()` reads it, and whether
+/// its value is the opposite of the quirk.
+fn quirk_name(name: &str) -> Option<(&'static str, bool)> {
+    match name {
+        "shift" => Some(("shift_vy", true)),
+        "memoryLeaveIUnchanged" => Some(("load_store_increments_i", true)),
+        "jump" => Some(("jump_vx", false)),
+        "logic" => Some(("logic_resets_vf", false)),
+        "wrap" => Some(("clip_sprites", true)),
+        _ => None,
+    }
+}
+
+/// `key_name()` returns the keyboard key (its SDL name) bound to an action of the database.
+fn key_name(action: &str) -> Option<&'static str> {
+    match action {
+        "up" => Some("Up"),
+        "down" => Some("Down"),
+        "left" => Some("Left"),
+        "right" => Some("Right"),
+        "a" => Some("Space"),
+        "b" => Some("Return"),
+        _ => None,
+    }
+}
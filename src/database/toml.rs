@@ -0,0 +1,250 @@
+//! TOML - a small reader for the TOML sidecar files holding ROM settings, and for ROM databases. It reads
+//! `key = value` lines and `[table]` or `[table.subtable]` headers, with strings, integers (decimal or `0x` hexadecimal), floats, booleans and arrays on one line.
+//! The document is returned as the same values as JSON.
+
+use super::json::Value;
+use std::collections::BTreeMap;
+
+/// `parse()` will return the table of a TOML document, or the line and the reason it could not be read.
+pub fn parse(text: &str) -> Result<Value, (usize, String)> {
+    let mut document = BTreeMap::new();
+    let mut path: Vec<String> = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let error = |message: String| (number + 1, message);
+        let mut parser = Parser {
+            chars: line.chars().collect(),
+            position: 0,
+        };
+        parser.skip_whitespace();
+        match parser.peek() {
+            None | Some('#') => continue,
+            Some('[') => {
+                parser.position += 1;
+                path = vec![parser.key().map_err(error)?];
+                while parser.peek() == Some('.') {
+                    parser.position += 1;
+                    path.push(parser.key().map_err(error)?);
+                }
+                parser.expect(']').map_err(error)?;
+                parser.end().map_err(error)?;
+                table(&mut document, &path).map_err(error)?;
+            }
+            Some(_) => {
+                let key = parser.key().map_err(error)?;
+                parser.expect('=').map_err(error)?;
+                let value = parser.value().map_err(error)?;
+                parser.end().map_err(error)?;
+                let members = table(&mut document, &path).map_err(error)?;
+                if members.insert(key.clone(), value).is_some() {
+                    return Err(error(format!("`{}` is already defined", key)));
+                }
+            }
+        }
+    }
+    Ok(Value::Object(document))
+}
+
+/// `table()` returns the members of the table at `path` in the `document`, creating the missing tables.
+fn table<'a>(
+    document: &'a mut BTreeMap<String, Value>,
+    path: &[String],
+) -> Result<&'a mut BTreeMap<String, Value>, String> {
+    let mut members = document;
+    for name in path {
+        let value = members
+            .entry(name.clone())
+            .or_insert_with(|| Value::Object(BTreeMap::new()));
+        members = match value {
+            Value::Object(members) => members,
+            _ => return Err(format!("`{}` is already defined", name)),
+        };
+    }
+    Ok(members)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.position += 1;
+                Ok(())
+            }
+            Some(c) => Err(format!("expected `{}`, found `{}`", expected, c)),
+            None => Err(format!("expected `{}`", expected)),
+        }
+    }
+
+    /// `Parser.end()` checks that nothing but a comment is left on the line.
+    fn end(&mut self) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.peek() {
+            None | Some('#') => Ok(()),
+            Some(c) => Err(format!("unexpected `{}`", c)),
+        }
+    }
+
+    /// `Parser.key()` reads a bare key (letters, digits, `_` and `-`) or a quoted one.
+    fn key(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        if self.peek() == Some('"') {
+            return self.string();
+        }
+        let start = self.position;
+        while let Some('a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-') = self.peek() {
+            self.position += 1;
+        }
+        if self.position == start {
+            return Err(String::from("expected a key"));
+        }
+        Ok(self.chars[start..self.position].iter().collect())
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('[') => self.array(),
+            Some(c) if !c.is_ascii_alphanumeric() && c != '-' && c != '+' => {
+                Err(format!("unexpected `{}`", c))
+            }
+            Some(_) => {
+                let start = self.position;
+                while let Some('a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' | '+' | '.') =
+                    self.peek()
+                {
+                    self.position += 1;
+                }
+                let word: String = self.chars[start..self.position].iter().collect();
+                let digits = word.replace('_', "");
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => {
+                        let number = match digits.strip_prefix("0x") {
+                            Some(hex) => i64::from_str_radix(hex, 16).ok().map(|n| n as f64),
+                            None => digits.parse().ok(),
+                        };
+                        number
+                            .map(Value::Number)
+                            .ok_or_else(|| format!("`{}` is not a value", word))
+                    }
+                }
+            }
+            None => Err(String::from("expected a value")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| String::from("unterminated string"))?;
+            self.position += 1;
+            match c {
+                '"' => return Ok(text),
+                '\\' => {
+                    let c = self
+                        .peek()
+                        .ok_or_else(|| String::from("unterminated string"))?;
+                    self.position += 1;
+                    text.push(match c {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        c => c,
+                    });
+                }
+                c => text.push(c),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut values = vec![];
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(']') {
+                self.position += 1;
+                return Ok(Value::Array(values));
+            }
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some(']') => (),
+                _ => return Err(String::from("expected `,` or `]`")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tables_and_values() {
+        let document = parse(
+            "# a comment\ntitle = \"Pong\"\ntickrate = 0x14\n\n[quirks]\nshift = true\n\
+             [colors.planes]\nfirst = [\"#FFCC00\", \"#FF6600\"]\nratio = 1_000.5\n",
+        )
+        .unwrap();
+        let members = document.as_object().unwrap();
+        assert_eq!(members["title"].as_str(), Some("Pong"));
+        assert_eq!(members["tickrate"].as_f64(), Some(20.0));
+        assert_eq!(
+            members["quirks"].as_object().unwrap()["shift"].as_bool(),
+            Some(true)
+        );
+        let planes = members["colors"].as_object().unwrap()["planes"]
+            .as_object()
+            .unwrap();
+        assert_eq!(
+            planes["first"],
+            Value::Array(vec![
+                Value::String(String::from("#FFCC00")),
+                Value::String(String::from("#FF6600"))
+            ])
+        );
+        assert_eq!(planes["ratio"].as_f64(), Some(1000.5));
+    }
+
+    #[test]
+    fn reports_the_line_of_errors() {
+        assert_eq!(
+            parse("a = 1\na = 2"),
+            Err((2, String::from("`a` is already defined")))
+        );
+        assert_eq!(parse("a = 1\n[a]").unwrap_err().0, 2);
+        for text in [
+            "a =",
+            "a = \"text",
+            "a = [1, 2",
+            "[a",
+            "= 1",
+            "a = 1 2",
+            "a = yes",
+        ] {
+            assert!(parse(text).is_err(), "{}", text);
+        }
+    }
+}
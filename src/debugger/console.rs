@@ -0,0 +1,310 @@
+//! `Debugger` is a command-line debugger. It reads commands from the standard input whenever the machine is paused.
+//! Every executed step is recorded in an `UndoLog`, so execution can also run backwards.
+
+use crate::debugger::undo_log::UndoLog;
+use crate::debugger::Tracer;
+use crate::vm::{Machine, OutputState};
+use log::debug;
+use std::collections::BTreeSet;
+use std::io::prelude::*;
+
+/// Number of steps which can be undone.
+const UNDO_LOG_CAPACITY: usize = 100_000;
+
+const HELP: &str = "Commands :
+    s,  step [count]          execute the next step(s)
+    sb, step-back [count]     undo the last step(s)
+    c,  continue              run until a breakpoint
+    rc, reverse-continue      run backwards until a breakpoint
+    b,  break <address>       set a breakpoint, e.g. `break 0x2A0`
+    d,  delete <address>      delete a breakpoint
+    l,  list                  list the breakpoints
+    r,  regs                  show the registers
+    cpu                       show the registers of the CDP1802, which runs the machine code called by 0NNN
+    smc                       list the self-modifying code found so far
+    m,  mem <address> [len]   show memory
+    q,  quit                  quit the machine";
+
+/// `Mode` : how the debugger lets the machine run.
+#[derive(Debug, PartialEq)]
+enum Mode {
+    Paused,
+    /// Execute the given number of steps, then pause.
+    Stepping(usize),
+    /// Run until a breakpoint is reached.
+    Running,
+}
+
+/// `Debugger` : breakpoints, the undo log and the current mode.
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    undo_log: UndoLog,
+    mode: Mode,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    /// `Debugger::new()` will return a debugger without breakpoints, paused before the first instruction.
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            undo_log: UndoLog::new(UNDO_LOG_CAPACITY),
+            mode: Mode::Paused,
+        }
+    }
+
+    /// `Debugger.process_keys()` replaces `Machine.process_keys()` while debugging.
+    /// It asks for commands when the machine is paused, then executes one recorded step (unless execution ran backwards).
+    /// It returns `None` when the user wants to quit.
+    pub fn process_keys<'a>(
+        &mut self,
+        machine: &'a mut Machine,
+        keys: Vec<bool>,
+    ) -> Option<OutputState<'a>> {
+        machine.vram.state_changed = false;
+        if self.mode == Mode::Running && self.breakpoints.contains(&machine.pc) {
+            println!("Breakpoint at 0x{:03X}.", machine.pc);
+            self.mode = Mode::Paused;
+        }
+        if self.mode == Mode::Paused || self.mode == Mode::Stepping(0) {
+            self.mode = Mode::Paused;
+            println!("{}", Debugger::format_state(machine));
+            match self.prompt(machine) {
+                Some(true) => (),
+                //execution ran backwards, let the caller draw the display before the next command
+                Some(false) => return Some(machine.output_state()),
+                None => return None,
+            }
+        }
+        self.step(machine, keys);
+        Some(machine.output_state())
+    }
+
+    /// `Debugger.step()` executes one step of the machine and records its journal in the undo log.
+    fn step(&mut self, machine: &mut Machine, keys: Vec<bool>) {
+        machine.journal = Some(vec![]);
+        //the debugger may go on from a machine halted by the sanity checker or by 00FD, the step records it
+        if machine.halted {
+            machine.set_halted(false);
+        }
+        machine.process_keys(keys);
+        self.undo_log
+            .record(machine.journal.take().unwrap_or_default());
+        if let Some(smc_detector) = machine.smc_detector.as_ref() {
+            for event in &smc_detector.last {
+                println!("Self-modifying code: {}.", event);
+            }
+        }
+        if let Mode::Stepping(count) = self.mode {
+            self.mode = Mode::Stepping(count - 1);
+        }
+        if machine.halted {
+            println!("Halted by the sanity checker, or by the program (00FD).");
+            self.mode = Mode::Paused;
+        }
+    }
+
+    /// `Debugger.step_back()` undoes up to `count` steps. It returns the number of undone steps.
+    fn step_back(&mut self, machine: &mut Machine, count: usize) -> usize {
+        (0..count)
+            .take_while(|_| self.undo_log.undo(machine))
+            .count()
+    }
+
+    /// `Debugger.reverse_continue()` undoes steps until the program counter is at a breakpoint.
+    fn reverse_continue(&mut self, machine: &mut Machine) -> usize {
+        let mut undone = 0;
+        while self.undo_log.undo(machine) {
+            undone += 1;
+            if self.breakpoints.contains(&machine.pc) {
+                println!("Breakpoint at 0x{:03X}.", machine.pc);
+                break;
+            }
+        }
+        undone
+    }
+
+    /// `Debugger.prompt()` reads and executes commands until the machine should go on.
+    /// It returns `Some(true)` to execute the next step, `Some(false)` after running backwards and `None` to quit.
+    fn prompt(&mut self, machine: &mut Machine) -> Option<bool> {
+        let stdin = std::io::stdin();
+        loop {
+            print!("(chip8) ");
+            std::io::stdout()
+                .flush()
+                .expect("Error: Could not write prompt.");
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+                return None;
+            }
+            debug!("[Debugger.prompt()] Command {}.", line.trim());
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let argument = words.get(1).copied();
+            match words.first().copied() {
+                None => continue,
+                Some("s") | Some("step") => {
+                    self.mode = Mode::Stepping(parse_count(argument));
+                    return Some(true);
+                }
+                Some("c") | Some("continue") => {
+                    self.mode = Mode::Running;
+                    return Some(true);
+                }
+                Some("sb") | Some("step-back") => {
+                    let undone = self.step_back(machine, parse_count(argument));
+                    return self.report_backwards(machine, undone);
+                }
+                Some("rc") | Some("reverse-continue") => {
+                    let undone = self.reverse_continue(machine);
+                    return self.report_backwards(machine, undone);
+                }
+                Some("b") | Some("break") => match argument.and_then(parse_address) {
+                    Some(address) => {
+                        self.breakpoints.insert(address);
+                    }
+                    None => println!("Usage: break <address>"),
+                },
+                Some("d") | Some("delete") => match argument.and_then(parse_address) {
+                    Some(address) => {
+                        if !self.breakpoints.remove(&address) {
+                            println!("No breakpoint at 0x{:03X}.", address);
+                        }
+                    }
+                    None => println!("Usage: delete <address>"),
+                },
+                Some("l") | Some("list") => {
+                    for address in &self.breakpoints {
+                        println!("0x{:03X}", address);
+                    }
+                }
+                Some("r") | Some("regs") => println!("{}", Debugger::format_state(machine)),
+                Some("cpu") => println!("{}", Debugger::format_cpu(machine)),
+                Some("smc") => match machine.smc_detector.as_ref() {
+                    Some(smc_detector) => print!("{}", smc_detector.report()),
+                    None => println!("Self-modifying code detection is off."),
+                },
+                Some("m") | Some("mem") => match argument.and_then(parse_address) {
+                    Some(address) => {
+                        let length = words.get(2).map_or(16, |length| parse_count(Some(length)));
+                        println!("{}", Debugger::format_memory(machine, address, length));
+                    }
+                    None => println!("Usage: mem <address> [len]"),
+                },
+                Some("q") | Some("quit") => return None,
+                Some(_) => println!("{}", HELP),
+            }
+        }
+    }
+
+    /// `Debugger.report_backwards()` tells how far execution ran backwards and pauses the machine.
+    fn report_backwards(&mut self, machine: &Machine, undone: usize) -> Option<bool> {
+        if self.undo_log.is_empty() {
+            println!(
+                "Undid {} step(s), reached the start of the undo log.",
+                undone
+            );
+        } else {
+            println!("Undid {} step(s).", undone);
+        }
+        self.mode = Mode::Paused;
+        debug!(
+            "[Debugger.report_backwards()] Now at 0x{:03X}, {} step(s) left to undo.",
+            machine.pc,
+            self.undo_log.len()
+        );
+        Some(false)
+    }
+
+    /// `Debugger::format_state()` shows the registers and the next instruction.
+    fn format_state(machine: &Machine) -> String {
+        Tracer::format_line(machine, &machine.get_operators())
+    }
+
+    /// `Debugger::format_cpu()` shows the registers of the CDP1802, as the last routine of machine code left them.
+    fn format_cpu(machine: &Machine) -> String {
+        machine.cdp1802.to_string()
+    }
+
+    /// `Debugger::format_memory()` shows `length` memory cells starting at `address`, 16 cells per line.
+    fn format_memory(machine: &Machine, address: u16, length: usize) -> String {
+        let start = (address as usize).min(machine.memory.cells.len());
+        let end = (start + length).min(machine.memory.cells.len());
+        machine.memory.cells[start..end]
+            .chunks(16)
+            .enumerate()
+            .map(|(line, cells)| {
+                let cells: Vec<String> = cells.iter().map(|cell| format!("{:02X}", cell)).collect();
+                format!("{:03X}: {}", start + line * 16, cells.join(" "))
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+/// `parse_count()` reads an optional positive count, 1 by default.
+fn parse_count(argument: Option<&str>) -> usize {
+    argument
+        .and_then(|count| count.parse().ok())
+        .filter(|&count| count > 0)
+        .unwrap_or(1)
+}
+
+/// `parse_address()` reads an hexadecimal address, with or without a `0x` prefix.
+fn parse_address(argument: &str) -> Option<u16> {
+    let digits = argument.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{Platform, PROGRAM_START};
+
+    #[test]
+    fn stepping_back_over_an_exit_lets_the_machine_run_again() {
+        //V0 := 1, exit
+        let mut machine = Machine::new();
+        machine.set_platform(Platform::SuperChip);
+        machine.load_program(&[0x60, 0x01, 0x00, 0xFD], PROGRAM_START, PROGRAM_START);
+        let mut debugger = Debugger::new();
+        debugger.mode = Mode::Stepping(3);
+        for _ in 0..2 {
+            debugger.step(&mut machine, vec![false; 16]);
+        }
+        assert!(machine.halted);
+        assert_eq!(debugger.mode, Mode::Paused);
+
+        //going on from the exit runs it again
+        debugger.step(&mut machine, vec![false; 16]);
+        assert!(machine.halted);
+        assert_eq!(debugger.step_back(&mut machine, 1), 1);
+        assert!(machine.halted);
+        assert_eq!(debugger.step_back(&mut machine, 1), 1);
+        assert!(!machine.halted);
+        assert_eq!(machine.pc, 0x202);
+        debugger.step(&mut machine, vec![false; 16]);
+        assert!(machine.halted);
+    }
+
+    #[test]
+    fn the_cpu_shows_the_registers_of_the_cdp1802_after_a_call() {
+        //call the routine at 0x204 : D := 0x2A, M(R6) := D (V2), D := 0x08, R5.0 := D, return to 0x208
+        let program = [
+            0x02, 0x04, 0x00, 0x00, 0xF8, 0x2A, 0x56, 0xF8, 0x08, 0xA5, 0xD4,
+        ];
+        let mut machine = Machine::new();
+        machine.load_program(&program, PROGRAM_START, PROGRAM_START);
+        machine.process_keys(vec![false; 16]);
+        assert_eq!((machine.pc, machine.registers.v[2]), (0x208, 0x2A));
+        assert_eq!(
+            Debugger::format_cpu(&machine),
+            "R0:0000 R1:0000 R2:0ECF R3:020B R4:0000 R5:0208 R6:0EF2 R7:0EF0 R8:0000 R9:0000 \
+             RA:0000 RB:0F00 RC:0000 RD:0000 RE:0000 RF:0000 P:4 X:2 D:08 DF:0 T:00 IE:1 Q:0"
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! Coverage counts, for every memory address, how many times it was executed as code, read as data through I
+//! and written. The counts can be written as a text report and as a heatmap image of the 4 KB address space.
+
+use crate::vm::{Machine, Operator};
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+/// The heatmap shows the address space as a square of `HEATMAP_COLUMNS` x `HEATMAP_COLUMNS` cells.
+const HEATMAP_COLUMNS: usize = 64;
+/// Each memory cell is drawn as a square of `HEATMAP_SCALE` x `HEATMAP_SCALE` pixels.
+const HEATMAP_SCALE: usize = 8;
+/// Number of most executed addresses listed in the report.
+const HOT_SPOTS: usize = 10;
+
+/// `Usage` : how a memory cell was used.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Usage {
+    Unused,
+    Code,
+    Data,
+    Written,
+    /// Used in more than one way, e.g. code which was also written (self-modifying code).
+    Mixed,
+}
+
+/// `Coverage` : execution, read and write counts of every memory cell.
+pub struct Coverage {
+    /// `executions` counts how often an opcode starting at the address was executed.
+    pub executions: Vec<u64>,
+    /// `code` marks both bytes of every executed opcode.
+    pub code: Vec<bool>,
+    pub reads: Vec<u64>,
+    pub writes: Vec<u64>,
+}
+
+impl Coverage {
+    /// `Coverage::new()` will return coverage of the first `size` cells of the memory, all counts set to zero.
+    pub fn new(size: usize) -> Coverage {
+        Coverage {
+            executions: vec![0; size],
+            code: vec![false; size],
+            reads: vec![0; size],
+            writes: vec![0; size],
+        }
+    }
+
+    /// `Coverage.record()` counts the opcode at the program counter and the memory it is going to read and write.
+    pub fn record(&mut self, machine: &Machine, operator: &Operator) {
+        let pc = machine.pc as usize;
+        if pc + 1 < self.code.len() {
+            self.executions[pc] += 1;
+            self.code[pc] = true;
+            self.code[pc + 1] = true;
+        }
+        for address in operator.memory_reads(machine) {
+            if let Some(count) = self.reads.get_mut(address) {
+                *count += 1;
+            }
+        }
+        for (address, _) in operator.memory_writes(machine) {
+            if let Some(count) = self.writes.get_mut(address) {
+                *count += 1;
+            }
+        }
+    }
+
+    /// `Coverage.usage()` tells how the memory cell at `address` was used.
+    pub fn usage(&self, address: usize) -> Usage {
+        let uses = [
+            (self.code[address], Usage::Code),
+            (self.reads[address] > 0, Usage::Data),
+            (self.writes[address] > 0, Usage::Written),
+        ];
+        let mut used = uses.iter().filter(|(used, _)| *used);
+        match (used.next(), used.next()) {
+            (None, _) => Usage::Unused,
+            (Some((_, usage)), None) => *usage,
+            (Some(_), Some(_)) => Usage::Mixed,
+        }
+    }
+
+    /// `Coverage.report()` lists the address ranges of each kind of usage, with their counts,
+    /// followed by the most executed addresses.
+    pub fn report(&self) -> String {
+        let count = |usage: Usage| {
+            (0..self.code.len())
+                .filter(|&address| self.usage(address) == usage)
+                .count()
+        };
+        let mut text = format!(
+            "Coverage of {} bytes : {} code, {} data, {} written, {} mixed, {} unused\n\n",
+            self.code.len(),
+            count(Usage::Code),
+            count(Usage::Data),
+            count(Usage::Written),
+            count(Usage::Mixed),
+            count(Usage::Unused)
+        );
+        text.push_str("Range          Usage    Executions      Reads     Writes\n");
+        let mut start = 0;
+        for address in 1..=self.code.len() {
+            if address < self.code.len() && self.usage(address) == self.usage(start) {
+                continue;
+            }
+            let sum = |counts: &[u64]| counts[start..address].iter().sum::<u64>();
+            text.push_str(&format!(
+                "0x{:03X}-0x{:03X}  {:<8} {:>10} {:>10} {:>10}\n",
+                start,
+                address - 1,
+                format!("{:?}", self.usage(start)),
+                sum(&self.executions),
+                sum(&self.reads),
+                sum(&self.writes)
+            ));
+            start = address;
+        }
+
+        let mut hot_spots: Vec<usize> = (0..self.executions.len())
+            .filter(|&address| self.executions[address] > 0)
+            .collect();
+        hot_spots.sort_by(|a, b| self.executions[*b].cmp(&self.executions[*a]));
+        text.push_str("\nMost executed addresses :\n");
+        for address in hot_spots.iter().take(HOT_SPOTS) {
+            text.push_str(&format!(
+                "0x{:03X} {:>10}\n",
+                address, self.executions[*address]
+            ));
+        }
+        text
+    }
+
+    /// `Coverage.write_report()` writes the text report to a file.
+    pub fn write_report(&self, filename: &str) -> std::io::Result<()> {
+        File::create(filename)?.write_all(self.report().as_bytes())
+    }
+
+    /// `Coverage.write_heatmap()` writes the heatmap as a binary PPM image, one square per memory cell,
+    /// starting with address 0x000 at the top left corner, 64 cells per row.
+    /// Code is green, data read through I is blue and written cells are red (mixed usage mixes the colors).
+    /// The brighter the color, the more often the cell was used.
+    pub fn write_heatmap(&self, filename: &str) -> std::io::Result<()> {
+        //only the 4 KB address space is drawn, whatever the size of the memory
+        let size = self.code.len().min(HEATMAP_COLUMNS * HEATMAP_COLUMNS);
+        let rows = size.div_ceil(HEATMAP_COLUMNS);
+        let (width, height) = (HEATMAP_COLUMNS * HEATMAP_SCALE, rows * HEATMAP_SCALE);
+        let mut writer = BufWriter::new(File::create(filename)?);
+        write!(writer, "P6\n{} {}\n255\n", width, height)?;
+
+        let executions = heat_scale(&self.executions);
+        let reads = heat_scale(&self.reads);
+        let writes = heat_scale(&self.writes);
+        for y in 0..height {
+            for x in 0..width {
+                let address = (y / HEATMAP_SCALE) * HEATMAP_COLUMNS + x / HEATMAP_SCALE;
+                let pixel = if address >= size {
+                    [0, 0, 0]
+                } else if self.usage(address) == Usage::Unused {
+                    [24, 24, 24]
+                } else {
+                    //the second byte of an opcode is code as well, it takes the heat of the first byte
+                    let code = if self.code[address] {
+                        executions(
+                            self.executions[address].max(
+                                address
+                                    .checked_sub(1)
+                                    .map_or(0, |previous| self.executions[previous]),
+                            ),
+                        )
+                    } else {
+                        0
+                    };
+                    [
+                        writes(self.writes[address]),
+                        code,
+                        reads(self.reads[address]),
+                    ]
+                };
+                writer.write_all(&pixel)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `heat_scale()` returns a function mapping a count to a brightness, on a logarithmic scale up to the highest count.
+/// Any non-zero count is at least dimly visible.
+fn heat_scale(counts: &[u64]) -> impl Fn(u64) -> u8 {
+    let max = counts.iter().copied().max().unwrap_or(0);
+    let max = ((max + 1) as f64).ln();
+    move |count| {
+        if count == 0 {
+            0
+        } else {
+            (64.0 + 191.0 * ((count + 1) as f64).ln() / max) as u8
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::PROGRAM_START;
+
+    /// `run()` executes `steps` instructions of a loop storing and loading V0 at 0x300, counting the coverage
+    /// of `size` cells.
+    fn run(steps: usize, size: usize) -> Coverage {
+        //V0 := 0, I := 0x300, then loop : V0 += 1, store V0, load V0
+        let program = [
+            0x60, 0x00, 0xA3, 0x00, 0x70, 0x01, 0xF0, 0x55, 0xF0, 0x65, 0x12, 0x04,
+        ];
+        let mut machine = Machine::new();
+        machine.load_program(&program, PROGRAM_START, PROGRAM_START);
+        machine.coverage = Some(Coverage::new(size));
+        for _ in 0..steps {
+            machine.process_keys(vec![false; 16]);
+        }
+        machine.coverage.unwrap()
+    }
+
+    #[test]
+    fn executions_reads_and_writes_are_counted() {
+        let coverage = run(2 + 4 * 3, 0x1000);
+        let executions: Vec<u64> = (0x200..0x20C)
+            .step_by(2)
+            .map(|address| coverage.executions[address])
+            .collect();
+        assert_eq!(executions, [1, 1, 3, 3, 3, 3]);
+        assert!(coverage.code[0x200..0x20C].iter().all(|&code| code));
+        assert_eq!((coverage.reads[0x300], coverage.writes[0x300]), (3, 3));
+        assert_eq!(coverage.usage(0x201), Usage::Code);
+        assert_eq!(coverage.usage(0x300), Usage::Mixed);
+        assert_eq!(coverage.usage(0x20C), Usage::Unused);
+
+        let report = coverage.report();
+        assert!(report.contains("0x200-0x20B  Code             14          0          0\n"));
+        assert!(report.contains("0x300-0x300  Mixed             0          3          3\n"));
+        assert!(report.ends_with(
+            "Most executed addresses :\n0x204          3\n0x206          3\n0x208          3\n\
+             0x20A          3\n0x200          1\n0x202          1\n"
+        ));
+    }
+
+    #[test]
+    fn counts_get_brighter_on_a_logarithmic_scale() {
+        let scale = heat_scale(&[0, 1, 3, 1000]);
+        assert_eq!(scale(0), 0);
+        assert_eq!(scale(1000), 255);
+        let brightness: Vec<u8> = [1, 3, 10, 100, 999]
+            .iter()
+            .map(|&count| scale(count))
+            .collect();
+        assert!(brightness[0] >= 64);
+        assert!(brightness.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn heatmap_draws_the_4_kb_address_space() {
+        let coverage = run(2 + 4 * 3, 0x2000);
+        let path = std::env::temp_dir().join(format!("chip8-heatmap-{}.ppm", std::process::id()));
+        coverage.write_heatmap(path.to_str().unwrap()).unwrap();
+        let image = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let header = b"P6\n512 512\n255\n";
+        assert!(image.starts_with(header));
+        assert_eq!(image.len(), header.len() + 512 * 512 * 3);
+        //the pixel at the top left corner of the square of a cell
+        let pixel = |address: usize| {
+            let (x, y) = (address % 64 * 8, address / 64 * 8);
+            let offset = header.len() + (y * 512 + x) * 3;
+            [image[offset], image[offset + 1], image[offset + 2]]
+        };
+        assert_eq!(pixel(0x000), [24, 24, 24]);
+        assert_eq!(pixel(0x204), [0, 255, 0]);
+        assert_eq!(pixel(0x205), [0, 255, 0]);
+        assert_eq!(pixel(0x200), [0, heat_scale(&coverage.executions)(1), 0]);
+        assert_eq!(pixel(0x300), [255, 0, 255]);
+    }
+}
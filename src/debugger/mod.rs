@@ -0,0 +1,24 @@
+//! Debugger - tools to look inside a running CHIP-8 machine.
+//! `Tracer` writes the state of the machine for every executed instruction to a file.
+//! `trace_diff` finds the first instruction where two traces (e.g. of this VM and of a reference emulator) diverge.
+//! `Coverage` counts how often every memory cell was executed, read and written.
+//! `Profiler` counts the cycles spent inside every subroutine.
+//! `SanityChecker` warns about (or halts on) suspicious behaviour of a ROM, in strict mode.
+//! `SmcDetector` finds self-modifying code.
+//! `Debugger` pauses the machine at breakpoints and steps forwards and backwards through the program.
+
+mod console;
+mod coverage;
+mod profiler;
+mod sanity;
+mod smc;
+pub mod trace_diff;
+mod tracer;
+pub mod undo_log;
+
+pub use console::Debugger;
+pub use coverage::{Coverage, Usage};
+pub use profiler::{Profiler, RoutineProfile};
+pub use sanity::{SanityChecker, Strictness, Violation};
+pub use smc::{SelfModification, SmcDetector};
+pub use tracer::Tracer;
@@ -0,0 +1,247 @@
+//! The profiler counts the cycles spent inside each subroutine.
+//! It follows the calls (2NNN) and returns (00EE) of the program with a call stack of its own.
+//! Every cycle is counted for the call path it was executed in, e.g. `main;sub_300;sub_340`.
+//! A call instruction is counted for the caller and a return instruction for the subroutine it returns from.
+//!
+//! The profile can be written as a flat profile and a call tree (text),
+//! or as folded stacks, the input format of flame graph tools (e.g. `flamegraph.pl`).
+
+use crate::vm::Operator;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::prelude::*;
+
+/// `Profiler` : cycles per call path and calls per subroutine.
+pub struct Profiler {
+    /// `call_stack` holds the addresses of the subroutines which were called and did not return yet.
+    call_stack: Vec<u16>,
+    /// `cycles` counts the cycles spent in each call path (not including the subroutines it called).
+    pub cycles: BTreeMap<Vec<u16>, u64>,
+    /// `calls` counts the calls of each subroutine.
+    pub calls: BTreeMap<u16, u64>,
+}
+
+/// `RoutineProfile` : the flat profile of a subroutine.
+#[derive(Debug, Default)]
+pub struct RoutineProfile {
+    /// Cycles spent in the subroutine itself.
+    pub self_cycles: u64,
+    /// Cycles spent in the subroutine and in the subroutines it called.
+    pub total_cycles: u64,
+    pub calls: u64,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler::new()
+    }
+}
+
+impl Profiler {
+    /// `Profiler::new()` will return a profiler without any counted cycle.
+    pub fn new() -> Profiler {
+        Profiler {
+            call_stack: vec![],
+            cycles: BTreeMap::new(),
+            calls: BTreeMap::new(),
+        }
+    }
+
+    /// `Profiler.record()` counts the cycle of the opcode at the program counter, then follows calls and returns.
+    pub fn record(&mut self, operator: &Operator) {
+        *self.cycles.entry(self.call_stack.clone()).or_insert(0) += 1;
+        match (
+            operator.nibble_1,
+            operator.nibble_2,
+            operator.nibble_3,
+            operator.nibble_4,
+        ) {
+            (0x2, _, _, _) => {
+                *self.calls.entry(operator.nnn_address).or_insert(0) += 1;
+                self.call_stack.push(operator.nnn_address);
+            }
+            (0, 0, 0xE, 0xE) => {
+                self.call_stack.pop();
+            }
+            _ => (),
+        }
+    }
+
+    /// `Profiler.flat_profile()` returns the profile of every subroutine, `None` being the main program.
+    pub fn flat_profile(&self) -> BTreeMap<Option<u16>, RoutineProfile> {
+        let mut profile: BTreeMap<Option<u16>, RoutineProfile> = BTreeMap::new();
+        for (path, &cycles) in &self.cycles {
+            profile.entry(path.last().copied()).or_default().self_cycles += cycles;
+            //a recursive subroutine is counted once per path
+            let mut routines: Vec<Option<u16>> =
+                path.iter().map(|&address| Some(address)).collect();
+            routines.push(None);
+            routines.sort();
+            routines.dedup();
+            for routine in routines {
+                profile.entry(routine).or_default().total_cycles += cycles;
+            }
+        }
+        for (&address, &calls) in &self.calls {
+            profile.entry(Some(address)).or_default().calls = calls;
+        }
+        profile
+    }
+
+    /// `Profiler.report()` formats the flat profile, sorted by self cycles, and the call tree.
+    pub fn report(&self) -> String {
+        let total: u64 = self.cycles.values().sum();
+        let percent = |cycles: u64| 100.0 * cycles as f64 / total.max(1) as f64;
+
+        let mut text = format!("Flat profile ({} cycles) :\n", total);
+        text.push_str("   Self   Self %    Total  Total %    Calls  Cycles/call  Routine\n");
+        let mut flat: Vec<(Option<u16>, RoutineProfile)> =
+            self.flat_profile().into_iter().collect();
+        flat.sort_by_key(|(_, profile)| Reverse(profile.self_cycles));
+        for (routine, profile) in &flat {
+            let per_call = match profile.calls {
+                0 => String::from("-"),
+                calls => format!("{:.1}", profile.total_cycles as f64 / calls as f64),
+            };
+            text.push_str(&format!(
+                "{:>7} {:>7.2}% {:>8} {:>7.2}% {:>8} {:>12}  {}\n",
+                profile.self_cycles,
+                percent(profile.self_cycles),
+                profile.total_cycles,
+                percent(profile.total_cycles),
+                profile.calls,
+                per_call,
+                routine_name(*routine)
+            ));
+        }
+
+        text.push_str("\nCall tree (total cycles, self cycles) :\n");
+        let mut totals: BTreeMap<&[u16], u64> = BTreeMap::new();
+        for (path, &cycles) in &self.cycles {
+            for depth in 0..=path.len() {
+                *totals.entry(&path[..depth]).or_insert(0) += cycles;
+            }
+        }
+        //the paths are sorted, so every path comes right after its caller
+        for (path, &cycles) in &totals {
+            let self_cycles = self.cycles.get(*path).copied().unwrap_or(0);
+            text.push_str(&format!(
+                "{}{} {} ({:.2}%), {}\n",
+                "  ".repeat(path.len()),
+                routine_name(path.last().copied()),
+                cycles,
+                percent(cycles),
+                self_cycles
+            ));
+        }
+        text
+    }
+
+    /// `Profiler.folded_stacks()` formats one line per call path : the routines separated by `;` and the cycles.
+    pub fn folded_stacks(&self) -> String {
+        self.cycles
+            .iter()
+            .map(|(path, cycles)| {
+                let mut routines = vec![routine_name(None)];
+                routines.extend(path.iter().map(|&address| routine_name(Some(address))));
+                format!("{} {}\n", routines.join(";"), cycles)
+            })
+            .collect()
+    }
+
+    /// `Profiler.write_report()` writes the flat profile and the call tree to a file.
+    pub fn write_report(&self, filename: &str) -> std::io::Result<()> {
+        File::create(filename)?.write_all(self.report().as_bytes())
+    }
+
+    /// `Profiler.write_folded_stacks()` writes the folded stacks to a file.
+    pub fn write_folded_stacks(&self, filename: &str) -> std::io::Result<()> {
+        File::create(filename)?.write_all(self.folded_stacks().as_bytes())
+    }
+}
+
+/// `routine_name()` names a subroutine after its address; `None` is the main program.
+fn routine_name(routine: Option<u16>) -> String {
+    match routine {
+        Some(address) => format!("sub_{:03X}", address),
+        None => String::from("main"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{Machine, PROGRAM_START};
+
+    /// `profile()` profiles two rounds of a program calling sub_210, which calls sub_220, then calling sub_220.
+    fn profile() -> Profiler {
+        let mut program = vec![0x22, 0x10, 0x22, 0x20, 0x12, 0x00];
+        program.resize(0x10, 0);
+        program.extend(&[0x22, 0x20, 0x00, 0xEE]);
+        program.resize(0x20, 0);
+        program.extend(&[0x70, 0x01, 0x00, 0xEE]);
+        let mut machine = Machine::new();
+        machine.load_program(&program, PROGRAM_START, PROGRAM_START);
+        machine.profiler = Some(Profiler::new());
+        for _ in 0..2 * 9 {
+            machine.process_keys(vec![false; 16]);
+        }
+        machine.profiler.unwrap()
+    }
+
+    #[test]
+    fn cycles_are_counted_per_call_path() {
+        let profiler = profile();
+        let cycles: Vec<(Vec<u16>, u64)> = profiler
+            .cycles
+            .iter()
+            .map(|(path, &cycles)| (path.clone(), cycles))
+            .collect();
+        assert_eq!(
+            cycles,
+            vec![
+                (vec![], 6),
+                (vec![0x210], 4),
+                (vec![0x210, 0x220], 4),
+                (vec![0x220], 4)
+            ]
+        );
+        assert_eq!(
+            profiler.calls.iter().collect::<Vec<_>>(),
+            vec![(&0x210, &2), (&0x220, &4)]
+        );
+        assert_eq!(
+            profiler.folded_stacks(),
+            "main 6\nmain;sub_210 4\nmain;sub_210;sub_220 4\nmain;sub_220 4\n"
+        );
+    }
+
+    #[test]
+    fn flat_profile_counts_self_and_total_cycles() {
+        let flat = profile().flat_profile();
+        let counts = |routine| {
+            let profile: &RoutineProfile = &flat[&routine];
+            (profile.self_cycles, profile.total_cycles, profile.calls)
+        };
+        assert_eq!(counts(None), (6, 18, 0));
+        assert_eq!(counts(Some(0x210)), (4, 8, 2));
+        assert_eq!(counts(Some(0x220)), (8, 8, 4));
+    }
+
+    #[test]
+    fn report_lists_the_hottest_routines_first() {
+        let report = profile().report();
+        assert!(report.starts_with("Flat profile (18 cycles) :\n"));
+        let position = |line: &str| report.find(line).unwrap();
+        let sub_220 =
+            position("      8   44.44%        8   44.44%        4          2.0  sub_220\n");
+        let main = position("      6   33.33%       18  100.00%        0            -  main\n");
+        let sub_210 =
+            position("      4   22.22%        8   44.44%        2          4.0  sub_210\n");
+        assert!(sub_220 < main && main < sub_210);
+        assert!(report.ends_with(
+            "main 18 (100.00%), 6\n  sub_210 8 (44.44%), 4\n    sub_220 4 (22.22%), 4\n  sub_220 4 (22.22%), 4\n"
+        ));
+    }
+}
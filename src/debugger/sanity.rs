@@ -0,0 +1,377 @@
+//! The sanity checker ("strict" mode) looks for suspicious behaviour of a ROM, before each instruction is executed :
+//! executing data, the fonts or uninitialized memory, writing into the interpreter memory (0x000-0x1FF, which holds
+//! the fonts), jumping to odd addresses, returning with an empty stack (or calling with a full one), reading memory
+//! which was never loaded or written, accessing memory past its end through I and running opcodes which do not exist.
+//! Each kind of violation is reported once per address.
+//! Depending on the `Strictness`, the checker only warns or also halts the machine.
+
+use crate::vm::{Instruction, Machine, Operator};
+use log::warn;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Addresses below `INTERPRETER_END` belong to the interpreter and hold the fonts.
+const INTERPRETER_END: usize = 0x200;
+/// The stack has 16 levels.
+const STACK_LEVELS: usize = 16;
+
+/// `Strictness` : what the checker does when it finds a violation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strictness {
+    Warn,
+    Halt,
+}
+
+/// `Violation` : suspicious behaviour of the instruction at `pc`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Violation {
+    /// The opcode at `pc` was read as data (a sprite or registers) before.
+    ExecutesData {
+        pc: u16,
+    },
+    /// The opcode at `pc` was never loaded or written.
+    ExecutesUninitialized {
+        pc: u16,
+    },
+    /// The opcode at `pc` is part of the fonts.
+    ExecutesFont {
+        pc: u16,
+    },
+    WritesInterpreterMemory {
+        pc: u16,
+        address: usize,
+    },
+    OddJump {
+        pc: u16,
+        target: u16,
+    },
+    /// Return (00EE) with an empty stack.
+    StackUnderflow {
+        pc: u16,
+    },
+    /// Call (2NNN) with all 16 stack levels in use.
+    StackOverflow {
+        pc: u16,
+    },
+    ReadsUninitialized {
+        pc: u16,
+        address: usize,
+    },
+    /// Reads or writes through I go past the end of the memory.
+    IndexOutOfBounds {
+        pc: u16,
+        index: u32,
+    },
+    /// The opcode at `pc` is not an instruction.
+    UnknownOpcode {
+        pc: u16,
+        opcode: u16,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::ExecutesData { pc } => {
+                write!(f, "0x{:03X}: executes memory which was read as data", pc)
+            }
+            Violation::ExecutesUninitialized { pc } => write!(
+                f,
+                "0x{:03X}: executes memory which was never loaded or written",
+                pc
+            ),
+            Violation::ExecutesFont { pc } => write!(f, "0x{:03X}: executes the fonts", pc),
+            Violation::WritesInterpreterMemory { pc, address } => write!(
+                f,
+                "0x{:03X}: writes into interpreter memory at 0x{:03X}",
+                pc, address
+            ),
+            Violation::OddJump { pc, target } => {
+                write!(f, "0x{:03X}: jumps to odd address 0x{:03X}", pc, target)
+            }
+            Violation::StackUnderflow { pc } => {
+                write!(f, "0x{:03X}: returns with an empty stack", pc)
+            }
+            Violation::StackOverflow { pc } => {
+                write!(f, "0x{:03X}: calls a subroutine with a full stack", pc)
+            }
+            Violation::ReadsUninitialized { pc, address } => write!(
+                f,
+                "0x{:03X}: reads 0x{:03X}, which was never loaded or written",
+                pc, address
+            ),
+            Violation::IndexOutOfBounds { pc, index } => write!(
+                f,
+                "0x{:03X}: accesses memory past its end through I = 0x{:04X}",
+                pc, index
+            ),
+            Violation::UnknownOpcode { pc, opcode } => {
+                write!(f, "0x{:03X}: unknown opcode 0x{:04X}", pc, opcode)
+            }
+        }
+    }
+}
+
+/// `SanityChecker` : what the program did with memory so far and the violations found.
+pub struct SanityChecker {
+    pub strictness: Strictness,
+    /// `data` marks the cells read as data through I.
+    data: Vec<bool>,
+    /// `written` marks the cells written by the program.
+    written: Vec<bool>,
+    reported: HashSet<Violation>,
+    /// `violations` lists the violations in the order they were found.
+    pub violations: Vec<Violation>,
+}
+
+impl SanityChecker {
+    /// `SanityChecker::new()` will return a checker for the first `size` cells of the memory,
+    /// the cells beyond them are not tracked and never reported as uninitialized.
+    pub fn new(strictness: Strictness, size: usize) -> SanityChecker {
+        SanityChecker {
+            strictness,
+            data: vec![false; size],
+            written: vec![false; size],
+            reported: HashSet::new(),
+            violations: vec![],
+        }
+    }
+
+    /// `SanityChecker.check()` checks the opcode at the program counter before it is executed.
+    /// It returns true if the machine should halt.
+    pub fn check(&mut self, machine: &Machine, operator: &Operator) -> bool {
+        let pc = machine.pc;
+        let mut found = vec![];
+        let initialized = |address: usize| {
+            machine.memory.loaded.get(address) == Some(&true)
+                || self.written.get(address) != Some(&false)
+        };
+
+        let opcode_cells = [pc as usize, pc as usize + 1];
+        if opcode_cells
+            .iter()
+            .any(|&address| self.data.get(address) == Some(&true))
+        {
+            found.push(Violation::ExecutesData { pc });
+        }
+        if !opcode_cells.iter().all(|&address| initialized(address)) {
+            found.push(Violation::ExecutesUninitialized { pc });
+        }
+        if machine.memory.font_range().contains(&(pc as usize)) {
+            found.push(Violation::ExecutesFont { pc });
+        }
+        if let Instruction::Unknown(opcode) = machine.decode(operator.opcode) {
+            found.push(Violation::UnknownOpcode { pc, opcode });
+        }
+
+        let target = match (
+            operator.nibble_1,
+            operator.nibble_2,
+            operator.nibble_3,
+            operator.nibble_4,
+        ) {
+            (0x1, _, _, _) | (0x2, _, _, _) => Some(operator.nnn_address),
+            (0xB, _, _, _) => Some(operator.nnn_address + machine.registers.v[0] as u16),
+            _ => None,
+        };
+        if let Some(target) = target.filter(|target| target % 2 == 1) {
+            found.push(Violation::OddJump { pc, target });
+        }
+        match (
+            operator.nibble_1,
+            operator.nibble_2,
+            operator.nibble_3,
+            operator.nibble_4,
+        ) {
+            (0, 0, 0xE, 0xE) if machine.stack.cells.is_empty() => {
+                found.push(Violation::StackUnderflow { pc })
+            }
+            (0x2, _, _, _) if machine.stack.cells.len() >= STACK_LEVELS => {
+                found.push(Violation::StackOverflow { pc })
+            }
+            _ => (),
+        }
+
+        let reads = operator.memory_reads(machine);
+        for &address in &reads {
+            if !initialized(address) {
+                found.push(Violation::ReadsUninitialized { pc, address });
+            }
+        }
+        let writes = operator.memory_writes(machine);
+        //the routine of machine code of 0NNN writes anywhere, not through I
+        let accessed = match operator.nibble_1 {
+            0x0 => reads.len(),
+            _ => reads.len().max(writes.len()),
+        };
+        if accessed > 0 && machine.i as usize + accessed > machine.memory.cells.len() {
+            found.push(Violation::IndexOutOfBounds {
+                pc,
+                index: machine.i,
+            });
+        }
+        for &(address, _) in &writes {
+            if address < INTERPRETER_END {
+                found.push(Violation::WritesInterpreterMemory { pc, address });
+            }
+        }
+
+        for address in reads {
+            if let Some(data) = self.data.get_mut(address) {
+                *data = true;
+            }
+        }
+        for (address, _) in writes {
+            if let Some(written) = self.written.get_mut(address) {
+                *written = true;
+            }
+        }
+        self.report(found)
+    }
+
+    /// `SanityChecker.report()` warns about the violations which were not reported before.
+    /// It returns true if the machine should halt.
+    fn report(&mut self, found: Vec<Violation>) -> bool {
+        let mut halt = false;
+        for violation in found {
+            if self.reported.insert(violation.clone()) {
+                warn!("[SanityChecker.report()] {}", violation);
+                eprintln!("Strict: {}", violation);
+                self.violations.push(violation);
+                halt = self.strictness == Strictness::Halt;
+            }
+        }
+        halt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::PROGRAM_START;
+
+    /// `check()` runs the `steps` first instructions of `program` with a checker and returns the machine.
+    fn check(program: &[u8], steps: usize, strictness: Strictness) -> Machine {
+        let mut machine = Machine::new();
+        machine.load_program(program, PROGRAM_START, PROGRAM_START);
+        let size = machine.memory.cells.len();
+        machine.sanity_checker = Some(SanityChecker::new(strictness, size));
+        for _ in 0..steps {
+            machine.process_keys(vec![false; 16]);
+        }
+        machine
+    }
+
+    fn violations(machine: &Machine) -> &[Violation] {
+        &machine.sanity_checker.as_ref().unwrap().violations
+    }
+
+    #[test]
+    fn returning_with_an_empty_stack_is_an_underflow() {
+        let machine = check(&[0x00, 0xEE], 1, Strictness::Warn);
+        assert_eq!(
+            violations(&machine),
+            [Violation::StackUnderflow { pc: 0x200 }]
+        );
+    }
+
+    #[test]
+    fn calling_with_a_full_stack_is_an_overflow() {
+        //the subroutine calls itself, the 17th call has no stack level left
+        let machine = check(&[0x22, 0x00], 17, Strictness::Warn);
+        assert_eq!(
+            violations(&machine),
+            [Violation::StackOverflow { pc: 0x200 }]
+        );
+    }
+
+    #[test]
+    fn storing_registers_past_the_end_of_memory_is_out_of_bounds() {
+        //I := 0xFFE, store V0-V2 at 0xFFE-0x1000, the last one wrapping around to 0x000
+        let machine = check(&[0xAF, 0xFE, 0xF2, 0x55], 2, Strictness::Halt);
+        assert_eq!(
+            violations(&machine),
+            [
+                Violation::IndexOutOfBounds {
+                    pc: 0x202,
+                    index: 0xFFE
+                },
+                Violation::WritesInterpreterMemory {
+                    pc: 0x202,
+                    address: 0x000
+                }
+            ]
+        );
+        //the instruction is not executed
+        assert!(machine.halted);
+        assert_eq!(machine.pc, 0x202);
+    }
+
+    #[test]
+    fn writes_of_machine_code_are_checked() {
+        //I := 0x000, call the routine at 0x206 : D := 0, M(RA) := D, return
+        let program = [0xA0, 0x00, 0x02, 0x06, 0x12, 0x04, 0xF8, 0x00, 0x5A, 0xD4];
+        let machine = check(&program, 2, Strictness::Warn);
+        assert_eq!(
+            violations(&machine),
+            [Violation::WritesInterpreterMemory {
+                pc: 0x202,
+                address: 0x000
+            }]
+        );
+    }
+
+    #[test]
+    fn executing_the_fonts_or_uninitialized_memory_is_reported() {
+        //jump to 0x300, where nothing was loaded
+        let machine = check(&[0x13, 0x00], 2, Strictness::Halt);
+        assert_eq!(
+            violations(&machine),
+            [Violation::ExecutesUninitialized { pc: 0x300 }]
+        );
+
+        //jump to the font of "0" (F0 90), which is not an instruction either
+        let machine = check(&[0x10, 0x00], 2, Strictness::Halt);
+        assert_eq!(
+            violations(&machine),
+            [
+                Violation::ExecutesFont { pc: 0x000 },
+                Violation::UnknownOpcode {
+                    pc: 0x000,
+                    opcode: 0xF090
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_opcodes_are_reported() {
+        let machine = check(&[0x80, 0x08], 1, Strictness::Halt);
+        assert_eq!(
+            violations(&machine),
+            [Violation::UnknownOpcode {
+                pc: 0x200,
+                opcode: 0x8008
+            }]
+        );
+        assert_eq!(
+            violations(&machine)[0].to_string(),
+            "0x200: unknown opcode 0x8008"
+        );
+    }
+
+    #[test]
+    fn violations_only_halt_in_halt_mode() {
+        //an unknown opcode, then V0 := 1
+        let program = [0x80, 0x08, 0x60, 0x01];
+        let machine = check(&program, 2, Strictness::Warn);
+        assert!(!machine.halted);
+        assert_eq!(machine.registers.v[0], 1);
+        assert_eq!(violations(&machine).len(), 1);
+
+        let machine = check(&program, 2, Strictness::Halt);
+        assert!(machine.halted);
+        assert_eq!(machine.pc, 0x200);
+        assert_eq!(machine.registers.v[0], 0);
+    }
+}
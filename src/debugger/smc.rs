@@ -0,0 +1,197 @@
+//! Self-modifying code detection. The detector remembers which memory cells were executed as opcodes
+//! and which instruction last wrote each cell (FX33, 5XY2, FX55 and the machine code of 0NNN), to find :
+//! writes into cells which were executed before, and the execution of cells which were written before.
+//! The events of the last instruction are shown by the `Tracer` and the `Debugger`; all of them are summarized at the end.
+
+use crate::vm::{Machine, Operator};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::prelude::*;
+
+/// `SelfModification` : one event of self-modifying code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SelfModification {
+    /// The instruction at `pc` writes `address`, which was executed before.
+    WritesCode { pc: u16, address: usize },
+    /// The instruction at `pc` was written by the instruction at `writer`.
+    ExecutesWritten { pc: u16, writer: u16 },
+}
+
+impl fmt::Display for SelfModification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SelfModification::WritesCode { pc, address } => write!(
+                f,
+                "0x{:03X} writes 0x{:03X}, which was executed as code",
+                pc, address
+            ),
+            SelfModification::ExecutesWritten { pc, writer } => {
+                write!(f, "0x{:03X} executes code written by 0x{:03X}", pc, writer)
+            }
+        }
+    }
+}
+
+/// `SmcDetector` : executed cells, the writer of every written cell and the events found.
+pub struct SmcDetector {
+    executed: Vec<bool>,
+    writers: Vec<Option<u16>>,
+    /// `last` holds the events of the last recorded instruction.
+    pub last: Vec<SelfModification>,
+    /// `counts` counts every event found so far.
+    pub counts: BTreeMap<SelfModification, u64>,
+}
+
+impl SmcDetector {
+    /// `SmcDetector::new()` will return a detector for the first `size` cells of the memory.
+    pub fn new(size: usize) -> SmcDetector {
+        SmcDetector {
+            executed: vec![false; size],
+            writers: vec![None; size],
+            last: vec![],
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// `SmcDetector.record()` looks for self-modifying code in the opcode at the program counter,
+    /// before it is executed.
+    pub fn record(&mut self, machine: &Machine, operator: &Operator) {
+        let pc = machine.pc;
+        self.last.clear();
+        for address in [pc as usize, pc as usize + 1].iter() {
+            if let Some(executed) = self.executed.get_mut(*address) {
+                *executed = true;
+            }
+            if let Some(&Some(writer)) = self.writers.get(*address) {
+                let event = SelfModification::ExecutesWritten { pc, writer };
+                if !self.last.contains(&event) {
+                    self.last.push(event);
+                }
+            }
+        }
+        for (address, _) in operator.memory_writes(machine) {
+            if self.executed.get(address) == Some(&true) {
+                self.last.push(SelfModification::WritesCode { pc, address });
+            }
+            if let Some(writer) = self.writers.get_mut(address) {
+                *writer = Some(pc);
+            }
+        }
+        for event in &self.last {
+            *self.counts.entry(*event).or_insert(0) += 1;
+        }
+    }
+
+    /// `SmcDetector.report()` lists every event found, with the number of times it happened.
+    pub fn report(&self) -> String {
+        if self.counts.is_empty() {
+            return String::from("No self-modifying code found.\n");
+        }
+        self.counts
+            .iter()
+            .map(|(event, count)| format!("{} ({} times)\n", event, count))
+            .collect()
+    }
+
+    /// `SmcDetector.write_report()` writes the report to a file.
+    pub fn write_report(&self, filename: &str) -> std::io::Result<()> {
+        File::create(filename)?.write_all(self.report().as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{Platform, PROGRAM_START};
+
+    /// `detect()` runs the `steps` first instructions of `program` on `platform`, recording each with a detector,
+    /// and returns the machine and the events of every step.
+    fn detect(
+        platform: Platform,
+        program: &[u8],
+        steps: usize,
+    ) -> (Machine, Vec<Vec<SelfModification>>) {
+        let mut machine = Machine::new();
+        machine.set_platform(platform);
+        machine.load_program(program, PROGRAM_START, PROGRAM_START);
+        machine.smc_detector = Some(SmcDetector::new(machine.memory.cells.len()));
+        let mut events = vec![];
+        for _ in 0..steps {
+            machine.process_keys(vec![false; 16]);
+            events.push(machine.smc_detector.as_ref().unwrap().last.clone());
+        }
+        (machine, events)
+    }
+
+    #[test]
+    fn writes_into_executed_code_are_found() {
+        //I := 0x200, V0 := 0x12, store V0 over the first instruction
+        let (machine, events) = detect(Platform::Chip8, &[0xA2, 0x00, 0x60, 0x12, 0xF0, 0x55], 3);
+        assert_eq!(
+            events,
+            [
+                vec![],
+                vec![],
+                vec![SelfModification::WritesCode {
+                    pc: 0x204,
+                    address: 0x200
+                }]
+            ]
+        );
+        let report = machine.smc_detector.unwrap().report();
+        assert_eq!(
+            report,
+            "0x204 writes 0x200, which was executed as code (1 times)\n"
+        );
+    }
+
+    #[test]
+    fn executing_written_code_is_found() {
+        //I := 0x208, V0 := 0x60, V1 := 0x05, store V0-V1 at 0x208 : V0 := 0x05 is run there
+        let program = [0xA2, 0x08, 0x60, 0x60, 0x61, 0x05, 0xF1, 0x55];
+        let (machine, events) = detect(Platform::Chip8, &program, 5);
+        assert_eq!(
+            events[4],
+            [SelfModification::ExecutesWritten {
+                pc: 0x208,
+                writer: 0x206
+            }]
+        );
+        assert_eq!(machine.registers.v[0], 0x05);
+    }
+
+    #[test]
+    fn writes_of_5xy2_wrap_around_the_end_of_memory() {
+        //I := 0xFFFF, V1 := 0x12, V2 := 0x0E, store V0-V2 at 0xFFFF, 0x0000 and 0x0001, then jump to 0x000,
+        //where the stored 0x120E jumps to 0x20E
+        let program = [
+            0xF0, 0x00, 0xFF, 0xFF, 0x61, 0x12, 0x62, 0x0E, 0x50, 0x22, 0x10, 0x00,
+        ];
+        let (machine, events) = detect(Platform::XoChip, &program, 6);
+        assert_eq!(&machine.memory.cells[..2], &[0x12, 0x0E]);
+        assert_eq!(
+            events[5],
+            [SelfModification::ExecutesWritten {
+                pc: 0x000,
+                writer: 0x208
+            }]
+        );
+        assert_eq!(machine.pc, 0x20E);
+    }
+
+    #[test]
+    fn writes_of_machine_code_are_found() {
+        //I := 0x200, call the routine at 0x206 : D := 0, M(RA) := D, return
+        let program = [0xA2, 0x00, 0x02, 0x06, 0x12, 0x04, 0xF8, 0x00, 0x5A, 0xD4];
+        let (machine, events) = detect(Platform::Chip8, &program, 2);
+        assert_eq!(
+            events[1],
+            [SelfModification::WritesCode {
+                pc: 0x202,
+                address: 0x200
+            }]
+        );
+        assert_eq!(machine.memory.cells[0x200], 0x00);
+    }
+}
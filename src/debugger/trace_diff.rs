@@ -0,0 +1,286 @@
+//! Compares two execution traces and finds the first instruction where they diverge.
+//! A trace line is read as a list of `NAME:VALUE` (or `NAME=VALUE`) columns, as written by the `Tracer`.
+//! Lines without a `PC` column (headers, log messages) are skipped.
+//! Both traces are aligned on their first common program counter, so a trace may start with a few
+//! extra instructions (e.g. a boot routine of another emulator).
+//! Timers (DT and ST) are not compared, because emulators decrement them at different moments.
+
+use std::collections::BTreeMap;
+
+/// Columns compared between two traces, when both traces have them.
+const COMPARED_COLUMNS: [&str; 21] = [
+    "PC", "OP", "V0", "V1", "V2", "V3", "V4", "V5", "V6", "V7", "V8", "V9", "VA", "VB", "VC", "VD",
+    "VE", "VF", "I", "SP", "W",
+];
+
+/// `TraceLine` : one executed instruction of a trace.
+#[derive(Debug)]
+pub struct TraceLine {
+    /// `line_number` is the line of the instruction in the trace file, starting from 1.
+    pub line_number: usize,
+    pub text: String,
+    pub columns: BTreeMap<String, String>,
+}
+
+impl TraceLine {
+    /// `TraceLine::parse()` reads the columns of a trace line. It returns `None` if the line has no `PC` column.
+    pub fn parse(line_number: usize, text: &str) -> Option<TraceLine> {
+        let mut columns = BTreeMap::new();
+        for token in text.split_whitespace() {
+            if let Some(separator) = token.find(&[':', '='][..]) {
+                let name = token[..separator].to_uppercase();
+                let value = normalize_value(&token[separator + 1..]);
+                if !name.is_empty() && !value.is_empty() {
+                    columns.entry(name).or_insert(value);
+                }
+            }
+        }
+        if columns.contains_key("PC") {
+            Some(TraceLine {
+                line_number,
+                text: text.trim_end().to_string(),
+                columns,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// `Divergence` : the first instruction where two traces differ.
+#[derive(Debug)]
+pub struct Divergence {
+    /// `index` is the position of the divergent instruction, counted from the aligned start of both traces.
+    pub index: usize,
+    /// `columns` lists (column, value in the first trace, value in the second trace) for every difference.
+    /// A missing instruction (one of the traces ended) has an empty list.
+    pub columns: Vec<(String, String, String)>,
+}
+
+/// `parse_trace()` reads all the instructions of a trace.
+pub fn parse_trace(text: &str) -> Vec<TraceLine> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(index, line)| TraceLine::parse(index + 1, line))
+        .collect()
+}
+
+/// `align()` finds the instructions of each trace which were executed before the start of the other trace.
+/// It returns the number of instructions to skip in each trace.
+pub fn align(a: &[TraceLine], b: &[TraceLine]) -> (usize, usize) {
+    let (first_a, first_b) = match (a.first(), b.first()) {
+        (Some(first_a), Some(first_b)) => (first_a, first_b),
+        _ => return (0, 0),
+    };
+    if let Some(skip_b) = b
+        .iter()
+        .position(|line| line.columns["PC"] == first_a.columns["PC"])
+    {
+        return (0, skip_b);
+    }
+    match a
+        .iter()
+        .position(|line| line.columns["PC"] == first_b.columns["PC"])
+    {
+        Some(skip_a) => (skip_a, 0),
+        None => (0, 0),
+    }
+}
+
+/// `first_divergence()` compares the aligned traces instruction by instruction.
+/// It returns `None` when both traces execute the same instructions with the same results.
+pub fn first_divergence(a: &[TraceLine], b: &[TraceLine]) -> Option<Divergence> {
+    for index in 0..a.len().max(b.len()) {
+        let (line_a, line_b) = match (a.get(index), b.get(index)) {
+            (Some(line_a), Some(line_b)) => (line_a, line_b),
+            _ => {
+                return Some(Divergence {
+                    index,
+                    columns: vec![],
+                })
+            }
+        };
+        let columns: Vec<(String, String, String)> = COMPARED_COLUMNS
+            .iter()
+            .filter_map(|&name| {
+                let (value_a, value_b) = match (line_a.columns.get(name), line_b.columns.get(name))
+                {
+                    (Some(value_a), Some(value_b)) => (value_a.clone(), value_b.clone()),
+                    //an instruction without memory writes has no `W` column
+                    (value_a, value_b) if name == "W" => (
+                        value_a.cloned().unwrap_or_default(),
+                        value_b.cloned().unwrap_or_default(),
+                    ),
+                    _ => return None,
+                };
+                if value_a != value_b {
+                    Some((name.to_string(), value_a, value_b))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        if !columns.is_empty() {
+            return Some(Divergence { index, columns });
+        }
+    }
+    None
+}
+
+/// `report()` describes the divergence of the aligned traces `a` and `b`,
+/// with `context` instructions before the divergence.
+pub fn report(
+    names: (&str, &str),
+    a: &[TraceLine],
+    b: &[TraceLine],
+    divergence: &Divergence,
+    context: usize,
+) -> String {
+    let mut text = String::new();
+    let index = divergence.index;
+    match (a.get(index), b.get(index)) {
+        (Some(line_a), Some(line_b)) => {
+            text.push_str(&format!(
+                "First divergence at instruction {} ({}:{}, {}:{})\n",
+                index, names.0, line_a.line_number, names.1, line_b.line_number
+            ));
+            for (name, value_a, value_b) in &divergence.columns {
+                text.push_str(&format!("  {:<3} {} | {}\n", name, value_a, value_b));
+            }
+        }
+        (None, _) => text.push_str(&format!("{} ends after {} instructions\n", names.0, index)),
+        (_, None) => text.push_str(&format!("{} ends after {} instructions\n", names.1, index)),
+    }
+    let first = index.saturating_sub(context);
+    for (name, trace) in &[(names.0, a), (names.1, b)] {
+        text.push_str(&format!("\n{} :\n", name));
+        for line in trace.iter().take(index + 1).skip(first) {
+            text.push_str(&format!("  {:>6}  {}\n", line.line_number, line.text));
+        }
+    }
+    text
+}
+
+/// `normalize_value()` removes a `0x`/`$` prefix, upper-cases hex digits and drops leading zeros,
+/// so `0x0a`, `0A` and `$A` are the same value.
+fn normalize_value(value: &str) -> String {
+    let value = value.trim_end_matches(',');
+    let value = value
+        .trim_start_matches("0x")
+        .trim_start_matches("0X")
+        .trim_start_matches('$');
+    if value.chars().all(|c| c.is_ascii_hexdigit()) && !value.is_empty() {
+        let trimmed = value.trim_start_matches('0');
+        if trimmed.is_empty() {
+            String::from("0")
+        } else {
+            trimmed.to_uppercase()
+        }
+    } else {
+        value.to_uppercase()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `trace()` writes a trace of the instructions `(pc, opcode, v0)`, with the delay timer counting down.
+    fn trace(instructions: &[(u16, u16, u8)]) -> String {
+        instructions
+            .iter()
+            .enumerate()
+            .map(|(index, (pc, opcode, v0))| {
+                format!(
+                    "PC:{:04X} OP:{:04X} V0:{:02X} DT:{:02X}\n",
+                    pc,
+                    opcode,
+                    v0,
+                    60 - index
+                )
+            })
+            .collect()
+    }
+
+    const PROGRAM: [(u16, u16, u8); 5] = [
+        (0x200, 0x6001, 0x00),
+        (0x202, 0x7001, 0x01),
+        (0x204, 0x7001, 0x02),
+        (0x206, 0x7001, 0x03),
+        (0x208, 0x1208, 0x04),
+    ];
+
+    #[test]
+    fn values_are_normalized() {
+        for value in ["0x0a", "0A", "$A", "000a,"] {
+            assert_eq!(normalize_value(value), "A", "{}", value);
+        }
+        assert_eq!(normalize_value("0000"), "0");
+        assert_eq!(normalize_value("0300=01,0301=02"), "0300=01,0301=02");
+        assert_eq!(normalize_value("ld"), "LD");
+    }
+
+    #[test]
+    fn lines_without_a_program_counter_are_skipped() {
+        let text = format!("a header\n{}\nthe end\n", trace(&PROGRAM[..2]));
+        let lines = parse_trace(&text);
+        assert_eq!(lines.len(), 2);
+        assert_eq!((lines[0].line_number, lines[1].line_number), (2, 3));
+        assert_eq!(lines[1].columns["V0"], "1");
+    }
+
+    #[test]
+    fn identical_traces_do_not_diverge() {
+        let a = parse_trace(&trace(&PROGRAM));
+        //another emulator writes lower case hexadecimal, and counts the timers down at other moments
+        let b = parse_trace(&trace(&PROGRAM).to_lowercase().replace("dt:3", "dt:4"));
+        assert_eq!(align(&a, &b), (0, 0));
+        assert!(first_divergence(&a, &b).is_none());
+    }
+
+    #[test]
+    fn divergence_is_found_at_the_first_different_instruction() {
+        let mut diverging = PROGRAM;
+        diverging[3].2 = 0x07;
+        diverging[4].2 = 0x08;
+        let (a, b) = (
+            parse_trace(&trace(&PROGRAM)),
+            parse_trace(&trace(&diverging)),
+        );
+        let divergence = first_divergence(&a, &b).unwrap();
+        assert_eq!(divergence.index, 3);
+        assert_eq!(
+            divergence.columns,
+            vec![(String::from("V0"), String::from("3"), String::from("7"))]
+        );
+        let report = report(("a", "b"), &a, &b, &divergence, 1);
+        assert!(report.starts_with("First divergence at instruction 3 (a:4, b:4)\n  V0  3 | 7\n"));
+    }
+
+    #[test]
+    fn a_shorter_trace_diverges_where_it_ends() {
+        let (a, b) = (
+            parse_trace(&trace(&PROGRAM)),
+            parse_trace(&trace(&PROGRAM[..3])),
+        );
+        let divergence = first_divergence(&a, &b).unwrap();
+        assert_eq!((divergence.index, divergence.columns.len()), (3, 0));
+        assert!(
+            report(("a", "b"), &a, &b, &divergence, 0).starts_with("b ends after 3 instructions\n")
+        );
+    }
+
+    #[test]
+    fn traces_are_aligned_on_their_first_common_instruction() {
+        //the second trace starts with a boot routine at 0x000
+        let boot = [(0x000, 0x1200, 0x00), (0x200, 0x6001, 0x00)];
+        let mut booted = boot.to_vec();
+        booted.extend(&PROGRAM[1..]);
+        let (a, b) = (parse_trace(&trace(&PROGRAM)), parse_trace(&trace(&booted)));
+        assert_eq!(align(&a, &b), (0, 1));
+        assert_eq!(align(&b, &a), (1, 0));
+        assert!(first_divergence(&a, &b[1..]).is_none());
+        let elsewhere = parse_trace(&trace(&[(0x300, 0x1300, 0x00)]));
+        assert_eq!(align(&a, &elsewhere), (0, 0));
+    }
+}
@@ -0,0 +1,162 @@
+//! An execution trace has one line per executed instruction.
+//! Each line shows the state of the machine just before the instruction is executed, in fixed columns :
+//!
+//! `PC:0200 OP:6A02 LD VA, 0x02           V0:00 V1:00 ... VF:00 I:0000 SP:00 DT:00 ST:00`
+//!
+//! Every value is written as `NAME:HEX`, so traces can be compared line by line with traces of other emulators.
+//! Instructions writing to memory (FX33 and FX55) get an extra column with the written cells, e.g. `W:0300=01,0301=02`.
+//! Self-modifying code gets an `SMC` column : `SMC:CODE@0300` for a write into code executed before
+//! and `SMC:WRITTEN-BY@0206` for executing code written by the instruction at 0x206.
+
+use crate::debugger::SelfModification;
+use crate::vm::{Machine, Operator};
+use log::debug;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+/// Width of the mnemonic column, long enough for the longest mnemonic (`DRW VX, VY, 15`).
+const MNEMONIC_WIDTH: usize = 20;
+
+/// `Tracer` : writes one line per executed instruction to the trace file.
+pub struct Tracer {
+    writer: BufWriter<File>,
+}
+
+impl Tracer {
+    /// `Tracer::create()` will create (or truncate) the trace file.
+    pub fn create(filename: &str) -> std::io::Result<Tracer> {
+        debug!("[Tracer::create()] Tracing to {}.", filename);
+        Ok(Tracer {
+            writer: BufWriter::new(File::create(filename)?),
+        })
+    }
+
+    /// `Tracer.record()` writes the line for the instruction which is about to be executed,
+    /// with the self-modifying code found in it.
+    pub fn record(&mut self, machine: &Machine, operator: &Operator) {
+        let mut line = Tracer::format_line(machine, operator);
+        if let Some(smc_detector) = machine.smc_detector.as_ref() {
+            if !smc_detector.last.is_empty() {
+                let events: Vec<String> = smc_detector
+                    .last
+                    .iter()
+                    .map(|event| match event {
+                        SelfModification::WritesCode { address, .. } => {
+                            format!("CODE@{:04X}", address)
+                        }
+                        SelfModification::ExecutesWritten { writer, .. } => {
+                            format!("WRITTEN-BY@{:04X}", writer)
+                        }
+                    })
+                    .collect();
+                line.push_str(&format!(" SMC:{}", events.join(",")));
+            }
+        }
+        writeln!(self.writer, "{}", line).expect("Error: Could not write trace.");
+    }
+
+    /// `Tracer::format_line()` formats the state of the `machine` and the `operator` at its program counter.
+    pub fn format_line(machine: &Machine, operator: &Operator) -> String {
+        let mut line = format!(
+            "PC:{:04X} OP:{:04X} {:<width$}",
+            machine.pc,
+            operator.opcode,
+            operator.mnemonic(),
+            width = MNEMONIC_WIDTH
+        );
+        for (index, value) in machine.registers.v.iter().enumerate() {
+            line.push_str(&format!(" V{:X}:{:02X}", index, value));
+        }
+        line.push_str(&format!(
+            " I:{:04X} SP:{:02X} DT:{:02X} ST:{:02X}",
+            machine.i,
+            machine.stack.cells.len(),
+            machine.timers.dt,
+            machine.timers.st
+        ));
+        let writes = operator.memory_writes(machine);
+        if !writes.is_empty() {
+            let writes: Vec<String> = writes
+                .iter()
+                .map(|(address, value)| format!("{:04X}={:02X}", address, value))
+                .collect();
+            line.push_str(&format!(" W:{}", writes.join(",")));
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::PROGRAM_START;
+
+    /// `trace()` returns the lines of the trace of the `steps` first instructions of `program`.
+    fn trace(program: &[u8], steps: usize) -> Vec<String> {
+        let mut machine = Machine::new();
+        machine.load_program(program, PROGRAM_START, PROGRAM_START);
+        let mut lines = vec![];
+        for _ in 0..steps {
+            lines.push(Tracer::format_line(
+                &machine,
+                &Operator::get_operators(&machine),
+            ));
+            machine.process_keys(vec![false; 16]);
+        }
+        lines
+    }
+
+    #[test]
+    fn lines_show_the_state_before_each_instruction() {
+        //VA := 2, I := 0x300, BCD of VA, call 0x208, return
+        let program = [0x6A, 0x02, 0xA3, 0x00, 0xFA, 0x33, 0x22, 0x08, 0x00, 0xEE];
+        let lines = trace(&program, 5);
+        assert_eq!(
+            lines[0],
+            "PC:0200 OP:6A02 LD VA, 0x02          V0:00 V1:00 V2:00 V3:00 V4:00 V5:00 V6:00 V7:00 \
+             V8:00 V9:00 VA:00 VB:00 VC:00 VD:00 VE:00 VF:00 I:0000 SP:00 DT:00 ST:00"
+        );
+        //the changes of an instruction show on the next line
+        assert!(lines[1].starts_with("PC:0202 OP:A300 LD I, 0x300 "));
+        assert!(lines[1].contains(" VA:02 ") && lines[1].contains(" I:0000 "));
+        assert!(lines[2].starts_with("PC:0204 OP:FA33 LD B, VA "));
+        assert!(lines[2].contains(" I:0300 "));
+        assert!(lines[3].starts_with("PC:0206 OP:2208 CALL 0x208 "));
+        assert!(lines[3].contains(" SP:00 "));
+        assert!(lines[4].starts_with("PC:0208 OP:00EE RET "));
+        assert!(lines[4].contains(" SP:01 "));
+    }
+
+    #[test]
+    fn memory_writes_get_a_column() {
+        //V0 := 1, V1 := 2, I := 0x300, store V0-V1
+        let program = [0x60, 0x01, 0x61, 0x02, 0xA3, 0x00, 0xF1, 0x55];
+        let lines = trace(&program, 4);
+        assert!(lines[..3].iter().all(|line| !line.contains(" W:")));
+        assert!(lines[3].ends_with(" ST:00 W:0300=01,0301=02"));
+    }
+
+    #[test]
+    fn self_modifying_code_gets_a_column() {
+        //V0 := 0x60, I := 0x200, store V0 over the first instruction, jump to it
+        let program = [0x60, 0x60, 0xA2, 0x00, 0xF0, 0x55, 0x12, 0x00];
+        let path = std::env::temp_dir().join(format!("chip8-trace-{}.txt", std::process::id()));
+        let mut machine = Machine::new();
+        machine.load_program(&program, PROGRAM_START, PROGRAM_START);
+        machine.tracer = Some(Tracer::create(path.to_str().unwrap()).unwrap());
+        machine.smc_detector = Some(crate::debugger::SmcDetector::new(0x1000));
+        for _ in 0..5 {
+            machine.process_keys(vec![false; 16]);
+        }
+        drop(machine);
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[2].ends_with(" W:0200=60 SMC:CODE@0200"));
+        assert!(lines[4].starts_with("PC:0200 "));
+        assert!(lines[4].ends_with(" ST:00 SMC:WRITTEN-BY@0204"));
+        assert!(lines[..2].iter().all(|line| !line.contains(" SMC:")));
+    }
+}
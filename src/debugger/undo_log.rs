@@ -0,0 +1,374 @@
+//! The undo log remembers every change made to the machine by each executed step,
+//! so the steps can be undone one by one (reverse stepping).
+//! The changes are recorded where they are made : while the debugger runs a step, the machine keeps a journal,
+//! and every register, memory cell, pixel, ... written by the instructions adds its old value to it.
+//! The journal of each step is kept in the log.
+
+use crate::vm::{Cdp1802, Colors, Machine, MegaChip, MegaScreen, VRam};
+use std::collections::VecDeque;
+
+/// `Mutation` : one change made to the machine, holding the value before the change.
+#[derive(Debug)]
+pub enum Mutation {
+    Register {
+        index: usize,
+        old: u8,
+    },
+    Memory {
+        address: usize,
+        old: u8,
+    },
+    Pixel {
+        x: usize,
+        y: usize,
+        old: u8,
+    },
+    ProgramCounter {
+        old: u16,
+    },
+    Index {
+        old: u32,
+    },
+    /// An address was pushed on the stack.
+    Push,
+    /// An address was popped from the stack.
+    Pop {
+        old: u16,
+    },
+    Timers {
+        dt: u8,
+        st: u8,
+    },
+    KeyBoard {
+        keys: Vec<bool>,
+        second_keys: Vec<bool>,
+        keypress_awaited: bool,
+        key_register: usize,
+    },
+    Cdp1802 {
+        old: Cdp1802,
+    },
+    /// The display was replaced by one of another size.
+    VRam {
+        old: Box<VRam>,
+    },
+    Flags {
+        old: [u8; 16],
+    },
+    Planes {
+        old: u8,
+    },
+    Audio {
+        pattern: [u8; 16],
+        pitch: u8,
+    },
+    /// The machine switched to the 64x64 display of hi-res CHIP-8 (the display is recorded too).
+    Hires {
+        old: bool,
+    },
+    /// The colors of CHIP-8X changed (02A0, BXYN).
+    Colors {
+        old: Option<Colors>,
+    },
+    Chip8x {
+        old: bool,
+    },
+    /// The machine halted (00FD, the sanity checker), or the debugger let it go on.
+    Halted {
+        old: bool,
+    },
+    /// The mode, palette, sprites or sound of MegaChip changed (0010, 0011, 02NN to 09NN).
+    MegaChip {
+        old: Box<MegaChip>,
+    },
+    /// The whole screen of MegaChip changed (00E0, the scrolls, 05NN).
+    MegaScreen {
+        old: Box<MegaScreen>,
+    },
+    MegaPixel {
+        x: usize,
+        y: usize,
+        old: u32,
+    },
+}
+
+/// `UndoLog` : the mutations of the last `capacity` steps, the most recent step last.
+pub struct UndoLog {
+    steps: VecDeque<Vec<Mutation>>,
+    capacity: usize,
+}
+
+impl UndoLog {
+    /// `UndoLog::new()` will return an empty log, remembering at most `capacity` steps.
+    pub fn new(capacity: usize) -> UndoLog {
+        UndoLog {
+            steps: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// `UndoLog.len()` returns the number of steps which can be undone.
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// `UndoLog.is_empty()` returns true when no step can be undone.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// `UndoLog.record()` adds the `mutations` of the journal of a step as one step.
+    /// The oldest step is forgotten when the log is full.
+    pub fn record(&mut self, mutations: Vec<Mutation>) {
+        if self.steps.len() == self.capacity {
+            self.steps.pop_front();
+        }
+        self.steps.push_back(mutations);
+    }
+
+    /// `UndoLog.undo()` reverts the most recent step. It returns false if there was no step to undo.
+    pub fn undo(&mut self, machine: &mut Machine) -> bool {
+        let mutations = match self.steps.pop_back() {
+            Some(mutations) => mutations,
+            None => return false,
+        };
+        for mutation in mutations.into_iter().rev() {
+            match mutation {
+                Mutation::Register { index, old } => machine.registers.v[index] = old,
+                Mutation::Memory { address, old } => machine.memory.cells[address] = old,
+                Mutation::Pixel { x, y, old } => {
+                    machine.vram.cells[y][x] = old;
+                    machine.vram.state_changed = true;
+                }
+                Mutation::ProgramCounter { old } => machine.pc = old,
+                Mutation::Index { old } => machine.i = old,
+                Mutation::Push => {
+                    machine.stack.cells.pop();
+                }
+                Mutation::Pop { old } => machine.stack.cells.push(old),
+                Mutation::Timers { dt, st } => {
+                    machine.timers.dt = dt;
+                    machine.timers.st = st;
+                }
+                Mutation::KeyBoard {
+                    keys,
+                    second_keys,
+                    keypress_awaited,
+                    key_register,
+                } => {
+                    machine.keyboard.keys = keys;
+                    machine.keyboard.second_keys = second_keys;
+                    machine.keyboard.keypress_awaited = keypress_awaited;
+                    machine.keyboard.key_register = key_register;
+                }
+                Mutation::Cdp1802 { old } => machine.cdp1802 = old,
+                Mutation::VRam { old } => {
+                    machine.vram = *old;
+                    machine.vram.state_changed = true;
+                }
+                Mutation::Flags { old } => machine.flags = old,
+                Mutation::Planes { old } => machine.planes = old,
+                Mutation::Audio { pattern, pitch } => {
+                    machine.audio_pattern = pattern;
+                    machine.pitch = pitch;
+                }
+                Mutation::Hires { old } => machine.hires = old,
+                Mutation::Colors { old } => {
+                    machine.vram.colors = old;
+                    machine.vram.state_changed = true;
+                }
+                Mutation::Chip8x { old } => machine.chip8x = old,
+                Mutation::Halted { old } => machine.halted = old,
+                Mutation::MegaChip { mut old } => {
+                    //the sound driver plays the sample again if it was changed
+                    old.sound_changed = machine
+                        .megachip
+                        .as_ref()
+                        .is_some_and(|megachip| megachip.sound != old.sound);
+                    machine.megachip = Some(*old);
+                }
+                Mutation::MegaScreen { old } => {
+                    machine.vram.mega = Some(*old);
+                    machine.vram.state_changed = true;
+                }
+                Mutation::MegaPixel { x, y, old } => {
+                    if let Some(screen) = machine.vram.mega.as_mut() {
+                        screen.buffer[y][x] = old;
+                    }
+                    machine.vram.state_changed = true;
+                }
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{BlendMode, Platform, PROGRAM_START};
+
+    /// `run()` executes `steps` steps of `program` with the instructions of XO-CHIP, recording them in an undo log.
+    fn run(program: &[u8], steps: usize) -> (Machine, UndoLog) {
+        let mut machine = Machine::new();
+        machine.set_platform(Platform::XoChip);
+        machine.load_program(program, PROGRAM_START, PROGRAM_START);
+        let mut undo_log = UndoLog::new(steps);
+        for _ in 0..steps {
+            machine.journal = Some(vec![]);
+            machine.process_keys(vec![false; 16]);
+            undo_log.record(machine.journal.take().unwrap());
+        }
+        (machine, undo_log)
+    }
+
+    #[test]
+    fn undo_reverts_every_step() {
+        //V0 := 0x12, V1 := 3, I := 0x300, store V0-V1, draw the font of 0, call and return, delay := V1
+        let program = [
+            0x60, 0x12, 0x61, 0x03, 0xA3, 0x00, 0xF1, 0x55, 0xF0, 0x29, 0xD0, 0x15, 0x22, 0x10,
+            0xF1, 0x15, 0x00, 0xEE,
+        ];
+        let steps = 9;
+        let (initial, _) = run(&program, 0);
+        let (mut machine, mut undo_log) = run(&program, steps);
+        assert_eq!(machine.pc, 0x210);
+        assert_eq!(machine.timers.dt, 3);
+        assert_eq!(&machine.memory.cells[0x300..0x302], &[0x12, 0x03]);
+        assert!(machine.vram.cells[3].contains(&1));
+
+        assert_eq!(
+            (0..steps)
+                .take_while(|_| undo_log.undo(&mut machine))
+                .count(),
+            steps
+        );
+        assert!(!undo_log.undo(&mut machine));
+        assert_eq!(machine.pc, initial.pc);
+        assert_eq!(machine.i, initial.i);
+        assert_eq!(machine.registers.v, initial.registers.v);
+        assert_eq!(machine.memory.cells, initial.memory.cells);
+        assert_eq!(machine.vram.cells, initial.vram.cells);
+        assert_eq!(machine.stack.cells, initial.stack.cells);
+        assert_eq!(machine.timers.dt, initial.timers.dt);
+    }
+
+    #[test]
+    fn undo_reverts_a_call_and_a_return() {
+        //call 0x206, return, jump to itself
+        let program = [0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x00, 0xEE];
+        let (mut machine, mut undo_log) = run(&program, 2);
+        assert_eq!(machine.pc, 0x202);
+        assert!(undo_log.undo(&mut machine));
+        assert_eq!(
+            (machine.pc, machine.stack.cells.clone()),
+            (0x206, vec![0x202])
+        );
+        assert!(undo_log.undo(&mut machine));
+        assert_eq!((machine.pc, machine.stack.cells.clone()), (0x200, vec![]));
+    }
+
+    #[test]
+    fn undo_restores_the_display_and_the_planes() {
+        //draw the font of 0, switch to hires, select both planes
+        let program = [0xD0, 0x05, 0x00, 0xFF, 0xF3, 0x01];
+        let (mut machine, mut undo_log) = run(&program, 3);
+        assert_eq!((machine.vram.cells.len(), machine.planes), (64, 3));
+        assert!(undo_log.undo(&mut machine));
+        assert!(undo_log.undo(&mut machine));
+        assert_eq!((machine.vram.cells.len(), machine.planes), (32, 1));
+        assert!(machine.vram.cells[0].contains(&1));
+    }
+
+    #[test]
+    fn undo_leaves_the_hires_display() {
+        let (mut machine, mut undo_log) = run(&[0xD0, 0x05], 1);
+        machine.journal = Some(vec![]);
+        machine.enable_hires();
+        undo_log.record(machine.journal.take().unwrap());
+        assert_eq!((machine.hires, machine.vram.cells.len()), (true, 64));
+        assert!(undo_log.undo(&mut machine));
+        assert_eq!((machine.hires, machine.vram.cells.len()), (false, 32));
+        assert!(machine.vram.cells[0].contains(&1));
+    }
+
+    #[test]
+    fn undo_restores_the_colors_of_chip_8x() {
+        //V0 := 0, V1 := 0, V2 := 5, color the zone of (0, 0) with V2, cycle the background
+        let program = [0x60, 0x00, 0x61, 0x00, 0x62, 0x05, 0xB0, 0x21, 0x02, 0xA0];
+        let mut machine = Machine::new();
+        machine.enable_chip8x();
+        machine.load_program(&program, PROGRAM_START, PROGRAM_START);
+        let initial = machine.vram.colors.clone();
+        let mut undo_log = UndoLog::new(5);
+        for _ in 0..5 {
+            machine.journal = Some(vec![]);
+            machine.process_keys(vec![false; 16]);
+            undo_log.record(machine.journal.take().unwrap());
+        }
+        let colors = machine.vram.colors.clone().unwrap();
+        assert_eq!((colors.background, colors.zones[0][0]), (1, 5));
+        assert!(undo_log.undo(&mut machine));
+        assert!(undo_log.undo(&mut machine));
+        assert_eq!(machine.vram.colors, initial);
+    }
+
+    #[test]
+    fn undo_restores_the_state_of_megachip() {
+        //MegaChip on, I := 0x20E, load color 1, sprites of 2 x 1, draw, MegaChip off, then the sprite at 0x20E
+        let program = [
+            0x00, 0x11, 0xA2, 0x0E, 0x02, 0x01, 0x03, 0x02, 0x04, 0x01, 0xD0, 0x01, 0x00, 0x10,
+            0x01, 0x01, 0xFF, 0xFF,
+        ];
+        let mut machine = Machine::new();
+        machine.enable_megachip();
+        machine.load_program(&program, PROGRAM_START, PROGRAM_START);
+        let mut undo_log = UndoLog::new(7);
+        for _ in 0..7 {
+            machine.journal = Some(vec![]);
+            machine.process_keys(vec![false; 16]);
+            undo_log.record(machine.journal.take().unwrap());
+        }
+        assert!(!machine.megachip_mode());
+        assert_eq!(machine.vram.cells.len(), 32);
+
+        assert!(undo_log.undo(&mut machine));
+        assert!(machine.megachip_mode());
+        assert_eq!(machine.vram.cells[0][..3], [1, 1, 0]);
+        let drawn = BlendMode::Normal.blend(0x0101_FFFF, 0xFF00_0000);
+        let screen = machine.vram.mega.as_ref().unwrap();
+        assert_eq!(screen.buffer[0][..3], [drawn, drawn, 0xFF00_0000]);
+
+        assert!(undo_log.undo(&mut machine));
+        assert_eq!(machine.vram.cells[0][..2], [0, 0]);
+        let screen = machine.vram.mega.as_ref().unwrap();
+        assert_eq!(screen.buffer[0][..2], [0xFF00_0000, 0xFF00_0000]);
+        let megachip = machine.megachip.as_ref().unwrap();
+        assert_eq!(
+            (
+                megachip.sprite_width,
+                megachip.sprite_height,
+                megachip.palette[1]
+            ),
+            (2, 1, 0x0101_FFFF)
+        );
+
+        while undo_log.undo(&mut machine) {}
+        let megachip = machine.megachip.as_ref().unwrap();
+        assert_eq!(
+            (
+                megachip.sprite_width,
+                megachip.sprite_height,
+                megachip.palette[1]
+            ),
+            (
+                MegaChip::new().sprite_width,
+                MegaChip::new().sprite_height,
+                0
+            )
+        );
+        assert!(!machine.megachip_mode());
+        assert!(machine.vram.mega.is_none());
+        assert_eq!(machine.vram.cells.len(), 32);
+    }
+}
@@ -0,0 +1,631 @@
+//! Decompiler - turns a ROM into Octo source, as a starting point for programs whose source was lost.
+//! It starts from the disassembly and recovers the control structures the Octo compiler produces :
+//!
+//! * a backward jump becomes `loop ... again`,
+//! * a skip followed by a forward jump becomes `if ... begin ... end` (with `else` when the block ends with
+//!   another forward jump), or `while` when the jump leaves the enclosing loop,
+//! * any other skip becomes `if ... then`.
+//!
+//! Structures which would not nest properly are left as plain jumps. Subroutines, jump targets and data get labels,
+//! and sprites are written as binary literals, one row per line.
+//! The start of the ROM is named `main`, and its entry point gets a label. Compiling the source gives back the same
+//! ROM, byte for byte. The source of a ROM loaded elsewhere than 0x200 starts with `:org`, it compiles to a ROM
+//! for 0x200 which jumps to it.
+
+use crate::disassembler::{self, ByteKind, Disassembly};
+use crate::vm::{Instruction, PROGRAM_START};
+use log::debug;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Number of data bytes written on one line.
+const DATA_BYTES_PER_LINE: usize = 8;
+
+/// `Structure` : a recovered control structure.
+#[derive(Debug, Clone, Copy)]
+enum Structure {
+    /// `loop` at `start`, `again` at `again` (the backward jump).
+    Loop { start: u16, again: u16 },
+    /// `if ... begin` at `skip`, optionally `else` at `else_jump`, `end` at `end`.
+    If {
+        skip: u16,
+        else_jump: Option<u16>,
+        end: u16,
+    },
+}
+
+impl Structure {
+    /// `Structure.outer()` returns the addresses the structure covers, `end` not included.
+    fn outer(&self) -> (u16, u16) {
+        match *self {
+            Structure::Loop { start, again } => (start, again + 2),
+            Structure::If { skip, end, .. } => (skip, end),
+        }
+    }
+
+    /// `Structure.inner()` returns the bodies of the structure, which other structures may be nested in.
+    fn inner(&self) -> Vec<(u16, u16)> {
+        match *self {
+            Structure::Loop { start, again } => vec![(start, again)],
+            Structure::If {
+                skip,
+                else_jump: Some(else_jump),
+                end,
+            } => vec![(skip + 4, else_jump), (else_jump + 2, end)],
+            Structure::If { skip, end, .. } => vec![(skip + 4, end)],
+        }
+    }
+}
+
+/// `Item` : what is written at an address.
+enum Item {
+    Statement(String),
+    Data(u8),
+    Sprite(u8),
+}
+
+/// `Decompiler` : the disassembly, the recovered structures and the labels the source needs.
+struct Decompiler {
+    disassembly: Disassembly,
+    /// `starts` holds the addresses where a statement or a data byte starts.
+    starts: BTreeSet<u16>,
+    /// `targets` holds every address any instruction refers to.
+    targets: BTreeSet<u16>,
+    structures: Vec<Structure>,
+    /// `whiles` holds the skips which are the condition of a `while`.
+    whiles: BTreeSet<u16>,
+    labels: BTreeSet<u16>,
+}
+
+/// `decompile()` will return the Octo source of a ROM loaded at `origin` and started at `entry_point`.
+pub fn decompile(rom: &[u8], origin: u16, entry_point: u16) -> String {
+    let mut decompiler = Decompiler::new(disassembler::disassemble(rom, origin, entry_point));
+    decompiler.find_structures();
+    let items = decompiler.items();
+    debug!(
+        "[decompile()] Recovered {} structures and {} whiles.",
+        decompiler.structures.len(),
+        decompiler.whiles.len()
+    );
+    decompiler.format(&items)
+}
+
+impl Decompiler {
+    fn new(disassembly: Disassembly) -> Decompiler {
+        let mut starts = BTreeSet::new();
+        let end = disassembly.origin + disassembly.bytes.len() as u16;
+        let mut address = disassembly.origin;
+        while address < end {
+            starts.insert(address);
+            address += disassembly
+                .instructions
+                .get(&address)
+                .map_or(1, |instruction| instruction.size());
+        }
+        let targets = disassembly
+            .instructions
+            .iter()
+            .filter_map(|(&address, instruction)| match instruction {
+                Instruction::LoadIndexLong => disassembly.opcode_at(address + 2),
+                _ => instruction.target(),
+            })
+            .collect();
+        //the entry point is named, even when nothing refers to it
+        let labels = starts
+            .iter()
+            .copied()
+            .filter(|&address| address == disassembly.entry_point)
+            .collect();
+        Decompiler {
+            disassembly,
+            starts,
+            targets,
+            structures: vec![],
+            whiles: BTreeSet::new(),
+            labels,
+        }
+    }
+
+    fn instruction(&self, address: u16) -> Option<Instruction> {
+        self.disassembly.instructions.get(&address).copied()
+    }
+
+    /// `Decompiler.is_straight()` tells if the instructions from `start` reach `end` one after the other, without data.
+    fn is_straight(&self, start: u16, end: u16) -> bool {
+        let mut address = start;
+        while address < end {
+            match self.instruction(address) {
+                Some(instruction) => address += instruction.size(),
+                None => return false,
+            }
+        }
+        address == end
+    }
+
+    /// `Decompiler.fits()` tells if `candidate` nests properly with the structures found so far :
+    /// any two structures are either apart, or one lies inside a body of the other.
+    fn fits(&self, candidate: &Structure) -> bool {
+        let inside = |(start, end): (u16, u16), structure: &Structure| {
+            structure
+                .inner()
+                .iter()
+                .any(|&(inner_start, inner_end)| inner_start <= start && end <= inner_end)
+        };
+        let (start, end) = candidate.outer();
+        self.structures.iter().all(|structure| {
+            let (other_start, other_end) = structure.outer();
+            end <= other_start
+                || other_end <= start
+                || inside((start, end), structure)
+                || inside((other_start, other_end), candidate)
+        })
+    }
+
+    /// `Decompiler.find_structures()` recovers the loops first, then the `while`s inside them, then the `if`s.
+    fn find_structures(&mut self) {
+        let instructions: Vec<(u16, Instruction)> = self
+            .disassembly
+            .instructions
+            .iter()
+            .map(|(&address, &instruction)| (address, instruction))
+            .collect();
+
+        //the outermost loops first, so the inner ones nest inside them
+        let mut loops: Vec<Structure> = instructions
+            .iter()
+            .filter_map(|&(address, instruction)| match instruction {
+                Instruction::Jump(start)
+                    if start <= address && self.is_straight(start, address) =>
+                {
+                    Some(Structure::Loop {
+                        start,
+                        again: address,
+                    })
+                }
+                _ => None,
+            })
+            .collect();
+        loops.sort_by_key(|structure| {
+            let (start, end) = structure.outer();
+            (start, std::cmp::Reverse(end))
+        });
+        for candidate in loops {
+            if self.fits(&candidate) {
+                self.structures.push(candidate);
+            }
+        }
+
+        for &(skip, instruction) in &instructions {
+            if !instruction.is_skip() || self.targets.contains(&(skip + 2)) {
+                continue;
+            }
+            let exit = match self.instruction(skip + 2) {
+                Some(Instruction::Jump(exit)) if exit > skip + 2 => exit,
+                _ => continue,
+            };
+            //a `while` leaves the innermost loop around it
+            let innermost = self
+                .structures
+                .iter()
+                .filter_map(|structure| match *structure {
+                    Structure::Loop { start, again } if start <= skip && skip < again => {
+                        Some((start, again))
+                    }
+                    _ => None,
+                })
+                .max_by_key(|&(start, _)| start);
+            if let Some((_, again)) = innermost {
+                if exit == again + 2 {
+                    self.whiles.insert(skip);
+                    continue;
+                }
+            }
+            if !self.is_straight(skip + 4, exit) {
+                continue;
+            }
+            let else_jump = match self.instruction(exit - 2) {
+                Some(Instruction::Jump(end))
+                    if exit - 2 >= skip + 4
+                        && end > exit
+                        && !self.targets.contains(&(exit - 2))
+                        && self.is_straight(exit, end) =>
+                {
+                    Some((exit - 2, end))
+                }
+                _ => None,
+            };
+            let mut candidates = vec![];
+            if let Some((else_jump, end)) = else_jump {
+                candidates.push(Structure::If {
+                    skip,
+                    else_jump: Some(else_jump),
+                    end,
+                });
+            }
+            candidates.push(Structure::If {
+                skip,
+                else_jump: None,
+                end: exit,
+            });
+            if let Some(candidate) = candidates
+                .into_iter()
+                .find(|candidate| self.fits(candidate))
+            {
+                self.structures.push(candidate);
+            }
+        }
+    }
+
+    /// `Decompiler.reference()` names the address an instruction refers to : its label if it starts a statement
+    /// in the ROM, the address itself otherwise.
+    fn reference(&mut self, address: u16) -> String {
+        if self.starts.contains(&address) {
+            self.labels.insert(address);
+            self.name(address)
+        } else {
+            format!("0x{:03X}", address)
+        }
+    }
+
+    fn name(&self, address: u16) -> String {
+        if address == self.disassembly.origin {
+            return String::from("main");
+        }
+        self.disassembly
+            .label(address)
+            .unwrap_or_else(|| format!("label_{:03X}", address))
+    }
+
+    /// `Decompiler.items()` writes every statement and data byte, by address.
+    /// Control structures are written as keywords; their jumps are not written on their own.
+    fn items(&mut self) -> BTreeMap<u16, Item> {
+        let mut items = BTreeMap::new();
+        let mut skipped = BTreeSet::new();
+        let mut keywords: BTreeMap<u16, String> = BTreeMap::new();
+        for structure in self.structures.clone() {
+            match structure {
+                Structure::Loop { again, .. } => {
+                    keywords.insert(again, String::from("again"));
+                }
+                Structure::If {
+                    skip, else_jump, ..
+                } => {
+                    let condition = skip_condition(&self.instruction(skip).unwrap(), false);
+                    keywords.insert(skip, format!("if {} begin", condition));
+                    skipped.insert(skip + 2);
+                    if let Some(else_jump) = else_jump {
+                        keywords.insert(else_jump, String::from("else"));
+                    }
+                }
+            }
+        }
+        for &skip in &self.whiles.clone() {
+            let condition = skip_condition(&self.instruction(skip).unwrap(), false);
+            keywords.insert(skip, format!("while {}", condition));
+            skipped.insert(skip + 2);
+        }
+
+        for address in self.starts.clone() {
+            if skipped.contains(&address) {
+                continue;
+            }
+            let item = match (keywords.remove(&address), self.instruction(address)) {
+                (Some(keyword), _) => Item::Statement(keyword),
+                (None, Some(instruction)) => Item::Statement(self.statement(address, instruction)),
+                (None, None) => {
+                    let byte = self.disassembly.bytes[(address - self.disassembly.origin) as usize];
+                    match self.disassembly.kind_at(address) {
+                        Some(ByteKind::Sprite) => Item::Sprite(byte),
+                        _ => Item::Data(byte),
+                    }
+                }
+            };
+            items.insert(address, item);
+        }
+        items
+    }
+
+    /// `Decompiler.statement()` writes the Octo statement of an instruction.
+    fn statement(&mut self, address: u16, instruction: Instruction) -> String {
+        match instruction {
+            Instruction::Sys(nnn) => format!("native {}", self.reference(nnn)),
+            Instruction::Cls => String::from("clear"),
+            Instruction::Ret => String::from("return"),
+            Instruction::Jump(nnn) => format!("jump {}", self.reference(nnn)),
+            Instruction::Call(nnn) => {
+                if self.starts.contains(&nnn) {
+                    self.reference(nnn)
+                } else {
+                    format!(":call 0x{:03X}", nnn)
+                }
+            }
+            Instruction::SkipEqualByte { .. }
+            | Instruction::SkipNotEqualByte { .. }
+            | Instruction::SkipEqual { .. }
+            | Instruction::SkipNotEqual { .. }
+            | Instruction::SkipKey(_)
+            | Instruction::SkipNotKey(_) => {
+                format!("if {} then", skip_condition(&instruction, true))
+            }
+            Instruction::LoadByte { x, nn } => format!("v{:x} := 0x{:02X}", x, nn),
+            Instruction::AddByte { x, nn } => format!("v{:x} += 0x{:02X}", x, nn),
+            Instruction::Load { x, y } => format!("v{:x} := v{:x}", x, y),
+            Instruction::Or { x, y } => format!("v{:x} |= v{:x}", x, y),
+            Instruction::And { x, y } => format!("v{:x} &= v{:x}", x, y),
+            Instruction::Xor { x, y } => format!("v{:x} ^= v{:x}", x, y),
+            Instruction::Add { x, y } => format!("v{:x} += v{:x}", x, y),
+            Instruction::Sub { x, y } => format!("v{:x} -= v{:x}", x, y),
+            Instruction::ShiftRight { x, y } => format!("v{:x} >>= v{:x}", x, y),
+            Instruction::SubNegated { x, y } => format!("v{:x} =- v{:x}", x, y),
+            Instruction::ShiftLeft { x, y } => format!("v{:x} <<= v{:x}", x, y),
+            Instruction::LoadIndex(nnn) => format!("i := {}", self.reference(nnn)),
+            Instruction::JumpOffset(nnn) => format!("jump0 {}", self.reference(nnn)),
+            Instruction::Random { x, nn } => format!("v{:x} := random 0x{:02X}", x, nn),
+            Instruction::Draw { x, y, n } => format!("sprite v{:x} v{:x} {}", x, y, n),
+            Instruction::LoadDelay(x) => format!("v{:x} := delay", x),
+            Instruction::WaitKey(x) => format!("v{:x} := key", x),
+            Instruction::SetDelay(x) => format!("delay := v{:x}", x),
+            Instruction::SetSound(x) => format!("buzzer := v{:x}", x),
+            Instruction::AddIndex(x) => format!("i += v{:x}", x),
+            Instruction::LoadFont(x) => format!("i := hex v{:x}", x),
+            Instruction::StoreBcd(x) => format!("bcd v{:x}", x),
+            Instruction::StoreRegisters(x) => format!("save v{:x}", x),
+            Instruction::LoadRegisters(x) => format!("load v{:x}", x),
+            Instruction::ScrollDown(n) => format!("scroll-down {}", n),
+            Instruction::ScrollUp(n) => format!("scroll-up {}", n),
+            Instruction::ScrollRight => String::from("scroll-right"),
+            Instruction::ScrollLeft => String::from("scroll-left"),
+            Instruction::Exit => String::from("exit"),
+            Instruction::LowRes => String::from("lores"),
+            Instruction::HighRes => String::from("hires"),
+            Instruction::StoreRange { x, y } => format!("save v{:x} - v{:x}", x, y),
+            Instruction::LoadRange { x, y } => format!("load v{:x} - v{:x}", x, y),
+            Instruction::LoadIndexLong => {
+                let target = self.disassembly.opcode_at(address + 2).unwrap_or(0);
+                format!("i := long {}", self.reference(target))
+            }
+            Instruction::Plane(n) => format!("plane {}", n),
+            Instruction::Audio => String::from("audio"),
+            Instruction::LoadBigFont(x) => format!("i := bighex v{:x}", x),
+            Instruction::SetPitch(x) => format!("pitch := v{:x}", x),
+            Instruction::StoreFlags(x) => format!("saveflags v{:x}", x),
+            Instruction::LoadFlags(x) => format!("loadflags v{:x}", x),
+            Instruction::Unknown(opcode) => {
+                format!("0x{:02X} 0x{:02X}", opcode >> 8, opcode & 0xFF)
+            }
+        }
+    }
+
+    /// `Decompiler.format()` writes the source : labels, structures indented by their depth,
+    /// data in rows and sprites one row of pixels per line.
+    fn format(&self, items: &BTreeMap<u16, Item>) -> String {
+        let mut ends: BTreeMap<u16, usize> = BTreeMap::new();
+        let mut loops: BTreeMap<u16, usize> = BTreeMap::new();
+        for structure in &self.structures {
+            match *structure {
+                Structure::Loop { start, .. } => *loops.entry(start).or_insert(0) += 1,
+                Structure::If { end, .. } => *ends.entry(end).or_insert(0) += 1,
+            }
+        }
+
+        let mut text = String::new();
+        let mut depth = 0;
+        let mut data: Vec<String> = vec![];
+        let indent = |depth: usize| "  ".repeat(depth + 1);
+        for (&address, item) in items {
+            let starts_block = self.labels.contains(&address)
+                || ends.contains_key(&address)
+                || loops.contains_key(&address);
+            let breaks =
+                starts_block || !matches!(item, Item::Data(_)) || data.len() == DATA_BYTES_PER_LINE;
+            if breaks && !data.is_empty() {
+                text.push_str(&format!("{}{}\n", indent(depth), data.join(" ")));
+                data.clear();
+            }
+            for _ in 0..ends.get(&address).copied().unwrap_or(0) {
+                depth -= 1;
+                text.push_str(&format!("{}end\n", indent(depth)));
+            }
+            if self.labels.contains(&address) {
+                text.push_str(&format!("\n: {}\n", self.name(address)));
+            }
+            for _ in 0..loops.get(&address).copied().unwrap_or(0) {
+                text.push_str(&format!("{}loop\n", indent(depth)));
+                depth += 1;
+            }
+            match item {
+                Item::Statement(statement) => {
+                    if statement == "again" || statement == "else" {
+                        depth -= 1;
+                    }
+                    //the statement an `if ... then` guards goes on the same line
+                    let keyword = statement == "again"
+                        || statement == "else"
+                        || statement.starts_with("if ")
+                        || statement.starts_with("while ");
+                    if text.ends_with(" then\n") && !starts_block && !keyword {
+                        text.pop();
+                        text.push_str(&format!(" {}\n", statement));
+                    } else {
+                        text.push_str(&format!("{}{}\n", indent(depth), statement));
+                    }
+                    if statement.ends_with(" begin") || statement == "else" {
+                        depth += 1;
+                    }
+                }
+                Item::Sprite(byte) => {
+                    text.push_str(&format!("{}0b{:08b}\n", indent(depth), byte));
+                }
+                Item::Data(byte) => data.push(format!("0x{:02X}", byte)),
+            }
+        }
+        if !data.is_empty() {
+            text.push_str(&format!("{}{}\n", indent(depth), data.join(" ")));
+        }
+        let end = self.disassembly.origin + self.disassembly.bytes.len() as u16;
+        for _ in 0..ends.get(&end).copied().unwrap_or(0) {
+            depth -= 1;
+            text.push_str(&format!("{}end\n", indent(depth)));
+        }
+        //the program starts at `main`
+        if !self.labels.contains(&self.disassembly.origin) {
+            text.insert_str(0, &format!(": {}\n", self.name(self.disassembly.origin)));
+        }
+        match self.disassembly.origin {
+            PROGRAM_START => text.trim_start().to_string(),
+            origin => format!(":org 0x{:03X}\n{}", origin, text.trim_start()),
+        }
+    }
+}
+
+/// `skip_condition()` writes the condition under which a skip instruction skips (`executes == false`),
+/// or executes the next instruction (`executes == true`).
+fn skip_condition(instruction: &Instruction, executes: bool) -> String {
+    let (equal, not_equal) = if executes { ("!=", "==") } else { ("==", "!=") };
+    let (pressed, released) = if executes {
+        ("-key", "key")
+    } else {
+        ("key", "-key")
+    };
+    match *instruction {
+        Instruction::SkipEqualByte { x, nn } => format!("v{:x} {} 0x{:02X}", x, equal, nn),
+        Instruction::SkipNotEqualByte { x, nn } => format!("v{:x} {} 0x{:02X}", x, not_equal, nn),
+        Instruction::SkipEqual { x, y } => format!("v{:x} {} v{:x}", x, equal, y),
+        Instruction::SkipNotEqual { x, y } => format!("v{:x} {} v{:x}", x, not_equal, y),
+        Instruction::SkipKey(x) => format!("v{:x} {}", x, pressed),
+        Instruction::SkipNotKey(x) => format!("v{:x} {}", x, released),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::octo;
+
+    /// `assert_round_trip()` checks that the decompiled `rom` compiles back to the same bytes.
+    fn assert_round_trip(rom: &[u8]) {
+        let source = decompile(rom, PROGRAM_START, PROGRAM_START);
+        match octo::compile(&source) {
+            Ok(compiled) => assert_eq!(compiled, rom, "decompiled source :\n{}", source),
+            Err(error) => panic!("{}\ndecompiled source :\n{}", error, source),
+        }
+    }
+
+    #[test]
+    fn compiled_programs_round_trip() {
+        let sources = [
+            //loops, conditions, subroutines and a sprite
+            ": main
+                i := ball
+                v0 := 10 v1 := 5
+                loop
+                    sprite v0 v1 3
+                    v0 += 1
+                    if v0 == 40 then v0 := 0
+                    if v1 != 7 begin v1 += 1 else v1 := 0 end
+                    draw
+                again
+            : draw
+                if v2 key then return
+                v3 := random 0x0F
+                loop
+                    v2 += v3
+                    while vf == 1
+                    v3 += 1
+                again
+                return
+            : ball
+                0b01100000 0b11110000 0b01100000",
+            //every CHIP-8 instruction
+            ": main
+                clear
+                v1 := 2 v2 := 3
+                v1 += 4 v1 := v2 v1 |= v2 v1 &= v2 v1 ^= v2 v1 += v2 v1 -= v2 v1 >>= v2 v1 =- v2 v1 <<= v2
+                if v1 == v2 then v3 := 1
+                if v1 != v2 then v3 := 2
+                if v1 -key then v3 := 3
+                v4 := delay delay := v4 buzzer := v4 v4 := key
+                i := 0x300 i += v4 i := hex v4 bcd v4 save v4 load v4
+                jump0 main",
+            //SUPER-CHIP and XO-CHIP instructions
+            ": main
+                hires lores
+                scroll-down 2 scroll-up 3 scroll-left scroll-right
+                plane 3 audio pitch := v1
+                i := long data
+                save v1 - v3 load v3 - v1
+                saveflags v2 loadflags v2
+                i := bighex v5
+                sprite v0 v1 0
+                exit
+            : data
+                1 2 3 4",
+        ];
+        for source in sources.iter() {
+            let rom = octo::compile(source).unwrap();
+            assert_round_trip(&rom);
+        }
+    }
+
+    #[test]
+    fn decompiled_source_is_stable() {
+        let rom = octo::compile(
+            ": main
+                loop
+                    v0 += 1
+                    if v0 == 3 begin v1 := 1 end
+                again",
+        )
+        .unwrap();
+        let source = decompile(&rom, PROGRAM_START, PROGRAM_START);
+        assert!(source.contains(": main"));
+        assert!(source.contains("loop"));
+        assert!(source.contains("again"));
+        assert_eq!(
+            decompile(
+                &octo::compile(&source).unwrap(),
+                PROGRAM_START,
+                PROGRAM_START
+            ),
+            source
+        );
+    }
+
+    #[test]
+    fn any_bytes_round_trip() {
+        //jumps into data, odd addresses, unknown opcodes and a truncated last opcode
+        let roms: [&[u8]; 4] = [
+            &[0x12, 0x03, 0xFF, 0x60, 0x01, 0x12, 0x03],
+            &[0x50, 0x01, 0xFF, 0xFF, 0x00, 0x00, 0xA2, 0x00],
+            &[0x22, 0x04, 0x12, 0x02, 0x00, 0xEE, 0x81],
+            &[0x3F, 0x00, 0x12, 0x00, 0x13, 0x00],
+        ];
+        for rom in roms.iter() {
+            assert_round_trip(rom);
+        }
+    }
+
+    #[test]
+    fn roms_loaded_elsewhere_compile_at_their_origin() {
+        //V0 := 1, jump to itself, loaded at 0x300 as CHIP-8X does
+        let rom = [0x60, 0x01, 0x13, 0x02];
+        let source = decompile(&rom, 0x300, 0x300);
+        assert!(source.starts_with(":org 0x300\n"), "{}", source);
+        //a jump to the program, then the program at 0x300
+        let mut expected = vec![0x13, 0x00];
+        expected.resize(0x100, 0);
+        expected.extend(rom);
+        assert_eq!(octo::compile(&source).unwrap(), expected);
+    }
+
+    #[test]
+    fn hires_programs_are_decompiled_from_0x2c0() {
+        //the jump to the interpreter, then V0 := 1 and a jump to itself at 0x2C0
+        let mut rom = vec![0x12, 0x60];
+        rom.resize(0xC0, 0);
+        rom.extend([0x60, 0x01, 0x12, 0xC2]);
+        let source = decompile(&rom, PROGRAM_START, PROGRAM_START);
+        assert!(source.contains(": label_2C0\n  v0 := 0x01"), "{}", source);
+        assert_eq!(octo::compile(&source).unwrap(), rom);
+    }
+}
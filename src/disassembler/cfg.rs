@@ -0,0 +1,322 @@
+//! Control-flow graph - the code of a ROM split into basic blocks, linked by jumps, skips and calls.
+//! It is written as a Graphviz DOT graph, to see the game loops of an unfamiliar ROM at a glance :
+//! `dot -Tsvg cfg.dot -o cfg.svg`.
+
+use super::Disassembly;
+use crate::vm::Instruction;
+use log::debug;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// `EdgeKind` : how the program goes from one block to another.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeKind {
+    /// The next instruction, after a block which does not jump (or after a call returns).
+    FallThrough,
+    /// A jump (1NNN).
+    Jump,
+    /// A skip instruction skipping the next instruction.
+    Skip,
+    /// A call (2NNN).
+    Call,
+    /// A computed jump (BNNN), whose target depends on V0.
+    Computed,
+}
+
+/// `Edge` : an edge leaving a block. Computed jumps have no known `target`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edge {
+    pub kind: EdgeKind,
+    pub target: Option<u16>,
+}
+
+/// `BasicBlock` : instructions always executed one after the other, from `start` up to `end` (not included).
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub start: u16,
+    pub end: u16,
+    pub edges: Vec<Edge>,
+}
+
+/// `ControlFlowGraph` : the disassembly and its basic blocks, by start address.
+pub struct ControlFlowGraph {
+    pub disassembly: Disassembly,
+    pub blocks: BTreeMap<u16, BasicBlock>,
+}
+
+impl ControlFlowGraph {
+    /// `ControlFlowGraph::new()` will return the control-flow graph of the instructions found by the disassembly.
+    /// A block starts at the entry point, at every target, after every jump, skip, call or return,
+    /// and after data. It ends right before the next block starts.
+    pub fn new(disassembly: Disassembly) -> ControlFlowGraph {
+        let mut leaders = BTreeSet::new();
+        leaders.insert(disassembly.entry_point);
+        let mut falls_into = BTreeSet::new();
+        for (&address, instruction) in &disassembly.instructions {
+            let next = address + instruction.size();
+            if ends_block(instruction) {
+                leaders.insert(next);
+            } else {
+                falls_into.insert(next);
+            }
+            for edge in edges(&disassembly, address, instruction) {
+                if let (EdgeKind::Jump | EdgeKind::Skip | EdgeKind::Call, Some(target)) =
+                    (edge.kind, edge.target)
+                {
+                    leaders.insert(target);
+                }
+            }
+        }
+        for &address in disassembly.instructions.keys() {
+            if !falls_into.contains(&address) {
+                leaders.insert(address);
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        for &start in &leaders {
+            let mut address = start;
+            while let Some(instruction) = disassembly.instructions.get(&address) {
+                let next = address + instruction.size();
+                let ends_here = ends_block(instruction)
+                    || leaders.contains(&next)
+                    || !disassembly.instructions.contains_key(&next);
+                if ends_here {
+                    blocks.insert(
+                        start,
+                        BasicBlock {
+                            start,
+                            end: next,
+                            edges: edges(&disassembly, address, instruction),
+                        },
+                    );
+                    break;
+                }
+                address = next;
+            }
+        }
+        debug!(
+            "[ControlFlowGraph::new()] Found {} basic blocks.",
+            blocks.len()
+        );
+        ControlFlowGraph {
+            disassembly,
+            blocks,
+        }
+    }
+
+    /// `ControlFlowGraph.to_dot()` writes the graph in the DOT language. Blocks list their instructions,
+    /// targets outside of the code (e.g. a call into the interpreter) are ellipses,
+    /// and computed jumps lead to a `?` node of their own.
+    pub fn to_dot(&self) -> String {
+        let mut text = String::from("digraph cfg {\n");
+        text.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        let mut outside = BTreeSet::new();
+        for block in self.blocks.values() {
+            let mut label = self
+                .disassembly
+                .label(block.start)
+                .map(|name| format!("{}:\\l", name))
+                .unwrap_or_default();
+            let mut address = block.start;
+            while address < block.end {
+                let mnemonic = self.disassembly.mnemonic(address).unwrap_or_default();
+                label.push_str(&format!("0x{:03X}  {}\\l", address, mnemonic));
+                address += self.disassembly.instructions[&address].size();
+            }
+            text.push_str(&format!(
+                "    {} [label=\"{}\"];\n",
+                node(block.start),
+                label
+            ));
+        }
+        for block in self.blocks.values() {
+            for edge in &block.edges {
+                let (target, attributes) = match (edge.kind, edge.target) {
+                    (EdgeKind::Computed, _) => {
+                        let target = format!("computed_{:03X}", block.start);
+                        text.push_str(&format!(
+                            "    {} [label=\"?\", shape=diamond, style=dashed];\n",
+                            target
+                        ));
+                        (target, " [label=\"computed\", style=dashed, color=red]")
+                    }
+                    (_, None) => continue,
+                    (kind, Some(target)) => {
+                        if !self.blocks.contains_key(&target) {
+                            outside.insert(target);
+                        }
+                        let attributes = match kind {
+                            EdgeKind::Jump => " [label=\"jump\"]",
+                            EdgeKind::Skip => " [label=\"skip\", color=blue]",
+                            EdgeKind::Call => " [label=\"call\", style=dashed]",
+                            _ => "",
+                        };
+                        (node(target), attributes)
+                    }
+                };
+                text.push_str(&format!(
+                    "    {} -> {}{};\n",
+                    node(block.start),
+                    target,
+                    attributes
+                ));
+            }
+        }
+        for target in outside {
+            text.push_str(&format!(
+                "    {} [label=\"0x{:03X}\", shape=ellipse];\n",
+                node(target),
+                target
+            ));
+        }
+        text.push_str("}\n");
+        text
+    }
+}
+
+/// `ends_block()` tells if `instruction` is the last of its block : it jumps, skips, calls or returns.
+fn ends_block(instruction: &Instruction) -> bool {
+    instruction.is_skip()
+        || matches!(
+            instruction,
+            Instruction::Jump(_)
+                | Instruction::JumpOffset(_)
+                | Instruction::Call(_)
+                | Instruction::Ret
+                | Instruction::Exit
+        )
+}
+
+/// `edges()` returns the edges leaving the block which ends with `instruction`, at `address`.
+fn edges(disassembly: &Disassembly, address: u16, instruction: &Instruction) -> Vec<Edge> {
+    let next = address + instruction.size();
+    let edge = |kind, target| Edge {
+        kind,
+        target: Some(target),
+    };
+    match *instruction {
+        Instruction::Jump(target) => vec![edge(EdgeKind::Jump, target)],
+        Instruction::JumpOffset(_) => vec![Edge {
+            kind: EdgeKind::Computed,
+            target: None,
+        }],
+        Instruction::Call(target) => vec![
+            edge(EdgeKind::Call, target),
+            edge(EdgeKind::FallThrough, next),
+        ],
+        Instruction::Ret | Instruction::Exit => vec![],
+        _ if instruction.is_skip() => {
+            //a skip jumps over a whole instruction, which is 4 bytes long for `LoadIndexLong`
+            let skipped = disassembly
+                .instructions
+                .get(&next)
+                .map_or(2, |instruction| instruction.size());
+            vec![
+                edge(EdgeKind::FallThrough, next),
+                edge(EdgeKind::Skip, next + skipped),
+            ]
+        }
+        _ => vec![edge(EdgeKind::FallThrough, next)],
+    }
+}
+
+/// `node()` names the DOT node of the block starting at `address`.
+fn node(address: u16) -> String {
+    format!("block_{:03X}", address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::disassemble;
+
+    /// `ROM` calls a subroutine, skips either to a jump or to a jump table (BNNN), and loops back.
+    const ROM: [u8; 16] = [
+        0x22, 0x0A, 0x30, 0x01, 0x12, 0x08, 0xB2, 0x0E, 0x12, 0x00, 0x60, 0x01, 0x00, 0xEE, 0x12,
+        0x0E,
+    ];
+
+    #[test]
+    fn blocks_end_at_branches_calls_and_returns() {
+        let cfg = ControlFlowGraph::new(disassemble(&ROM, 0x200, 0x200));
+        let edge = |kind, target| Edge {
+            kind,
+            target: Some(target),
+        };
+        let blocks: Vec<(u16, u16, Vec<Edge>)> = cfg
+            .blocks
+            .values()
+            .map(|block| (block.start, block.end, block.edges.clone()))
+            .collect();
+        assert_eq!(
+            blocks,
+            [
+                (
+                    0x200,
+                    0x202,
+                    vec![
+                        edge(EdgeKind::Call, 0x20A),
+                        edge(EdgeKind::FallThrough, 0x202)
+                    ]
+                ),
+                (
+                    0x202,
+                    0x204,
+                    vec![
+                        edge(EdgeKind::FallThrough, 0x204),
+                        edge(EdgeKind::Skip, 0x206)
+                    ]
+                ),
+                (0x204, 0x206, vec![edge(EdgeKind::Jump, 0x208)]),
+                (
+                    0x206,
+                    0x208,
+                    vec![Edge {
+                        kind: EdgeKind::Computed,
+                        target: None
+                    }]
+                ),
+                (0x208, 0x20A, vec![edge(EdgeKind::Jump, 0x200)]),
+                (0x20A, 0x20E, vec![]),
+                (0x20E, 0x210, vec![edge(EdgeKind::Jump, 0x20E)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn graphs_are_written_in_dot() {
+        let cfg = ControlFlowGraph::new(disassemble(&ROM, 0x200, 0x200));
+        assert_eq!(
+            cfg.to_dot(),
+            r#"digraph cfg {
+    node [shape=box, fontname="monospace"];
+    block_200 [label="label_200:\l0x200  CALL sub_20A\l"];
+    block_202 [label="0x202  SE V0, 0x01\l"];
+    block_204 [label="0x204  JP label_208\l"];
+    block_206 [label="0x206  JP V0, label_20E\l"];
+    block_208 [label="label_208:\l0x208  JP label_200\l"];
+    block_20A [label="sub_20A:\l0x20A  LD V0, 0x01\l0x20C  RET\l"];
+    block_20E [label="label_20E:\l0x20E  JP label_20E\l"];
+    block_200 -> block_20A [label="call", style=dashed];
+    block_200 -> block_202;
+    block_202 -> block_204;
+    block_202 -> block_206 [label="skip", color=blue];
+    block_204 -> block_208 [label="jump"];
+    computed_206 [label="?", shape=diamond, style=dashed];
+    block_206 -> computed_206 [label="computed", style=dashed, color=red];
+    block_208 -> block_200 [label="jump"];
+    block_20E -> block_20E [label="jump"];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn targets_outside_of_the_code_are_ellipses() {
+        //call into the interpreter, then return
+        let cfg = ControlFlowGraph::new(disassemble(&[0x21, 0x00, 0x00, 0xEE], 0x200, 0x200));
+        let dot = cfg.to_dot();
+        assert!(dot.contains("    block_200 -> block_100 [label=\"call\", style=dashed];\n"));
+        assert!(dot.contains("    block_100 [label=\"0x100\", shape=ellipse];\n"));
+    }
+}
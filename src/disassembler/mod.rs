@@ -0,0 +1,427 @@
+//! Disassembler - turns a ROM back into a listing of addresses, raw bytes and mnemonics.
+//! Code is separated from data by recursive descent : starting at the entry point, every reachable
+//! instruction is followed through jumps, calls and skips. Everything never reached is data.
+//! Jump and call targets get labels, and the bytes drawn by DXYN, as pointed to by the last ANNN before it,
+//! are marked as sprites.
+//! `ControlFlowGraph` splits the code into basic blocks linked by jumps, skips and calls.
+
+mod cfg;
+
+pub use self::cfg::{BasicBlock, ControlFlowGraph, Edge, EdgeKind};
+
+use crate::vm::{self, Instruction};
+use log::debug;
+use std::collections::BTreeMap;
+
+/// Number of data bytes shown on one line of the listing.
+const DATA_BYTES_PER_LINE: usize = 4;
+
+/// `ByteKind` : what a byte of the ROM was found to be.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteKind {
+    /// Never reached by the program, nor drawn.
+    Data,
+    /// Part of a reachable instruction.
+    Code,
+    /// Drawn as a sprite by DXYN.
+    Sprite,
+}
+
+/// `LabelKind` : why an address got a label.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LabelKind {
+    /// Target of a call (2NNN), named `sub_NNN`.
+    Subroutine,
+    /// Target of a jump (1NNN or BNNN), named `label_NNN`.
+    Jump,
+    /// Sprite pointed to by ANNN, named `sprite_NNN`.
+    Sprite,
+    /// Any other address loaded into I, named `data_NNN`.
+    Data,
+}
+
+/// `Disassembly` : the ROM, the kind of each of its bytes, the instructions found and the labels.
+/// `origin` is where the ROM is loaded, and `entry_point` where its code starts.
+pub struct Disassembly {
+    pub origin: u16,
+    pub entry_point: u16,
+    pub bytes: Vec<u8>,
+    pub kinds: Vec<ByteKind>,
+    /// `instructions` holds the reachable instructions, by address.
+    pub instructions: BTreeMap<u16, Instruction>,
+    pub labels: BTreeMap<u16, LabelKind>,
+}
+
+/// `disassemble()` will return the disassembly of a ROM loaded at `origin` and started at `entry_point`.
+/// The code of hi-res CHIP-8 programs starts at 0x2C0, where their interpreter jumps.
+pub fn disassemble(rom: &[u8], origin: u16, entry_point: u16) -> Disassembly {
+    let mut disassembly = Disassembly {
+        origin,
+        entry_point: vm::code_start(rom, origin, entry_point),
+        bytes: rom.to_vec(),
+        kinds: vec![ByteKind::Data; rom.len()],
+        instructions: BTreeMap::new(),
+        labels: BTreeMap::new(),
+    };
+    disassembly.descend();
+    debug!(
+        "[disassemble()] Found {} instructions and {} labels.",
+        disassembly.instructions.len(),
+        disassembly.labels.len()
+    );
+    disassembly
+}
+
+impl Disassembly {
+    /// `Disassembly.descend()` follows every path of the program from the entry point.
+    /// Each path carries the address last loaded into I (if known), to find the sprites drawn by DXYN.
+    fn descend(&mut self) {
+        let mut paths: Vec<(u16, Option<u16>)> = vec![(self.entry_point, None)];
+        let mut sprites: Vec<(u16, u8)> = vec![];
+        while let Some((mut address, mut i)) = paths.pop() {
+            while let Some(opcode) = self.opcode_at(address) {
+                if self.instructions.contains_key(&address) {
+                    break;
+                }
+                let instruction = Instruction::decode(opcode);
+                if let Instruction::Unknown(_) = instruction {
+                    break;
+                }
+                let size = instruction.size();
+                if self.opcode_at(address + size - 2).is_none() {
+                    break;
+                }
+                self.instructions.insert(address, instruction);
+                for byte in address..address + size {
+                    self.kinds[(byte - self.origin) as usize] = ByteKind::Code;
+                }
+
+                let next = address + size;
+                match instruction {
+                    Instruction::Jump(target) => {
+                        self.add_label(target, LabelKind::Jump);
+                        paths.push((target, i));
+                        break;
+                    }
+                    //the jump table starts at NNN, V0 selects the entry
+                    Instruction::JumpOffset(target) => {
+                        self.add_label(target, LabelKind::Jump);
+                        paths.push((target, i));
+                        break;
+                    }
+                    Instruction::Call(target) => {
+                        self.add_label(target, LabelKind::Subroutine);
+                        paths.push((target, i));
+                    }
+                    Instruction::Ret | Instruction::Exit => break,
+                    Instruction::LoadIndex(target) => {
+                        self.add_label(target, LabelKind::Data);
+                        i = Some(target);
+                    }
+                    Instruction::LoadIndexLong => {
+                        let target = self.opcode_at(address + 2).unwrap_or(0);
+                        self.add_label(target, LabelKind::Data);
+                        i = Some(target);
+                    }
+                    Instruction::AddIndex(_)
+                    | Instruction::LoadFont(_)
+                    | Instruction::LoadBigFont(_) => i = None,
+                    Instruction::Draw { n, .. } => {
+                        //DXY0 draws a 16x16 SUPER-CHIP sprite, 32 bytes long
+                        if let Some(i) = i {
+                            sprites.push((i, if n == 0 { 32 } else { n }));
+                        }
+                    }
+                    _ => (),
+                }
+                if instruction.is_skip() {
+                    //a skip jumps over a whole instruction, which is 4 bytes long for `LoadIndexLong`
+                    let skipped = self
+                        .opcode_at(next)
+                        .map_or(2, |opcode| Instruction::decode(opcode).size());
+                    paths.push((next + skipped, i));
+                }
+                address = next;
+            }
+        }
+        //sprites are marked last, code wins over data drawn from it
+        for (start, height) in sprites {
+            for address in start..start + height as u16 {
+                if let Some(kind) = self.kind_at(address) {
+                    if kind == ByteKind::Data {
+                        self.kinds[(address - self.origin) as usize] = ByteKind::Sprite;
+                    }
+                }
+            }
+            if let Some(label) = self.labels.get_mut(&start) {
+                if *label == LabelKind::Data {
+                    *label = LabelKind::Sprite;
+                }
+            }
+        }
+    }
+
+    /// `Disassembly.add_label()` labels `address`. An address used in more than one way keeps the first `LabelKind`,
+    /// e.g. a subroutine which is also jumped to stays a subroutine.
+    fn add_label(&mut self, address: u16, kind: LabelKind) {
+        let label = self.labels.entry(address).or_insert(kind);
+        *label = (*label).min(kind);
+    }
+
+    /// `Disassembly.opcode_at()` returns the opcode at `address`, if both of its bytes are in the ROM.
+    pub fn opcode_at(&self, address: u16) -> Option<u16> {
+        let offset = address.checked_sub(self.origin)? as usize;
+        if offset + 1 < self.bytes.len() {
+            Some((self.bytes[offset] as u16) << 8 | self.bytes[offset + 1] as u16)
+        } else {
+            None
+        }
+    }
+
+    /// `Disassembly.kind_at()` returns the kind of the byte at `address`, if it is in the ROM.
+    pub fn kind_at(&self, address: u16) -> Option<ByteKind> {
+        let offset = address.checked_sub(self.origin)? as usize;
+        self.kinds.get(offset).copied()
+    }
+
+    /// `Disassembly.label()` returns the name of the label at `address`, if there is one.
+    /// Addresses outside of the ROM (e.g. the fonts) are not labeled.
+    pub fn label(&self, address: u16) -> Option<String> {
+        self.kind_at(address)?;
+        self.labels.get(&address).map(|kind| {
+            let prefix = match kind {
+                LabelKind::Subroutine => "sub",
+                LabelKind::Jump => "label",
+                LabelKind::Sprite => "sprite",
+                LabelKind::Data => "data",
+            };
+            format!("{}_{:03X}", prefix, address)
+        })
+    }
+
+    /// `Disassembly.mnemonic()` returns the mnemonic of the instruction at `address`, with its target labeled,
+    /// if an instruction was found there.
+    pub fn mnemonic(&self, address: u16) -> Option<String> {
+        let instruction = self.instructions.get(&address)?;
+        let mut mnemonic = instruction.format(|target| self.label(target));
+        if let Instruction::LoadIndexLong = instruction {
+            let target = self.opcode_at(address + 2).unwrap_or(0);
+            mnemonic.push_str(&format!(
+                " {}",
+                self.label(target)
+                    .unwrap_or_else(|| format!("0x{:04X}", target))
+            ));
+        }
+        Some(mnemonic)
+    }
+
+    /// `Disassembly.listing()` formats one line per instruction and per run of data bytes :
+    /// the address, the raw bytes and the mnemonic (or `db` for data), with labels on lines of their own.
+    /// Sprite bytes get one line each, showing their pixels.
+    pub fn listing(&self) -> String {
+        let mut text = String::new();
+        let end = self.origin as usize + self.bytes.len();
+        let mut address = self.origin as usize;
+        while address < end {
+            if let Some(label) = self.label(address as u16) {
+                text.push_str(&format!("{}:\n", label));
+            }
+            let offset = address - self.origin as usize;
+            if let Some(instruction) = self.instructions.get(&(address as u16)) {
+                let size = instruction.size() as usize;
+                text.push_str(&format_line(
+                    address,
+                    &self.bytes[offset..offset + size],
+                    &self.mnemonic(address as u16).unwrap_or_default(),
+                ));
+                address += size;
+            } else if self.kinds[offset] == ByteKind::Sprite {
+                let byte = self.bytes[offset];
+                let pixels: String = (0..8)
+                    .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+                    .collect();
+                text.push_str(&format_line(
+                    address,
+                    &[byte],
+                    &format!("db 0x{:02X}  ; {}", byte, pixels),
+                ));
+                address += 1;
+            } else {
+                //a run of data ends at the next label, instruction or sprite
+                let mut length = 1;
+                while length < DATA_BYTES_PER_LINE
+                    && address + length < end
+                    && self.kinds[offset + length] == ByteKind::Data
+                    && !self.instructions.contains_key(&((address + length) as u16))
+                    && !self.labels.contains_key(&((address + length) as u16))
+                {
+                    length += 1;
+                }
+                let bytes = &self.bytes[offset..offset + length];
+                let values: Vec<String> =
+                    bytes.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+                text.push_str(&format_line(
+                    address,
+                    bytes,
+                    &format!("db {}", values.join(", ")),
+                ));
+                address += length;
+            }
+        }
+        text
+    }
+}
+
+/// `format_line()` formats a line of the listing : the address, the raw bytes and the text.
+fn format_line(address: usize, bytes: &[u8], text: &str) -> String {
+    let raw: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    format!(
+        "{:03X}: {:<width$}  {}\n",
+        address,
+        raw.join(" "),
+        text,
+        width = DATA_BYTES_PER_LINE * 3 - 1
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_opcode_family_has_its_mnemonic() {
+        let cases: &[(&[u8], &str)] = &[
+            //CHIP-8
+            (&[0x00, 0xE0], "CLS"),
+            (&[0x00, 0xEE], "RET"),
+            (&[0x03, 0x45], "SYS 0x345"),
+            (&[0x13, 0x45], "JP 0x345"),
+            (&[0x23, 0x45], "CALL 0x345"),
+            (&[0x3A, 0x12], "SE VA, 0x12"),
+            (&[0x4A, 0x12], "SNE VA, 0x12"),
+            (&[0x5A, 0xB0], "SE VA, VB"),
+            (&[0x6A, 0x12], "LD VA, 0x12"),
+            (&[0x7A, 0x12], "ADD VA, 0x12"),
+            (&[0x8A, 0xB0], "LD VA, VB"),
+            (&[0x8A, 0xB1], "OR VA, VB"),
+            (&[0x8A, 0xB2], "AND VA, VB"),
+            (&[0x8A, 0xB3], "XOR VA, VB"),
+            (&[0x8A, 0xB4], "ADD VA, VB"),
+            (&[0x8A, 0xB5], "SUB VA, VB"),
+            (&[0x8A, 0xB6], "SHR VA, VB"),
+            (&[0x8A, 0xB7], "SUBN VA, VB"),
+            (&[0x8A, 0xBE], "SHL VA, VB"),
+            (&[0x9A, 0xB0], "SNE VA, VB"),
+            (&[0xA3, 0x45], "LD I, 0x345"),
+            (&[0xB3, 0x45], "JP V0, 0x345"),
+            (&[0xCA, 0x12], "RND VA, 0x12"),
+            (&[0xDA, 0xB5], "DRW VA, VB, 5"),
+            (&[0xEA, 0x9E], "SKP VA"),
+            (&[0xEA, 0xA1], "SKNP VA"),
+            (&[0xFA, 0x07], "LD VA, DT"),
+            (&[0xFA, 0x0A], "LD VA, K"),
+            (&[0xFA, 0x15], "LD DT, VA"),
+            (&[0xFA, 0x18], "LD ST, VA"),
+            (&[0xFA, 0x1E], "ADD I, VA"),
+            (&[0xFA, 0x29], "LD F, VA"),
+            (&[0xFA, 0x33], "LD B, VA"),
+            (&[0xFA, 0x55], "LD [I], VA"),
+            (&[0xFA, 0x65], "LD VA, [I]"),
+            //SUPER-CHIP
+            (&[0x00, 0xC4], "SCD 4"),
+            (&[0x00, 0xFB], "SCR"),
+            (&[0x00, 0xFC], "SCL"),
+            (&[0x00, 0xFD], "EXIT"),
+            (&[0x00, 0xFE], "LOW"),
+            (&[0x00, 0xFF], "HIGH"),
+            (&[0xFA, 0x30], "LD HF, VA"),
+            (&[0xFA, 0x75], "LD R, VA"),
+            (&[0xFA, 0x85], "LD VA, R"),
+            //XO-CHIP
+            (&[0x00, 0xD4], "SCU 4"),
+            (&[0x5A, 0xB2], "LD [I], VA-VB"),
+            (&[0x5A, 0xB3], "LD VA-VB, [I]"),
+            (&[0xF0, 0x00, 0x12, 0x34], "LD I, LONG 0x1234"),
+            (&[0xF3, 0x01], "PLANE 3"),
+            (&[0xF0, 0x02], "AUDIO"),
+            (&[0xFA, 0x3A], "LD PITCH, VA"),
+            //MegaChip, whose instructions are 0NNN opcodes
+            (&[0x00, 0x11], "SYS 0x011"),
+            (&[0x00, 0x10], "SYS 0x010"),
+            (&[0x02, 0x04], "SYS 0x204"),
+            (&[0x07, 0x00], "SYS 0x700"),
+        ];
+        for (rom, mnemonic) in cases {
+            let disassembly = disassemble(rom, 0x200, 0x200);
+            assert_eq!(
+                disassembly.mnemonic(0x200).as_deref(),
+                Some(*mnemonic),
+                "{:02X?}",
+                rom
+            );
+            assert!(disassembly.kinds.iter().all(|&kind| kind == ByteKind::Code));
+        }
+    }
+
+    #[test]
+    fn listings_show_code_sprites_and_data_between_them() {
+        let rom = [
+            0x00, 0xFF, 0xA2, 0x0E, 0xD0, 0x15, 0x22, 0x14, 0xF0, 0x00, 0x02, 0x18, 0x12, 0x1A,
+            0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xAA, 0x50, 0x12, 0x00, 0xEE, 0x01, 0x02, 0x00, 0xFD,
+            0xDE, 0xAD, 0xBE,
+        ];
+        assert_eq!(
+            disassemble(&rom, 0x200, 0x200).listing(),
+            "\
+200: 00 FF        HIGH
+202: A2 0E        LD I, sprite_20E
+204: D0 15        DRW V0, V1, 5
+206: 22 14        CALL sub_214
+208: F0 00 02 18  LD I, LONG data_218
+20C: 12 1A        JP label_21A
+sprite_20E:
+20E: F0           db 0xF0  ; ####....
+20F: 90           db 0x90  ; #..#....
+210: F0           db 0xF0  ; ####....
+211: 90           db 0x90  ; #..#....
+212: F0           db 0xF0  ; ####....
+213: AA           db 0xAA
+sub_214:
+214: 50 12        LD [I], V0-V1
+216: 00 EE        RET
+data_218:
+218: 01 02        db 0x01, 0x02
+label_21A:
+21A: 00 FD        EXIT
+21C: DE AD BE     db 0xDE, 0xAD, 0xBE
+"
+        );
+    }
+
+    #[test]
+    fn skips_follow_both_paths_and_unknown_opcodes_end_them() {
+        //skip over the 4 bytes of LD I, LONG, then an unknown opcode : the rest is data
+        let rom = [
+            0x30, 0x01, 0xF0, 0x00, 0x02, 0x0A, 0x80, 0x08, 0x13, 0x00, 0x60, 0x01,
+        ];
+        let disassembly = disassemble(&rom, 0x200, 0x200);
+        let addresses: Vec<u16> = disassembly.instructions.keys().copied().collect();
+        assert_eq!(addresses, [0x200, 0x202]);
+        assert_eq!(disassembly.kind_at(0x206), Some(ByteKind::Data));
+        assert_eq!(disassembly.kind_at(0x20A), Some(ByteKind::Data));
+        assert_eq!(disassembly.label(0x20A).as_deref(), Some("data_20A"));
+    }
+
+    #[test]
+    fn hi_res_programs_start_at_0x2c0() {
+        //the hi-res interpreter jumps to 0x2C0, the code before it is data
+        let mut rom = vec![0x12, 0x60];
+        rom.resize(0xC0, 0);
+        rom.extend(&[0x00, 0xE0, 0x12, 0xC2]);
+        let disassembly = disassemble(&rom, 0x200, 0x200);
+        assert_eq!(disassembly.entry_point, 0x2C0);
+        let addresses: Vec<u16> = disassembly.instructions.keys().copied().collect();
+        assert_eq!(addresses, [0x2C0, 0x2C2]);
+        assert_eq!(disassembly.kind_at(0x200), Some(ByteKind::Data));
+    }
+}
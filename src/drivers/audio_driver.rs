@@ -0,0 +1,67 @@
+use crate::vm::Sample;
+use log::{debug, warn};
+use sdl2;
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+
+pub struct AudioDriver {
+    /// `audio_subsystem` is missing when there is no sound device : samples are not played then.
+    audio_subsystem: Option<sdl2::AudioSubsystem>,
+    /// `queue` plays the current sample, at its own rate.
+    queue: Option<AudioQueue<u8>>,
+    /// `looping` is the sample queued again when it is about to end.
+    looping: Option<Vec<u8>>,
+}
+
+impl AudioDriver {
+    pub fn new(sdl_context: &sdl2::Sdl) -> Self {
+        let audio_subsystem = sdl_context
+            .audio()
+            .map_err(|error| warn!("[AudioDriver::new()] No sound : {}", error))
+            .ok();
+        AudioDriver {
+            audio_subsystem,
+            queue: None,
+            looping: None,
+        }
+    }
+
+    /// `AudioDriver.play()` stops the sample played, and starts playing `sample`, if any.
+    pub fn play(&mut self, sample: Option<&Sample>) {
+        self.queue = None;
+        self.looping = None;
+        let (audio_subsystem, sample) = match (self.audio_subsystem.as_ref(), sample) {
+            (Some(audio_subsystem), Some(sample)) if sample.rate > 0 => (audio_subsystem, sample),
+            _ => return,
+        };
+        debug!(
+            "[AudioDriver.play()] Playing {} samples at {} Hz.",
+            sample.data.len(),
+            sample.rate
+        );
+        let spec = AudioSpecDesired {
+            freq: Some(sample.rate as i32),
+            channels: Some(1),
+            samples: None,
+        };
+        match audio_subsystem.open_queue::<u8, _>(None, &spec) {
+            Ok(queue) => {
+                queue.queue(&sample.data);
+                queue.resume();
+                self.queue = Some(queue);
+                if sample.looping {
+                    self.looping = Some(sample.data.clone());
+                }
+            }
+            Err(error) => warn!("[AudioDriver.play()] Could not play the sample : {}", error),
+        }
+    }
+
+    /// `AudioDriver.update()` queues a looping sample again, before it ends.
+    pub fn update(&mut self) {
+        if let (Some(queue), Some(data)) = (self.queue.as_ref(), self.looping.as_ref()) {
+            if (queue.size() as usize) < data.len() {
+                queue.queue(data);
+            }
+        }
+    }
+}
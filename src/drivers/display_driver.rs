@@ -1,3 +1,4 @@
+use crate::vm::VRam;
 use log::debug;
 use sdl2;
 use sdl2::pixels;
@@ -6,9 +7,28 @@ use sdl2::render::Canvas;
 use sdl2::video::Window;
 
 const SCALE_FACTOR: u32 = 20;
+/// Colors of the zones of CHIP-8X : black, red, blue, violet, green, yellow, aqua and white.
+const CHIP_8X_COLORS: [[u8; 3]; 8] = [
+    [0, 0, 0],
+    [255, 0, 0],
+    [0, 0, 255],
+    [255, 0, 255],
+    [0, 255, 0],
+    [255, 255, 0],
+    [0, 255, 255],
+    [255, 255, 255],
+];
+/// Colors of the background of CHIP-8X : dark blue, black, green and red.
+const CHIP_8X_BACKGROUNDS: [[u8; 3]; 4] = [[0, 0, 128], [0, 0, 0], [0, 128, 0], [128, 0, 0]];
+/// Colors of the pixels set on the second plane of XO-CHIP only, and on both planes.
+const SECOND_PLANE_COLOR: [u8; 3] = [255, 102, 0];
+const BOTH_PLANES_COLOR: [u8; 3] = [102, 34, 0];
 
 pub struct DisplayDriver {
     canvas: Canvas<Window>,
+    /// `background` and `foreground` are the colors of the pixels which are off and on.
+    background: pixels::Color,
+    foreground: pixels::Color,
 }
 
 impl DisplayDriver {
@@ -27,21 +47,68 @@ impl DisplayDriver {
         canvas.present();
 
         //return
-        DisplayDriver { canvas }
+        DisplayDriver {
+            canvas,
+            background: pixels::Color::RGB(0, 0, 0),
+            foreground: pixels::Color::RGB(0, 255, 0),
+        }
+    }
+
+    /// `DisplayDriver.set_title()` names the window after the ROM.
+    pub fn set_title(&mut self, title: &str) {
+        self.canvas
+            .window_mut()
+            .set_title(&format!("{} - CHIP-8 VM", title))
+            .expect("Error: Could not set window title.");
+    }
+
+    /// `DisplayDriver.set_colors()` changes the colors of the pixels, the next time the canvas is drawn.
+    pub fn set_colors(&mut self, background: [u8; 3], foreground: [u8; 3]) {
+        self.background = pixels::Color::RGB(background[0], background[1], background[2]);
+        self.foreground = pixels::Color::RGB(foreground[0], foreground[1], foreground[2]);
     }
 
-    pub fn draw_canvas(&mut self, vram: &Vec<Vec<u8>>) {
+    /// `DisplayDriver.draw_canvas()` draws the VRAM with square pixels, as big as fit in the window.
+    /// A VRAM of another size than the window (e.g. 64x64 for hi-res CHIP-8) is centered.
+    /// The colors of CHIP-8X replace the colors of the pixels, and MegaChip shows the colors of its last frame.
+    pub fn draw_canvas(&mut self, vram: &VRam) {
+        let cells = &vram.cells;
         debug!("[DisplayDriver.draw_canvas()] Started drawing canvas.");
-        for (h, row) in vram.iter().enumerate() {
+        let (window_width, window_height) = self
+            .canvas
+            .output_size()
+            .expect("Error: Could not get the size of the window.");
+        let vram_height = cells.len().max(1) as u32;
+        let vram_width = cells.first().map_or(1, |row| row.len().max(1)) as u32;
+        let scale = (window_width / vram_width)
+            .min(window_height / vram_height)
+            .max(1);
+        let left = (window_width.saturating_sub(vram_width * scale) / 2) as i32;
+        let top = (window_height.saturating_sub(vram_height * scale) / 2) as i32;
+
+        self.canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        for (h, row) in cells.iter().enumerate() {
             for (w, col) in row.iter().enumerate() {
                 //set color to draw
-                self.canvas.set_draw_color(DisplayDriver::get_color(col));
+                let color = match (vram.mega.as_ref(), vram.colors.as_ref()) {
+                    (Some(screen), _) => {
+                        DisplayDriver::get_megachip_color(screen.frame[h][w], screen.alpha)
+                    }
+                    (None, Some(colors)) => DisplayDriver::get_chip8x_color(
+                        col,
+                        colors.background,
+                        colors.zones[h][w / 8],
+                    ),
+                    (None, None) => self.get_color(col),
+                };
+                self.canvas.set_draw_color(color);
 
                 //draw a rectangle for each pixel
-                let h = (h as u32) * SCALE_FACTOR;
-                let w = (w as u32) * SCALE_FACTOR;
+                let h = top + (h as u32 * scale) as i32;
+                let w = left + (w as u32 * scale) as i32;
                 self.canvas
-                    .fill_rect(Rect::new(w as i32, h as i32, SCALE_FACTOR, SCALE_FACTOR))
+                    .fill_rect(Rect::new(w, h, scale, scale))
                     .expect("Error : Could not draw pixel.");
             }
         }
@@ -77,10 +144,29 @@ impl DisplayDriver {
         canvas
     }
 
-    fn get_color(pixel: &u8) -> pixels::Color {
-        match pixel {
-            0 => pixels::Color::RGB(0, 0, 0),
-            _ => pixels::Color::RGB(0, 255, 0),
+    /// `DisplayDriver.get_color()` returns the color of a pixel : a pixel holds a bit for each plane of XO-CHIP,
+    /// and other machines only use the first one.
+    fn get_color(&self, pixel: &u8) -> pixels::Color {
+        let rgb = |[r, g, b]: [u8; 3]| pixels::Color::RGB(r, g, b);
+        match pixel & 0x3 {
+            0 => self.background,
+            1 => self.foreground,
+            2 => rgb(SECOND_PLANE_COLOR),
+            _ => rgb(BOTH_PLANES_COLOR),
         }
     }
+
+    fn get_chip8x_color(pixel: &u8, background: u8, zone: u8) -> pixels::Color {
+        let [r, g, b] = match pixel {
+            0 => CHIP_8X_BACKGROUNDS[background as usize % 4],
+            _ => CHIP_8X_COLORS[zone as usize % 8],
+        };
+        pixels::Color::RGB(r, g, b)
+    }
+
+    /// `DisplayDriver::get_megachip_color()` returns an ARGB color of MegaChip, faded by the alpha of the screen.
+    fn get_megachip_color(argb: u32, alpha: u8) -> pixels::Color {
+        let channel = |shift: u32| ((argb >> shift & 0xFF) * alpha as u32 / 255) as u8;
+        pixels::Color::RGB(channel(16), channel(8), channel(0))
+    }
 }
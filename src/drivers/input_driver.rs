@@ -1,10 +1,12 @@
-use log::info;
+use log::{info, warn};
 use sdl2;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 
 pub struct InputDriver {
     event_pump: sdl2::EventPump,
+    /// `bindings` map more keyboard keys to CHIP-8 keys, before the default layout.
+    bindings: Vec<(Keycode, usize)>,
 }
 
 impl InputDriver {
@@ -13,9 +15,23 @@ impl InputDriver {
             event_pump: sdl_context
                 .event_pump()
                 .expect("Error : Could not initialize event pump."),
+            bindings: vec![],
         }
     }
 
+    /// `InputDriver.bind()` maps the keyboard key called `key_name` (its SDL name, e.g. `Space`) to a CHIP-8 key.
+    pub fn bind(&mut self, key_name: &str, chip8_key: usize) {
+        match Keycode::from_name(key_name) {
+            Some(keycode) => self.bindings.push((keycode, chip8_key)),
+            None => warn!(
+                "[InputDriver.bind()] There is no key called {}, it is not bound.",
+                key_name
+            ),
+        }
+    }
+
+    /// `InputDriver.process_events()` returns the 16 keys of the keypad, followed by the 16 keys of the second
+    /// keypad of CHIP-8X (0x10 to 0x1f), on the numeric keypad of the keyboard.
     pub fn process_events(&mut self) -> Result<Vec<bool>, ()> {
         //Quit if user wants to quit
         for event in self.event_pump.poll_iter() {
@@ -41,9 +57,14 @@ impl InputDriver {
             .pressed_scancodes()
             .filter_map(Keycode::from_scancode)
             .collect();
-        let mut chip8_keys = vec![false; 16];
+        let mut chip8_keys = vec![false; 32];
         for key in keys {
-            let index = match key {
+            let bound = self
+                .bindings
+                .iter()
+                .find(|&&(keycode, _)| keycode == key)
+                .map(|&(_, chip8_key)| chip8_key);
+            let index = bound.or(match key {
                 Keycode::Num1 => Some(0x1),
                 Keycode::Num2 => Some(0x2),
                 Keycode::Num3 => Some(0x3),
@@ -64,8 +85,24 @@ impl InputDriver {
                 Keycode::Down => Some(0x8),
                 Keycode::Left => Some(0x4),
                 Keycode::Right => Some(0x6),
+                Keycode::Kp7 => Some(0x11),
+                Keycode::Kp8 => Some(0x12),
+                Keycode::Kp9 => Some(0x13),
+                Keycode::KpDivide => Some(0x1c),
+                Keycode::Kp4 => Some(0x14),
+                Keycode::Kp5 => Some(0x15),
+                Keycode::Kp6 => Some(0x16),
+                Keycode::KpMultiply => Some(0x1d),
+                Keycode::Kp1 => Some(0x17),
+                Keycode::Kp2 => Some(0x18),
+                Keycode::Kp3 => Some(0x19),
+                Keycode::KpMinus => Some(0x1e),
+                Keycode::Kp0 => Some(0x1a),
+                Keycode::KpPeriod => Some(0x10),
+                Keycode::KpEnter => Some(0x1b),
+                Keycode::KpPlus => Some(0x1f),
                 _ => None,
-            };
+            });
             if let Some(i) = index {
                 info!("[InputDriver.process_events()] Some key = {} was read.", i);
                 chip8_keys[i] = true;
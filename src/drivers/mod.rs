@@ -1,13 +1,18 @@
+mod audio_driver;
 mod display_driver;
 mod input_driver;
 mod rom_reader;
 
+use audio_driver::AudioDriver;
 use display_driver::DisplayDriver;
 use input_driver::InputDriver;
 use rom_reader::RomReader;
+
+pub use rom_reader::{load_rom, max_rom_size, Rom, RomError, MAX_MEGACHIP_ROM_SIZE, MAX_ROM_SIZE};
 use sdl2;
 
 pub struct Drivers {
+    pub audio_driver: AudioDriver,
     pub display_driver: DisplayDriver,
     pub input_driver: InputDriver,
     pub rom_reader: RomReader,
@@ -18,6 +23,7 @@ impl Drivers {
         let sdl_context = Drivers::init_sdl_context();
         //return
         Drivers {
+            audio_driver: AudioDriver::new(&sdl_context),
             display_driver: DisplayDriver::new(&sdl_context, 64, 32),
             input_driver: InputDriver::new(&sdl_context),
             rom_reader: RomReader::new(),
@@ -0,0 +1,192 @@
+//! Octo cartridges - GIF images (a picture of a cartridge, with a label) which carry the Octo source of a program
+//! and the options to run it with. The payload is hidden in the color indices of the first frame : every pixel holds
+//! 2 bits in its 2 least significant bits, most significant first, so 4 pixels make a byte. The first 4 bytes are
+//! the length of the rest (big-endian), a UTF-8 JSON object :
+//!
+//! ```text
+//! { "program": ": main ...", "options": { "tickrate": 20, "fillColor": "#FFCC00", "shiftQuirks": true, ... } }
+//! ```
+
+use super::gif;
+use crate::database::json::{self, Value};
+use crate::database::{parse_color, RomSettings};
+use crate::vm::Platform;
+
+/// `Cartridge` : the Octo source of a program, and how to run it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cartridge {
+    pub program: String,
+    pub settings: RomSettings,
+}
+
+/// `read_cartridge()` will return the program and the options of an Octo cartridge.
+pub fn read_cartridge(data: &[u8]) -> Result<Cartridge, String> {
+    let frame = gif::first_frame(data)?;
+    let bytes: Vec<u8> = frame
+        .pixels
+        .chunks_exact(4)
+        .map(|pixels| pixels.iter().fold(0, |byte, pixel| byte << 2 | (pixel & 3)))
+        .collect();
+    if bytes.len() < 4 {
+        return Err(String::from("the image is too small to be a cartridge"));
+    }
+    let size = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    let payload = bytes
+        .get(4..4 + size)
+        .ok_or_else(|| String::from("the image holds no cartridge"))?;
+    let payload = std::str::from_utf8(payload)
+        .map_err(|_| String::from("the cartridge payload is not text"))?;
+    let document = json::parse(payload)
+        .map_err(|(line, message)| format!("cartridge payload {}: {}", line, message))?;
+
+    let program = document
+        .as_object()
+        .and_then(|members| members.get("program"))
+        .and_then(Value::as_str)
+        .ok_or_else(|| String::from("the cartridge has no program"))?
+        .to_string();
+    let settings = match document
+        .as_object()
+        .and_then(|members| members.get("options"))
+    {
+        Some(options) => read_options(options),
+        None => RomSettings::default(),
+    };
+    Ok(Cartridge { program, settings })
+}
+
+/// `read_options()` translates the options of Octo into settings. Options this machine has no use for
+/// (e.g. `screenRotation`) and invalid ones are ignored, as Octo does.
+fn read_options(options: &Value) -> RomSettings {
+    let mut settings = RomSettings::default();
+    let options = match options.as_object() {
+        Some(options) => options,
+        None => return settings,
+    };
+    if let Some(tick_rate) = options
+        .get("tickrate")
+        .and_then(Value::as_f64)
+        .filter(|&rate| rate >= 1.0)
+    {
+        settings.tick_rate = Some(tick_rate as u32);
+    }
+    let color = |name: &str| {
+        options
+            .get(name)
+            .and_then(Value::as_str)
+            .and_then(parse_color)
+    };
+    settings.background = color("backgroundColor");
+    settings.foreground = color("fillColor");
+    //Octo names the quirks after the behaviour of SUPER-CHIP, which is the opposite of some of ours
+    for (option, quirk, same) in [
+        ("shiftQuirks", "shift_vy", false),
+        ("loadStoreQuirks", "load_store_increments_i", false),
+        ("jumpQuirks", "jump_vx", true),
+        ("logicQuirks", "logic_resets_vf", true),
+        ("clipQuirks", "clip_sprites", true),
+    ] {
+        if let Some(value) = options.get(option).and_then(Value::as_bool) {
+            settings.quirks.push((quirk.to_string(), value == same));
+        }
+    }
+    //the memory size tells the platform
+    settings.platform = match options.get("maxSize").and_then(Value::as_f64) {
+        Some(size) if size <= 3216.0 => Some(Platform::SuperChip),
+        Some(size) if size <= 3584.0 => Some(Platform::Chip8),
+        Some(_) => Some(Platform::XoChip),
+        None => None,
+    };
+    settings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::rom_reader::{decode, MAX_ROM_SIZE};
+
+    /// `encode()` will return a GIF image of a single row holding `payload` as a cartridge : its length, then
+    /// itself, 2 bits per pixel. The pixels are written uncompressed, a clear code every 2 pixels keeps the codes
+    /// 3 bits long.
+    fn encode(payload: &str) -> Vec<u8> {
+        let mut bytes = (payload.len() as u32).to_be_bytes().to_vec();
+        bytes.extend(payload.as_bytes());
+        let pixels: Vec<u8> = bytes
+            .iter()
+            .flat_map(|byte| (0..4).rev().map(move |pair| byte >> (pair * 2) & 3))
+            .collect();
+
+        let mut codes = vec![];
+        for pair in pixels.chunks(2) {
+            codes.push(4);
+            codes.extend(pair);
+        }
+        codes.push(5);
+        let mut compressed = vec![0u8; (codes.len() * 3).div_ceil(8)];
+        for (index, &code) in codes.iter().enumerate() {
+            for bit in 0..3 {
+                let position = index * 3 + bit;
+                compressed[position / 8] |= (code >> bit & 1) << (position % 8);
+            }
+        }
+
+        let width = (pixels.len() as u16).to_le_bytes();
+        let mut image = b"GIF89a".to_vec();
+        image.extend(&[width[0], width[1], 1, 0, 0x81, 0, 0]);
+        image.extend(&[
+            0, 0, 0, 0x55, 0x55, 0x55, 0xAA, 0xAA, 0xAA, 0xFF, 0xFF, 0xFF,
+        ]);
+        image.extend(&[0x2C, 0, 0, 0, 0, width[0], width[1], 1, 0, 0, 2]);
+        for block in compressed.chunks(255) {
+            image.push(block.len() as u8);
+            image.extend(block);
+        }
+        image.extend(&[0, 0x3B]);
+        image
+    }
+
+    #[test]
+    fn cartridges_hold_the_program_and_its_options() {
+        let image = encode(
+            r##"{"program": ": main v0 := 5 v1 := 0x2A", "options": {"tickrate": 20,
+            "backgroundColor": "#112233", "fillColor": "#FFCC00", "shiftQuirks": true,
+            "loadStoreQuirks": false, "jumpQuirks": true, "logicQuirks": false, "clipQuirks": true,
+            "maxSize": 3216, "screenRotation": 90}}"##,
+        );
+        let cartridge = read_cartridge(&image).unwrap();
+        assert_eq!(cartridge.program, ": main v0 := 5 v1 := 0x2A");
+        let settings = &cartridge.settings;
+        assert_eq!(settings.tick_rate, Some(20));
+        assert_eq!(settings.background, Some([0x11, 0x22, 0x33]));
+        assert_eq!(settings.foreground, Some([0xFF, 0xCC, 0x00]));
+        assert_eq!(settings.platform, Some(Platform::SuperChip));
+        let quirks: Vec<(&str, bool)> = settings
+            .quirks
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+            .collect();
+        assert_eq!(
+            quirks,
+            [
+                ("shift_vy", false),
+                ("load_store_increments_i", true),
+                ("jump_vx", true),
+                ("logic_resets_vf", false),
+                ("clip_sprites", true),
+            ]
+        );
+
+        //the program is compiled when the cartridge is loaded
+        let rom = decode("game.gif", image, MAX_ROM_SIZE).unwrap();
+        assert_eq!(rom.bytes, [0x60, 0x05, 0x61, 0x2A]);
+        assert_eq!(rom.settings.as_ref(), Some(settings));
+    }
+
+    #[test]
+    fn cartridges_without_options_get_default_settings() {
+        let cartridge = read_cartridge(&encode(r#"{"program": ": main"}"#)).unwrap();
+        assert_eq!(cartridge.settings, RomSettings::default());
+        assert!(read_cartridge(&encode(r#"{"options": {}}"#)).is_err());
+        assert!(read_cartridge(&encode("not json")).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+//! GIF - a decoder for the first frame of GIF87a and GIF89a images, as color indices.
+
+/// `Frame` : the color index of every pixel of an image, row by row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+/// `is_gif()` returns true when `data` starts like a GIF image.
+pub fn is_gif(data: &[u8]) -> bool {
+    data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a")
+}
+
+/// `first_frame()` will return the first image of a GIF, or the reason it could not be decoded.
+pub fn first_frame(data: &[u8]) -> Result<Frame, String> {
+    if !is_gif(data) {
+        return Err(String::from("not a GIF image"));
+    }
+    let byte = |position: usize| {
+        data.get(position)
+            .copied()
+            .ok_or_else(|| String::from("the image is truncated"))
+    };
+    let word = |position: usize| -> Result<usize, String> {
+        Ok(byte(position)? as usize | (byte(position + 1)? as usize) << 8)
+    };
+
+    //the logical screen descriptor, and the global color table
+    let flags = byte(10)?;
+    let mut position = 13;
+    if flags & 0x80 != 0 {
+        position += 3 << ((flags & 0x07) + 1);
+    }
+
+    //extensions come before the image, they are skipped
+    loop {
+        match byte(position)? {
+            0x21 => {
+                position += 2;
+                position = skip_sub_blocks(data, position)?;
+            }
+            0x2C => break,
+            0x3B => return Err(String::from("the image has no frame")),
+            other => return Err(format!("unexpected block 0x{:02X}", other)),
+        }
+    }
+
+    //the image descriptor, and the local color table
+    let width = word(position + 5)?;
+    let height = word(position + 7)?;
+    let flags = byte(position + 9)?;
+    position += 10;
+    if flags & 0x80 != 0 {
+        position += 3 << ((flags & 0x07) + 1);
+    }
+    let min_code_size = byte(position)?;
+    if !(2..=8).contains(&min_code_size) {
+        return Err(format!("invalid LZW code size {}", min_code_size));
+    }
+    let mut compressed = vec![];
+    let end = skip_sub_blocks(data, position + 1)?;
+    let mut block = position + 1;
+    while block < end - 1 {
+        let size = data[block] as usize;
+        compressed.extend_from_slice(&data[block + 1..block + 1 + size]);
+        block += 1 + size;
+    }
+
+    let mut pixels = decompress(&compressed, min_code_size, width * height)?;
+    if pixels.len() < width * height {
+        return Err(String::from("the image data is truncated"));
+    }
+    pixels.truncate(width * height);
+    if flags & 0x40 != 0 {
+        pixels = deinterlace(&pixels, width, height);
+    }
+    Ok(Frame {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// `skip_sub_blocks()` returns the position after the data sub-blocks starting at `position`.
+fn skip_sub_blocks(data: &[u8], mut position: usize) -> Result<usize, String> {
+    loop {
+        let size = *data
+            .get(position)
+            .ok_or_else(|| String::from("the image is truncated"))? as usize;
+        position += 1 + size;
+        if size == 0 {
+            return Ok(position);
+        }
+        if position > data.len() {
+            return Err(String::from("the image is truncated"));
+        }
+    }
+}
+
+/// `decompress()` will return the color indices compressed with the variable-length LZW of GIF, stopping after
+/// the `size` pixels of the image.
+fn decompress(data: &[u8], min_code_size: u8, size: usize) -> Result<Vec<u8>, String> {
+    let clear = 1usize << min_code_size;
+    let end = clear + 1;
+    let roots = || -> Vec<Vec<u8>> {
+        (0..clear)
+            .map(|index| vec![index as u8])
+            .chain([vec![], vec![]])
+            .collect()
+    };
+    let mut table = roots();
+    let mut code_size = min_code_size as usize + 1;
+    let mut previous: Option<usize> = None;
+    let mut pixels = vec![];
+
+    //codes are packed from the least significant bit
+    let mut bit = 0;
+    while bit + code_size <= data.len() * 8 && pixels.len() < size {
+        let mut code = 0;
+        for offset in 0..code_size {
+            let position = bit + offset;
+            code |= ((data[position / 8] >> (position % 8)) as usize & 1) << offset;
+        }
+        bit += code_size;
+
+        if code == clear {
+            table = roots();
+            code_size = min_code_size as usize + 1;
+            previous = None;
+            continue;
+        }
+        if code == end {
+            break;
+        }
+        let entry = match (table.get(code), previous) {
+            (Some(entry), _) => entry.clone(),
+            (None, Some(previous)) if code == table.len() => {
+                let mut entry = table[previous].clone();
+                entry.push(table[previous][0]);
+                entry
+            }
+            _ => return Err(format!("invalid LZW code {}", code)),
+        };
+        pixels.extend_from_slice(&entry);
+        if let Some(previous) = previous {
+            if table.len() < 4096 {
+                let mut new_entry = table[previous].clone();
+                new_entry.push(entry[0]);
+                table.push(new_entry);
+            }
+        }
+        if table.len() == 1 << code_size && code_size < 12 {
+            code_size += 1;
+        }
+        previous = Some(code);
+    }
+    Ok(pixels)
+}
+
+/// `deinterlace()` puts the rows of an interlaced image back in order.
+fn deinterlace(pixels: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut rows = vec![];
+    for (start, step) in [(0, 8), (4, 8), (2, 4), (1, 2)] {
+        rows.extend((start..height).step_by(step));
+    }
+    let mut ordered = vec![0; pixels.len()];
+    for (index, row) in rows.into_iter().enumerate() {
+        ordered[row * width..(row + 1) * width]
+            .copy_from_slice(&pixels[index * width..(index + 1) * width]);
+    }
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 4x2 image, with a global color table of 4 colors and a graphic control extension.
+    const IMAGE: [u8; 52] = [
+        0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x04, 0x00, 0x02, 0x00, 0x81, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0xFF, 0x00, 0x21, 0xF9, 0x04, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x2C, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x02, 0x00, 0x00, 0x02, 0x05,
+        0x44, 0x34, 0x23, 0x01, 0x05, 0x00, 0x3B,
+    ];
+
+    #[test]
+    fn decodes_the_first_frame() {
+        assert!(is_gif(&IMAGE));
+        assert_eq!(
+            first_frame(&IMAGE).unwrap(),
+            Frame {
+                width: 4,
+                height: 2,
+                pixels: vec![0, 1, 2, 3, 3, 2, 1, 0],
+            }
+        );
+    }
+
+    #[test]
+    fn truncated_or_corrupted_images_are_errors() {
+        //the image is complete without its trailer
+        for length in 0..IMAGE.len() - 1 {
+            assert!(first_frame(&IMAGE[..length]).is_err(), "{}", length);
+        }
+        for position in 0..IMAGE.len() {
+            for bits in [0x01, 0x80, 0xFF] {
+                let mut corrupted = IMAGE;
+                corrupted[position] ^= bits;
+                let _ = first_frame(&corrupted);
+            }
+        }
+        assert!(first_frame(b"GIF89a\x04\x00\x02\x00\x00\x00\x00\x3B").is_err());
+    }
+}
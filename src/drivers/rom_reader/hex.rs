@@ -0,0 +1,166 @@
+//! Hex - readers for ROMs written as text : Intel HEX records (`:10020000...`), and plain hexadecimal dumps
+//! (`00 E0 A2 2A`, `0x00, 0xE0`, `00E0A22A`, optionally with an address at the start of the lines, `0200: 00 E0`).
+
+/// `is_intel_hex()` returns true when `data` is text starting with a record.
+pub fn is_intel_hex(data: &[u8]) -> bool {
+    data.iter().all(u8::is_ascii)
+        && data
+            .iter()
+            .find(|byte| !byte.is_ascii_whitespace())
+            .is_some_and(|&byte| byte == b':')
+}
+
+/// `is_hex_dump()` returns true when `data` is only hexadecimal digits, separators and `0x` prefixes.
+pub fn is_hex_dump(data: &[u8]) -> bool {
+    data.iter().any(u8::is_ascii_hexdigit)
+        && data
+            .iter()
+            .all(|&byte| byte.is_ascii_hexdigit() || b" \t\r\n,:xX".contains(&byte))
+}
+
+/// `read_intel_hex()` will return the bytes of Intel HEX records, with the gaps between records filled with 0.
+/// The addresses are memory addresses when they start at 0x200 or above, and offsets in the ROM otherwise.
+/// Records beyond a ROM of `max_size` bytes loaded at 0x200 are rejected.
+pub fn read_intel_hex(text: &str, max_size: usize) -> Result<Vec<u8>, String> {
+    let mut chunks: Vec<(usize, Vec<u8>)> = vec![];
+    let mut base = 0;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = |reason: &str| format!("line {}: {}", number + 1, reason);
+        let record = line
+            .strip_prefix(':')
+            .filter(|digits| digits.len().is_multiple_of(2))
+            .and_then(|digits| parse_hex(digits).ok())
+            .filter(|record| record.len() >= 5 && record.len() == 5 + record[0] as usize)
+            .ok_or_else(|| invalid("invalid record"))?;
+        if record.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) != 0 {
+            return Err(invalid("wrong checksum"));
+        }
+        let address = (record[1] as usize) << 8 | record[2] as usize;
+        let data = &record[4..record.len() - 1];
+        match record[3] {
+            0x00 if base + address + data.len() > 0x200 + max_size => {
+                return Err(invalid("address beyond the largest ROM"))
+            }
+            0x00 => chunks.push((base + address, data.to_vec())),
+            0x01 => break,
+            0x02 if data.len() == 2 => base = ((data[0] as usize) << 8 | data[1] as usize) << 4,
+            0x04 if data.len() == 2 => base = ((data[0] as usize) << 8 | data[1] as usize) << 16,
+            //start addresses
+            0x03 | 0x05 => (),
+            _ => return Err(invalid("invalid record type")),
+        }
+    }
+
+    let start = chunks
+        .iter()
+        .map(|&(address, _)| address)
+        .min()
+        .unwrap_or(0);
+    let start = if start >= 0x200 { 0x200 } else { 0 };
+    let mut rom = vec![];
+    for (address, data) in chunks {
+        let offset = address - start;
+        if rom.len() < offset + data.len() {
+            rom.resize(offset + data.len(), 0);
+        }
+        rom[offset..offset + data.len()].copy_from_slice(&data);
+    }
+    Ok(rom)
+}
+
+/// `read_hex_dump()` will return the bytes of a hexadecimal dump.
+pub fn read_hex_dump(text: &str) -> Result<Vec<u8>, String> {
+    let mut rom = vec![];
+    for (number, line) in text.lines().enumerate() {
+        //an address ends with a colon
+        let line = match line.split_once(':') {
+            Some((_, bytes)) => bytes,
+            None => line,
+        };
+        for token in line.split(|c: char| c.is_ascii_whitespace() || c == ',') {
+            let digits = token
+                .strip_prefix("0x")
+                .or_else(|| token.strip_prefix("0X"))
+                .unwrap_or(token);
+            rom.extend(parse_hex(digits).map_err(|_| {
+                format!("line {}: `{}` is not hexadecimal bytes", number + 1, token)
+            })?);
+        }
+    }
+    Ok(rom)
+}
+
+/// `parse_hex()` reads bytes written as pairs of hexadecimal digits.
+fn parse_hex(digits: &str) -> Result<Vec<u8>, ()> {
+    if !digits.len().is_multiple_of(2) || !digits.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(());
+    }
+    Ok((0..digits.len())
+        .step_by(2)
+        .map(|index| {
+            u8::from_str_radix(&digits[index..index + 2], 16)
+                .expect("Error: Invalid hexadecimal digits.")
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_intel_hex_records_at_memory_addresses() {
+        let text = ":0402000000E0A22A4E\n:020208001200E2\n:00000001FF\n";
+        assert!(is_intel_hex(text.as_bytes()));
+        assert_eq!(
+            read_intel_hex(text, 3584).unwrap(),
+            [0x00, 0xE0, 0xA2, 0x2A, 0x00, 0x00, 0x00, 0x00, 0x12, 0x00]
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_checksums_and_invalid_records() {
+        assert_eq!(
+            read_intel_hex(":0402000000E0A22A4F\n", 3584),
+            Err(String::from("line 1: wrong checksum"))
+        );
+        assert_eq!(
+            read_intel_hex("\n:0402000000E0A22A\n", 3584),
+            Err(String::from("line 2: invalid record"))
+        );
+        assert!(read_intel_hex(":0402000000E0A2\n", 3584).is_err());
+    }
+
+    #[test]
+    fn rejects_addresses_beyond_the_largest_rom() {
+        //an extended linear address of 0x1000000, then a byte at its start
+        let text = ":020000040100F9\n:01000000FF00\n";
+        assert_eq!(
+            read_intel_hex(text, 3584),
+            Err(String::from("line 2: address beyond the largest ROM"))
+        );
+        assert_eq!(
+            read_intel_hex(":0402000000E0A22A4E\n", 3).unwrap_err(),
+            "line 1: address beyond the largest ROM"
+        );
+    }
+
+    #[test]
+    fn reads_hex_dumps() {
+        for text in [
+            "00 E0 A2 2A",
+            "0x00, 0xE0,\n0xA2, 0x2A",
+            "00E0A22A",
+            "0200: 00 E0\n0202: A2 2A\n",
+        ] {
+            assert!(is_hex_dump(text.as_bytes()), "{}", text);
+            assert_eq!(read_hex_dump(text).unwrap(), [0x00, 0xE0, 0xA2, 0x2A]);
+        }
+        assert!(!is_hex_dump(b"00 E0 zz"));
+        assert!(read_hex_dump("00 E").is_err());
+    }
+}
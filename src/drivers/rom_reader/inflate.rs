@@ -0,0 +1,310 @@
+//! Inflate - a decompressor for DEFLATE streams (RFC 1951), the compression of zip archives.
+
+/// `inflate()` will return the decompressed `data`, or the reason it could not be decompressed.
+/// Data which would decompress to more than `max_size` bytes is rejected.
+pub fn inflate(data: &[u8], max_size: usize) -> Result<Vec<u8>, String> {
+    let mut input = Bits { data, position: 0 };
+    let mut output = Output {
+        bytes: vec![],
+        max_size,
+    };
+    loop {
+        let last = input.read(1)? == 1;
+        match input.read(2)? {
+            0 => stored_block(&mut input, &mut output)?,
+            1 => {
+                let mut lengths = [8u8; 288];
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                compressed_block(&mut input, &mut output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_tables(&mut input)?;
+                compressed_block(&mut input, &mut output, &literals, &distances)?;
+            }
+            _ => return Err(String::from("invalid block type")),
+        }
+        if last {
+            return Ok(output.bytes);
+        }
+    }
+}
+
+/// `Output` : the decompressed bytes, which may not grow beyond `max_size`.
+struct Output {
+    bytes: Vec<u8>,
+    max_size: usize,
+}
+
+impl Output {
+    /// `Output.check()` makes sure `count` more bytes can be added.
+    fn check(&self, count: usize) -> Result<(), String> {
+        match self.bytes.len() + count > self.max_size {
+            true => Err(format!(
+                "the data decompresses to more than {} bytes",
+                self.max_size
+            )),
+            false => Ok(()),
+        }
+    }
+}
+
+/// `Bits` reads the bits of the input, least significant first.
+struct Bits<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, count: usize) -> Result<usize, String> {
+        let mut value = 0;
+        for offset in 0..count {
+            let byte = self
+                .data
+                .get(self.position / 8)
+                .ok_or_else(|| String::from("the compressed data is truncated"))?;
+            value |= ((byte >> (self.position % 8)) as usize & 1) << offset;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+}
+
+/// `Huffman` : a canonical Huffman code, as the number of codes of each length and the symbols sorted by code.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// `Huffman::new()` will return the code given by the code length of every symbol (0 when unused).
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols = vec![];
+        for length in 1..16 {
+            for (symbol, _) in lengths
+                .iter()
+                .enumerate()
+                .filter(|&(_, &symbol_length)| symbol_length == length)
+            {
+                symbols.push(symbol as u16);
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    /// `Huffman.decode()` reads one symbol, a bit at a time.
+    fn decode(&self, input: &mut Bits) -> Result<usize, String> {
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for length in 1..16 {
+            code |= input.read(1)?;
+            let count = self.counts[length] as usize;
+            if code < first + count {
+                return Ok(self.symbols[index + code - first] as usize);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(String::from("invalid Huffman code"))
+    }
+}
+
+fn stored_block(input: &mut Bits, output: &mut Output) -> Result<(), String> {
+    input.position = input.position.div_ceil(8) * 8;
+    let length = input.read(16)?;
+    let complement = input.read(16)?;
+    if length != !complement & 0xFFFF {
+        return Err(String::from("invalid stored block length"));
+    }
+    let start = input.position / 8;
+    let bytes = input
+        .data
+        .get(start..start + length)
+        .ok_or_else(|| String::from("the compressed data is truncated"))?;
+    output.check(length)?;
+    output.bytes.extend_from_slice(bytes);
+    input.position += length * 8;
+    Ok(())
+}
+
+/// `dynamic_tables()` reads the codes of a block compressed with its own Huffman codes.
+fn dynamic_tables(input: &mut Bits) -> Result<(Huffman, Huffman), String> {
+    const ORDER: [usize; 19] = [
+        16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+    ];
+    let literal_count = input.read(5)? + 257;
+    let distance_count = input.read(5)? + 1;
+    let length_count = input.read(4)? + 4;
+    let mut code_lengths = [0u8; 19];
+    for &symbol in &ORDER[..length_count] {
+        code_lengths[symbol] = input.read(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![];
+    while lengths.len() < literal_count + distance_count {
+        let (length, repeat) = match code_lengths.decode(input)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| String::from("repeat without a previous length"))?;
+                (previous, 3 + input.read(2)?)
+            }
+            17 => (0, 3 + input.read(3)?),
+            _ => (0, 11 + input.read(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(length, repeat));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err(String::from("too many code lengths"));
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn compressed_block(
+    input: &mut Bits,
+    output: &mut Output,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), String> {
+    const LENGTHS: [usize; 29] = [
+        3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+        131, 163, 195, 227, 258,
+    ];
+    const LENGTH_BITS: [usize; 29] = [
+        0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+    ];
+    const DISTANCES: [usize; 30] = [
+        1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+        2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+    ];
+    const DISTANCE_BITS: [usize; 30] = [
+        0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12,
+        13, 13,
+    ];
+    loop {
+        let symbol = literals.decode(input)?;
+        match symbol {
+            0..=255 => {
+                output.check(1)?;
+                output.bytes.push(symbol as u8);
+            }
+            256 => return Ok(()),
+            257..=285 => {
+                let index = symbol - 257;
+                let length = LENGTHS[index] + input.read(LENGTH_BITS[index])?;
+                let index = distances.decode(input)?;
+                if index >= 30 {
+                    return Err(String::from("invalid distance"));
+                }
+                let distance = DISTANCES[index] + input.read(DISTANCE_BITS[index])?;
+                if distance > output.bytes.len() {
+                    return Err(String::from("distance beyond the start of the data"));
+                }
+                output.check(length)?;
+                //the copy can overlap what it writes
+                for _ in 0..length {
+                    output
+                        .bytes
+                        .push(output.bytes[output.bytes.len() - distance]);
+                }
+            }
+            _ => return Err(String::from("invalid literal or length")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `lines()` returns the text compressed in `DYNAMIC`.
+    fn lines() -> Vec<u8> {
+        (0..20)
+            .flat_map(|n| {
+                format!("the quick brown fox jumps over the lazy dog {}\n", n).into_bytes()
+            })
+            .collect()
+    }
+
+    /// `lines()` compressed with Huffman codes of its own (a dynamic block).
+    const DYNAMIC: [u8; 105] = [
+        0x95, 0xD1, 0xCB, 0x01, 0x82, 0x30, 0x14, 0x05, 0xD1, 0x3D, 0x55, 0xBC, 0x12, 0xBC, 0x22,
+        0x6A, 0xCA, 0xE1, 0x13, 0x05, 0x3F, 0x04, 0x43, 0x22, 0x60, 0xF5, 0xD2, 0xC2, 0xAC, 0x67,
+        0x76, 0x27, 0xF5, 0xDE, 0x3E, 0x79, 0x68, 0x9F, 0xD6, 0xC4, 0xB0, 0x8C, 0x76, 0x0B, 0xAB,
+        0x3D, 0xF2, 0x7B, 0x9A, 0x2D, 0x7C, 0x7D, 0xB4, 0xB4, 0xE7, 0x57, 0xFD, 0xDB, 0xAC, 0x0B,
+        0x77, 0x3B, 0x14, 0x09, 0xDC, 0x42, 0xF7, 0x11, 0xDD, 0x25, 0xBA, 0x4F, 0xE8, 0xAE, 0xD0,
+        0x7D, 0x46, 0xF7, 0x05, 0xDD, 0x57, 0x74, 0x3B, 0xA6, 0x03, 0x31, 0x99, 0xA6, 0x18, 0xA7,
+        0x98, 0xA7, 0x18, 0xA8, 0x98, 0xA8, 0x18, 0xA9, 0x98, 0xA9, 0x18, 0xAA, 0x5C, 0xF1, 0x07,
+    ];
+
+    /// `zeros()` returns 100,000 zeros compressed in 114 bytes.
+    fn zeros() -> Vec<u8> {
+        let mut data = vec![
+            0xED, 0xC1, 0x31, 0x01, 0x00, 0x00, 0x00, 0xC2, 0xA0, 0xF5, 0x4F, 0x6D, 0x0D, 0x0F,
+            0xA0,
+        ];
+        data.resize(111, 0);
+        data.extend([0x80, 0x57, 0x03]);
+        data
+    }
+
+    #[test]
+    fn inflates_a_stored_block() {
+        let data = [
+            0x01, 0x06, 0x00, 0xF9, 0xFF, b's', b't', b'o', b'r', b'e', b'd',
+        ];
+        assert_eq!(inflate(&data, 100).unwrap(), b"stored");
+        //the length and its complement do not match
+        let mut corrupted = data;
+        corrupted[3] = 0;
+        assert!(inflate(&corrupted, 100).is_err());
+    }
+
+    #[test]
+    fn inflates_a_fixed_block() {
+        let data = [
+            0xCB, 0x48, 0xCD, 0xC9, 0xC9, 0xD7, 0x51, 0xC8, 0x40, 0xA2, 0x14, 0x01,
+        ];
+        assert_eq!(inflate(&data, 100).unwrap(), b"hello, hello, hello!");
+    }
+
+    #[test]
+    fn inflates_a_dynamic_block() {
+        assert_eq!(DYNAMIC[0] >> 1 & 0x3, 2);
+        assert_eq!(inflate(&DYNAMIC, 1000).unwrap(), lines());
+    }
+
+    #[test]
+    fn rejects_data_decompressing_beyond_the_maximum_size() {
+        assert_eq!(inflate(&zeros(), 100_000).unwrap().len(), 100_000);
+        assert!(inflate(&zeros(), 99_999).is_err());
+        assert!(inflate(&DYNAMIC, lines().len() - 1).is_err());
+    }
+
+    #[test]
+    fn truncated_or_corrupted_data_is_an_error() {
+        for length in 0..DYNAMIC.len() {
+            assert!(inflate(&DYNAMIC[..length], 1000).is_err(), "{}", length);
+        }
+        //block type 3 is invalid
+        assert!(inflate(&[0x07], 1000).is_err());
+        //corrupted bytes must not panic
+        for position in 0..DYNAMIC.len() {
+            let mut corrupted = DYNAMIC;
+            corrupted[position] ^= 0x5A;
+            let _ = inflate(&corrupted, 1000);
+        }
+    }
+}
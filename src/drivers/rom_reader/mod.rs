@@ -0,0 +1,396 @@
+//! ROM reader - loads ROMs from raw binary files (e.g. `.ch8`), Octo cartridges (GIF images carrying the source of
+//! a program and its options), zip archives, Intel HEX records and hexadecimal dumps. The format is detected from
+//! the content of the file. `-` reads the standard input, and `<archive>:<name>` picks a file in an archive
+//! (an archive holding a single ROM needs no name).
+//! The settings in a sidecar file next to the ROM (`game.ch8.toml` or `game.ch8.json`) are read with it.
+
+mod cartridge;
+mod gif;
+mod hex;
+mod inflate;
+mod zip;
+
+use self::cartridge::read_cartridge;
+
+use crate::database::{RomDatabase, RomSettings};
+use crate::octo;
+use log::{debug, info};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// The largest CHIP-8 ROM : its memory of 4 KB from 0x200 to the end.
+pub const MAX_ROM_SIZE: usize = 3584;
+/// The largest MegaChip ROM : its memory of 16 MB from 0x200 to the end.
+pub const MAX_MEGACHIP_ROM_SIZE: usize = 0x100_0000 - 0x200;
+
+/// The largest file extracted from an archive : enough for the Intel HEX records of a MegaChip ROM,
+/// while a zip bomb is stopped before it fills the memory.
+const MAX_ARCHIVED_FILE_SIZE: usize = 0x400_0000;
+
+/// Extensions of the files of an archive which are taken for ROMs.
+const ROM_EXTENSIONS: [&str; 10] = [
+    "ch8", "c8", "c8x", "sc8", "xo8", "rom", "bin", "hex", "gif", "8o",
+];
+
+/// `RomError` : why a ROM could not be loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RomError {
+    NotFound(String),
+    Unreadable {
+        filename: String,
+        message: String,
+    },
+    Empty(String),
+    TooLarge {
+        filename: String,
+        size: usize,
+        max_size: usize,
+    },
+    /// The file is not a valid archive, cartridge, etc.
+    Invalid {
+        filename: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::NotFound(filename) => write!(f, "{} not found", filename),
+            RomError::Unreadable { filename, message } => {
+                write!(f, "Could not read {} : {}", filename, message)
+            }
+            RomError::Empty(filename) => write!(f, "{} is empty", filename),
+            RomError::TooLarge {
+                filename,
+                size,
+                max_size,
+            } => write!(
+                f,
+                "{} is {} bytes, a ROM can not be larger than {} bytes",
+                filename, size, max_size
+            ),
+            RomError::Invalid { filename, message } => write!(f, "{} : {}", filename, message),
+        }
+    }
+}
+
+impl Error for RomError {}
+
+/// `Rom` : the bytes of a ROM, and the settings which came with it (the options of a cartridge, a sidecar file).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rom {
+    pub bytes: Vec<u8>,
+    pub settings: Option<RomSettings>,
+}
+
+/// `max_rom_size()` returns the size of the largest ROM loaded at `load_address` in a memory of `memory_size` bytes.
+pub fn max_rom_size(memory_size: usize, load_address: u16) -> usize {
+    memory_size.saturating_sub(load_address as usize)
+}
+
+/// `load_rom()` will return the ROM read from a file, whatever its format, if it is no larger than `max_size` bytes
+/// (see `max_rom_size()`).
+pub fn load_rom(filename: &str, max_size: usize) -> Result<Rom, RomError> {
+    let rom = read(filename, max_size)?;
+    if rom.bytes.len() > max_size {
+        return Err(RomError::TooLarge {
+            filename: filename.to_string(),
+            size: rom.bytes.len(),
+            max_size,
+        });
+    }
+    debug!("[load_rom()] bytes read = {}.", rom.bytes.len());
+    Ok(rom)
+}
+
+/// `read()` will return the ROM read from a file, whatever its format. Its size is only checked against `max_size`
+/// where a malformed file could make it huge (Intel HEX addresses).
+fn read(filename: &str, max_size: usize) -> Result<Rom, RomError> {
+    debug!("[load_rom()] Going to read {}.", filename);
+    if filename == "-" {
+        let mut data = vec![];
+        io::stdin()
+            .read_to_end(&mut data)
+            .map_err(|error| RomError::Unreadable {
+                filename: String::from("the standard input"),
+                message: error.to_string(),
+            })?;
+        return decode(filename, data, max_size);
+    }
+
+    //`<archive>:<name>`, unless the file itself has a colon in its name
+    let (path, name) = match filename.rsplit_once(':') {
+        Some((path, name)) if !Path::new(filename).exists() => (path, Some(name)),
+        _ => (filename, None),
+    };
+    let data = fs::read(path).map_err(|error| match error.kind() {
+        io::ErrorKind::NotFound => RomError::NotFound(path.to_string()),
+        _ => RomError::Unreadable {
+            filename: path.to_string(),
+            message: error.to_string(),
+        },
+    })?;
+    match name {
+        Some(name) if zip::is_zip(&data) => extract(path, &data, Some(name), max_size),
+        Some(_) => Err(RomError::Invalid {
+            filename: path.to_string(),
+            message: String::from("not an archive"),
+        }),
+        None if zip::is_zip(&data) => extract(path, &data, None, max_size),
+        None => {
+            let mut rom = decode(path, data, max_size)?;
+            if let Some(sidecar) = read_sidecar(path)? {
+                rom.settings
+                    .get_or_insert_with(RomSettings::default)
+                    .merge(sidecar);
+            }
+            Ok(rom)
+        }
+    }
+}
+
+/// `read_sidecar()` will return the settings of the sidecar file of a ROM, if there is one.
+fn read_sidecar(filename: &str) -> Result<Option<RomSettings>, RomError> {
+    for extension in ["toml", "json"] {
+        let sidecar = format!("{}.{}", filename, extension);
+        if Path::new(&sidecar).exists() {
+            debug!("[load_rom()] Reading the sidecar {}.", sidecar);
+            return RomSettings::load(&sidecar)
+                .map(Some)
+                .map_err(|error| RomError::Invalid {
+                    filename: filename.to_string(),
+                    message: format!("invalid sidecar, {}", error),
+                });
+        }
+    }
+    Ok(None)
+}
+
+/// `extract()` loads the ROM called `name` from an archive, or its only ROM.
+fn extract(
+    filename: &str,
+    data: &[u8],
+    name: Option<&str>,
+    max_size: usize,
+) -> Result<Rom, RomError> {
+    let invalid = |message: String| RomError::Invalid {
+        filename: filename.to_string(),
+        message,
+    };
+    let entries = zip::entries(data).map_err(invalid)?;
+    let entry = match name {
+        Some(name) => entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| invalid(format!("{} is not in the archive", name)))?,
+        None => {
+            let is_rom = |entry: &&zip::Entry| {
+                Path::new(&entry.name)
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .is_some_and(|extension| {
+                        ROM_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+                    })
+            };
+            let roms: Vec<&zip::Entry> = match entries.len() {
+                1 => entries.iter().collect(),
+                _ => entries.iter().filter(is_rom).collect(),
+            };
+            match roms[..] {
+                [entry] => entry,
+                [] => return Err(invalid(String::from("no ROM in the archive"))),
+                _ => {
+                    let names: Vec<&str> = roms.iter().map(|entry| entry.name.as_str()).collect();
+                    return Err(invalid(format!(
+                        "more than one ROM in the archive, pick one with {}:<name> among {}",
+                        filename,
+                        names.join(", ")
+                    )));
+                }
+            }
+        }
+    };
+    debug!("[load_rom()] Extracting {} from {}.", entry.name, filename);
+    let content = zip::extract(data, entry, MAX_ARCHIVED_FILE_SIZE).map_err(invalid)?;
+    decode(&format!("{}:{}", filename, entry.name), content, max_size)
+}
+
+/// `decode()` will return the ROM held by the content of a file : a cartridge, hexadecimal text or the ROM itself.
+fn decode(filename: &str, data: Vec<u8>, max_size: usize) -> Result<Rom, RomError> {
+    let invalid = |message: String| RomError::Invalid {
+        filename: filename.to_string(),
+        message,
+    };
+    if data.is_empty() {
+        return Err(RomError::Empty(filename.to_string()));
+    }
+    let (bytes, settings) = if gif::is_gif(&data) {
+        debug!("[load_rom()] {} is an Octo cartridge.", filename);
+        let cartridge = read_cartridge(&data).map_err(invalid)?;
+        let rom = octo::compile(&cartridge.program)
+            .map_err(|error| invalid(format!("the program does not compile, {}", error)))?;
+        (rom, Some(cartridge.settings))
+    } else if filename.ends_with(".8o") {
+        debug!("[load_rom()] {} is an Octo source.", filename);
+        let source = String::from_utf8_lossy(&data);
+        let rom = octo::compile(&source).map_err(|error| invalid(error.to_string()))?;
+        (rom, None)
+    } else if hex::is_intel_hex(&data) {
+        debug!("[load_rom()] {} holds Intel HEX records.", filename);
+        let text = String::from_utf8_lossy(&data);
+        (hex::read_intel_hex(&text, max_size).map_err(invalid)?, None)
+    } else if hex::is_hex_dump(&data) {
+        debug!("[load_rom()] {} is a hexadecimal dump.", filename);
+        let text = String::from_utf8_lossy(&data);
+        (hex::read_hex_dump(&text).map_err(invalid)?, None)
+    } else {
+        (data, None)
+    };
+
+    if bytes.is_empty() {
+        return Err(RomError::Empty(filename.to_string()));
+    }
+    Ok(Rom { bytes, settings })
+}
+
+pub struct RomReader {
+    pub rom: Vec<u8>,
+    pub size: usize,
+    /// `max_size` is the size of the largest ROM read, `MAX_ROM_SIZE` unless the machine has more memory.
+    pub max_size: usize,
+    /// `database` holds the settings of the known ROMs, the embedded database to begin with.
+    pub database: RomDatabase,
+    /// `settings` are the settings of the ROM read, from the database and the file.
+    pub settings: Option<RomSettings>,
+}
+
+impl RomReader {
+    pub fn new() -> Self {
+        RomReader {
+            size: 0,
+            rom: vec![],
+            max_size: MAX_ROM_SIZE,
+            database: RomDatabase::embedded(),
+            settings: None,
+        }
+    }
+
+    /// `RomReader.read_rom()` reads a ROM (see `load_rom()`). The settings of the ROM are those found in
+    /// the database, replaced by those which came with the file.
+    pub fn read_rom(&mut self, filename: &str) -> Result<(), RomError> {
+        let Rom { bytes, settings } = load_rom(filename, self.max_size)?;
+        self.size = bytes.len();
+        self.rom = bytes;
+
+        self.settings = self.database.lookup(&self.rom).cloned();
+        if let Some(settings) = self.settings.as_ref() {
+            info!(
+                "[RomReader.read_rom()] Found {} in the ROM database.",
+                settings.title.as_deref().unwrap_or("the ROM")
+            );
+        }
+        if let Some(settings) = settings {
+            self.settings
+                .get_or_insert_with(RomSettings::default)
+                .merge(settings);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::zip::tests::{archive, PONG};
+    use super::*;
+
+    /// `write_archive()` writes an archive of two ROMs and a text file in the temporary directory.
+    fn write_archive(name: &str) -> String {
+        let data = archive(&[
+            ("maze.ch8", 0, b"\xA2\x1E", b"\xA2\x1E"),
+            ("pong.txt", 8, &PONG, b"pong, pong, pong, pong, pong"),
+            ("clear.hex", 0, b"00 E0", b"00 E0"),
+        ]);
+        let path = std::env::temp_dir().join(format!("chip8-{}-{}.zip", name, std::process::id()));
+        fs::write(&path, data).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn known_roms_get_the_settings_of_the_database() {
+        use crate::database::tests::{PROGRAMS, ROM};
+        let path = std::env::temp_dir().join(format!("chip8-zero-{}.ch8", std::process::id()));
+        fs::write(&path, ROM).unwrap();
+        let mut rom_reader = RomReader::new();
+        rom_reader.database = RomDatabase::parse(PROGRAMS).unwrap();
+        rom_reader.read_rom(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(&rom_reader.rom[..rom_reader.size], &ROM);
+        let settings = rom_reader.settings.unwrap();
+        assert_eq!(settings.title.as_deref(), Some("Zero"));
+        assert_eq!(settings.tick_rate, Some(20));
+    }
+
+    #[test]
+    fn the_largest_rom_depends_on_the_memory_of_the_platform() {
+        use crate::vm::{Platform, PROGRAM_START};
+        assert_eq!(
+            max_rom_size(Platform::Chip8.memory_size(), PROGRAM_START),
+            MAX_ROM_SIZE
+        );
+        let path = std::env::temp_dir().join(format!("chip8-large-{}.xo8", std::process::id()));
+        fs::write(&path, vec![0x12; 8000]).unwrap();
+        let filename = path.to_str().unwrap();
+        let max_size = max_rom_size(Platform::XoChip.memory_size(), PROGRAM_START);
+        assert_eq!(load_rom(filename, max_size).unwrap().bytes.len(), 8000);
+        assert_eq!(
+            load_rom(filename, MAX_ROM_SIZE),
+            Err(RomError::TooLarge {
+                filename: filename.to_string(),
+                size: 8000,
+                max_size: MAX_ROM_SIZE
+            })
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reads_a_rom_picked_in_an_archive() {
+        let path = write_archive("pick");
+        assert_eq!(
+            load_rom(&format!("{}:maze.ch8", path), MAX_ROM_SIZE)
+                .unwrap()
+                .bytes,
+            [0xA2, 0x1E]
+        );
+        //the content of the file is decoded
+        assert_eq!(
+            load_rom(&format!("{}:clear.hex", path), MAX_ROM_SIZE)
+                .unwrap()
+                .bytes,
+            [0x00, 0xE0]
+        );
+        assert!(matches!(
+            load_rom(&format!("{}:missing.ch8", path), MAX_ROM_SIZE),
+            Err(RomError::Invalid { .. })
+        ));
+        //the archive holds more than one ROM
+        assert!(matches!(
+            load_rom(&path, MAX_ROM_SIZE),
+            Err(RomError::Invalid { .. })
+        ));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn intel_hex_records_beyond_the_largest_rom_are_rejected() {
+        let data = b":020000040100F9\n:01000000FF00\n".to_vec();
+        assert!(matches!(
+            decode("far.hex", data, MAX_ROM_SIZE),
+            Err(RomError::Invalid { .. })
+        ));
+    }
+}
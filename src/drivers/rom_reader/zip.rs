@@ -0,0 +1,232 @@
+//! Zip - a reader for the files of zip archives, stored or deflated (no encryption, no zip64).
+
+use super::inflate::inflate;
+use crate::database::crc32;
+
+/// `is_zip()` returns true when `data` starts like a zip archive.
+pub fn is_zip(data: &[u8]) -> bool {
+    data.starts_with(b"PK\x03\x04") || data.starts_with(b"PK\x05\x06")
+}
+
+/// `Entry` : a file of an archive, where its data starts and how it is compressed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    method: u16,
+    flags: u16,
+    crc: u32,
+    compressed_size: usize,
+    size: usize,
+    header_offset: usize,
+}
+
+/// `entries()` will return the files of an archive (not its directories), as listed in its central directory.
+pub fn entries(data: &[u8]) -> Result<Vec<Entry>, String> {
+    let truncated = || String::from("the archive is truncated");
+    let word = |position: usize| {
+        data.get(position..position + 2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+            .ok_or_else(truncated)
+    };
+    let long = |position: usize| {
+        data.get(position..position + 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .ok_or_else(truncated)
+    };
+
+    //the end of central directory record is last, followed by a comment of up to 64 KB
+    let end = (0..data.len().saturating_sub(21))
+        .rev()
+        .take(0x10000 + 22)
+        .find(|&position| data[position..].starts_with(b"PK\x05\x06"))
+        .ok_or_else(|| String::from("the archive has no central directory"))?;
+    let count = word(end + 10)? as usize;
+    let mut position = long(end + 16)? as usize;
+    if count == 0xFFFF || position == 0xFFFF_FFFF {
+        return Err(String::from("zip64 archives are not supported"));
+    }
+
+    let mut entries = vec![];
+    for _ in 0..count {
+        if long(position)? != 0x0201_4B50 {
+            return Err(String::from("invalid central directory"));
+        }
+        let name_length = word(position + 28)? as usize;
+        let name = data
+            .get(position + 46..position + 46 + name_length)
+            .ok_or_else(truncated)?;
+        let entry = Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: word(position + 10)?,
+            flags: word(position + 8)?,
+            crc: long(position + 16)?,
+            compressed_size: long(position + 20)? as usize,
+            size: long(position + 24)? as usize,
+            header_offset: long(position + 42)? as usize,
+        };
+        position +=
+            46 + name_length + word(position + 30)? as usize + word(position + 32)? as usize;
+        if !entry.name.ends_with('/') {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// `extract()` will return the content of a file of the archive, if it is no larger than `max_size` bytes.
+pub fn extract(data: &[u8], entry: &Entry, max_size: usize) -> Result<Vec<u8>, String> {
+    let truncated = || String::from("the archive is truncated");
+    if entry.flags & 1 != 0 {
+        return Err(format!("{} is encrypted", entry.name));
+    }
+    if entry.size > max_size {
+        return Err(format!(
+            "{} is too large ({} bytes, at most {})",
+            entry.name, entry.size, max_size
+        ));
+    }
+    let header = data
+        .get(entry.header_offset..entry.header_offset + 30)
+        .filter(|header| header.starts_with(b"PK\x03\x04"))
+        .ok_or_else(|| format!("invalid local header for {}", entry.name))?;
+    let start = entry.header_offset
+        + 30
+        + u16::from_le_bytes([header[26], header[27]]) as usize
+        + u16::from_le_bytes([header[28], header[29]]) as usize;
+    let compressed = data
+        .get(start..start + entry.compressed_size)
+        .ok_or_else(truncated)?;
+    let content = match entry.method {
+        0 => compressed.to_vec(),
+        //no more than the size the archive gives, a file which inflates beyond it is corrupted
+        8 => inflate(compressed, entry.size)
+            .map_err(|error| format!("{} : {}", entry.name, error))?,
+        method => {
+            return Err(format!(
+                "{} uses an unsupported compression method ({})",
+                entry.name, method
+            ))
+        }
+    };
+    if content.len() != entry.size || crc32(&content) != entry.crc {
+        return Err(format!("{} is corrupted", entry.name));
+    }
+    Ok(content)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// "pong, pong, pong, pong, pong" deflated.
+    pub const PONG: [u8; 11] = [
+        0x2B, 0xC8, 0xCF, 0x4B, 0xD7, 0x51, 0x28, 0xC0, 0x4A, 0x02, 0x00,
+    ];
+
+    /// `archive()` builds a zip archive of `(name, method, compressed data, content)` files.
+    pub fn archive(files: &[(&str, u16, &[u8], &[u8])]) -> Vec<u8> {
+        let mut data = vec![];
+        let mut directory = vec![];
+        for &(name, method, compressed, content) in files {
+            let mut header = vec![];
+            header.extend(20u16.to_le_bytes());
+            header.extend(0u16.to_le_bytes());
+            header.extend(method.to_le_bytes());
+            header.extend([0; 4]);
+            header.extend(crc32(content).to_le_bytes());
+            header.extend((compressed.len() as u32).to_le_bytes());
+            header.extend((content.len() as u32).to_le_bytes());
+            header.extend((name.len() as u16).to_le_bytes());
+            header.extend(0u16.to_le_bytes());
+
+            directory.extend(b"PK\x01\x02");
+            directory.extend(20u16.to_le_bytes());
+            directory.extend(&header);
+            directory.extend([0; 10]);
+            directory.extend((data.len() as u32).to_le_bytes());
+            directory.extend(name.as_bytes());
+
+            data.extend(b"PK\x03\x04");
+            data.extend(&header);
+            data.extend(name.as_bytes());
+            data.extend(compressed);
+        }
+        let offset = data.len() as u32;
+        data.extend(&directory);
+        data.extend(b"PK\x05\x06");
+        data.extend([0; 4]);
+        data.extend((files.len() as u16).to_le_bytes());
+        data.extend((files.len() as u16).to_le_bytes());
+        data.extend((directory.len() as u32).to_le_bytes());
+        data.extend(offset.to_le_bytes());
+        data.extend([0; 2]);
+        data
+    }
+
+    fn two_files() -> Vec<u8> {
+        archive(&[
+            ("games/", 0, b"", b""),
+            ("games/maze.ch8", 0, b"\xA2\x1E", b"\xA2\x1E"),
+            ("pong.txt", 8, &PONG, b"pong, pong, pong, pong, pong"),
+        ])
+    }
+
+    #[test]
+    fn lists_and_extracts_stored_and_deflated_files() {
+        let data = two_files();
+        assert!(is_zip(&data));
+        let entries = entries(&data).unwrap();
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["games/maze.ch8", "pong.txt"]);
+        assert_eq!(extract(&data, &entries[0], 100).unwrap(), b"\xA2\x1E");
+        assert_eq!(
+            extract(&data, &entries[1], 100).unwrap(),
+            b"pong, pong, pong, pong, pong"
+        );
+    }
+
+    #[test]
+    fn files_larger_than_the_maximum_size_are_not_extracted() {
+        let data = two_files();
+        let entries = entries(&data).unwrap();
+        assert!(extract(&data, &entries[1], 27).is_err());
+    }
+
+    #[test]
+    fn corrupted_files_are_detected() {
+        let mut data = two_files();
+        //the second byte of the stored ROM
+        let position = data
+            .windows(2)
+            .position(|bytes| bytes == b"\xA2\x1E")
+            .unwrap();
+        data[position + 1] = 0x1F;
+        let entries = entries(&data).unwrap();
+        assert!(extract(&data, &entries[0], 100).is_err());
+        assert!(extract(&data, &entries[1], 100).is_ok());
+    }
+
+    #[test]
+    fn truncated_or_corrupted_archives_are_errors() {
+        let data = two_files();
+        for length in 0..data.len() {
+            let data = &data[..length];
+            if let Ok(entries) = entries(data) {
+                for entry in entries.iter() {
+                    let _ = extract(data, entry, 100);
+                }
+            }
+        }
+        for position in 0..data.len() {
+            let mut corrupted = data.clone();
+            corrupted[position] ^= 0xFF;
+            if let Ok(entries) = entries(&corrupted) {
+                for entry in entries.iter() {
+                    let _ = extract(&corrupted, entry, 100);
+                }
+            }
+        }
+        assert!(entries(&data[..data.len() - 1]).is_err());
+        assert!(entries(b"PK\x03\x04").is_err());
+    }
+}
@@ -3,5 +3,13 @@
 //! This program emulates CHIP-8 : an interpreted programming language, developed by Joseph Weisbecker. There are number of
 //! classic video games ported to CHIP-8, such as Pong, Space Inveders, Tetris and Pac-Man.
 
+pub mod assembler;
+pub mod database;
+pub mod debugger;
+pub mod decompiler;
+pub mod disassembler;
 pub mod drivers;
+pub mod lint;
+pub mod octo;
+pub mod patch;
 pub mod vm;
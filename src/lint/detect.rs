@@ -0,0 +1,295 @@
+//! Platform and quirks detection - guesses which interpreter a ROM was written for, from the instructions it uses :
+//!
+//! * any SUPER-CHIP or XO-CHIP instruction (e.g. 00FF) selects that platform and the quirks of its interpreter.
+//!
+//! For CHIP-8 ROMs :
+//!
+//! * 8XY6 or 8XYE with X ≠ Y shifts VY into VX, unless Y is always 0 (then VY is likely unused),
+//! * I used again after FX55 or FX65, without being loaded first, means I is left unchanged.
+//!
+//! The ROM is also run for a while, without keys pressed, to watch how it behaves : the shifts it executed
+//! are preferred over the ones found in the disassembly (which may be data), and I used again after FX55 or FX65
+//! is found through calls and computed jumps too.
+//!
+//! Without any hint, the machine keeps its own quirks (`Quirks::new()`).
+
+use crate::disassembler::{self, ControlFlowGraph, EdgeKind};
+use crate::vm::{Instruction, Machine, Platform, Quirks};
+use log::debug;
+use std::collections::BTreeSet;
+
+/// Number of instructions the ROM is run for, to watch how it behaves.
+const PROBE_CYCLES: usize = 20_000;
+
+/// `Detection` : the platform a ROM needs, the quirks to run it with and the hints which led to them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detection {
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub reasons: Vec<String>,
+}
+
+/// `detect()` will return the platform and quirks guessed for a ROM loaded at `origin`, whose code starts at
+/// `entry_point`.
+pub fn detect(rom: &[u8], origin: u16, entry_point: u16) -> Detection {
+    let cfg = ControlFlowGraph::new(disassembler::disassemble(rom, origin, entry_point));
+    let instructions = &cfg.disassembly.instructions;
+    let mut reasons = vec![];
+
+    let (platform, first) = instructions
+        .iter()
+        .map(|(&address, instruction)| (instruction.platform(), address))
+        .max_by_key(|&(platform, address)| (platform, std::cmp::Reverse(address)))
+        .unwrap_or((Platform::Chip8, 0));
+    if platform > Platform::Chip8 {
+        reasons.push(format!(
+            "`{}` at 0x{:03X} is a {} instruction",
+            instructions[&first], first, platform
+        ));
+        let quirks = Quirks::for_platform(platform);
+        debug!("[detect()] Detected {} with quirks : {}.", platform, quirks);
+        return Detection {
+            platform,
+            quirks,
+            reasons,
+        };
+    }
+
+    let mut quirks = Quirks::new();
+    let behaviour = probe(rom, origin, entry_point);
+
+    let mut shifts: Vec<(u16, usize, usize)> = instructions
+        .iter()
+        .filter_map(|(&address, instruction)| match *instruction {
+            Instruction::ShiftRight { x, y } | Instruction::ShiftLeft { x, y } if x != y => {
+                Some((address, x, y))
+            }
+            _ => None,
+        })
+        .collect();
+    //the shifts which ran are code for sure
+    if shifts
+        .iter()
+        .any(|(address, _, _)| behaviour.executed.contains(address))
+    {
+        shifts.retain(|(address, _, _)| behaviour.executed.contains(address));
+    }
+    if let Some(&(address, _, _)) = shifts.iter().find(|&&(_, _, y)| y != 0) {
+        quirks.shift_vy = true;
+        reasons.push(format!(
+            "`{}` at 0x{:03X} shifts another register than VX",
+            instructions[&address], address
+        ));
+    } else if let Some(&(address, _, _)) = shifts.first() {
+        quirks.shift_vy = false;
+        reasons.push(format!(
+            "`{}` at 0x{:03X} only uses V0 as VY, VX is shifted in place",
+            instructions[&address], address
+        ));
+    }
+
+    if let Some((address, instruction, reuse)) = behaviour.reused_index {
+        quirks.load_store_increments_i = false;
+        reasons.push(format!(
+            "I was used again at 0x{:03X} after `{}` at 0x{:03X}, without being loaded, while the ROM ran",
+            reuse, instruction, address
+        ));
+    } else if let Some((address, reuse)) = reused_index(&cfg) {
+        quirks.load_store_increments_i = false;
+        reasons.push(format!(
+            "I is used again at 0x{:03X} after `{}` at 0x{:03X}, without being loaded",
+            reuse, instructions[&address], address
+        ));
+    }
+    debug!("[detect()] Detected {} with quirks : {}.", platform, quirks);
+    Detection {
+        platform,
+        quirks,
+        reasons,
+    }
+}
+
+/// `reused_index()` looks for FX55 or FX65 followed (through jumps, skips and returning calls) by an instruction
+/// using I, with no instruction loading I in between. It returns the addresses of both.
+fn reused_index(cfg: &ControlFlowGraph) -> Option<(u16, u16)> {
+    let instructions = &cfg.disassembly.instructions;
+    for (&address, instruction) in instructions {
+        if !matches!(
+            instruction,
+            Instruction::StoreRegisters(_) | Instruction::LoadRegisters(_)
+        ) {
+            continue;
+        }
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![address + instruction.size()];
+        while let Some(mut next) = pending.pop() {
+            if !visited.insert(next) {
+                continue;
+            }
+            while let Some(instruction) = instructions.get(&next) {
+                match instruction {
+                    Instruction::LoadIndex(_)
+                    | Instruction::LoadIndexLong
+                    | Instruction::LoadFont(_)
+                    | Instruction::LoadBigFont(_) => break,
+                    Instruction::Draw { .. }
+                    | Instruction::AddIndex(_)
+                    | Instruction::StoreBcd(_)
+                    | Instruction::StoreRegisters(_)
+                    | Instruction::LoadRegisters(_)
+                    | Instruction::StoreRange { .. }
+                    | Instruction::LoadRange { .. } => return Some((address, next)),
+                    _ => (),
+                }
+                //the end of a block : follow its edges, calls are assumed to leave I alone
+                let block = cfg
+                    .blocks
+                    .range(..=next)
+                    .next_back()
+                    .map(|(_, block)| block);
+                if let Some(block) = block.filter(|block| block.end == next + instruction.size()) {
+                    for edge in &block.edges {
+                        if let (
+                            EdgeKind::FallThrough | EdgeKind::Jump | EdgeKind::Skip,
+                            Some(target),
+                        ) = (edge.kind, edge.target)
+                        {
+                            pending.push(target);
+                        }
+                    }
+                    break;
+                }
+                next += instruction.size();
+            }
+        }
+    }
+    None
+}
+
+/// `Behaviour` : what the ROM did while it ran.
+struct Behaviour {
+    /// `executed` holds the addresses of the executed instructions.
+    executed: BTreeSet<u16>,
+    /// `reused_index` holds the first FX55 or FX65 (its address and itself) followed by an instruction using I
+    /// (its address), with no instruction loading I in between.
+    reused_index: Option<(u16, Instruction, u16)>,
+}
+
+/// `probe()` runs the ROM for `PROBE_CYCLES` instructions without keys pressed, with the quirks of the machine.
+/// It stops early when the ROM waits for a key, or reaches an instruction the machine would not survive
+/// (a return with an empty stack, memory used beyond its end) or machine code (0NNN).
+fn probe(rom: &[u8], origin: u16, entry_point: u16) -> Behaviour {
+    let mut machine = Machine::new();
+    machine.load_program(rom, origin, entry_point);
+    let size = machine.memory.cells.len();
+    let mut behaviour = Behaviour {
+        executed: BTreeSet::new(),
+        reused_index: None,
+    };
+    let mut stored = None;
+    for _ in 0..PROBE_CYCLES {
+        if machine.halted || machine.keyboard.keypress_awaited || machine.pc as usize + 1 >= size {
+            break;
+        }
+        let pc = machine.pc;
+        let instruction = machine.decode(opcode(&machine.memory.cells, pc));
+        let beyond_memory = machine.i as usize + 16 >= size;
+        match instruction {
+            Instruction::Sys(_) => break,
+            Instruction::Ret if machine.stack.cells.is_empty() => break,
+            Instruction::StoreBcd(_)
+            | Instruction::StoreRegisters(_)
+            | Instruction::LoadRegisters(_)
+                if beyond_memory =>
+            {
+                break
+            }
+            _ => (),
+        }
+        behaviour.executed.insert(pc);
+        match instruction {
+            Instruction::LoadIndex(_) | Instruction::LoadFont(_) | Instruction::LoadBigFont(_) => {
+                stored = None
+            }
+            Instruction::Draw { .. }
+            | Instruction::AddIndex(_)
+            | Instruction::StoreBcd(_)
+            | Instruction::StoreRegisters(_)
+            | Instruction::LoadRegisters(_) => {
+                if let Some((address, stored)) = stored {
+                    behaviour.reused_index.get_or_insert((address, stored, pc));
+                }
+                if matches!(
+                    instruction,
+                    Instruction::StoreRegisters(_) | Instruction::LoadRegisters(_)
+                ) {
+                    stored = Some((pc, instruction));
+                }
+            }
+            _ => (),
+        }
+        machine.process_keys(vec![false; 16]);
+    }
+    debug!("[probe()] Executed {} addresses.", behaviour.executed.len());
+    behaviour
+}
+
+/// `opcode()` returns the opcode at `address` of the `memory` of the machine.
+fn opcode(memory: &[u8], address: u16) -> u16 {
+    (memory[address as usize] as u16) << 8 | memory[address as usize + 1] as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{CHIP_8X_PROGRAM_START, PROGRAM_START};
+
+    #[test]
+    fn index_reused_in_a_subroutine_is_found_while_running() {
+        //I := 0x300, save V1, call a subroutine loading V1 from I, jump to itself
+        let rom = [
+            0xA3, 0x00, 0xF1, 0x55, 0x22, 0x0A, 0x12, 0x06, 0x00, 0x00, 0xF1, 0x65, 0x00, 0xEE,
+        ];
+        let detection = detect(&rom, PROGRAM_START, PROGRAM_START);
+        assert_eq!(detection.platform, Platform::Chip8);
+        assert!(!detection.quirks.load_store_increments_i);
+        assert!(
+            detection.reasons[0].contains("0x20A") && detection.reasons[0].contains("ran"),
+            "{:?}",
+            detection.reasons
+        );
+    }
+
+    #[test]
+    fn executed_shifts_win_over_the_disassembly() {
+        //V1 >>= V0 runs, V1 >>= V2 is only reached with a key pressed
+        let rom = [0x81, 0x06, 0xE0, 0xA1, 0x81, 0x26, 0xF0, 0x0A, 0x12, 0x00];
+        let detection = detect(&rom, PROGRAM_START, PROGRAM_START);
+        assert!(
+            detection.reasons[0].contains("0x200"),
+            "{:?}",
+            detection.reasons
+        );
+        //without running, 8126 would be taken as shifting VY
+        assert!(!detection.quirks.shift_vy);
+    }
+
+    #[test]
+    fn super_chip_instructions_select_the_platform() {
+        let detection = detect(&[0x00, 0xFF, 0x12, 0x02], PROGRAM_START, PROGRAM_START);
+        assert_eq!(detection.platform, Platform::SuperChip);
+    }
+
+    #[test]
+    fn roms_are_detected_where_they_are_loaded() {
+        //V1 >>= V2, then a jump to itself : both only make sense at 0x300
+        let rom = [0x81, 0x26, 0x13, 0x02];
+        let detection = detect(&rom, CHIP_8X_PROGRAM_START, CHIP_8X_PROGRAM_START);
+        assert!(detection.quirks.shift_vy);
+        assert!(
+            detection.reasons[0].contains("0x300") && !detection.reasons[0].contains("0x200"),
+            "{:?}",
+            detection.reasons
+        );
+    }
+}
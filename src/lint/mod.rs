@@ -0,0 +1,595 @@
+//! Lint - a static analyzer looking for likely bugs in a ROM, without running it.
+//! It works on the disassembly and its control-flow graph, and finds :
+//!
+//! * unreachable code : data which decodes to a run of instructions ending with a jump or a return,
+//! * jumps and calls into the middle of another instruction,
+//! * skips followed by `LD I, LONG` (F000), which only XO-CHIP skips as a whole,
+//! * sprites (DXYN) read beyond the end of memory,
+//! * subroutines which never return, and returns from the main program,
+//! * writes (FX55, FX33, 5XY2) into the font memory (0x000-0x1FF),
+//! * quirk-sensitive instructions (8XY6, 8XYE, FX55, FX65, BNNN), which behave differently across interpreters.
+//!
+//! The address in I is only followed inside a basic block. The report also lists the SUPER-CHIP and XO-CHIP
+//! instructions the ROM uses, and so the platform it needs.
+//! `detect()` guesses the platform and quirks to run a ROM with.
+
+mod detect;
+
+pub use self::detect::{detect, Detection};
+
+use crate::disassembler::{self, ByteKind, ControlFlowGraph, EdgeKind};
+use crate::vm::{Instruction, Platform};
+use log::debug;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Addresses below `FONT_MEMORY_END` belong to the interpreter and hold the fonts.
+const FONT_MEMORY_END: u16 = 0x200;
+/// CHIP-8 and SUPER-CHIP have 4 KB of memory, XO-CHIP has 64 KB.
+const MEMORY_SIZE: u32 = 0x1000;
+const XO_CHIP_MEMORY_SIZE: u32 = 0x10000;
+/// Unreachable code is reported from this many instructions on, fewer are likely data.
+const MIN_UNREACHABLE_INSTRUCTIONS: usize = 4;
+
+/// `Finding` : a likely bug, or a hint, at `address`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Finding {
+    UnreachableCode {
+        address: u16,
+        instructions: usize,
+    },
+    /// The jump or call at `address` goes to `target`, inside the instruction at `instruction`.
+    JumpsIntoInstruction {
+        address: u16,
+        target: u16,
+        instruction: u16,
+    },
+    /// The skip at `address` is followed by `LD I, LONG`.
+    SkipsLongLoad {
+        address: u16,
+    },
+    SpriteBeyondMemory {
+        address: u16,
+        i: u32,
+        height: u32,
+    },
+    /// The subroutine at `address`, called from `call`, never reaches a return.
+    NeverReturns {
+        address: u16,
+        call: u16,
+    },
+    /// The return at `address` is reached from the main program, not from a subroutine.
+    ReturnsFromMain {
+        address: u16,
+    },
+    /// `i` is `None` when I points to a font character (FX29).
+    WritesFontMemory {
+        address: u16,
+        i: Option<u16>,
+    },
+    QuirkSensitive {
+        address: u16,
+        instruction: Instruction,
+    },
+}
+
+impl Finding {
+    pub fn address(&self) -> u16 {
+        match *self {
+            Finding::UnreachableCode { address, .. }
+            | Finding::JumpsIntoInstruction { address, .. }
+            | Finding::SkipsLongLoad { address }
+            | Finding::SpriteBeyondMemory { address, .. }
+            | Finding::NeverReturns { address, .. }
+            | Finding::ReturnsFromMain { address }
+            | Finding::WritesFontMemory { address, .. }
+            | Finding::QuirkSensitive { address, .. } => address,
+        }
+    }
+
+    /// `Finding.is_warning()` tells if the finding is a likely bug. Quirk-sensitive instructions are only hints.
+    pub fn is_warning(&self) -> bool {
+        !matches!(self, Finding::QuirkSensitive { .. })
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = if self.is_warning() { "warning" } else { "note" };
+        write!(f, "0x{:03X}: {}: ", self.address(), severity)?;
+        match *self {
+            Finding::UnreachableCode { instructions, .. } => write!(
+                f,
+                "{} instructions are never reached, is a jump or a call missing?",
+                instructions
+            ),
+            Finding::JumpsIntoInstruction {
+                target,
+                instruction,
+                ..
+            } => write!(
+                f,
+                "goes to 0x{:03X}, in the middle of the instruction at 0x{:03X}",
+                target, instruction
+            ),
+            Finding::SkipsLongLoad { .. } => write!(
+                f,
+                "skips `LD I, LONG`, which is 4 bytes long : only XO-CHIP skips both words"
+            ),
+            Finding::SpriteBeyondMemory { i, height, .. } => write!(
+                f,
+                "draws a sprite of {} bytes at 0x{:03X}, beyond the end of memory",
+                height, i
+            ),
+            Finding::NeverReturns { call, .. } => {
+                write!(f, "the subroutine called at 0x{:03X} never returns", call)
+            }
+            Finding::ReturnsFromMain { .. } => {
+                write!(f, "returns from the main program, with an empty stack")
+            }
+            Finding::WritesFontMemory { i: Some(i), .. } => {
+                write!(f, "writes into font memory at 0x{:03X}", i)
+            }
+            Finding::WritesFontMemory { i: None, .. } => {
+                write!(f, "writes into font memory, I points to a font character")
+            }
+            Finding::QuirkSensitive { instruction, .. } => {
+                let quirk = match instruction {
+                    Instruction::ShiftRight { .. } | Instruction::ShiftLeft { .. } => {
+                        "shifts VY into VX, or VX in place"
+                    }
+                    Instruction::StoreRegisters(_) | Instruction::LoadRegisters(_) => {
+                        "increments I, or leaves it unchanged"
+                    }
+                    _ => "jumps to NNN + V0, or to XNN + VX",
+                };
+                write!(
+                    f,
+                    "`{}` depends on the interpreter : it {}",
+                    instruction, quirk
+                )
+            }
+        }
+    }
+}
+
+/// `Report` : the findings, by address, the SUPER-CHIP and XO-CHIP instructions used, with their addresses,
+/// and the suggested quirks.
+pub struct Report {
+    pub findings: Vec<Finding>,
+    pub features: BTreeMap<&'static str, Vec<u16>>,
+    pub platform: Platform,
+    /// `detection` holds the quirks suggested to run the ROM with.
+    pub detection: Detection,
+}
+
+impl Report {
+    pub fn warnings(&self) -> usize {
+        self.findings
+            .iter()
+            .filter(|finding| finding.is_warning())
+            .count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Platform: {}", self.platform)?;
+        for (pattern, addresses) in &self.features {
+            let addresses: Vec<String> = addresses
+                .iter()
+                .map(|address| format!("0x{:03X}", address))
+                .collect();
+            writeln!(f, "    {}  at {}", pattern, addresses.join(", "))?;
+        }
+        writeln!(f, "Quirks: {}", self.detection.quirks)?;
+        for reason in &self.detection.reasons {
+            writeln!(f, "    {}", reason)?;
+        }
+        for finding in &self.findings {
+            writeln!(f, "{}", finding)?;
+        }
+        let warnings = self.warnings();
+        writeln!(
+            f,
+            "{} warnings, {} notes.",
+            warnings,
+            self.findings.len() - warnings
+        )
+    }
+}
+
+/// `Index` : what is known about I while walking a basic block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Index {
+    Unknown,
+    Address(u16),
+    /// I points to a font character (FX29 or FX30).
+    Font,
+}
+
+/// `lint()` will return the report of a ROM loaded at `origin`, whose code starts at `entry_point`.
+pub fn lint(rom: &[u8], origin: u16, entry_point: u16) -> Report {
+    let cfg = ControlFlowGraph::new(disassembler::disassemble(rom, origin, entry_point));
+    let disassembly = &cfg.disassembly;
+    let mut findings = vec![];
+
+    let mut features: BTreeMap<&'static str, Vec<u16>> = BTreeMap::new();
+    let mut platform = Platform::Chip8;
+    for (&address, instruction) in &disassembly.instructions {
+        if instruction.platform() > Platform::Chip8 {
+            features
+                .entry(pattern(instruction))
+                .or_default()
+                .push(address);
+            platform = platform.max(instruction.platform());
+        }
+        let quirk_sensitive = match *instruction {
+            Instruction::ShiftRight { x, y } | Instruction::ShiftLeft { x, y } => x != y,
+            Instruction::StoreRegisters(_) | Instruction::LoadRegisters(_) => true,
+            //B0NN adds V0 either way
+            Instruction::JumpOffset(nnn) => nnn >> 8 != 0,
+            _ => false,
+        };
+        if quirk_sensitive {
+            findings.push(Finding::QuirkSensitive {
+                address,
+                instruction: *instruction,
+            });
+        }
+        if let Instruction::Jump(target) | Instruction::Call(target) = *instruction {
+            //the instruction before the target, if it covers the target
+            let covering = disassembly
+                .instructions
+                .range(..target)
+                .next_back()
+                .filter(|(&start, instruction)| start + instruction.size() > target);
+            if let Some((&start, _)) = covering {
+                findings.push(Finding::JumpsIntoInstruction {
+                    address,
+                    target,
+                    instruction: start,
+                });
+            }
+        }
+        if instruction.is_skip() {
+            let next = address + instruction.size();
+            if let Some(Instruction::LoadIndexLong) = disassembly.instructions.get(&next) {
+                findings.push(Finding::SkipsLongLoad { address });
+            }
+        }
+    }
+
+    findings.extend(unreachable_code(&cfg));
+    findings.extend(index_findings(&cfg, platform));
+    findings.extend(return_findings(&cfg));
+    findings.sort_by_key(|finding| finding.address());
+    debug!("[lint()] Found {} findings.", findings.len());
+    Report {
+        findings,
+        features,
+        platform,
+        detection: detect(rom, origin, entry_point),
+    }
+}
+
+/// `unreachable_code()` finds runs of data which decode to instructions, ending with a jump or a return.
+fn unreachable_code(cfg: &ControlFlowGraph) -> Vec<Finding> {
+    let disassembly = &cfg.disassembly;
+    let is_data = |address: u16| disassembly.kind_at(address) == Some(ByteKind::Data);
+    let end = disassembly.origin + disassembly.bytes.len() as u16;
+    let mut findings = vec![];
+    let mut address = disassembly.origin;
+    while address < end {
+        if !is_data(address) || !is_data(address + 1) {
+            address += 1;
+            continue;
+        }
+        let start = address;
+        let mut instructions = 0;
+        let mut ends_with_jump = false;
+        while let Some(opcode) = disassembly.opcode_at(address) {
+            let instruction = Instruction::decode(opcode);
+            let plausible = is_data(address)
+                && is_data(address + 1)
+                && !matches!(instruction, Instruction::Unknown(_) | Instruction::Sys(_));
+            if !plausible {
+                break;
+            }
+            instructions += 1;
+            address += 2;
+            if matches!(instruction, Instruction::Jump(_) | Instruction::Ret) {
+                ends_with_jump = true;
+                break;
+            }
+        }
+        if ends_with_jump && instructions >= MIN_UNREACHABLE_INSTRUCTIONS {
+            findings.push(Finding::UnreachableCode {
+                address: start,
+                instructions,
+            });
+        }
+        if address == start {
+            address += 2;
+        }
+    }
+    findings
+}
+
+/// `index_findings()` follows I through every basic block, to find sprites read beyond the end of memory
+/// and writes into font memory.
+fn index_findings(cfg: &ControlFlowGraph, platform: Platform) -> Vec<Finding> {
+    let disassembly = &cfg.disassembly;
+    let memory_size = match platform {
+        Platform::XoChip => XO_CHIP_MEMORY_SIZE,
+        _ => MEMORY_SIZE,
+    };
+    let mut findings = vec![];
+    for block in cfg.blocks.values() {
+        let mut i = Index::Unknown;
+        let mut address = block.start;
+        while address < block.end {
+            let instruction = disassembly.instructions[&address];
+            let written = match instruction {
+                Instruction::StoreRegisters(x) => Some(x as u16),
+                Instruction::StoreBcd(_) => Some(2),
+                Instruction::StoreRange { x, y } => {
+                    Some((x as i32 - y as i32).unsigned_abs() as u16)
+                }
+                _ => None,
+            };
+            match (i, written) {
+                (Index::Address(start), Some(_)) if start < FONT_MEMORY_END => {
+                    findings.push(Finding::WritesFontMemory {
+                        address,
+                        i: Some(start),
+                    })
+                }
+                (Index::Font, Some(_)) => {
+                    findings.push(Finding::WritesFontMemory { address, i: None })
+                }
+                _ => (),
+            }
+            match instruction {
+                Instruction::LoadIndex(nnn) => i = Index::Address(nnn),
+                Instruction::LoadIndexLong => {
+                    i = Index::Address(disassembly.opcode_at(address + 2).unwrap_or(0))
+                }
+                Instruction::LoadFont(_) | Instruction::LoadBigFont(_) => i = Index::Font,
+                //FX55 and FX65 may move I, depending on the interpreter
+                Instruction::AddIndex(_)
+                | Instruction::StoreRegisters(_)
+                | Instruction::LoadRegisters(_) => i = Index::Unknown,
+                Instruction::Draw { n, .. } => {
+                    let height = if n == 0 { 32 } else { n as u32 };
+                    if let Index::Address(start) = i {
+                        if start as u32 + height > memory_size {
+                            findings.push(Finding::SpriteBeyondMemory {
+                                address,
+                                i: start as u32,
+                                height,
+                            });
+                        }
+                    }
+                }
+                _ => (),
+            }
+            address += instruction.size();
+        }
+    }
+    findings
+}
+
+/// `return_findings()` walks every subroutine, and the main program, without entering the subroutines they call :
+/// a subroutine should reach a return (or an exit, or a computed jump), the main program should not.
+fn return_findings(cfg: &ControlFlowGraph) -> Vec<Finding> {
+    let disassembly = &cfg.disassembly;
+    let mut calls: BTreeMap<u16, u16> = BTreeMap::new();
+    for (&address, instruction) in &disassembly.instructions {
+        if let Instruction::Call(target) = *instruction {
+            calls.entry(target).or_insert(address);
+        }
+    }
+
+    let mut findings = vec![];
+    let (returns, _) = walk(cfg, disassembly.entry_point);
+    for address in returns {
+        findings.push(Finding::ReturnsFromMain { address });
+    }
+    for (&address, &call) in &calls {
+        let (returns, may_end) = walk(cfg, address);
+        if cfg.blocks.contains_key(&address) && returns.is_empty() && !may_end {
+            findings.push(Finding::NeverReturns { address, call });
+        }
+    }
+    findings
+}
+
+/// `walk()` follows the blocks reachable from `start`, without entering calls. It returns the addresses
+/// of the returns reached, and whether an exit or a computed jump (which may lead anywhere) was reached.
+fn walk(cfg: &ControlFlowGraph, start: u16) -> (BTreeSet<u16>, bool) {
+    let disassembly = &cfg.disassembly;
+    let mut returns = BTreeSet::new();
+    let mut may_end = false;
+    let mut visited = BTreeSet::new();
+    let mut pending = vec![start];
+    while let Some(address) = pending.pop() {
+        if !visited.insert(address) {
+            continue;
+        }
+        let block = match cfg.blocks.get(&address) {
+            Some(block) => block,
+            None => continue,
+        };
+        match disassembly
+            .instructions
+            .range(block.start..block.end)
+            .next_back()
+        {
+            Some((&last, Instruction::Ret)) => {
+                returns.insert(last);
+            }
+            Some((_, Instruction::Exit | Instruction::JumpOffset(_))) => may_end = true,
+            _ => (),
+        }
+        for edge in &block.edges {
+            match (edge.kind, edge.target) {
+                (EdgeKind::Call, _) | (_, None) => (),
+                (_, Some(target)) => pending.push(target),
+            }
+        }
+    }
+    (returns, may_end)
+}
+
+/// `pattern()` returns the opcode pattern of a SUPER-CHIP or XO-CHIP instruction, and its mnemonic.
+fn pattern(instruction: &Instruction) -> &'static str {
+    match instruction {
+        Instruction::ScrollDown(_) => "00CN  SCD n",
+        Instruction::ScrollUp(_) => "00DN  SCU n",
+        Instruction::ScrollRight => "00FB  SCR",
+        Instruction::ScrollLeft => "00FC  SCL",
+        Instruction::Exit => "00FD  EXIT",
+        Instruction::LowRes => "00FE  LOW",
+        Instruction::HighRes => "00FF  HIGH",
+        Instruction::StoreRange { .. } => "5XY2  LD [I], Vx-Vy",
+        Instruction::LoadRange { .. } => "5XY3  LD Vx-Vy, [I]",
+        Instruction::Draw { .. } => "DXY0  DRW Vx, Vy, 0",
+        Instruction::LoadIndexLong => "F000  LD I, LONG",
+        Instruction::Plane(_) => "FN01  PLANE n",
+        Instruction::Audio => "F002  AUDIO",
+        Instruction::LoadBigFont(_) => "FX30  LD HF, Vx",
+        Instruction::SetPitch(_) => "FX3A  LD PITCH, Vx",
+        Instruction::StoreFlags(_) => "FX75  LD R, Vx",
+        Instruction::LoadFlags(_) => "FX85  LD Vx, R",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `findings()` lints a ROM loaded and started at 0x200.
+    fn findings(rom: &[u8]) -> Vec<Finding> {
+        lint(rom, 0x200, 0x200).findings
+    }
+
+    #[test]
+    fn clean_roms_have_no_findings() {
+        //draw the sprite at 0x20C, call a subroutine which returns, loop
+        let rom = [
+            0xA2, 0x0C, 0xD0, 0x11, 0x22, 0x08, 0x12, 0x06, 0x70, 0x01, 0x00, 0xEE, 0x80,
+        ];
+        let report = lint(&rom, 0x200, 0x200);
+        assert_eq!(report.findings, []);
+        assert_eq!(report.platform, Platform::Chip8);
+        assert!(report.to_string().ends_with("0 warnings, 0 notes.\n"));
+    }
+
+    #[test]
+    fn code_jumped_over_is_unreachable() {
+        //jump over 4 instructions, which end with a jump
+        let rom = [
+            0x12, 0x0A, 0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x12, 0x02, 0x12, 0x0A,
+        ];
+        assert_eq!(
+            findings(&rom),
+            [Finding::UnreachableCode {
+                address: 0x202,
+                instructions: 4
+            }]
+        );
+    }
+
+    #[test]
+    fn jumps_into_the_middle_of_an_instruction_are_found() {
+        //V0 := 0x12, jump to its second byte
+        let found = findings(&[0x60, 0x12, 0x12, 0x01]);
+        assert_eq!(
+            found,
+            [Finding::JumpsIntoInstruction {
+                address: 0x202,
+                target: 0x201,
+                instruction: 0x200
+            }]
+        );
+        assert_eq!(
+            found[0].to_string(),
+            "0x202: warning: goes to 0x201, in the middle of the instruction at 0x200"
+        );
+    }
+
+    #[test]
+    fn skips_over_long_loads_are_found() {
+        let rom = [0x30, 0x01, 0xF0, 0x00, 0x03, 0x00, 0x12, 0x06];
+        assert_eq!(findings(&rom), [Finding::SkipsLongLoad { address: 0x200 }]);
+    }
+
+    #[test]
+    fn sprites_beyond_the_end_of_memory_are_found() {
+        //I := 0xFFE, draw 5 bytes
+        let rom = [0xAF, 0xFE, 0xD0, 0x15, 0x12, 0x04];
+        assert_eq!(
+            findings(&rom),
+            [Finding::SpriteBeyondMemory {
+                address: 0x202,
+                i: 0xFFE,
+                height: 5
+            }]
+        );
+    }
+
+    #[test]
+    fn missing_returns_are_found() {
+        //the subroutine at 0x204 loops forever
+        let rom = [0x22, 0x04, 0x12, 0x02, 0x12, 0x04];
+        assert_eq!(
+            findings(&rom),
+            [Finding::NeverReturns {
+                address: 0x204,
+                call: 0x200
+            }]
+        );
+        assert_eq!(
+            findings(&[0x00, 0xEE]),
+            [Finding::ReturnsFromMain { address: 0x200 }]
+        );
+    }
+
+    #[test]
+    fn writes_into_font_memory_are_found() {
+        //I := 0x100, BCD of V0
+        let rom = [0xA1, 0x00, 0xF0, 0x33, 0x12, 0x04];
+        assert_eq!(
+            findings(&rom),
+            [Finding::WritesFontMemory {
+                address: 0x202,
+                i: Some(0x100)
+            }]
+        );
+        //I := the font of V0, BCD of V0
+        let rom = [0xF0, 0x29, 0xF0, 0x33, 0x12, 0x04];
+        assert_eq!(
+            findings(&rom),
+            [Finding::WritesFontMemory {
+                address: 0x202,
+                i: None
+            }]
+        );
+    }
+
+    #[test]
+    fn quirk_sensitive_instructions_are_notes() {
+        //V0 := V1 >> 1
+        let report = lint(&[0x80, 0x16, 0x12, 0x02], 0x200, 0x200);
+        assert_eq!(
+            report.findings,
+            [Finding::QuirkSensitive {
+                address: 0x200,
+                instruction: Instruction::ShiftRight { x: 0, y: 1 }
+            }]
+        );
+        assert_eq!(report.warnings(), 0);
+    }
+}
@@ -1,45 +1,718 @@
 extern crate env_logger;
 extern crate log;
 
-use chip8::drivers::Drivers;
-use chip8::vm::Machine;
+use chip8::assembler;
+use chip8::database::{self, RomDatabase, RomSettings};
+use chip8::debugger::{
+    trace_diff, Coverage, Debugger, Profiler, SanityChecker, SmcDetector, Strictness, Tracer,
+};
+use chip8::decompiler;
+use chip8::disassembler::{self, ControlFlowGraph};
+use chip8::drivers::{load_rom, max_rom_size, Drivers, Rom, RomError};
+use chip8::lint;
+use chip8::octo;
+use chip8::patch;
+use chip8::vm::{
+    FontSet, FontStyle, Machine, Platform, Quirks, CHIP_8X_PROGRAM_START, MEGACHIP_MEMORY_SIZE,
+    PROGRAM_START, XO_CHIP_MEMORY_SIZE,
+};
 use log::{debug, info};
+use std::cmp;
 use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
 use std::thread;
 use std::time::Duration;
 
+/// Instructions per frame of MegaChip ROMs, unless their settings have a tick rate.
+const MEGACHIP_TICK_RATE: u32 = 1000;
+
+const USAGE: &str = "Usage:
+    chip8 [--trace <trace-file>] [--coverage <report-file>] [--heatmap <image-file>]
+          [--profile <report-file>] [--profile-folded <folded-stacks-file>]
+          [--strict <warn|halt>] [--smc <report-file>] [--debug]
+          [--platform <auto|chip8|schip|xochip>] [--database <json-or-toml-file>]
+          [--patch <ips-or-bps-file>]... [--load-address <address>] [--entry-point <address>]
+          [--font <vip|dream6800|eti660|chip48|font-file>] [--font-base <address>] [--chip8x] [--megachip]
+          <rom-file>
+    chip8 trace-diff [--context <lines>] <trace-file> <trace-file>
+    chip8 disasm [--load-address <address>] [--entry-point <address>] [--chip8x] <rom-file>
+    chip8 decompile [--load-address <address>] [--entry-point <address>] [--chip8x] <rom-file>
+    chip8 cfg [--load-address <address>] [--entry-point <address>] [--chip8x] <rom-file> [--format dot]
+    chip8 lint [--load-address <address>] [--entry-point <address>] [--chip8x] <rom-file>
+    chip8 info <rom-file> [--write-sidecar]
+    chip8 asm <source-file> [-o <rom-file>]
+    chip8 octo <octo-source-file> [-o <rom-file>]";
+
 fn main() {
     env_logger::init();
     //Read from commandline.
-    let args: Vec<String> = env::args().collect();
-    let filename = &args[1];
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("trace-diff") => diff_traces(&args[1..]),
+        Some("disasm") => disassemble_rom(&args[1..]),
+        Some("decompile") => decompile_rom(&args[1..]),
+        Some("cfg") => export_cfg(&args[1..]),
+        Some("lint") => lint_rom(&args[1..]),
+        Some("info") => show_info(&args[1..]),
+        Some("asm") => assemble_source(&args[1..]),
+        Some("octo") => compile_octo(&args[1..]),
+        _ => run_rom(&args),
+    }
+}
+
+fn run_rom(args: &[String]) {
+    let mut filename = None;
+    let mut trace_filename = None;
+    let mut coverage_filename = None;
+    let mut heatmap_filename = None;
+    let mut profile_filename = None;
+    let mut folded_stacks_filename = None;
+    let mut strictness = None;
+    let mut smc_filename = None;
+    let mut debugger = None;
+    let mut platform = None;
+    let mut database_filename = None;
+    let mut patch_filenames = vec![];
+    let mut load_address = None;
+    let mut entry_point = None;
+    let mut font = None;
+    let mut font_base = None;
+    let mut chip8x = false;
+    let mut megachip = false;
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trace" => trace_filename = Some(args.next().unwrap_or_else(|| exit_with_usage())),
+            "--coverage" => {
+                coverage_filename = Some(args.next().unwrap_or_else(|| exit_with_usage()))
+            }
+            "--heatmap" => {
+                heatmap_filename = Some(args.next().unwrap_or_else(|| exit_with_usage()))
+            }
+            "--profile" => {
+                profile_filename = Some(args.next().unwrap_or_else(|| exit_with_usage()))
+            }
+            "--profile-folded" => {
+                folded_stacks_filename = Some(args.next().unwrap_or_else(|| exit_with_usage()))
+            }
+            "--strict" => {
+                strictness = match args.next().as_deref() {
+                    Some("warn") => Some(Strictness::Warn),
+                    Some("halt") => Some(Strictness::Halt),
+                    _ => exit_with_usage(),
+                }
+            }
+            "--smc" => smc_filename = Some(args.next().unwrap_or_else(|| exit_with_usage())),
+            "--debug" => debugger = Some(Debugger::new()),
+            "--platform" => {
+                platform = match args.next().as_deref() {
+                    Some("auto") => None,
+                    Some(name) => {
+                        Some(Platform::from_name(name).unwrap_or_else(|| exit_with_usage()))
+                    }
+                    None => exit_with_usage(),
+                }
+            }
+            "--database" => {
+                database_filename = Some(args.next().unwrap_or_else(|| exit_with_usage()))
+            }
+            "--patch" => patch_filenames.push(args.next().unwrap_or_else(|| exit_with_usage())),
+            "--load-address" => {
+                load_address = Some(parse_address(args.next()).unwrap_or_else(|| exit_with_usage()))
+            }
+            "--entry-point" => {
+                entry_point = Some(parse_address(args.next()).unwrap_or_else(|| exit_with_usage()))
+            }
+            "--font" => font = Some(args.next().unwrap_or_else(|| exit_with_usage())),
+            "--font-base" => {
+                font_base = Some(parse_address(args.next()).unwrap_or_else(|| exit_with_usage()))
+            }
+            "--chip8x" => chip8x = true,
+            "--megachip" => megachip = true,
+            _ => filename = Some(arg),
+        }
+    }
+    let filename = filename.unwrap_or_else(|| exit_with_usage());
 
     //initialize
     debug!("[main()] Initializing the Machine.");
     let mut machine: Machine = Machine::new();
+    if megachip {
+        machine.enable_megachip();
+    }
+    if let Some(trace_filename) = trace_filename.as_ref() {
+        debug!("[main()] Tracing instructions to {}.", trace_filename);
+        machine.tracer =
+            Some(Tracer::create(trace_filename).expect("Error: Could not create trace file."));
+    }
+    if profile_filename.is_some() || folded_stacks_filename.is_some() {
+        debug!("[main()] Profiling subroutines.");
+        machine.profiler = Some(Profiler::new());
+    }
     debug!("[main()] Initializing Drivers.");
     let mut drivers: Drivers = Drivers::init_drivers();
-    drivers.rom_reader.read_rom(filename);
+    if let Some(database_filename) = database_filename {
+        debug!("[main()] Reading the ROM database {}.", database_filename);
+        let database = RomDatabase::load(&database_filename).unwrap_or_else(|error| {
+            eprintln!("Error: {}", error);
+            process::exit(1);
+        });
+        drivers.rom_reader.database.extend(database);
+    }
 
-    //load ROM in memory
-    debug!("[main()] Loding ROM in memory.");
-    machine.memory.load_data(&drivers.rom_reader.rom);
+    //read the ROM, Octo sources and cartridges are compiled first
+    debug!("[main()] Reading the ROM.");
+    //the platform may come with the ROM, the ROM is checked again once it is known
+    let max_rom_size = max_rom_size(memory_size(platform, megachip), load_address.unwrap_or(0));
+    drivers.rom_reader.max_size = max_rom_size;
+    drivers
+        .rom_reader
+        .read_rom(&filename)
+        .unwrap_or_else(|error| exit_with_rom_error(error));
+    let mut rom = drivers.rom_reader.rom[..drivers.rom_reader.size].to_vec();
+    for patch_filename in &patch_filenames {
+        debug!("[main()] Applying the patch {}.", patch_filename);
+        rom = apply_patch(patch_filename, &rom, max_rom_size);
+    }
+
+    //the settings of the ROM, from the database and the ROM file
+    let settings = drivers.rom_reader.settings.clone().unwrap_or_default();
+    let (load_address, entry_point) = program_layout(load_address, entry_point, &settings, chip8x);
+
+    //the quirks of the platform asked for, of the one in the database,
+    //or of the one the ROM seems to be written for, changed as the database says
+    let (platform, mut quirks) = match platform.or(settings.platform) {
+        Some(platform) => (platform, Quirks::for_platform(platform)),
+        None => {
+            let detection = lint::detect(&rom, load_address, entry_point);
+            for reason in &detection.reasons {
+                info!("[main()] Detected {} : {}.", detection.platform, reason);
+            }
+            (detection.platform, detection.quirks)
+        }
+    };
+    settings.apply_quirks(&mut quirks);
+    info!("[main()] Running as {} with quirks : {}.", platform, quirks);
+    machine.quirks = quirks;
+    machine.set_platform(platform);
+
+    check_rom_size(&filename, &rom, machine.memory.cells.len(), load_address);
+    //the font goes below the program, which it would corrupt otherwise
+    if font.is_some() || font_base.is_some() {
+        let font_set = match font.as_deref() {
+            Some(name) => read_font(name),
+            None => FontSet::new(),
+        };
+        let font_base = font_base.unwrap_or(0);
+        check_font_base(&font_set, font_base, load_address);
+        debug!("[main()] Loading the font at 0x{:03X}.", font_base);
+        machine.memory.load_font(&font_set, font_base);
+    }
+    if chip8x {
+        machine.enable_chip8x();
+    }
+    machine.load_program(&rom, load_address, entry_point);
+
+    //the debugging tools follow the 4 KB address space and the program, not the 16 MB memory of MegaChip
+    let tracked_size = cmp::max(0x1000, load_address as usize + rom.len());
+    if coverage_filename.is_some() || heatmap_filename.is_some() {
+        debug!("[main()] Counting memory coverage.");
+        machine.coverage = Some(Coverage::new(tracked_size));
+    }
+    if let Some(strictness) = strictness {
+        debug!(
+            "[main()] Checking the ROM in strict mode ({:?}).",
+            strictness
+        );
+        machine.sanity_checker = Some(SanityChecker::new(strictness, tracked_size));
+    }
+    if trace_filename.is_some() || debugger.is_some() || smc_filename.is_some() {
+        debug!("[main()] Detecting self-modifying code.");
+        machine.smc_detector = Some(SmcDetector::new(tracked_size));
+    }
+
+    if let Some(title) = settings.title.as_ref() {
+        info!(
+            "[main()] Running {} by {}.",
+            title,
+            settings.author.as_deref().unwrap_or("an unknown author")
+        );
+        drivers.display_driver.set_title(title);
+    }
+    if settings.background.is_some() || settings.foreground.is_some() {
+        drivers.display_driver.set_colors(
+            settings.background.unwrap_or([0, 0, 0]),
+            settings.foreground.unwrap_or([0, 255, 0]),
+        );
+    }
+    for (chip8_key, key_name) in &settings.keymap {
+        drivers.input_driver.bind(key_name, *chip8_key);
+    }
+    //one instruction every 2 ms, unless the ROM needs a tick rate of its own (instructions per frame, at 60 Hz),
+    //MegaChip draws whole frames of big sprites, it runs at 1000 instructions per frame
+    let tick_rate = match megachip {
+        true => settings.tick_rate.or(Some(MEGACHIP_TICK_RATE)),
+        false => settings.tick_rate,
+    };
+    let cycle_duration = match tick_rate {
+        Some(tick_rate) => Duration::from_micros(1_000_000 / (60 * tick_rate as u64)),
+        None => Duration::from_millis(2),
+    };
 
     //while true
     info!("[main()] Listening to key-board events.");
     while let Ok(keys) = drivers.input_driver.process_events() {
         //process input
         info!("[main()] Keys found {:?}", keys);
-        let output_state = machine.process_keys(keys);
+        let output_state = match debugger.as_mut() {
+            Some(debugger) => match debugger.process_keys(&mut machine, keys) {
+                Some(output_state) => output_state,
+                None => break,
+            },
+            None => machine.process_keys(keys),
+        };
         //update
         if output_state.vram.state_changed {
             info!("[main()] Drawing canvas.");
-            drivers.display_driver.draw_canvas(&output_state.vram.cells);
+            drivers.display_driver.draw_canvas(output_state.vram);
         }
         if output_state.play_sound {
             debug!("[main()] One day it will beep!");
         }
-        
-        thread::sleep(Duration::from_millis(2));
+        if output_state.sound_changed {
+            drivers.audio_driver.play(output_state.sound);
+        }
+        drivers.audio_driver.update();
+
+        thread::sleep(cycle_duration);
+    }
+
+    if let Some(coverage) = machine.coverage.as_ref() {
+        if let Some(coverage_filename) = coverage_filename {
+            debug!("[main()] Writing coverage report to {}.", coverage_filename);
+            coverage
+                .write_report(&coverage_filename)
+                .expect("Error: Could not write coverage report.");
+        }
+        if let Some(heatmap_filename) = heatmap_filename {
+            debug!("[main()] Writing heatmap to {}.", heatmap_filename);
+            coverage
+                .write_heatmap(&heatmap_filename)
+                .expect("Error: Could not write heatmap.");
+        }
+    }
+    if let Some(profiler) = machine.profiler.as_ref() {
+        if let Some(profile_filename) = profile_filename {
+            debug!("[main()] Writing profile to {}.", profile_filename);
+            profiler
+                .write_report(&profile_filename)
+                .expect("Error: Could not write profile.");
+        }
+        if let Some(folded_stacks_filename) = folded_stacks_filename {
+            debug!(
+                "[main()] Writing folded stacks to {}.",
+                folded_stacks_filename
+            );
+            profiler
+                .write_folded_stacks(&folded_stacks_filename)
+                .expect("Error: Could not write folded stacks.");
+        }
+    }
+    if let (Some(smc_detector), Some(smc_filename)) = (machine.smc_detector.as_ref(), smc_filename)
+    {
+        debug!(
+            "[main()] Writing self-modifying code report to {}.",
+            smc_filename
+        );
+        smc_detector
+            .write_report(&smc_filename)
+            .expect("Error: Could not write self-modifying code report.");
+    }
+}
+
+fn diff_traces(args: &[String]) {
+    let mut filenames = vec![];
+    let mut context = 3;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--context" => {
+                context = args
+                    .next()
+                    .and_then(|lines| lines.parse().ok())
+                    .unwrap_or_else(|| exit_with_usage())
+            }
+            _ => filenames.push(arg.as_str()),
+        }
+    }
+    if filenames.len() != 2 {
+        exit_with_usage();
+    }
+
+    let traces: Vec<Vec<trace_diff::TraceLine>> = filenames
+        .iter()
+        .map(|filename| {
+            let text = fs::read_to_string(filename).unwrap_or_else(|error| {
+                eprintln!("Error: Could not read {} : {}", filename, error);
+                process::exit(2);
+            });
+            trace_diff::parse_trace(&text)
+        })
+        .collect();
+    let (skip_a, skip_b) = trace_diff::align(&traces[0], &traces[1]);
+    let (a, b) = (&traces[0][skip_a..], &traces[1][skip_b..]);
+    match trace_diff::first_divergence(a, b) {
+        Some(divergence) => {
+            print!(
+                "{}",
+                trace_diff::report((filenames[0], filenames[1]), a, b, &divergence, context)
+            );
+            process::exit(1);
+        }
+        None => println!("Traces are identical ({} instructions).", a.len()),
     }
 }
+
+fn disassemble_rom(args: &[String]) {
+    let program = read_program(args);
+    let disassembly =
+        disassembler::disassemble(&program.rom, program.load_address, program.entry_point);
+    print!("{}", disassembly.listing());
+}
+
+fn decompile_rom(args: &[String]) {
+    let program = read_program(args);
+    print!(
+        "{}",
+        decompiler::decompile(&program.rom, program.load_address, program.entry_point)
+    );
+}
+
+fn export_cfg(args: &[String]) {
+    let mut program_args = vec![];
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next().as_deref() {
+                Some("dot") => (),
+                _ => exit_with_usage(),
+            },
+            _ => program_args.push(arg),
+        }
+    }
+    let program = read_program(&program_args);
+    let disassembly =
+        disassembler::disassemble(&program.rom, program.load_address, program.entry_point);
+    print!("{}", ControlFlowGraph::new(disassembly).to_dot());
+}
+
+/// `lint_rom()` prints the report, and exits with 1 when there are warnings.
+fn lint_rom(args: &[String]) {
+    let program = read_program(args);
+    let report = lint::lint(&program.rom, program.load_address, program.entry_point);
+    print!("{}", report);
+    if report.warnings() > 0 {
+        process::exit(1);
+    }
+}
+
+/// `show_info()` prints what is known about a ROM : its settings, and the platform and quirks it runs with.
+/// With `--write-sidecar`, they are written to the sidecar file of the ROM.
+fn show_info(args: &[String]) {
+    let mut filename = None;
+    let mut write_sidecar = false;
+    for arg in args {
+        match arg.as_str() {
+            "--write-sidecar" => write_sidecar = true,
+            _ => filename = Some(arg.as_str()),
+        }
+    }
+    let filename = filename.unwrap_or_else(|| exit_with_usage());
+    let rom = load_rom(filename, max_rom_size(memory_size(None, false), 0))
+        .unwrap_or_else(|error| exit_with_rom_error(error));
+    let mut settings = rom_settings(&rom);
+    let (load_address, entry_point) = program_layout(None, None, &settings, false);
+    let detection = lint::detect(&rom.bytes, load_address, entry_point);
+    let (platform, mut quirks) = match settings.platform {
+        Some(platform) => (platform, Quirks::for_platform(platform)),
+        None => (detection.platform, detection.quirks),
+    };
+    check_rom_size(filename, &rom.bytes, platform.memory_size(), load_address);
+    settings.apply_quirks(&mut quirks);
+
+    println!("File: {}", filename);
+    println!("Size: {} bytes", rom.bytes.len());
+    println!("SHA-1: {}", database::sha1(&rom.bytes));
+    if let Some(title) = settings.title.as_ref() {
+        println!("Title: {}", title);
+    }
+    if let Some(author) = settings.author.as_ref() {
+        println!("Author: {}", author);
+    }
+    match settings.platform {
+        Some(_) => println!("Platform: {}", platform),
+        None => println!("Platform: {} (detected)", platform),
+    }
+    println!("Quirks: {}", quirks);
+    println!("Load address: 0x{:03X}", load_address);
+    println!("Entry point: 0x{:03X}", entry_point);
+    if let Some(tick_rate) = settings.tick_rate {
+        println!("Tick rate: {} instructions per frame", tick_rate);
+    }
+    for (name, color) in [
+        ("Background", settings.background),
+        ("Foreground", settings.foreground),
+    ] {
+        if let Some([r, g, b]) = color {
+            println!("{}: #{:02X}{:02X}{:02X}", name, r, g, b);
+        }
+    }
+    for (chip8_key, key_name) in &settings.keymap {
+        println!("Key {:X}: {}", chip8_key, key_name);
+    }
+
+    if write_sidecar {
+        //the sidecar is read back only next to a ROM file
+        if !Path::new(filename).is_file() {
+            eprintln!(
+                "Error: {} is not a ROM file, it can not have a sidecar.",
+                filename
+            );
+            process::exit(1);
+        }
+        settings.platform = Some(platform);
+        settings.quirks = quirks
+            .by_name()
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value))
+            .collect();
+        settings.load_address = Some(load_address);
+        settings.entry_point = Some(entry_point);
+        let sidecar = format!("{}.toml", filename);
+        fs::write(&sidecar, settings.to_toml()).unwrap_or_else(|error| {
+            eprintln!("Error: Could not write {} : {}", sidecar, error);
+            process::exit(2);
+        });
+        println!("Wrote {}.", sidecar);
+    }
+}
+
+/// `apply_patch()` returns `rom` changed by the patch file, it exits when the patch can not be applied.
+fn apply_patch(patch_filename: &str, rom: &[u8], max_size: usize) -> Vec<u8> {
+    let patch_data = fs::read(patch_filename).unwrap_or_else(|error| {
+        eprintln!("Error: Could not read {} : {}", patch_filename, error);
+        process::exit(2);
+    });
+    let rom = patch::apply(&patch_data, rom).unwrap_or_else(|error| {
+        eprintln!("Error: {} : {}", patch_filename, error);
+        process::exit(1);
+    });
+    if rom.len() > max_size {
+        exit_with_rom_error(RomError::TooLarge {
+            filename: format!("the ROM patched with {}", patch_filename),
+            size: rom.len(),
+            max_size,
+        });
+    }
+    rom
+}
+
+/// `read_font()` returns the built-in font called `name`, or the font read from the file called `name`.
+fn read_font(name: &str) -> FontSet {
+    if let Some(style) = FontStyle::from_name(name) {
+        return FontSet::builtin(style);
+    }
+    let data = fs::read(name).unwrap_or_else(|error| {
+        eprintln!("Error: Could not read {} : {}", name, error);
+        process::exit(2);
+    });
+    FontSet::from_bytes(&data).unwrap_or_else(|error| {
+        eprintln!("Error: {} : {}", name, error);
+        process::exit(1);
+    })
+}
+
+/// `parse_address()` reads a memory address, in hexadecimal (`0x600`) or decimal.
+fn parse_address(text: Option<String>) -> Option<u16> {
+    let text = text?;
+    let address = match text.strip_prefix("0x") {
+        Some(digits) => u16::from_str_radix(digits, 16).ok()?,
+        None => text.parse().ok()?,
+    };
+    Some(address).filter(|&address| address < 0x1000)
+}
+
+/// `rom_settings()` returns the settings of a ROM found in the embedded database, replaced by those which came
+/// with the file.
+fn rom_settings(rom: &Rom) -> RomSettings {
+    let mut settings = RomDatabase::embedded()
+        .lookup(&rom.bytes)
+        .cloned()
+        .unwrap_or_default();
+    if let Some(rom_settings) = rom.settings.clone() {
+        settings.merge(rom_settings);
+    }
+    settings
+}
+
+/// `program_layout()` returns where a ROM is loaded and where it starts : as asked, as its settings say,
+/// or at 0x200 (0x300 for CHIP-8X). The program starts where it is loaded, unless its entry point is elsewhere.
+fn program_layout(
+    load_address: Option<u16>,
+    entry_point: Option<u16>,
+    settings: &RomSettings,
+    chip8x: bool,
+) -> (u16, u16) {
+    let load_address = load_address
+        .or(settings.load_address)
+        .unwrap_or(match chip8x {
+            true => CHIP_8X_PROGRAM_START,
+            false => PROGRAM_START,
+        });
+    let entry_point = entry_point.or(settings.entry_point).unwrap_or(load_address);
+    (load_address, entry_point)
+}
+
+/// `Program` : a ROM to analyze, where it is loaded and where it starts.
+struct Program {
+    rom: Vec<u8>,
+    load_address: u16,
+    entry_point: u16,
+}
+
+/// `read_program()` reads the ROM named in `args`, loaded and started as `--load-address`, `--entry-point`
+/// and `--chip8x` say, or as its settings say.
+fn read_program(args: &[String]) -> Program {
+    let mut filename = None;
+    let mut load_address = None;
+    let mut entry_point = None;
+    let mut chip8x = false;
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--load-address" => {
+                load_address = Some(parse_address(args.next()).unwrap_or_else(|| exit_with_usage()))
+            }
+            "--entry-point" => {
+                entry_point = Some(parse_address(args.next()).unwrap_or_else(|| exit_with_usage()))
+            }
+            "--chip8x" => chip8x = true,
+            _ if filename.is_none() => filename = Some(arg),
+            _ => exit_with_usage(),
+        }
+    }
+    let filename = filename.unwrap_or_else(|| exit_with_usage());
+    let rom = load_rom(&filename, max_rom_size(memory_size(None, false), 0))
+        .unwrap_or_else(|error| exit_with_rom_error(error));
+    let settings = rom_settings(&rom);
+    let (load_address, entry_point) = program_layout(load_address, entry_point, &settings, chip8x);
+    check_rom_size(
+        &filename,
+        &rom.bytes,
+        memory_size(settings.platform, false),
+        load_address,
+    );
+    Program {
+        rom: rom.bytes,
+        load_address,
+        entry_point,
+    }
+}
+
+/// `memory_size()` returns the size of the memory of the machine running a ROM : 16 MB with MegaChip,
+/// the memory of its platform, or the 64 KB of XO-CHIP (the most) while the platform is not known.
+fn memory_size(platform: Option<Platform>, megachip: bool) -> usize {
+    match (megachip, platform) {
+        (true, _) => MEGACHIP_MEMORY_SIZE,
+        (false, Some(platform)) => platform.memory_size(),
+        (false, None) => XO_CHIP_MEMORY_SIZE,
+    }
+}
+
+/// `check_rom_size()` exits when `rom` does not fit in a memory of `memory_size` bytes from `load_address`.
+fn check_rom_size(filename: &str, rom: &[u8], memory_size: usize, load_address: u16) {
+    let max_size = max_rom_size(memory_size, load_address);
+    if rom.len() > max_size {
+        exit_with_rom_error(RomError::TooLarge {
+            filename: filename.to_string(),
+            size: rom.len(),
+            max_size,
+        });
+    }
+}
+
+/// `check_font_base()` exits when the font at `font_base` would run past `load_address`, into the program.
+fn check_font_base(font_set: &FontSet, font_base: u16, load_address: u16) {
+    let font_end = font_base as usize + font_set.fonts.len() + font_set.big_fonts.len();
+    if font_end > load_address as usize {
+        eprintln!(
+            "Error: The font at 0x{:03X} ends at 0x{:03X}, past the program loaded at 0x{:03X}.",
+            font_base, font_end, load_address
+        );
+        process::exit(1);
+    }
+}
+
+/// `exit_with_rom_error()` exits with 2 when the ROM file could not be read, and with 1 when it is not a ROM.
+fn exit_with_rom_error(error: RomError) -> ! {
+    eprintln!("Error: {}", error);
+    match error {
+        RomError::NotFound(_) | RomError::Unreadable { .. } => process::exit(2),
+        _ => process::exit(1),
+    }
+}
+
+fn assemble_source(args: &[String]) {
+    build_rom(args, |filename| {
+        assembler::assemble_file(filename).map_err(|error| error.to_string())
+    });
+}
+
+fn compile_octo(args: &[String]) {
+    build_rom(args, compile_octo_file);
+}
+
+/// `compile_octo_file()` compiles an Octo source file, the errors start with the file name.
+fn compile_octo_file(filename: &str) -> Result<Vec<u8>, String> {
+    let source = fs::read_to_string(filename)
+        .map_err(|error| format!("Could not read {} : {}", filename, error))?;
+    octo::compile(&source).map_err(|error| format!("{}:{}", filename, error))
+}
+
+/// `build_rom()` builds the ROM of a source file with `build` and writes it to the `-o` file,
+/// or next to the source by default.
+fn build_rom(args: &[String], build: impl Fn(&str) -> Result<Vec<u8>, String>) {
+    let mut filename = None;
+    let mut rom_filename = None;
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => rom_filename = Some(args.next().unwrap_or_else(|| exit_with_usage())),
+            _ => filename = Some(arg),
+        }
+    }
+    let filename = filename.unwrap_or_else(|| exit_with_usage());
+    let rom_filename = rom_filename.unwrap_or_else(|| {
+        Path::new(&filename)
+            .with_extension("ch8")
+            .display()
+            .to_string()
+    });
+
+    let rom = build(&filename).unwrap_or_else(|error| {
+        eprintln!("Error: {}", error);
+        process::exit(1);
+    });
+    fs::write(&rom_filename, &rom).unwrap_or_else(|error| {
+        eprintln!("Error: Could not write {} : {}", rom_filename, error);
+        process::exit(2);
+    });
+    println!("Wrote {} bytes to {}.", rom.len(), rom_filename);
+}
+
+fn exit_with_usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
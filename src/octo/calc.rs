@@ -0,0 +1,132 @@
+//! `:calc` expressions. Like Octo, operators have no precedence : an expression is evaluated from right to left,
+//! unless parentheses say otherwise. `{ 2 * 3 + 1 }` is 8, `{ ( 2 * 3 ) + 1 }` is 7.
+
+use std::collections::HashMap;
+
+/// `Calculator` : the tokens of an expression and the values of the names it may use.
+pub struct Calculator<'a> {
+    tokens: &'a [String],
+    position: usize,
+    names: &'a HashMap<String, f64>,
+}
+
+impl<'a> Calculator<'a> {
+    /// `Calculator::new()` will return a calculator for the `tokens` of an expression, without the braces.
+    pub fn new(tokens: &'a [String], names: &'a HashMap<String, f64>) -> Calculator<'a> {
+        Calculator {
+            tokens,
+            position: 0,
+            names,
+        }
+    }
+
+    /// `Calculator.evaluate()` returns the value of the whole expression.
+    pub fn evaluate(&mut self) -> Result<f64, String> {
+        let value = self.expression()?;
+        match self.tokens.get(self.position) {
+            None => Ok(value),
+            Some(token) => Err(format!("unexpected `{}` in expression", token)),
+        }
+    }
+
+    fn next(&mut self) -> Result<&'a str, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or_else(|| String::from("incomplete expression"))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    /// `Calculator.expression()` reads a term, optionally followed by a binary operator and the rest of the expression.
+    fn expression(&mut self) -> Result<f64, String> {
+        let left = self.term()?;
+        let operator = match self.tokens.get(self.position) {
+            Some(operator) if operator != ")" => operator.as_str(),
+            _ => return Ok(left),
+        };
+        self.position += 1;
+        let right = self.expression()?;
+        let (a, b) = (left as i64, right as i64);
+        let value = match operator {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" if right == 0.0 => return Err(String::from("division by zero")),
+            "/" => left / right,
+            "%" if b == 0 => return Err(String::from("division by zero")),
+            "%" => (a % b) as f64,
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" => (a << b.clamp(0, 63)) as f64,
+            ">>" => (a >> b.clamp(0, 63)) as f64,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "<" => (left < right) as i64 as f64,
+            ">" => (left > right) as i64 as f64,
+            "<=" => (left <= right) as i64 as f64,
+            ">=" => (left >= right) as i64 as f64,
+            "==" => (left == right) as i64 as f64,
+            "!=" => (left != right) as i64 as f64,
+            _ => return Err(format!("unknown operator `{}`", operator)),
+        };
+        Ok(value)
+    }
+
+    /// `Calculator.term()` reads a number, a name, a unary operator and its term, or an expression in parentheses.
+    fn term(&mut self) -> Result<f64, String> {
+        let token = self.next()?;
+        let value = match token {
+            "(" => {
+                let value = self.expression()?;
+                match self.next()? {
+                    ")" => value,
+                    token => return Err(format!("expected `)`, found `{}`", token)),
+                }
+            }
+            "-" => -self.term()?,
+            "~" => !(self.term()? as i64) as f64,
+            "!" => (self.term()? == 0.0) as i64 as f64,
+            "abs" => self.term()?.abs(),
+            "sqrt" => self.term()?.sqrt(),
+            "sin" => self.term()?.sin(),
+            "cos" => self.term()?.cos(),
+            "tan" => self.term()?.tan(),
+            "exp" => self.term()?.exp(),
+            "log" => self.term()?.ln(),
+            "sign" => self.term()?.signum(),
+            "ceil" => self.term()?.ceil(),
+            "floor" => self.term()?.floor(),
+            "PI" => std::f64::consts::PI,
+            "E" => std::f64::consts::E,
+            _ => match parse_number(token) {
+                Some(value) => value as f64,
+                None => *self
+                    .names
+                    .get(token)
+                    .ok_or_else(|| format!("`{}` is not defined", token))?,
+            },
+        };
+        Ok(value)
+    }
+}
+
+/// `parse_number()` reads a decimal, hexadecimal (`0x`) or binary (`0b`) number, possibly negative.
+pub fn parse_number(text: &str) -> Option<i64> {
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(digits) => (-1, digits),
+        None => (1, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()
+    } else if digits.starts_with(|first: char| first.is_ascii_digit()) {
+        digits.parse().ok()
+    } else {
+        None
+    };
+    value.map(|value| sign * value)
+}
@@ -0,0 +1,807 @@
+//! `Compiler` reads the source token by token and emits the ROM in a single pass.
+//! Addresses of labels which are not defined yet are left empty and patched at the end.
+
+use crate::octo::calc::{parse_number, Calculator};
+use crate::octo::CompileError;
+use crate::vm::{Instruction, PROGRAM_START};
+use log::debug;
+use std::collections::HashMap;
+
+/// XO-CHIP programs may use the whole 64 KB address space.
+const MEMORY_END: usize = 0x10000;
+/// Number of macro expansions after which a macro is considered to expand itself forever.
+const MAX_EXPANSIONS: usize = 100_000;
+/// The flag register, used by the pseudo comparisons.
+const VF: usize = 0xF;
+
+/// `Token` : a word of the source and its line.
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+/// `Macro` : the parameters and the body of a `:macro`.
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// `Patch` : how to fill in the address of a label, once it is known.
+#[derive(Debug, Clone, Copy)]
+enum Patch {
+    /// The 12-bit address of an instruction (jump, call, `i :=`).
+    Address,
+    /// The 16-bit word after `i := long`.
+    Long,
+    /// The two `vN := NN` instructions of `:unpack`, with the nibble for the high byte.
+    Unpack(u8),
+}
+
+/// `Fixup` : a place in the ROM waiting for the address of a label.
+struct Fixup {
+    address: usize,
+    label: String,
+    patch: Patch,
+    line: usize,
+}
+
+/// `Operand` : the right side of an assignment or a comparison.
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Register(usize),
+    Byte(u8),
+}
+
+/// `Condition` : the condition of an `if` or a `while`, e.g. `v0 == 3`, `v1 < v2` or `v3 key`.
+struct Condition {
+    x: usize,
+    comparison: String,
+    operand: Option<Operand>,
+}
+
+/// `Block` : a control structure which is not closed yet.
+enum Block {
+    /// `if ... begin`, with the jump to the `else` or the `end`.
+    If(usize),
+    /// `else`, with the jump to the `end`.
+    Else(usize),
+    /// `loop`, with its start and the jumps of its `while`s, which exit the loop.
+    Loop(usize, Vec<usize>),
+}
+
+/// `Compiler` : the tokens left to read, the ROM emitted so far and everything defined by the program.
+pub struct Compiler {
+    /// `tokens` holds the tokens left to read, the next one last.
+    tokens: Vec<Token>,
+    rom: Vec<u8>,
+    /// `here` is the address of the next emitted byte.
+    here: usize,
+    line: usize,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, usize>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+    expansions: usize,
+}
+
+impl Compiler {
+    /// `Compiler::new()` will return a compiler for the `source`, split into tokens. Comments start with `#`.
+    pub fn new(source: &str) -> Compiler {
+        let mut tokens: Vec<Token> = source
+            .lines()
+            .enumerate()
+            .flat_map(|(index, text)| {
+                let code = text.split('#').next().unwrap_or("");
+                code.split_whitespace()
+                    .map(|word| Token {
+                        text: String::from(word),
+                        line: index + 1,
+                    })
+                    .collect::<Vec<Token>>()
+            })
+            .collect();
+        tokens.reverse();
+        Compiler {
+            tokens,
+            rom: vec![],
+            here: PROGRAM_START as usize,
+            line: 0,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: vec![],
+            blocks: vec![],
+            expansions: 0,
+        }
+    }
+
+    /// `Compiler.compile()` compiles every statement, then fills in the addresses of the labels.
+    pub fn compile(mut self) -> Result<Vec<u8>, CompileError> {
+        //the jump to `main` belongs to the first line, it is not needed when the program starts with `main`
+        let len = self.tokens.len();
+        let starts_with_main =
+            len >= 2 && self.tokens[len - 1].text == ":" && self.tokens[len - 2].text == "main";
+        if !starts_with_main {
+            self.line = 1;
+            self.emit_address(Instruction::Jump(0), "main")?;
+        }
+        while let Some(token) = self.tokens.pop() {
+            self.line = token.line;
+            self.statement(&token.text)?;
+        }
+        if let Some(block) = self.blocks.last() {
+            let missing = match block {
+                Block::If(_) | Block::Else(_) => "end",
+                Block::Loop(_, _) => "again",
+            };
+            return Err(self.error(format!("missing `{}` at the end of the program", missing)));
+        }
+        for fixup in &self.fixups {
+            let target = *self.labels.get(&fixup.label).ok_or_else(|| CompileError {
+                line: fixup.line,
+                message: format!("`{}` is never defined", fixup.label),
+            })?;
+            let offset = fixup.address - PROGRAM_START as usize;
+            match fixup.patch {
+                Patch::Address => {
+                    if target > 0xFFF {
+                        return Err(CompileError {
+                            line: fixup.line,
+                            message: format!("`{}` is above 0xFFF, use `i := long`", fixup.label),
+                        });
+                    }
+                    self.rom[offset] = (self.rom[offset] & 0xF0) | (target >> 8) as u8;
+                    self.rom[offset + 1] = target as u8;
+                }
+                Patch::Long => self.rom[offset..offset + 2].copy_from_slice(&target.to_be_bytes()),
+                Patch::Unpack(nibble) => {
+                    self.rom[offset + 1] = nibble << 4 | (target >> 8) as u8 & 0xF;
+                    self.rom[offset + 3] = target as u8;
+                }
+            }
+        }
+        debug!("[Compiler.compile()] Compiled {} bytes.", self.rom.len());
+        Ok(self.rom)
+    }
+
+    /// `Compiler.statement()` compiles the statement starting with `token`.
+    fn statement(&mut self, token: &str) -> Result<(), CompileError> {
+        match token {
+            ":" => {
+                let name = self.name()?;
+                if self.labels.insert(name.clone(), self.here as u16).is_some() {
+                    return Err(self.error(format!("`{}` is defined more than once", name)));
+                }
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.register()?;
+                self.aliases.insert(name, register);
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.value()?;
+                self.constants.insert(name, value as f64);
+            }
+            ":calc" => {
+                let name = self.name()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+            }
+            ":macro" => self.define_macro()?,
+            ":org" => {
+                let address = self.value()?;
+                if address < PROGRAM_START as i64 || address >= MEMORY_END as i64 {
+                    return Err(self.error(format!("`:org {}` is outside of the program", address)));
+                }
+                self.here = address as usize;
+            }
+            ":byte" => {
+                let value = self.value()?;
+                self.emit_byte(value as u8)?;
+            }
+            ":unpack" => {
+                let nibble = self.value()? as u8 & 0xF;
+                let token = self.token()?;
+                match self.resolve(&token) {
+                    Some(target) => {
+                        self.emit(Instruction::LoadByte {
+                            x: 0,
+                            nn: nibble << 4 | (target >> 8) as u8 & 0xF,
+                        })?;
+                        self.emit(Instruction::LoadByte {
+                            x: 1,
+                            nn: target as u8,
+                        })?;
+                    }
+                    None => {
+                        self.add_fixup(&token, Patch::Unpack(nibble))?;
+                        self.emit(Instruction::LoadByte { x: 0, nn: 0 })?;
+                        self.emit(Instruction::LoadByte { x: 1, nn: 0 })?;
+                    }
+                }
+            }
+            ":breakpoint" => {
+                self.token()?;
+            }
+            ":monitor" => {
+                self.token()?;
+                self.token()?;
+            }
+            ";" | "return" => self.emit(Instruction::Ret)?,
+            "clear" => self.emit(Instruction::Cls)?,
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(Instruction::StoreBcd(x))?;
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let instruction = if self.peek() == Some("-") {
+                    self.token()?;
+                    let y = self.register()?;
+                    match token {
+                        "save" => Instruction::StoreRange { x, y },
+                        _ => Instruction::LoadRange { x, y },
+                    }
+                } else {
+                    match token {
+                        "save" => Instruction::StoreRegisters(x),
+                        _ => Instruction::LoadRegisters(x),
+                    }
+                };
+                self.emit(instruction)?;
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::StoreFlags(x))?;
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::LoadFlags(x))?;
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(Instruction::Draw { x, y, n })?;
+            }
+            "jump" => self.emit_target(Instruction::Jump(0))?,
+            "jump0" => self.emit_target(Instruction::JumpOffset(0))?,
+            "native" => self.emit_target(Instruction::Sys(0))?,
+            ":call" => self.emit_target(Instruction::Call(0))?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(match token {
+                    "delay" => Instruction::SetDelay(x),
+                    "buzzer" => Instruction::SetSound(x),
+                    _ => Instruction::SetPitch(x),
+                })?;
+            }
+            "i" => self.index()?,
+            "scroll-down" | "scroll-up" | "plane" => {
+                let n = self.nibble()?;
+                self.emit(match token {
+                    "scroll-down" => Instruction::ScrollDown(n),
+                    "scroll-up" => Instruction::ScrollUp(n),
+                    _ => Instruction::Plane(n),
+                })?;
+            }
+            "scroll-left" => self.emit(Instruction::ScrollLeft)?,
+            "scroll-right" => self.emit(Instruction::ScrollRight)?,
+            "exit" => self.emit(Instruction::Exit)?,
+            "lores" => self.emit(Instruction::LowRes)?,
+            "hires" => self.emit(Instruction::HighRes)?,
+            "audio" => self.emit(Instruction::Audio)?,
+            "if" => {
+                let condition = self.condition()?;
+                match self.token()?.as_str() {
+                    //the next statement is skipped when the condition is false
+                    "then" => self.emit_skip(&condition, false)?,
+                    //the jump to the `else` or the `end` is skipped when the condition is true
+                    "begin" => {
+                        self.emit_skip(&condition, true)?;
+                        self.blocks.push(Block::If(self.here));
+                        self.emit(Instruction::Jump(0))?;
+                    }
+                    other => {
+                        return Err(
+                            self.error(format!("expected `then` or `begin`, found `{}`", other))
+                        )
+                    }
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If(jump)) => {
+                    let end = self.here;
+                    self.emit(Instruction::Jump(0))?;
+                    self.patch(jump, self.here as u16);
+                    self.blocks.push(Block::Else(end));
+                }
+                _ => return Err(self.error(String::from("`else` without `if ... begin`"))),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If(jump)) | Some(Block::Else(jump)) => {
+                    self.patch(jump, self.here as u16)
+                }
+                _ => return Err(self.error(String::from("`end` without `if ... begin`"))),
+            },
+            "loop" => self.blocks.push(Block::Loop(self.here, vec![])),
+            "while" => {
+                //the jump out of the loop is skipped when the condition is true
+                let condition = self.condition()?;
+                self.emit_skip(&condition, true)?;
+                let jump = self.here;
+                self.emit(Instruction::Jump(0))?;
+                match self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|block| matches!(block, Block::Loop(_, _)))
+                {
+                    Some(Block::Loop(_, whiles)) => whiles.push(jump),
+                    _ => return Err(self.error(String::from("`while` outside of a loop"))),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop(start, whiles)) => {
+                    self.emit(Instruction::Jump(start as u16))?;
+                    for jump in whiles {
+                        self.patch(jump, self.here as u16);
+                    }
+                }
+                _ => return Err(self.error(String::from("`again` without `loop`"))),
+            },
+            _ => self.other(token)?,
+        }
+        Ok(())
+    }
+
+    /// `Compiler.other()` compiles a statement which does not start with a keyword :
+    /// a register operation, a macro, a number (a byte of data) or a label (a call).
+    fn other(&mut self, token: &str) -> Result<(), CompileError> {
+        if let Some(x) = self.register_named(token) {
+            return self.register_operation(x);
+        }
+        if self.macros.contains_key(token) {
+            return self.expand_macro(token);
+        }
+        if token == "{" {
+            let value = self.calc()?;
+            return self.emit_byte(value as u8);
+        }
+        if self.labels.contains_key(token) {
+            return self.emit_address(Instruction::Call(0), token);
+        }
+        if let Some(value) = self.number(token) {
+            return self.emit_byte(value as u8);
+        }
+        //a label defined further down
+        if is_name(token) {
+            return self.emit_address(Instruction::Call(0), token);
+        }
+        Err(self.error(format!("unexpected `{}`", token)))
+    }
+
+    /// `Compiler.register_operation()` compiles `vx <operator> <operand>`.
+    fn register_operation(&mut self, x: usize) -> Result<(), CompileError> {
+        let operator = self.token()?;
+        let operand = self.token()?;
+        let y = self.register_named(&operand);
+        let instruction = match (operator.as_str(), operand.as_str(), y) {
+            (":=", _, Some(y)) => Instruction::Load { x, y },
+            (":=", "random", None) => Instruction::Random {
+                x,
+                nn: self.value()? as u8,
+            },
+            (":=", "delay", None) => Instruction::LoadDelay(x),
+            (":=", "key", None) => Instruction::WaitKey(x),
+            (":=", _, None) => Instruction::LoadByte {
+                x,
+                nn: self.byte(&operand)?,
+            },
+            ("+=", _, Some(y)) => Instruction::Add { x, y },
+            ("+=", _, None) => Instruction::AddByte {
+                x,
+                nn: self.byte(&operand)?,
+            },
+            ("-=", _, Some(y)) => Instruction::Sub { x, y },
+            ("-=", _, None) => Instruction::AddByte {
+                x,
+                nn: self.byte(&operand)?.wrapping_neg(),
+            },
+            ("=-", _, Some(y)) => Instruction::SubNegated { x, y },
+            ("|=", _, Some(y)) => Instruction::Or { x, y },
+            ("&=", _, Some(y)) => Instruction::And { x, y },
+            ("^=", _, Some(y)) => Instruction::Xor { x, y },
+            (">>=", _, Some(y)) => Instruction::ShiftRight { x, y },
+            ("<<=", _, Some(y)) => Instruction::ShiftLeft { x, y },
+            _ => {
+                return Err(self.error(format!(
+                    "unknown register operation `{} {}`",
+                    operator, operand
+                )))
+            }
+        };
+        self.emit(instruction)
+    }
+
+    /// `Compiler.index()` compiles the operations on I : `i := <address>`, `i := long <address>`,
+    /// `i := hex vx`, `i := bighex vx` and `i += vx`.
+    fn index(&mut self) -> Result<(), CompileError> {
+        let operator = self.token()?;
+        match operator.as_str() {
+            "+=" => {
+                let x = self.register()?;
+                self.emit(Instruction::AddIndex(x))
+            }
+            ":=" => match self.peek() {
+                Some("hex") | Some("bighex") => {
+                    let font = self.token()?;
+                    let x = self.register()?;
+                    self.emit(match font.as_str() {
+                        "hex" => Instruction::LoadFont(x),
+                        _ => Instruction::LoadBigFont(x),
+                    })
+                }
+                Some("long") => {
+                    self.token()?;
+                    self.emit(Instruction::LoadIndexLong)?;
+                    let token = self.token()?;
+                    match self.resolve(&token) {
+                        Some(target) => {
+                            self.emit_byte((target >> 8) as u8)?;
+                            self.emit_byte(target as u8)
+                        }
+                        None => {
+                            self.add_fixup(&token, Patch::Long)?;
+                            self.emit_byte(0)?;
+                            self.emit_byte(0)
+                        }
+                    }
+                }
+                _ => self.emit_target(Instruction::LoadIndex(0)),
+            },
+            _ => Err(self.error(format!("unknown operation on i `{}`", operator))),
+        }
+    }
+
+    /// `Compiler.condition()` reads a condition : `vx == <operand>`, `vx != <operand>`, the pseudo comparisons
+    /// `<`, `>`, `<=` and `>=`, or `vx key` and `vx -key`.
+    fn condition(&mut self) -> Result<Condition, CompileError> {
+        let x = self.register()?;
+        let comparison = self.token()?;
+        let operand = match comparison.as_str() {
+            "key" | "-key" => None,
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => {
+                let token = self.token()?;
+                Some(match self.register_named(&token) {
+                    Some(y) => Operand::Register(y),
+                    None => Operand::Byte(self.byte(&token)?),
+                })
+            }
+            _ => return Err(self.error(format!("unknown comparison `{}`", comparison))),
+        };
+        Ok(Condition {
+            x,
+            comparison,
+            operand,
+        })
+    }
+
+    /// `Compiler.emit_skip()` emits the instructions which skip the next instruction when the condition is `skip_when`.
+    /// The pseudo comparisons subtract into VF first : after `vf := a` and `vf -= b`, VF is 1 when `a >= b`.
+    fn emit_skip(&mut self, condition: &Condition, skip_when: bool) -> Result<(), CompileError> {
+        let x = condition.x;
+        let skip_if_equal = |operand: Option<Operand>, equal: bool| match (operand, equal) {
+            (Some(Operand::Register(y)), true) => Instruction::SkipEqual { x, y },
+            (Some(Operand::Register(y)), false) => Instruction::SkipNotEqual { x, y },
+            (Some(Operand::Byte(nn)), true) => Instruction::SkipEqualByte { x, nn },
+            (_, _) => Instruction::SkipNotEqualByte {
+                x,
+                nn: match operand {
+                    Some(Operand::Byte(nn)) => nn,
+                    _ => 0,
+                },
+            },
+        };
+        let operand = condition.operand;
+        match condition.comparison.as_str() {
+            "==" => self.emit(skip_if_equal(operand, skip_when)),
+            "!=" => self.emit(skip_if_equal(operand, !skip_when)),
+            "key" if skip_when => self.emit(Instruction::SkipKey(x)),
+            "key" => self.emit(Instruction::SkipNotKey(x)),
+            "-key" if skip_when => self.emit(Instruction::SkipNotKey(x)),
+            "-key" => self.emit(Instruction::SkipKey(x)),
+            comparison => {
+                let operand = operand.unwrap_or(Operand::Byte(0));
+                let register = Operand::Register(x);
+                //(a, b, VF when the comparison is true)
+                let (a, b, flag) = match comparison {
+                    "<" => (register, operand, 0),
+                    ">=" => (register, operand, 1),
+                    ">" => (operand, register, 0),
+                    _ => (operand, register, 1),
+                };
+                match (a, b) {
+                    (Operand::Byte(nn), Operand::Register(y)) => {
+                        self.emit(Instruction::LoadByte { x: VF, nn })?;
+                        self.emit(Instruction::Sub { x: VF, y })?;
+                    }
+                    (Operand::Register(y), Operand::Byte(nn)) => {
+                        self.emit(Instruction::LoadByte { x: VF, nn })?;
+                        self.emit(Instruction::SubNegated { x: VF, y })?;
+                    }
+                    (Operand::Register(a), Operand::Register(y)) => {
+                        self.emit(Instruction::Load { x: VF, y: a })?;
+                        self.emit(Instruction::Sub { x: VF, y })?;
+                    }
+                    (Operand::Byte(_), Operand::Byte(_)) => unreachable!(),
+                }
+                let nn = if skip_when { flag } else { 1 - flag };
+                self.emit(Instruction::SkipEqualByte { x: VF, nn })
+            }
+        }
+    }
+
+    /// `Compiler.define_macro()` reads `:macro name parameters { body }`.
+    fn define_macro(&mut self) -> Result<(), CompileError> {
+        let name = self.name()?;
+        let mut parameters = vec![];
+        loop {
+            match self.token()?.as_str() {
+                "{" => break,
+                parameter => parameters.push(String::from(parameter)),
+            }
+        }
+        let mut body = vec![];
+        let mut depth = 0;
+        loop {
+            let token = self.tokens.pop().ok_or_else(|| {
+                self.error(format!("missing `}}` at the end of `:macro {}`", name))
+            })?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => break,
+                "}" => depth -= 1,
+                _ => (),
+            }
+            body.push(token);
+        }
+        self.macros.insert(name, Macro { parameters, body });
+        Ok(())
+    }
+
+    /// `Compiler.expand_macro()` replaces a macro call by its body, with the parameters replaced by the arguments.
+    fn expand_macro(&mut self, name: &str) -> Result<(), CompileError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(self.error(format!("`{}` expands itself forever", name)));
+        }
+        let count = self.macros[name].parameters.len();
+        let mut arguments = HashMap::new();
+        for index in 0..count {
+            let argument = self.token()?;
+            arguments.insert(self.macros[name].parameters[index].clone(), argument);
+        }
+        let line = self.line;
+        let expansion: Vec<Token> = self.macros[name]
+            .body
+            .iter()
+            .map(|token| Token {
+                text: arguments.get(&token.text).unwrap_or(&token.text).clone(),
+                line,
+            })
+            .collect();
+        self.tokens.extend(expansion.into_iter().rev());
+        Ok(())
+    }
+
+    /// `Compiler.calc()` evaluates the expression up to the closing `}`, the opening `{` being read already.
+    fn calc(&mut self) -> Result<f64, CompileError> {
+        let mut expression = vec![];
+        loop {
+            match self.token()?.as_str() {
+                "}" => break,
+                token => expression.push(String::from(token)),
+            }
+        }
+        let mut names = self.constants.clone();
+        for (label, &address) in &self.labels {
+            names.insert(label.clone(), address as f64);
+        }
+        names.insert(String::from("HERE"), self.here as f64);
+        Calculator::new(&expression, &names)
+            .evaluate()
+            .map_err(|message| self.error(message))
+    }
+
+    fn token(&mut self) -> Result<String, CompileError> {
+        match self.tokens.pop() {
+            Some(token) => {
+                self.line = token.line;
+                Ok(token.text)
+            }
+            None => Err(self.error(String::from("unexpected end of the program"))),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.last().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), CompileError> {
+        let token = self.token()?;
+        if token != expected {
+            return Err(self.error(format!("expected `{}`, found `{}`", expected, token)));
+        }
+        Ok(())
+    }
+
+    /// `Compiler.name()` reads the name of a new label, constant, alias or macro.
+    fn name(&mut self) -> Result<String, CompileError> {
+        let name = self.token()?;
+        if !is_name(&name) || self.register_named(&name).is_some() {
+            return Err(self.error(format!("`{}` is not a valid name", name)));
+        }
+        Ok(name)
+    }
+
+    /// `Compiler.register_named()` returns the register named `v0` to `vf` (or `V0` to `VF`), or by an alias.
+    fn register_named(&self, token: &str) -> Option<usize> {
+        if let Some(&register) = self.aliases.get(token) {
+            return Some(register);
+        }
+        let mut characters = token.chars();
+        match (characters.next(), characters.next(), characters.next()) {
+            (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => {
+                digit.to_digit(16).map(|digit| digit as usize)
+            }
+            _ => None,
+        }
+    }
+
+    fn register(&mut self) -> Result<usize, CompileError> {
+        let token = self.token()?;
+        self.register_named(&token)
+            .ok_or_else(|| self.error(format!("`{}` is not a register", token)))
+    }
+
+    /// `Compiler.number()` returns the value of a number, a constant or a label defined so far.
+    fn number(&self, token: &str) -> Option<i64> {
+        parse_number(token)
+            .or_else(|| self.constants.get(token).map(|&value| value as i64))
+            .or_else(|| self.labels.get(token).map(|&address| address as i64))
+    }
+
+    /// `Compiler.value()` reads a number, a constant, a label defined so far or a `{ calc }` expression.
+    fn value(&mut self) -> Result<i64, CompileError> {
+        let token = self.token()?;
+        if token == "{" {
+            return Ok(self.calc()? as i64);
+        }
+        self.number(&token)
+            .ok_or_else(|| self.error(format!("`{}` is not a value", token)))
+    }
+
+    /// `Compiler.byte()` returns the value of `token` as a byte, negative values in two's complement.
+    fn byte(&mut self, token: &str) -> Result<u8, CompileError> {
+        let value = if token == "{" {
+            self.calc()? as i64
+        } else {
+            self.number(token)
+                .ok_or_else(|| self.error(format!("`{}` is not a value", token)))?
+        };
+        if !(-0x80..=0xFF).contains(&value) {
+            return Err(self.error(format!("{} does not fit in a byte", value)));
+        }
+        Ok(value as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, CompileError> {
+        let value = self.value()?;
+        if !(0..=0xF).contains(&value) {
+            return Err(self.error(format!("{} does not fit in a nibble", value)));
+        }
+        Ok(value as u8)
+    }
+
+    /// `Compiler.resolve()` returns the address named by `token`, if it is known already.
+    fn resolve(&self, token: &str) -> Option<u16> {
+        self.number(token).map(|value| value as u16)
+    }
+
+    fn add_fixup(&mut self, label: &str, patch: Patch) -> Result<(), CompileError> {
+        if !is_name(label) {
+            return Err(self.error(format!("`{}` is not an address", label)));
+        }
+        self.fixups.push(Fixup {
+            address: self.here,
+            label: String::from(label),
+            patch,
+            line: self.line,
+        });
+        Ok(())
+    }
+
+    /// `Compiler.emit_target()` reads the address of a jump, call or `i :=` and emits the instruction.
+    fn emit_target(&mut self, instruction: Instruction) -> Result<(), CompileError> {
+        let token = self.token()?;
+        if token == "{" {
+            let target = self.calc()? as u16;
+            return self.emit_patched(instruction, target);
+        }
+        self.emit_address(instruction, &token)
+    }
+
+    /// `Compiler.emit_address()` emits `instruction` with the address named by `token`,
+    /// which is filled in at the end if it is not known yet.
+    fn emit_address(&mut self, instruction: Instruction, token: &str) -> Result<(), CompileError> {
+        match self.resolve(token) {
+            Some(target) => self.emit_patched(instruction, target),
+            None => {
+                self.add_fixup(token, Patch::Address)?;
+                self.emit(instruction)
+            }
+        }
+    }
+
+    fn emit_patched(&mut self, instruction: Instruction, target: u16) -> Result<(), CompileError> {
+        if target > 0xFFF {
+            return Err(self.error(format!("0x{:X} is above 0xFFF, use `i := long`", target)));
+        }
+        let address = self.here;
+        self.emit(instruction)?;
+        self.patch(address, target);
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> Result<(), CompileError> {
+        let [high, low] = instruction.encode().to_be_bytes();
+        self.emit_byte(high)?;
+        self.emit_byte(low)
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), CompileError> {
+        if self.here >= MEMORY_END {
+            return Err(self.error(String::from("the program does not fit in memory")));
+        }
+        let offset = self.here - PROGRAM_START as usize;
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    /// `Compiler.patch()` sets the 12-bit address of the instruction at `address`.
+    fn patch(&mut self, address: usize, target: u16) {
+        let offset = address - PROGRAM_START as usize;
+        self.rom[offset] = (self.rom[offset] & 0xF0) | (target >> 8) as u8 & 0xF;
+        self.rom[offset + 1] = target as u8;
+    }
+
+    fn error(&self, message: String) -> CompileError {
+        CompileError {
+            line: self.line,
+            message,
+        }
+    }
+}
+
+/// `is_name()` tells if `token` can name a label, a constant, an alias or a macro.
+fn is_name(token: &str) -> bool {
+    token
+        .chars()
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && token.chars().all(|character| {
+            character.is_ascii_alphanumeric() || character == '_' || character == '-'
+        })
+}
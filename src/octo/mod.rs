@@ -0,0 +1,158 @@
+//! Octo - a compiler for Octo, the high-level assembly language most modern CHIP-8 programs are written in.
+//!
+//! ```text
+//! : main                      # labels
+//!   v0 := 10                  # register assignment and arithmetic
+//!   i := ball
+//!   loop
+//!     sprite v0 v1 4
+//!     v0 += 1
+//!     if v0 == 60 then v0 := 0
+//!     while v1 != 30          # exits the loop
+//!   again
+//!   ball                      # a bare label calls a subroutine
+//! : ball 0x60 0xF0 0xF0 0x60  # sprite data, one byte per number
+//! ```
+//!
+//! It supports labels, `loop`/`again`/`while`, `if ... then` and `if ... begin ... else ... end`,
+//! the pseudo comparisons `<`, `>`, `<=` and `>=` (which use VF), `:alias`, `:const`, `:calc`, `:macro`,
+//! `:org`, `:byte`, `:unpack` and `:call`, and the SUPER-CHIP and XO-CHIP instructions.
+//! The program starts with a jump to the `main` label, unless it starts with `: main`.
+
+mod calc;
+mod compiler;
+
+use std::error::Error;
+use std::fmt;
+
+/// `CompileError` : what went wrong, and on which line of the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.line, self.message)
+    }
+}
+
+impl Error for CompileError {}
+
+/// `compile()` will return the ROM compiled from the Octo `source`.
+pub fn compile(source: &str) -> Result<Vec<u8>, CompileError> {
+    compiler::Compiler::new(source).compile()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{Machine, PROGRAM_START};
+
+    /// `run()` compiles `source` and executes `steps` instructions of it.
+    fn run(source: &str, steps: usize) -> Machine {
+        let rom = compile(source).unwrap();
+        let mut machine = Machine::new();
+        machine.load_program(&rom, PROGRAM_START, PROGRAM_START);
+        for _ in 0..steps {
+            machine.process_keys(vec![false; 16]);
+        }
+        machine
+    }
+
+    #[test]
+    fn pseudo_comparisons_run_on_the_machine() {
+        for comparison in ["<", ">", "<=", ">="] {
+            for (a, b) in [(3, 5), (5, 3), (4, 4), (0, 255), (255, 0)] {
+                //register with register and with a byte, with `then` and with `begin ... end`
+                let source = format!(
+                    ": main
+                        v1 := {a} v2 := {b}
+                        v4 := 0 v5 := 0 v6 := 0 v7 := 0
+                        if v1 {c} v2 then v4 := 1
+                        if v1 {c} {b} then v5 := 1
+                        if v1 {c} v2 begin v6 := 1 end
+                        if v1 {c} {b} begin v7 := 1 else v7 := 2 end
+                        loop again",
+                    a = a,
+                    b = b,
+                    c = comparison
+                );
+                let machine = run(&source, 40);
+                let flag = match comparison {
+                    "<" => a < b,
+                    ">" => a > b,
+                    "<=" => a <= b,
+                    _ => a >= b,
+                } as u8;
+                assert_eq!(
+                    &machine.registers.v[4..8],
+                    &[flag, flag, flag, 2 - flag],
+                    "v1 {} v2 with v1 = {} and v2 = {}",
+                    comparison,
+                    a,
+                    b
+                );
+                assert_eq!(&machine.registers.v[1..3], &[a, b]);
+            }
+        }
+    }
+
+    #[test]
+    fn equality_and_key_conditions_run_on_the_machine() {
+        let machine = run(
+            ": main
+                v1 := 7 v2 := 7
+                if v1 == v2 then v3 := 1
+                if v1 != 7 then v4 := 1
+                if v1 != v2 begin v5 := 1 else v5 := 2 end
+                if v1 key then v6 := 1
+                if v1 -key then v7 := 1
+                loop again",
+            20,
+        );
+        assert_eq!(&machine.registers.v[3..8], &[1, 0, 2, 0, 1]);
+    }
+
+    #[test]
+    fn compiles_directives_and_forward_references() {
+        let rom = compile(
+            ":alias x v3
+            :const SPEED 2
+            :calc DOUBLE { SPEED * 2 }
+            :macro step reg { reg += DOUBLE }
+            : main
+                x := SPEED
+                step x
+                i := data
+                :unpack 0xA data
+                jump main
+            : data
+                :byte 0xAB",
+        )
+        .unwrap();
+        assert_eq!(
+            rom,
+            [
+                0x12, 0x02, 0x63, 0x02, 0x73, 0x04, 0xA2, 0x0E, 0x60, 0xA2, 0x61, 0x0E, 0x12, 0x02,
+                0xAB
+            ]
+        );
+    }
+
+    #[test]
+    fn jumps_to_main_unless_the_program_starts_with_it() {
+        let rom = compile(": sub return : main sub").unwrap();
+        assert_eq!(rom, [0x12, 0x04, 0x00, 0xEE, 0x22, 0x02]);
+    }
+
+    #[test]
+    fn reports_errors_with_their_line() {
+        let error = |source: &str| compile(source).unwrap_err().line;
+        assert_eq!(error(": main\n  v0 := 1\n  jump nowhere"), 3);
+        assert_eq!(error(": main\n  again"), 2);
+        assert_eq!(error(": main\n: main"), 2);
+        assert_eq!(error(": main\n  loop\n  v0 += 1"), 3);
+    }
+}
@@ -0,0 +1,282 @@
+//! BPS - the patched ROM is built by actions copying bytes from the ROM, from the patch or from the patched ROM
+//! itself. Numbers are variable-length, 7 bits per byte, the last byte has its high bit set :
+//!
+//! ```text
+//! "BPS1" source-size target-size metadata-size metadata { action } source-crc:4 target-crc:4 patch-crc:4
+//! ```
+
+use super::PatchError;
+use crate::database::crc32;
+use crate::drivers::MAX_MEGACHIP_ROM_SIZE;
+use std::convert::TryFrom;
+
+/// `Reader` reads the numbers and bytes of a patch, in order.
+struct Reader<'a> {
+    patch: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        let byte = *self
+            .patch
+            .get(self.position)
+            .ok_or_else(|| PatchError::new("the BPS patch is truncated"))?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn number(&mut self) -> Result<usize, PatchError> {
+        let (mut number, mut shift) = (0usize, 1usize);
+        loop {
+            let byte = self.byte()?;
+            number = (byte as usize & 0x7F)
+                .checked_mul(shift)
+                .and_then(|value| value.checked_add(number))
+                .ok_or_else(|| PatchError::new("invalid number in the BPS patch"))?;
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+            //a crafted patch can make the number overflow
+            shift = shift
+                .checked_mul(0x80)
+                .ok_or_else(|| PatchError::new("invalid number in the BPS patch"))?;
+            number = number
+                .checked_add(shift)
+                .ok_or_else(|| PatchError::new("invalid number in the BPS patch"))?;
+        }
+    }
+
+    /// `Reader.offset()` reads a signed relative offset : its sign is the lowest bit.
+    fn offset(&mut self) -> Result<isize, PatchError> {
+        let number = self.number()?;
+        let magnitude = (number >> 1) as isize;
+        Ok(if number & 1 != 0 {
+            -magnitude
+        } else {
+            magnitude
+        })
+    }
+}
+
+pub fn apply(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.len() < 16 {
+        return Err(PatchError::new("the BPS patch is truncated"));
+    }
+    let checksum = |position: usize| {
+        u32::from_le_bytes([
+            patch[position],
+            patch[position + 1],
+            patch[position + 2],
+            patch[position + 3],
+        ])
+    };
+    let end = patch.len() - 12;
+    if crc32(&patch[..patch.len() - 4]) != checksum(patch.len() - 4) {
+        return Err(PatchError::new("the BPS patch is corrupted"));
+    }
+    if crc32(rom) != checksum(end) {
+        return Err(PatchError::new("the BPS patch is for another ROM"));
+    }
+
+    let mut reader = Reader { patch, position: 4 };
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    let metadata_size = reader.number()?;
+    reader.position = reader.position.saturating_add(metadata_size);
+    if source_size != rom.len() {
+        return Err(PatchError::new("the BPS patch is for another ROM"));
+    }
+    if target_size > MAX_MEGACHIP_ROM_SIZE {
+        return Err(PatchError::new("the patched ROM is too large"));
+    }
+
+    let mut target = Vec::with_capacity(target_size);
+    let (mut source_offset, mut target_offset) = (0isize, 0isize);
+    let out_of_bounds = || PatchError::new("the BPS patch copies bytes out of bounds");
+    while reader.position < end {
+        let action = reader.number()?;
+        let length = (action >> 2) + 1;
+        if length > target_size - target.len() {
+            return Err(out_of_bounds());
+        }
+        match action & 3 {
+            //source read : the bytes of the ROM at the same offset
+            0 => {
+                let start = target.len();
+                let bytes = rom.get(start..start + length).ok_or_else(out_of_bounds)?;
+                target.extend_from_slice(bytes);
+            }
+            //target read : the bytes of the patch
+            1 => {
+                for _ in 0..length {
+                    target.push(reader.byte()?);
+                }
+            }
+            //source copy : bytes of the ROM, anywhere
+            2 => {
+                source_offset = source_offset
+                    .checked_add(reader.offset()?)
+                    .ok_or_else(out_of_bounds)?;
+                let start = usize::try_from(source_offset).map_err(|_| out_of_bounds())?;
+                let bytes = rom.get(start..start + length).ok_or_else(out_of_bounds)?;
+                target.extend_from_slice(bytes);
+                source_offset += length as isize;
+            }
+            //target copy : bytes already written, which can overlap the bytes being written
+            _ => {
+                target_offset = target_offset
+                    .checked_add(reader.offset()?)
+                    .ok_or_else(out_of_bounds)?;
+                for _ in 0..length {
+                    let byte = usize::try_from(target_offset)
+                        .ok()
+                        .and_then(|offset| target.get(offset))
+                        .copied()
+                        .ok_or_else(out_of_bounds)?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+    if target.len() != target_size || crc32(&target) != checksum(end + 4) {
+        return Err(PatchError::new(
+            "the patched ROM does not match the BPS patch",
+        ));
+    }
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `encode()` writes a number as BPS does.
+    fn encode(mut number: usize, bytes: &mut Vec<u8>) {
+        loop {
+            let low = (number & 0x7F) as u8;
+            number >>= 7;
+            if number == 0 {
+                bytes.push(0x80 | low);
+                return;
+            }
+            bytes.push(low);
+            number -= 1;
+        }
+    }
+
+    /// `patch()` builds a BPS patch from its actions, with the checksums of `source` and `target`.
+    fn patch(source: &[u8], target: &[u8], target_size: usize, actions: &[u8]) -> Vec<u8> {
+        let mut patch = b"BPS1".to_vec();
+        encode(source.len(), &mut patch);
+        encode(target_size, &mut patch);
+        encode(0, &mut patch);
+        patch.extend(actions);
+        patch.extend(crc32(source).to_le_bytes());
+        patch.extend(crc32(target).to_le_bytes());
+        patch.extend(crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    /// `action()` encodes an action of `length` bytes, followed by its offset if it has one.
+    fn action(kind: usize, length: usize, offset: Option<isize>) -> Vec<u8> {
+        let mut bytes = vec![];
+        encode((length - 1) << 2 | kind, &mut bytes);
+        if let Some(offset) = offset {
+            encode(
+                (offset.unsigned_abs() << 1) | (offset < 0) as usize,
+                &mut bytes,
+            );
+        }
+        bytes
+    }
+
+    const SOURCE: [u8; 4] = [0x00, 0xE0, 0x12, 0x00];
+
+    /// A patch of `SOURCE` using every action : `00 E0 A2 2A A2 2A A2 12 00`.
+    fn every_action(target: &[u8]) -> Vec<u8> {
+        let mut actions = action(0, 2, None);
+        actions.extend(action(1, 2, None));
+        actions.extend([0xA2, 0x2A]);
+        actions.extend(action(3, 3, Some(2)));
+        actions.extend(action(2, 2, Some(2)));
+        patch(&SOURCE, target, 9, &actions)
+    }
+
+    const TARGET: [u8; 9] = [0x00, 0xE0, 0xA2, 0x2A, 0xA2, 0x2A, 0xA2, 0x12, 0x00];
+
+    #[test]
+    fn applies_every_action() {
+        assert_eq!(apply(&every_action(&TARGET), &SOURCE).unwrap(), TARGET);
+    }
+
+    #[test]
+    fn checks_the_source_target_and_patch_checksums() {
+        let error = |message: &str| Err(PatchError::new(message));
+        assert_eq!(
+            apply(&every_action(&TARGET), &[0x00, 0xE0, 0x12, 0x02]),
+            error("the BPS patch is for another ROM")
+        );
+        assert_eq!(
+            apply(&every_action(&[0; 9]), &SOURCE),
+            error("the patched ROM does not match the BPS patch")
+        );
+        let mut corrupted = every_action(&TARGET);
+        corrupted[8] ^= 1;
+        assert_eq!(
+            apply(&corrupted, &SOURCE),
+            error("the BPS patch is corrupted")
+        );
+    }
+
+    #[test]
+    fn rejects_copies_out_of_bounds() {
+        let out_of_bounds = Err(PatchError::new("the BPS patch copies bytes out of bounds"));
+        //a source read past the end of the ROM
+        let actions = action(0, 5, None);
+        assert_eq!(
+            apply(&patch(&SOURCE, &[0; 5], 5, &actions), &SOURCE),
+            out_of_bounds
+        );
+        //a source copy before its start
+        let actions = action(2, 1, Some(-1));
+        assert_eq!(
+            apply(&patch(&SOURCE, &[0], 1, &actions), &SOURCE),
+            out_of_bounds
+        );
+        //a target copy of a byte not written yet
+        let actions = action(3, 1, Some(0));
+        assert_eq!(
+            apply(&patch(&SOURCE, &[0], 1, &actions), &SOURCE),
+            out_of_bounds
+        );
+        //more bytes than the patched ROM holds
+        let mut actions = action(1, 2, None);
+        actions.extend([0, 0]);
+        assert_eq!(
+            apply(&patch(&SOURCE, &[0; 2], 1, &actions), &SOURCE),
+            out_of_bounds
+        );
+        //an offset beyond any ROM
+        let actions = action(2, 1, Some(isize::MAX));
+        assert_eq!(
+            apply(&patch(&SOURCE, &[0], 1, &actions), &SOURCE),
+            out_of_bounds
+        );
+    }
+
+    #[test]
+    fn rejects_numbers_which_overflow_and_huge_roms() {
+        let mut actions = vec![0; 12];
+        actions.push(0x80);
+        assert_eq!(
+            apply(&patch(&SOURCE, &[], 0, &actions), &SOURCE),
+            Err(PatchError::new("invalid number in the BPS patch"))
+        );
+        assert_eq!(
+            apply(&patch(&SOURCE, &[], usize::MAX >> 1, &[]), &SOURCE),
+            Err(PatchError::new("the patched ROM is too large"))
+        );
+    }
+}
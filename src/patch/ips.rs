@@ -0,0 +1,87 @@
+//! IPS - records of bytes to write at an offset (3 bytes, big-endian), given as they are or as a run of one byte :
+//!
+//! ```text
+//! "PATCH" { offset:3 size:2 bytes:size | offset:3 0:2 count:2 byte:1 } "EOF" [length:3]
+//! ```
+//!
+//! The optional length after `EOF` truncates the patched ROM.
+
+use super::PatchError;
+
+pub fn apply(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut rom = rom.to_vec();
+    let truncated = || PatchError::new("the IPS patch is truncated");
+    let number = |position: usize, size: usize| {
+        patch
+            .get(position..position + size)
+            .map(|bytes| {
+                bytes
+                    .iter()
+                    .fold(0, |number, &byte| number << 8 | byte as usize)
+            })
+            .ok_or_else(truncated)
+    };
+
+    let mut position = 5;
+    loop {
+        if patch.get(position..position + 3) == Some(b"EOF") {
+            position += 3;
+            break;
+        }
+        let offset = number(position, 3)?;
+        let size = number(position + 3, 2)?;
+        position += 5;
+        let bytes = match size {
+            0 => {
+                let count = number(position, 2)?;
+                let byte = number(position + 2, 1)? as u8;
+                position += 3;
+                vec![byte; count]
+            }
+            _ => {
+                let bytes = patch
+                    .get(position..position + size)
+                    .ok_or_else(truncated)?
+                    .to_vec();
+                position += size;
+                bytes
+            }
+        };
+        if rom.len() < offset + bytes.len() {
+            rom.resize(offset + bytes.len(), 0);
+        }
+        rom[offset..offset + bytes.len()].copy_from_slice(&bytes);
+    }
+    if let Ok(length) = number(position, 3) {
+        rom.truncate(length);
+    }
+    Ok(rom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_records_and_runs() {
+        let patch = b"PATCH\x00\x00\x01\x00\x02\xAA\xBB\x00\x00\x06\x00\x00\x00\x03\xCCEOF";
+        assert_eq!(
+            apply(patch, &[0, 1, 2, 3]).unwrap(),
+            [0x00, 0xAA, 0xBB, 0x03, 0x00, 0x00, 0xCC, 0xCC, 0xCC]
+        );
+    }
+
+    #[test]
+    fn truncates_to_the_length_after_eof() {
+        let patch = b"PATCH\x00\x00\x00\x00\x01\xAAEOF\x00\x00\x02";
+        assert_eq!(apply(patch, &[0, 1, 2, 3]).unwrap(), [0xAA, 0x01]);
+    }
+
+    #[test]
+    fn truncated_patches_are_errors() {
+        let patch = b"PATCH\x00\x00\x01\x00\x02\xAA\xBB\x00\x00\x06\x00\x00\x00\x03\xCCEOF";
+        for length in 5..patch.len() - 3 {
+            assert!(apply(&patch[..length], &[0, 1]).is_err(), "{}", length);
+        }
+    }
+}
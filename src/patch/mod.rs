@@ -0,0 +1,42 @@
+//! Patches - changes to a ROM distributed without the ROM itself (e.g. bug fixes, translations), in the IPS or
+//! the BPS format. The format is recognized from the first bytes of the patch. BPS patches carry the CRC-32 of
+//! the ROM they apply to, of the patched ROM and of themselves, which are checked.
+
+mod bps;
+mod ips;
+
+use std::error::Error;
+use std::fmt;
+
+/// `PatchError` : why a patch could not be applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchError {
+    pub message: String,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for PatchError {}
+
+impl PatchError {
+    fn new(message: &str) -> PatchError {
+        PatchError {
+            message: message.to_string(),
+        }
+    }
+}
+
+/// `apply()` will return `rom` changed by `patch`.
+pub fn apply(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(b"PATCH") {
+        ips::apply(patch, rom)
+    } else if patch.starts_with(b"BPS1") {
+        bps::apply(patch, rom)
+    } else {
+        Err(PatchError::new("not an IPS or BPS patch"))
+    }
+}
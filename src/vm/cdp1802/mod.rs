@@ -0,0 +1,437 @@
+//! CDP1802 - the CPU of the COSMAC VIP, which ran the CHIP-8 interpreter. 0NNN calls a routine of 1802 machine code
+//! at NNN, with R3 as its program counter ; the routine returns to the interpreter with `SEP R4` (D4).
+//! The 1802 has 16 registers of 16 bits, any of them being the program counter (P) or the data pointer (X),
+//! an 8-bit accumulator D with a carry DF, and the Q output. The hex keypad of the VIP is read with `OUT 2`
+//! (selects a key) and `B3` (branches if it is pressed). Interrupts and DMA are not emulated : `IDL` does nothing.
+
+use std::fmt;
+
+/// `Cdp1802` : the registers of the CDP1802.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cdp1802 {
+    /// `r` are the 16 registers R0 to RF.
+    pub r: [u16; 16],
+    /// `p` selects the register which is the program counter.
+    pub p: u8,
+    /// `x` selects the register which points to the data (M(RX)).
+    pub x: u8,
+    /// `d` is the accumulator, `df` its carry (or not-borrow).
+    pub d: u8,
+    pub df: bool,
+    /// `t` holds X and P when an interrupt is taken, or by `MARK`.
+    pub t: u8,
+    /// `ie` enables interrupts.
+    pub ie: bool,
+    /// `q` is the output flip-flop, which drives the beeper of the VIP.
+    pub q: bool,
+    /// `key_latch` is the key of the hex keypad selected by `OUT 2`, read by EF3.
+    pub key_latch: u8,
+}
+
+impl Default for Cdp1802 {
+    fn default() -> Self {
+        Cdp1802::new()
+    }
+}
+
+impl Cdp1802 {
+    /// `Cdp1802::new()` will return the CPU as it is reset : every register at 0, P and X selecting R0,
+    /// interrupts enabled.
+    pub fn new() -> Cdp1802 {
+        Cdp1802 {
+            r: [0; 16],
+            p: 0,
+            x: 0,
+            d: 0,
+            df: false,
+            t: 0,
+            ie: true,
+            q: false,
+            key_latch: 0,
+        }
+    }
+
+    /// `Cdp1802.run()` executes instructions until `SEP R{return_register}`, or until `max_steps` instructions
+    /// were executed. It returns true if the routine returned.
+    pub fn run(
+        &mut self,
+        memory: &mut [u8],
+        keys: &[bool],
+        return_register: u8,
+        max_steps: usize,
+    ) -> bool {
+        for _ in 0..max_steps {
+            self.step(memory, keys);
+            if self.p == return_register {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// `Cdp1802.step()` executes the instruction at R(P). Addresses wrap around the end of the memory.
+    pub fn step(&mut self, memory: &mut [u8], keys: &[bool]) {
+        let opcode = self.fetch(memory);
+        let (i, n) = (opcode >> 4, (opcode & 0xF) as usize);
+        let rx = self.x as usize;
+        match (i, n) {
+            //IDL waits for an interrupt or DMA, which are not emulated
+            (0x0, 0) => (),
+            //LDN : D = M(RN)
+            (0x0, _) => self.d = read(memory, self.r[n]),
+            //INC, DEC
+            (0x1, _) => self.r[n] = self.r[n].wrapping_add(1),
+            (0x2, _) => self.r[n] = self.r[n].wrapping_sub(1),
+            //short branches, to an address of the current page
+            (0x3, _) => {
+                let address = self.fetch(memory);
+                if self.condition(n, keys) {
+                    let pc = self.p as usize;
+                    self.r[pc] = (self.r[pc] & 0xFF00) | address as u16;
+                }
+            }
+            //LDA : D = M(RN), RN + 1
+            (0x4, _) => {
+                self.d = read(memory, self.r[n]);
+                self.r[n] = self.r[n].wrapping_add(1);
+            }
+            //STR : M(RN) = D
+            (0x5, _) => write(memory, self.r[n], self.d),
+            //IRX : RX + 1
+            (0x6, 0) => self.r[rx] = self.r[rx].wrapping_add(1),
+            //OUT N : the bus takes M(RX), RX + 1 ; OUT 2 selects a key of the keypad
+            (0x6, 1..=7) => {
+                let byte = read(memory, self.r[rx]);
+                if n == 2 {
+                    self.key_latch = byte & 0xF;
+                }
+                self.r[rx] = self.r[rx].wrapping_add(1);
+            }
+            //INP N : M(RX) = D = the bus, nothing is connected
+            (0x6, 9..=15) => {
+                self.d = 0;
+                write(memory, self.r[rx], 0);
+            }
+            (0x6, _) => (),
+            //RET, DIS : X and P from M(RX), RX + 1
+            (0x7, 0) | (0x7, 1) => {
+                let byte = read(memory, self.r[rx]);
+                self.r[rx] = self.r[rx].wrapping_add(1);
+                self.x = byte >> 4;
+                self.p = byte & 0xF;
+                self.ie = n == 0;
+            }
+            //LDXA : D = M(RX), RX + 1
+            (0x7, 2) => {
+                self.d = read(memory, self.r[rx]);
+                self.r[rx] = self.r[rx].wrapping_add(1);
+            }
+            //STXD : M(RX) = D, RX - 1
+            (0x7, 3) => {
+                write(memory, self.r[rx], self.d);
+                self.r[rx] = self.r[rx].wrapping_sub(1);
+            }
+            //ADC, SDB, SHRC, SMB
+            (0x7, 4) => self.add(read(memory, self.r[rx]), self.df),
+            (0x7, 5) => self.subtract(read(memory, self.r[rx]), self.d, self.df),
+            (0x7, 6) => {
+                let carry = self.df;
+                self.df = self.d & 1 != 0;
+                self.d = self.d >> 1 | (carry as u8) << 7;
+            }
+            (0x7, 7) => self.subtract(self.d, read(memory, self.r[rx]), self.df),
+            //SAV : M(RX) = T
+            (0x7, 8) => write(memory, self.r[rx], self.t),
+            //MARK : T = XP, M(R2) = T, X = P, R2 - 1
+            (0x7, 9) => {
+                self.t = self.x << 4 | self.p;
+                write(memory, self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            //REQ, SEQ
+            (0x7, 0xA) => self.q = false,
+            (0x7, 0xB) => self.q = true,
+            //ADCI, SDBI, SHLC, SMBI
+            (0x7, 0xC) => {
+                let byte = self.fetch(memory);
+                self.add(byte, self.df);
+            }
+            (0x7, 0xD) => {
+                let byte = self.fetch(memory);
+                self.subtract(byte, self.d, self.df);
+            }
+            (0x7, 0xE) => {
+                let carry = self.df;
+                self.df = self.d & 0x80 != 0;
+                self.d = self.d << 1 | carry as u8;
+            }
+            (0x7, _) => {
+                let byte = self.fetch(memory);
+                self.subtract(self.d, byte, self.df);
+            }
+            //GLO, GHI, PLO, PHI
+            (0x8, _) => self.d = self.r[n] as u8,
+            (0x9, _) => self.d = (self.r[n] >> 8) as u8,
+            (0xA, _) => self.r[n] = (self.r[n] & 0xFF00) | self.d as u16,
+            (0xB, _) => self.r[n] = (self.r[n] & 0x00FF) | (self.d as u16) << 8,
+            //NOP
+            (0xC, 4) => (),
+            //long skips
+            (0xC, 5..=7) | (0xC, 0xC..=0xF) => {
+                let skip = match n {
+                    5 => !self.q,
+                    6 => self.d != 0,
+                    7 => !self.df,
+                    0xC => self.ie,
+                    0xD => self.q,
+                    0xE => self.d == 0,
+                    _ => self.df,
+                };
+                if skip {
+                    let pc = self.p as usize;
+                    self.r[pc] = self.r[pc].wrapping_add(2);
+                }
+            }
+            //LSKP
+            (0xC, 8) => {
+                let pc = self.p as usize;
+                self.r[pc] = self.r[pc].wrapping_add(2);
+            }
+            //long branches
+            (0xC, _) => {
+                let address = (self.fetch(memory) as u16) << 8 | self.fetch(memory) as u16;
+                if self.condition(n & 0x7, keys) != (n & 0x8 != 0) {
+                    self.r[self.p as usize] = address;
+                }
+            }
+            //SEP, SEX
+            (0xD, _) => self.p = n as u8,
+            (0xE, _) => self.x = n as u8,
+            //LDX, OR, AND, XOR, ADD, SD, SHR, SM : with M(RX)
+            (0xF, 0..=7) => {
+                let byte = read(memory, self.r[rx]);
+                self.alu(n, byte);
+            }
+            //LDI, ORI, ANI, XRI, ADI, SDI, SHL, SMI : with the byte after the opcode
+            (0xF, _) => match n {
+                0xE => {
+                    self.df = self.d & 0x80 != 0;
+                    self.d <<= 1;
+                }
+                _ => {
+                    let byte = self.fetch(memory);
+                    self.alu(n - 8, byte);
+                }
+            },
+            _ => unreachable!("opcodes have 4 bits"),
+        }
+    }
+}
+
+//private methods
+impl Cdp1802 {
+    /// `Cdp1802.fetch()` reads the byte at R(P), and moves R(P) to the next one.
+    fn fetch(&mut self, memory: &[u8]) -> u8 {
+        let pc = self.p as usize;
+        let byte = read(memory, self.r[pc]);
+        self.r[pc] = self.r[pc].wrapping_add(1);
+        byte
+    }
+
+    /// `Cdp1802.condition()` tells whether the branch numbered `n` (0 to 7) is taken : always, Q, D = 0, DF,
+    /// and the flags EF1 to EF4. Branches 8 to 15 are taken on the opposite condition.
+    fn condition(&self, n: usize, keys: &[bool]) -> bool {
+        let condition = match n & 0x7 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            //EF3 : the key selected by OUT 2 is pressed
+            6 => keys.get(self.key_latch as usize).copied().unwrap_or(false),
+            _ => false,
+        };
+        condition != (n & 0x8 != 0)
+    }
+
+    /// `Cdp1802.alu()` executes LDX, OR, AND, XOR, ADD, SD, SHR or SM (0 to 7) with `byte`.
+    fn alu(&mut self, operation: usize, byte: u8) {
+        match operation {
+            0 => self.d = byte,
+            1 => self.d |= byte,
+            2 => self.d &= byte,
+            3 => self.d ^= byte,
+            4 => self.add(byte, false),
+            5 => self.subtract(byte, self.d, true),
+            6 => {
+                self.df = self.d & 1 != 0;
+                self.d >>= 1;
+            }
+            _ => self.subtract(self.d, byte, true),
+        }
+    }
+
+    /// `Cdp1802.add()` sets D to D plus `byte`, with a carry in, and DF to the carry out.
+    fn add(&mut self, byte: u8, carry: bool) {
+        let sum = self.d as u16 + byte as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    /// `Cdp1802.subtract()` sets D to `minuend` minus `subtrahend`, minus 1 when there is a borrow in
+    /// (`not_borrow` is false). DF is set when there is no borrow out.
+    fn subtract(&mut self, minuend: u8, subtrahend: u8, not_borrow: bool) {
+        let difference = minuend as i16 - subtrahend as i16 - !not_borrow as i16;
+        self.d = difference as u8;
+        self.df = difference >= 0;
+    }
+}
+
+impl fmt::Display for Cdp1802 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let registers: Vec<String> = self
+            .r
+            .iter()
+            .enumerate()
+            .map(|(index, value)| format!("R{:X}:{:04X}", index, value))
+            .collect();
+        write!(
+            f,
+            "{} P:{:X} X:{:X} D:{:02X} DF:{} T:{:02X} IE:{} Q:{}",
+            registers.join(" "),
+            self.p,
+            self.x,
+            self.d,
+            self.df as u8,
+            self.t,
+            self.ie as u8,
+            self.q as u8
+        )
+    }
+}
+
+fn read(memory: &[u8], address: u16) -> u8 {
+    memory[address as usize % memory.len()]
+}
+
+fn write(memory: &mut [u8], address: u16, value: u8) {
+    let length = memory.len();
+    memory[address as usize % length] = value;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `run()` executes `steps` instructions of `program`, loaded at 0x000 of a memory of 4 KB and run with R0
+    /// as the program counter, as after a reset. It returns the CPU and the memory.
+    fn run(program: &[u8], steps: usize, keys: &[bool]) -> (Cdp1802, Vec<u8>) {
+        let mut memory = vec![0; 0x1000];
+        memory[..program.len()].copy_from_slice(program);
+        let mut cpu = Cdp1802::new();
+        for _ in 0..steps {
+            cpu.step(&mut memory, keys);
+        }
+        (cpu, memory)
+    }
+
+    #[test]
+    fn branches_follow_their_condition() {
+        //D := 0, BZ 0x10 is taken
+        let (cpu, _) = run(&[0xF8, 0x00, 0x32, 0x10], 2, &[]);
+        assert_eq!(cpu.r[0], 0x010);
+        //D := 1, BZ 0x10 is not taken, BNZ 0x20 is
+        let (cpu, _) = run(&[0xF8, 0x01, 0x32, 0x10, 0x3A, 0x20], 2, &[]);
+        assert_eq!(cpu.r[0], 0x004);
+        let (cpu, _) = run(&[0xF8, 0x01, 0x32, 0x10, 0x3A, 0x20], 3, &[]);
+        assert_eq!(cpu.r[0], 0x020);
+        //LBR 0x0123 leaves the page
+        let (cpu, _) = run(&[0xC0, 0x01, 0x23], 1, &[]);
+        assert_eq!(cpu.r[0], 0x123);
+        //LSZ skips 2 bytes when D is 0
+        let (cpu, _) = run(&[0xCE], 1, &[]);
+        assert_eq!(cpu.r[0], 0x003);
+
+        //R2 := 0x10, X := 2, OUT 2 selects the key at M(0x10), B3 0x30 is taken while it is pressed
+        let mut program = vec![0xF8, 0x10, 0xA2, 0xE2, 0x62, 0x36, 0x30];
+        program.resize(0x10, 0);
+        program.push(0x5);
+        let mut keys = [false; 16];
+        let (cpu, _) = run(&program, 5, &keys);
+        assert_eq!((cpu.key_latch, cpu.r[0]), (5, 0x007));
+        keys[5] = true;
+        let (cpu, _) = run(&program, 5, &keys);
+        assert_eq!(cpu.r[0], 0x030);
+    }
+
+    #[test]
+    fn loads_and_stores_go_through_the_registers() {
+        //R1 := 0x150, D := 0x2A, STR R1, D := 0, LDN R1
+        let program = [
+            0xF8, 0x01, 0xB1, 0xF8, 0x50, 0xA1, 0xF8, 0x2A, 0x51, 0xF8, 0x00, 0x01,
+        ];
+        let (cpu, memory) = run(&program, 8, &[]);
+        assert_eq!(memory[0x150], 0x2A);
+        assert_eq!((cpu.d, cpu.r[1]), (0x2A, 0x150));
+
+        //R1 := 0x150, X := 1, D := 7, STXD moves R1 down, LDA R1 and LDXA move it up
+        let program = [
+            0xF8, 0x01, 0xB1, 0xF8, 0x50, 0xA1, 0xE1, 0xF8, 0x07, 0x73, 0x41, 0x72,
+        ];
+        let (cpu, memory) = run(&program, 7, &[]);
+        assert_eq!(memory[0x150], 0x07);
+        assert_eq!(cpu.r[1], 0x14F);
+        let (cpu, _) = run(&program, 8, &[]);
+        assert_eq!((cpu.d, cpu.r[1]), (0x00, 0x150));
+        let (cpu, _) = run(&program, 9, &[]);
+        assert_eq!((cpu.d, cpu.r[1]), (0x07, 0x151));
+
+        //GLO and GHI read the halves of a register
+        let (cpu, _) = run(&[0xF8, 0x12, 0xB3, 0xF8, 0x34, 0xA3, 0x93], 5, &[]);
+        assert_eq!((cpu.r[3], cpu.d), (0x1234, 0x12));
+    }
+
+    #[test]
+    fn arithmetic_sets_the_carry() {
+        let program = [
+            0xF8, 0xF0, //D := 0xF0
+            0xFC, 0x20, //ADI 0x20 : 0x110, a carry
+            0x7C, 0x01, //ADCI 0x01 : 0x10 + 1 + the carry
+            0xFF, 0x20, //SMI 0x20 : 0x12 - 0x20, a borrow
+            0x7F, 0x00, //SMBI 0x00 : 0xF2 - 0 - the borrow
+            0x76, //SHRC : the carry goes into bit 7, bit 0 into the carry
+        ];
+        let expected = [
+            (0xF0, false),
+            (0x10, true),
+            (0x12, false),
+            (0xF2, false),
+            (0xF1, true),
+            (0xF8, true),
+        ];
+        for (steps, &(d, df)) in expected.iter().enumerate() {
+            let (cpu, _) = run(&program, steps + 1, &[]);
+            assert_eq!((cpu.d, cpu.df), (d, df), "after {} steps", steps + 1);
+        }
+    }
+
+    #[test]
+    fn sep_and_sex_switch_the_program_counter_and_the_data_pointer() {
+        let mut program = vec![
+            0xF8, 0x20, 0xA3, //R3 := 0x20
+            0xF8, 0x40, 0xA5, //R5 := 0x40
+            0xD3, //SEP 3 : R3 runs the program
+            0x30, 0x07, //R0 goes on here once R3 gives it back : branch to itself
+        ];
+        program.resize(0x20, 0);
+        program.extend(&[0xE5, 0xF0, 0xD0]); //SEX 5, LDX : D := M(R5), SEP 0
+        program.resize(0x40, 0);
+        program.push(0x99);
+
+        let (cpu, _) = run(&program, 5, &[]);
+        assert_eq!((cpu.p, cpu.r[3]), (3, 0x20));
+        let (cpu, _) = run(&program, 8, &[]);
+        assert_eq!((cpu.p, cpu.x, cpu.d), (0, 5, 0x99));
+        assert_eq!((cpu.r[0], cpu.r[3]), (0x07, 0x23));
+    }
+}
@@ -0,0 +1,433 @@
+//! The decoder turns an opcode into an `Instruction`, which knows its operands and its assembly mnemonic.
+//! The machine, the tracer and the disassembler share it, so every tool agrees on what an opcode means.
+//! Mnemonics follow Cowgod's CHIP-8 technical reference, e.g. `LD V1, 0x0A` for `0x610A`.
+//! The SUPER-CHIP and XO-CHIP extensions are decoded as well, the disassembler shows them and the machine executes
+//! them when its platform has them (`Instruction::decode_for()`).
+
+use super::memory::{MEMORY_SIZE, XO_CHIP_MEMORY_SIZE};
+use std::fmt;
+
+/// `Platform` : a CHIP-8 instruction set, each one extending the one before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    /// `Platform.name()` returns the name `Platform::from_name()` reads.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::Chip8 => "chip8",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+        }
+    }
+
+    /// `Platform.memory_size()` returns the size of the memory of the platform : 4 KB, 64 KB for XO-CHIP.
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Platform::XoChip => XO_CHIP_MEMORY_SIZE,
+            _ => MEMORY_SIZE,
+        }
+    }
+
+    /// `Platform::from_name()` will return the platform called `chip8`, `schip` or `xochip`.
+    pub fn from_name(name: &str) -> Option<Platform> {
+        match name {
+            "chip8" => Some(Platform::Chip8),
+            "schip" => Some(Platform::SuperChip),
+            "xochip" => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Platform::Chip8 => "CHIP-8",
+            Platform::SuperChip => "SUPER-CHIP",
+            Platform::XoChip => "XO-CHIP",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// `Instruction` : a decoded opcode. `x` and `y` are register indexes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0NNN : call a machine code routine (run by the CDP1802 of this machine).
+    Sys(u16),
+    /// 00E0 : clear the screen.
+    Cls,
+    /// 00EE : return from a subroutine.
+    Ret,
+    /// 1NNN : jump to NNN.
+    Jump(u16),
+    /// 2NNN : call the subroutine at NNN.
+    Call(u16),
+    /// 3XNN : skip the next instruction if VX equals NN.
+    SkipEqualByte { x: usize, nn: u8 },
+    /// 4XNN : skip the next instruction if VX does not equal NN.
+    SkipNotEqualByte { x: usize, nn: u8 },
+    /// 5XY0 : skip the next instruction if VX equals VY.
+    SkipEqual { x: usize, y: usize },
+    /// 6XNN : set VX to NN.
+    LoadByte { x: usize, nn: u8 },
+    /// 7XNN : add NN to VX (without carry).
+    AddByte { x: usize, nn: u8 },
+    /// 8XY0 : set VX to VY.
+    Load { x: usize, y: usize },
+    /// 8XY1 : set VX to VX | VY.
+    Or { x: usize, y: usize },
+    /// 8XY2 : set VX to VX & VY.
+    And { x: usize, y: usize },
+    /// 8XY3 : set VX to VX ^ VY.
+    Xor { x: usize, y: usize },
+    /// 8XY4 : add VY to VX, VF is the carry.
+    Add { x: usize, y: usize },
+    /// 8XY5 : subtract VY from VX, VF is the "not borrow".
+    Sub { x: usize, y: usize },
+    /// 8XY6 : shift VX right by one, VF is the shifted out bit.
+    ShiftRight { x: usize, y: usize },
+    /// 8XY7 : set VX to VY - VX, VF is the "not borrow".
+    SubNegated { x: usize, y: usize },
+    /// 8XYE : shift VX left by one, VF is the shifted out bit.
+    ShiftLeft { x: usize, y: usize },
+    /// 9XY0 : skip the next instruction if VX does not equal VY.
+    SkipNotEqual { x: usize, y: usize },
+    /// ANNN : set I to NNN.
+    LoadIndex(u16),
+    /// BNNN : jump to NNN + V0.
+    JumpOffset(u16),
+    /// CXNN : set VX to a random number & NN.
+    Random { x: usize, nn: u8 },
+    /// DXYN : draw the N bytes long sprite at I at (VX, VY), VF is the collision.
+    Draw { x: usize, y: usize, n: u8 },
+    /// EX9E : skip the next instruction if the key VX is pressed.
+    SkipKey(usize),
+    /// EXA1 : skip the next instruction if the key VX is not pressed.
+    SkipNotKey(usize),
+    /// FX07 : set VX to the delay timer.
+    LoadDelay(usize),
+    /// FX0A : wait for a key press and store it in VX.
+    WaitKey(usize),
+    /// FX15 : set the delay timer to VX.
+    SetDelay(usize),
+    /// FX18 : set the sound timer to VX.
+    SetSound(usize),
+    /// FX1E : add VX to I.
+    AddIndex(usize),
+    /// FX29 : set I to the font sprite of the digit VX.
+    LoadFont(usize),
+    /// FX33 : store the BCD of VX at I, I+1 and I+2.
+    StoreBcd(usize),
+    /// FX55 : store V0 to VX in memory, starting at I.
+    StoreRegisters(usize),
+    /// FX65 : load V0 to VX from memory, starting at I.
+    LoadRegisters(usize),
+    /// 00CN (SUPER-CHIP) : scroll the display down by N pixels.
+    ScrollDown(u8),
+    /// 00DN (XO-CHIP) : scroll the display up by N pixels.
+    ScrollUp(u8),
+    /// 00FB (SUPER-CHIP) : scroll the display right by 4 pixels.
+    ScrollRight,
+    /// 00FC (SUPER-CHIP) : scroll the display left by 4 pixels.
+    ScrollLeft,
+    /// 00FD (SUPER-CHIP) : exit the interpreter.
+    Exit,
+    /// 00FE (SUPER-CHIP) : switch to the 64x32 display.
+    LowRes,
+    /// 00FF (SUPER-CHIP) : switch to the 128x64 display.
+    HighRes,
+    /// 5XY2 (XO-CHIP) : store VX to VY in memory, starting at I.
+    StoreRange { x: usize, y: usize },
+    /// 5XY3 (XO-CHIP) : load VX to VY from memory, starting at I.
+    LoadRange { x: usize, y: usize },
+    /// F000 NNNN (XO-CHIP) : set I to the 16-bit address in the next word. The only 4 bytes long instruction.
+    LoadIndexLong,
+    /// FN01 (XO-CHIP) : select the drawing planes N.
+    Plane(u8),
+    /// F002 (XO-CHIP) : load the 16 bytes audio pattern at I.
+    Audio,
+    /// FX30 (SUPER-CHIP) : set I to the big font sprite of the digit VX.
+    LoadBigFont(usize),
+    /// FX3A (XO-CHIP) : set the audio pitch to VX.
+    SetPitch(usize),
+    /// FX75 (SUPER-CHIP) : store V0 to VX in the flag registers.
+    StoreFlags(usize),
+    /// FX85 (SUPER-CHIP) : load V0 to VX from the flag registers.
+    LoadFlags(usize),
+    /// Any opcode the machine does not know.
+    Unknown(u16),
+}
+
+impl Instruction {
+    /// `Instruction::decode()` will return the instruction of the `opcode`.
+    pub fn decode(opcode: u16) -> Instruction {
+        let nibbles = (
+            ((opcode & 0xF000) >> 12) as u8,
+            ((opcode & 0x0F00) >> 8) as u8,
+            ((opcode & 0x00F0) >> 4) as u8,
+            (opcode & 0x000F) as u8,
+        );
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let y = ((opcode & 0x00F0) >> 4) as usize;
+        let n = (opcode & 0x000F) as u8;
+        let nn = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+        match nibbles {
+            (0, 0, 0xE, 0) => Instruction::Cls,
+            (0, 0, 0xE, 0xE) => Instruction::Ret,
+            (0, 0, 0xC, _) => Instruction::ScrollDown(n),
+            (0, 0, 0xD, _) => Instruction::ScrollUp(n),
+            (0, 0, 0xF, 0xB) => Instruction::ScrollRight,
+            (0, 0, 0xF, 0xC) => Instruction::ScrollLeft,
+            (0, 0, 0xF, 0xD) => Instruction::Exit,
+            (0, 0, 0xF, 0xE) => Instruction::LowRes,
+            (0, 0, 0xF, 0xF) => Instruction::HighRes,
+            (0, _, _, _) => Instruction::Sys(nnn),
+            (0x1, _, _, _) => Instruction::Jump(nnn),
+            (0x2, _, _, _) => Instruction::Call(nnn),
+            (0x3, _, _, _) => Instruction::SkipEqualByte { x, nn },
+            (0x4, _, _, _) => Instruction::SkipNotEqualByte { x, nn },
+            (0x5, _, _, 0) => Instruction::SkipEqual { x, y },
+            (0x5, _, _, 0x2) => Instruction::StoreRange { x, y },
+            (0x5, _, _, 0x3) => Instruction::LoadRange { x, y },
+            (0x6, _, _, _) => Instruction::LoadByte { x, nn },
+            (0x7, _, _, _) => Instruction::AddByte { x, nn },
+            (0x8, _, _, 0x0) => Instruction::Load { x, y },
+            (0x8, _, _, 0x1) => Instruction::Or { x, y },
+            (0x8, _, _, 0x2) => Instruction::And { x, y },
+            (0x8, _, _, 0x3) => Instruction::Xor { x, y },
+            (0x8, _, _, 0x4) => Instruction::Add { x, y },
+            (0x8, _, _, 0x5) => Instruction::Sub { x, y },
+            (0x8, _, _, 0x6) => Instruction::ShiftRight { x, y },
+            (0x8, _, _, 0x7) => Instruction::SubNegated { x, y },
+            (0x8, _, _, 0xE) => Instruction::ShiftLeft { x, y },
+            (0x9, _, _, 0x0) => Instruction::SkipNotEqual { x, y },
+            (0xA, _, _, _) => Instruction::LoadIndex(nnn),
+            (0xB, _, _, _) => Instruction::JumpOffset(nnn),
+            (0xC, _, _, _) => Instruction::Random { x, nn },
+            (0xD, _, _, _) => Instruction::Draw { x, y, n },
+            (0xE, _, 0x9, 0xE) => Instruction::SkipKey(x),
+            (0xE, _, 0xA, 0x1) => Instruction::SkipNotKey(x),
+            (0xF, 0, 0x0, 0x0) => Instruction::LoadIndexLong,
+            (0xF, _, 0x0, 0x1) => Instruction::Plane(x as u8),
+            (0xF, 0, 0x0, 0x2) => Instruction::Audio,
+            (0xF, _, 0x0, 0x7) => Instruction::LoadDelay(x),
+            (0xF, _, 0x0, 0xA) => Instruction::WaitKey(x),
+            (0xF, _, 0x1, 0x5) => Instruction::SetDelay(x),
+            (0xF, _, 0x1, 0x8) => Instruction::SetSound(x),
+            (0xF, _, 0x1, 0xE) => Instruction::AddIndex(x),
+            (0xF, _, 0x2, 0x9) => Instruction::LoadFont(x),
+            (0xF, _, 0x3, 0x0) => Instruction::LoadBigFont(x),
+            (0xF, _, 0x3, 0x3) => Instruction::StoreBcd(x),
+            (0xF, _, 0x3, 0xA) => Instruction::SetPitch(x),
+            (0xF, _, 0x5, 0x5) => Instruction::StoreRegisters(x),
+            (0xF, _, 0x6, 0x5) => Instruction::LoadRegisters(x),
+            (0xF, _, 0x7, 0x5) => Instruction::StoreFlags(x),
+            (0xF, _, 0x8, 0x5) => Instruction::LoadFlags(x),
+            (_, _, _, _) => Instruction::Unknown(opcode),
+        }
+    }
+
+    /// `Instruction::decode_for()` will return the instruction of the `opcode` on `platform`, where the instructions
+    /// of later platforms do not exist : 00CN and 00FB to 00FF are calls of machine code (0NNN), the others unknown.
+    /// DXY0 stays a draw, of nothing before SUPER-CHIP.
+    pub fn decode_for(opcode: u16, platform: Platform) -> Instruction {
+        let instruction = Instruction::decode(opcode);
+        match instruction {
+            Instruction::Draw { .. } => instruction,
+            _ if instruction.platform() <= platform => instruction,
+            _ if opcode & 0xF000 == 0 => Instruction::Sys(opcode),
+            _ => Instruction::Unknown(opcode),
+        }
+    }
+
+    /// `Instruction.encode()` will return the opcode of the instruction, the reverse of `Instruction::decode()`.
+    pub fn encode(&self) -> u16 {
+        let xy = |x: usize, y: usize, n: u16| (x as u16) << 8 | (y as u16) << 4 | n;
+        let xnn = |x: usize, nn: u8| (x as u16) << 8 | nn as u16;
+        let register = |x: usize| (x as u16) << 8;
+        match *self {
+            Instruction::Sys(nnn) => nnn,
+            Instruction::Cls => 0x00E0,
+            Instruction::Ret => 0x00EE,
+            Instruction::Jump(nnn) => 0x1000 | nnn,
+            Instruction::Call(nnn) => 0x2000 | nnn,
+            Instruction::SkipEqualByte { x, nn } => 0x3000 | xnn(x, nn),
+            Instruction::SkipNotEqualByte { x, nn } => 0x4000 | xnn(x, nn),
+            Instruction::SkipEqual { x, y } => 0x5000 | xy(x, y, 0x0),
+            Instruction::LoadByte { x, nn } => 0x6000 | xnn(x, nn),
+            Instruction::AddByte { x, nn } => 0x7000 | xnn(x, nn),
+            Instruction::Load { x, y } => 0x8000 | xy(x, y, 0x0),
+            Instruction::Or { x, y } => 0x8000 | xy(x, y, 0x1),
+            Instruction::And { x, y } => 0x8000 | xy(x, y, 0x2),
+            Instruction::Xor { x, y } => 0x8000 | xy(x, y, 0x3),
+            Instruction::Add { x, y } => 0x8000 | xy(x, y, 0x4),
+            Instruction::Sub { x, y } => 0x8000 | xy(x, y, 0x5),
+            Instruction::ShiftRight { x, y } => 0x8000 | xy(x, y, 0x6),
+            Instruction::SubNegated { x, y } => 0x8000 | xy(x, y, 0x7),
+            Instruction::ShiftLeft { x, y } => 0x8000 | xy(x, y, 0xE),
+            Instruction::SkipNotEqual { x, y } => 0x9000 | xy(x, y, 0x0),
+            Instruction::LoadIndex(nnn) => 0xA000 | nnn,
+            Instruction::JumpOffset(nnn) => 0xB000 | nnn,
+            Instruction::Random { x, nn } => 0xC000 | xnn(x, nn),
+            Instruction::Draw { x, y, n } => 0xD000 | xy(x, y, n as u16),
+            Instruction::SkipKey(x) => 0xE09E | register(x),
+            Instruction::SkipNotKey(x) => 0xE0A1 | register(x),
+            Instruction::LoadDelay(x) => 0xF007 | register(x),
+            Instruction::WaitKey(x) => 0xF00A | register(x),
+            Instruction::SetDelay(x) => 0xF015 | register(x),
+            Instruction::SetSound(x) => 0xF018 | register(x),
+            Instruction::AddIndex(x) => 0xF01E | register(x),
+            Instruction::LoadFont(x) => 0xF029 | register(x),
+            Instruction::StoreBcd(x) => 0xF033 | register(x),
+            Instruction::StoreRegisters(x) => 0xF055 | register(x),
+            Instruction::LoadRegisters(x) => 0xF065 | register(x),
+            Instruction::ScrollDown(n) => 0x00C0 | n as u16,
+            Instruction::ScrollUp(n) => 0x00D0 | n as u16,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HighRes => 0x00FF,
+            Instruction::StoreRange { x, y } => 0x5000 | xy(x, y, 0x2),
+            Instruction::LoadRange { x, y } => 0x5000 | xy(x, y, 0x3),
+            Instruction::LoadIndexLong => 0xF000,
+            Instruction::Plane(n) => 0xF001 | (n as u16) << 8,
+            Instruction::Audio => 0xF002,
+            Instruction::LoadBigFont(x) => 0xF030 | register(x),
+            Instruction::SetPitch(x) => 0xF03A | register(x),
+            Instruction::StoreFlags(x) => 0xF075 | register(x),
+            Instruction::LoadFlags(x) => 0xF085 | register(x),
+            Instruction::Unknown(opcode) => opcode,
+        }
+    }
+
+    /// `Instruction.size()` returns the number of bytes of the instruction : 4 for `LoadIndexLong`, 2 for all others.
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LoadIndexLong => 4,
+            _ => 2,
+        }
+    }
+
+    /// `Instruction.platform()` returns the platform which introduced the instruction.
+    /// DXY0 draws a 16x16 sprite on SUPER-CHIP, and nothing on CHIP-8.
+    pub fn platform(&self) -> Platform {
+        match self {
+            Instruction::ScrollDown(_)
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Exit
+            | Instruction::LowRes
+            | Instruction::HighRes
+            | Instruction::Draw { n: 0, .. }
+            | Instruction::LoadBigFont(_)
+            | Instruction::StoreFlags(_)
+            | Instruction::LoadFlags(_) => Platform::SuperChip,
+            Instruction::ScrollUp(_)
+            | Instruction::StoreRange { .. }
+            | Instruction::LoadRange { .. }
+            | Instruction::LoadIndexLong
+            | Instruction::Plane(_)
+            | Instruction::Audio
+            | Instruction::SetPitch(_) => Platform::XoChip,
+            _ => Platform::Chip8,
+        }
+    }
+
+    /// `Instruction.is_skip()` tells if the instruction may skip the next instruction.
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Instruction::SkipEqualByte { .. }
+                | Instruction::SkipNotEqualByte { .. }
+                | Instruction::SkipEqual { .. }
+                | Instruction::SkipNotEqual { .. }
+                | Instruction::SkipKey(_)
+                | Instruction::SkipNotKey(_)
+        )
+    }
+
+    /// `Instruction.target()` returns the address the instruction refers to : a jump or call target, or the address loaded into I.
+    pub fn target(&self) -> Option<u16> {
+        match *self {
+            Instruction::Sys(nnn)
+            | Instruction::Jump(nnn)
+            | Instruction::Call(nnn)
+            | Instruction::LoadIndex(nnn)
+            | Instruction::JumpOffset(nnn) => Some(nnn),
+            _ => None,
+        }
+    }
+
+    /// `Instruction.format()` writes the mnemonic, naming the target address with `name` when it returns a label.
+    pub fn format(&self, name: impl Fn(u16) -> Option<String>) -> String {
+        let address = |nnn: u16| name(nnn).unwrap_or_else(|| format!("0x{:03X}", nnn));
+        match *self {
+            Instruction::Sys(nnn) => format!("SYS {}", address(nnn)),
+            Instruction::Cls => String::from("CLS"),
+            Instruction::Ret => String::from("RET"),
+            Instruction::Jump(nnn) => format!("JP {}", address(nnn)),
+            Instruction::Call(nnn) => format!("CALL {}", address(nnn)),
+            Instruction::SkipEqualByte { x, nn } => format!("SE V{:X}, 0x{:02X}", x, nn),
+            Instruction::SkipNotEqualByte { x, nn } => format!("SNE V{:X}, 0x{:02X}", x, nn),
+            Instruction::SkipEqual { x, y } => format!("SE V{:X}, V{:X}", x, y),
+            Instruction::LoadByte { x, nn } => format!("LD V{:X}, 0x{:02X}", x, nn),
+            Instruction::AddByte { x, nn } => format!("ADD V{:X}, 0x{:02X}", x, nn),
+            Instruction::Load { x, y } => format!("LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+            Instruction::Add { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+            Instruction::SubNegated { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNotEqual { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+            Instruction::LoadIndex(nnn) => format!("LD I, {}", address(nnn)),
+            Instruction::JumpOffset(nnn) => format!("JP V0, {}", address(nnn)),
+            Instruction::Random { x, nn } => format!("RND V{:X}, 0x{:02X}", x, nn),
+            Instruction::Draw { x, y, n } => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKey(x) => format!("SKP V{:X}", x),
+            Instruction::SkipNotKey(x) => format!("SKNP V{:X}", x),
+            Instruction::LoadDelay(x) => format!("LD V{:X}, DT", x),
+            Instruction::WaitKey(x) => format!("LD V{:X}, K", x),
+            Instruction::SetDelay(x) => format!("LD DT, V{:X}", x),
+            Instruction::SetSound(x) => format!("LD ST, V{:X}", x),
+            Instruction::AddIndex(x) => format!("ADD I, V{:X}", x),
+            Instruction::LoadFont(x) => format!("LD F, V{:X}", x),
+            Instruction::StoreBcd(x) => format!("LD B, V{:X}", x),
+            Instruction::StoreRegisters(x) => format!("LD [I], V{:X}", x),
+            Instruction::LoadRegisters(x) => format!("LD V{:X}, [I]", x),
+            Instruction::ScrollDown(n) => format!("SCD {}", n),
+            Instruction::ScrollUp(n) => format!("SCU {}", n),
+            Instruction::ScrollRight => String::from("SCR"),
+            Instruction::ScrollLeft => String::from("SCL"),
+            Instruction::Exit => String::from("EXIT"),
+            Instruction::LowRes => String::from("LOW"),
+            Instruction::HighRes => String::from("HIGH"),
+            Instruction::StoreRange { x, y } => format!("LD [I], V{:X}-V{:X}", x, y),
+            Instruction::LoadRange { x, y } => format!("LD V{:X}-V{:X}, [I]", x, y),
+            Instruction::LoadIndexLong => String::from("LD I, LONG"),
+            Instruction::Plane(n) => format!("PLANE {}", n),
+            Instruction::Audio => String::from("AUDIO"),
+            Instruction::LoadBigFont(x) => format!("LD HF, V{:X}", x),
+            Instruction::SetPitch(x) => format!("LD PITCH, V{:X}", x),
+            Instruction::StoreFlags(x) => format!("LD R, V{:X}", x),
+            Instruction::LoadFlags(x) => format!("LD V{:X}, R", x),
+            Instruction::Unknown(_) => String::from("UNKNOWN"),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(|_| None))
+    }
+}
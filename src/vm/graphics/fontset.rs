@@ -1,32 +1,185 @@
-//!
-//!
+//! Font sets - the sprites of the hexadecimal digits, which interpreters keep in their own memory :
+//! a small font of 16 characters of 4x5 pixels (FX29), and a big font of 16 characters of 8x10 pixels (FX30,
+//! SUPER-CHIP). Each interpreter drew its small font its own way.
 
-#[derive(Debug)]
+/// Size of a character of the small font, in bytes.
+pub const SMALL_CHARACTER_SIZE: usize = 5;
+/// Size of a character of the big font, in bytes.
+pub const BIG_CHARACTER_SIZE: usize = 10;
+
+const VIP_FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, //0
+    0x60, 0x20, 0x20, 0x20, 0x70, //1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, //2
+    0xF0, 0x10, 0x70, 0x10, 0xF0, //3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, //4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, //5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, //6
+    0xF0, 0x10, 0x10, 0x10, 0x10, //7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, //8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, //9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, //A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, //B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, //C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, //D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, //E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, //F
+];
+
+const DREAM_6800_FONT: [u8; 80] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, //0
+    0x40, 0x40, 0x40, 0x40, 0x40, //1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, //2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, //3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, //4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, //5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, //6
+    0xE0, 0x20, 0x20, 0x20, 0x20, //7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, //8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, //9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, //A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, //B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, //C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, //D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, //E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, //F
+];
+
+const ETI_660_FONT: [u8; 80] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, //0
+    0x20, 0x20, 0x20, 0x20, 0x20, //1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, //2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, //3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, //4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, //5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, //6
+    0xE0, 0x20, 0x20, 0x20, 0x20, //7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, //8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, //9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, //A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, //B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, //C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, //D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, //E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, //F
+];
+
+const CHIP_48_FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, //0
+    0x20, 0x60, 0x20, 0x20, 0x70, //1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, //2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, //3
+    0x90, 0x90, 0xF0, 0x10, 0x10, //4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, //5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, //6
+    0xF0, 0x10, 0x20, 0x40, 0x40, //7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, //8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, //9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, //A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, //B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, //C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, //D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, //E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, //F
+];
+
+/// The big font of SUPER-CHIP 1.1 has the digits only, A-F are those of Octo.
+const SUPER_CHIP_BIG_FONT: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, //0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, //1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, //2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, //3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, //4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, //5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, //6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, //7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, //8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, //9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, //A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, //B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, //C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, //D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, //E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, //F
+];
+
+/// `FontStyle` : the interpreters whose small font is built in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontStyle {
+    Vip,
+    Dream6800,
+    Eti660,
+    Chip48,
+}
+
+impl FontStyle {
+    /// `FontStyle::from_name()` will return the style called `vip`, `dream6800`, `eti660` or `chip48`.
+    pub fn from_name(name: &str) -> Option<FontStyle> {
+        match name {
+            "vip" => Some(FontStyle::Vip),
+            "dream6800" => Some(FontStyle::Dream6800),
+            "eti660" => Some(FontStyle::Eti660),
+            "chip48" => Some(FontStyle::Chip48),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct FontSet {
+    /// `fonts` holds the 16 characters of the small font.
     pub fonts: Vec<u8>,
+    /// `big_fonts` holds the 16 characters of the big font.
+    pub big_fonts: Vec<u8>,
+}
+
+impl Default for FontSet {
+    fn default() -> Self {
+        FontSet::new()
+    }
 }
 
 impl FontSet {
+    /// `FontSet::new()` will return the font this machine always had (the one of CHIP-48), and the big font
+    /// of SUPER-CHIP.
     pub fn new() -> FontSet {
+        FontSet::builtin(FontStyle::Chip48)
+    }
+
+    /// `FontSet::builtin()` will return the small font of an interpreter, and the big font of SUPER-CHIP.
+    pub fn builtin(style: FontStyle) -> FontSet {
+        let fonts = match style {
+            FontStyle::Vip => VIP_FONT,
+            FontStyle::Dream6800 => DREAM_6800_FONT,
+            FontStyle::Eti660 => ETI_660_FONT,
+            FontStyle::Chip48 => CHIP_48_FONT,
+        };
         FontSet {
-            fonts: vec![
-                0xF0, 0x90, 0x90, 0x90, 0xF0, //0
-                0x20, 0x60, 0x20, 0x20, 0x70, //1
-                0xF0, 0x10, 0xF0, 0x80, 0xF0, //2
-                0xF0, 0x10, 0xF0, 0x10, 0xF0, //3
-                0x90, 0x90, 0xF0, 0x10, 0x10, //4
-                0xF0, 0x80, 0xF0, 0x10, 0xF0, //5
-                0xF0, 0x80, 0xF0, 0x90, 0xF0, //6
-                0xF0, 0x10, 0x20, 0x40, 0x40, //7
-                0xF0, 0x90, 0xF0, 0x90, 0xF0, //8
-                0xF0, 0x90, 0xF0, 0x10, 0xF0, //9
-                0xF0, 0x90, 0xF0, 0x90, 0x90, //A
-                0xE0, 0x90, 0xE0, 0x90, 0xE0, //B
-                0xF0, 0x80, 0x80, 0x80, 0xF0, //C
-                0xE0, 0x90, 0x90, 0x90, 0xE0, //D
-                0xF0, 0x80, 0xF0, 0x80, 0xF0, //E
-                0xF0, 0x80, 0xF0, 0x80, 0x80, //F
-            ],
+            fonts: fonts.to_vec(),
+            big_fonts: SUPER_CHIP_BIG_FONT.to_vec(),
         }
     }
+
+    /// `FontSet::from_bytes()` will return a custom font : 16 small characters (80 bytes), optionally followed
+    /// by 10 or 16 big characters (100 or 160 bytes). Without big characters, the big font is the one of SUPER-CHIP.
+    pub fn from_bytes(data: &[u8]) -> Result<FontSet, String> {
+        let small_size = 16 * SMALL_CHARACTER_SIZE;
+        let mut big_fonts = match data.len().checked_sub(small_size) {
+            Some(0) => SUPER_CHIP_BIG_FONT.to_vec(),
+            Some(size) if size == 10 * BIG_CHARACTER_SIZE || size == 16 * BIG_CHARACTER_SIZE => {
+                data[small_size..].to_vec()
+            }
+            _ => {
+                return Err(format!(
+                    "a font is 80 bytes, or 180 or 240 bytes with a big font, not {} bytes",
+                    data.len()
+                ))
+            }
+        };
+        big_fonts.resize(16 * BIG_CHARACTER_SIZE, 0);
+        Ok(FontSet {
+            fonts: data[..small_size].to_vec(),
+            big_fonts,
+        })
+    }
 }
@@ -1,10 +1,62 @@
 //! The `VRAM` of the Chip 8 are black and white and the screen has a total of 2048 pixels (64 x 32).
 //! This can easily be implemented using a vector that hold the pixel state (1 or 0).
+//! The hi-res CHIP-8 interpreter shows two pages of VRAM, for a screen of 64 x 64.
+//! MegaChip has a screen of 256 x 192 colored pixels : its cells hold the colors of the palette drawn.
+
+/// Number of the colors the background of CHIP-8X cycles through.
+pub const BACKGROUND_COLORS: u8 = 4;
+/// Color of the zones of CHIP-8X, until the program sets them : red.
+const DEFAULT_ZONE_COLOR: u8 = 1;
 
 #[derive(Debug)]
 pub struct VRam {
     pub cells: Vec<Vec<u8>>,
     pub state_changed: bool,
+    /// `colors` are the color attributes of CHIP-8X, which has a color board. Other machines are black and white.
+    pub colors: Option<Colors>,
+    /// `mega` is the screen of MegaChip, in MegaChip mode.
+    pub mega: Option<MegaScreen>,
+}
+
+/// `Colors` : the color attributes of the CHIP-8X (the VP-590 color board of the COSMAC VIP). The background has
+/// one color, and the pixels which are on take the color of their zone : 8 pixels wide, 1 pixel high.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Colors {
+    /// `background` is the color of the background : 0 (blue), 1 (black), 2 (green) or 3 (red).
+    pub background: u8,
+    /// `zones` hold the color of each zone, from 0 to 7 : black, red, blue, violet, green, yellow, aqua, white.
+    pub zones: Vec<Vec<u8>>,
+}
+
+impl Colors {
+    /// `Colors::new()` will return the colors of a VRAM of `width` x `height` : a blue background, and red zones.
+    pub fn new(width: usize, height: usize) -> Colors {
+        Colors {
+            background: 0,
+            zones: vec![vec![DEFAULT_ZONE_COLOR; width.div_ceil(8)]; height],
+        }
+    }
+}
+
+/// `MegaScreen` : the ARGB pixels of the screen of MegaChip. Sprites are drawn into `buffer`, and 00E0 shows it as
+/// `frame` before clearing it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MegaScreen {
+    pub buffer: Vec<Vec<u32>>,
+    pub frame: Vec<Vec<u32>>,
+    /// `alpha` is the opacity of the whole screen (05NN), used to fade it in and out.
+    pub alpha: u8,
+}
+
+impl MegaScreen {
+    /// `MegaScreen::new()` will return a black screen of `width` x `height`, fully opaque.
+    pub fn new(width: usize, height: usize) -> MegaScreen {
+        MegaScreen {
+            buffer: vec![vec![0xFF00_0000; width]; height],
+            frame: vec![vec![0xFF00_0000; width]; height],
+            alpha: 0xFF,
+        }
+    }
 }
 
 impl VRam {
@@ -13,6 +65,8 @@ impl VRam {
         VRam {
             cells: vec![vec![0; width]; height],
             state_changed: false,
+            colors: None,
+            mega: None,
         }
     }
 }
@@ -1,20 +1,118 @@
+use crate::debugger::undo_log::Mutation;
+use crate::vm::graphics::fontset::BIG_CHARACTER_SIZE;
+use crate::vm::graphics::vram::BACKGROUND_COLORS;
+use crate::vm::megachip::{BlendMode, Sample, SCREEN_HEIGHT, SCREEN_WIDTH};
 use crate::vm::operator::Operator;
-use crate::vm::Machine;
-use log::{debug, info};
+use crate::vm::{
+    Cdp1802, Machine, Platform, SUPER_CHIP_VRAM_HEIGHT, SUPER_CHIP_VRAM_WIDTH, VRAM_HEIGHT,
+    VRAM_WIDTH,
+};
+use log::{debug, info, warn};
 use rand::Rng;
 
-//pub fn execute_0nnn() {}
+/// Where the interpreter of the COSMAC VIP keeps V0 to VF, the top of its stack and the display (4 KB of memory).
+const VIP_REGISTERS: usize = 0xEF0;
+const VIP_STACK_TOP: u16 = 0xECF;
+const VIP_DISPLAY: usize = 0xF00;
+/// Number of instructions after which a routine of machine code which did not return is given up.
+const CDP1802_MAX_STEPS: usize = 1_000_000;
+/// Number of pixels 00FB and 00FC scroll the display by.
+const HORIZONTAL_SCROLL: isize = 4;
+
+/// `instructions::execute_0nnn()`
+/// Type = Call
+/// Explanation = Calls the routine of CDP1802 machine code at NNN, which returns with `SEP R4` (D4).
+/// The routine sees the machine as the interpreter of the COSMAC VIP left it : V0 to VF at 0xEF0, the display
+/// at 0xF00 (64 x 32 only), I in RA, the delay and sound timers in R8.1 and R8.0, R6 and R7 pointing to VX and VY,
+/// R2 to the stack and X selecting R2. What the routine changed there is taken back when it returns, and the program
+/// goes on at R5, the program counter of the interpreter (the next instruction, unless the routine changed it).
+pub fn execute_0nnn(machine: &mut Machine, operator: &Operator) {
+    info!(
+        "[execute_0nnn] Calling the machine code at 0x{:03X}.",
+        operator.nnn_address
+    );
+    let (memory, cpu, returned) = run_machine_code(machine, operator);
+    if !returned {
+        warn!(
+            "[execute_0nnn()] The machine code at 0x{:03X} did not return after {} instructions.",
+            operator.nnn_address, CDP1802_MAX_STEPS
+        );
+    }
+    let old = std::mem::replace(&mut machine.cdp1802, cpu);
+    machine.record(Mutation::Cdp1802 { old });
+    //the routine may write anywhere in memory
+    for (address, &value) in memory.iter().enumerate() {
+        if machine.memory.cells[address] != value {
+            machine.write_memory(address, value);
+        }
+    }
+    let display = machine.vram.cells.len() == 32 && machine.vram.cells[0].len() == 64;
+    for x in 0..16 {
+        machine.set_register(x, machine.memory.cells[VIP_REGISTERS + x]);
+    }
+    let (ra, r8) = (machine.cdp1802.r[0xA], machine.cdp1802.r[0x8]);
+    machine.set_index(ra as u32);
+    machine.set_timers((r8 >> 8) as u8, r8 as u8);
+    if display {
+        for y in 0..machine.vram.cells.len() {
+            for x in 0..machine.vram.cells[y].len() {
+                let bit = (machine.memory.cells[VIP_DISPLAY + y * 8 + x / 8] >> (7 - x % 8)) & 1;
+                if machine.vram.cells[y][x] != bit {
+                    machine.set_pixel(x, y, bit);
+                    machine.vram.state_changed = true;
+                }
+            }
+        }
+    }
+    machine.pc = machine.cdp1802.r[0x5];
+}
+
+/// `instructions::run_machine_code()` runs the routine of machine code called by 0NNN on copies of the memory
+/// and of the CDP1802, set up as `execute_0nnn()` describes. It returns them as the routine left them, with
+/// whether the routine returned.
+pub fn run_machine_code(machine: &Machine, operator: &Operator) -> (Vec<u8>, Cdp1802, bool) {
+    let display = machine.vram.cells.len() == 32 && machine.vram.cells[0].len() == 64;
+    let mut memory = machine.memory.cells.clone();
+    memory[VIP_REGISTERS..VIP_REGISTERS + 16].copy_from_slice(&machine.registers.v);
+    if display {
+        for (y, row) in machine.vram.cells.iter().enumerate() {
+            for (byte, pixels) in row.chunks(8).enumerate() {
+                memory[VIP_DISPLAY + y * 8 + byte] = pixels
+                    .iter()
+                    .fold(0, |bits, &pixel| bits << 1 | (pixel & 1));
+            }
+        }
+    }
+
+    let mut cpu = machine.cdp1802.clone();
+    cpu.r[0x2] = VIP_STACK_TOP;
+    cpu.r[0x3] = operator.nnn_address;
+    cpu.r[0x5] = machine.pc + 2;
+    cpu.r[0x6] = (VIP_REGISTERS + operator.x) as u16;
+    cpu.r[0x7] = (VIP_REGISTERS + operator.y) as u16;
+    cpu.r[0x8] = (machine.timers.dt as u16) << 8 | machine.timers.st as u16;
+    cpu.r[0xA] = machine.i as u16;
+    cpu.r[0xB] = (VIP_DISPLAY as u16) & 0xFF00;
+    cpu.x = 0x2;
+    cpu.p = 0x3;
+    let returned = cpu.run(&mut memory, &machine.keyboard.keys, 0x4, CDP1802_MAX_STEPS);
+    (memory, cpu, returned)
+}
 
 /// `instructions::execute_00e0()`
 /// Type = Display
-/// Explanation = Clears the screen.
+/// Explanation = Clears the screen. On XO-CHIP, only the selected planes are cleared.
 pub fn execute_00e0(machine: &mut Machine) {
     info!("[execute_00e0()] Clearing display.");
     let vram_height = machine.vram.cells.len();
     let vram_width = machine.vram.cells[0].len();
+    let planes = machine.planes;
     for h in 0..vram_height {
         for w in 0..vram_width {
-            machine.vram.cells[h][w] = 0;
+            let pixel = machine.vram.cells[h][w];
+            if pixel & planes != 0 {
+                machine.set_pixel(w, h, pixel & !planes);
+            }
         }
     }
     machine.vram.state_changed = true;
@@ -22,16 +120,313 @@ pub fn execute_00e0(machine: &mut Machine) {
     debug!("[execute_00e0()] Cleared display.");
 }
 
+/// `instructions::execute_02a0()`
+/// Type = Display (CHIP-8X)
+/// Explanation = Cycles the background color : blue, black, green, red.
+pub fn execute_02a0(machine: &mut Machine) {
+    info!("[execute_02a0]");
+    machine.record_colors();
+    if let Some(colors) = machine.vram.colors.as_mut() {
+        colors.background = (colors.background + 1) % BACKGROUND_COLORS;
+        machine.vram.state_changed = true;
+    }
+    machine.pc += 2;
+}
+
 /// `instructions::execute_00ee()`
 /// Type = Flow
-/// Explanation = Returns from a subroutine.
+/// Explanation = Returns from a subroutine. With an empty stack, it returns to 0x000.
 pub fn execute_00ee(machine: &mut Machine) {
     info!("[execute_00ee]");
-    machine.pc = machine
-        .stack
-        .cells
-        .pop()
-        .expect("Returning from the subroutine is impossible, because the Stack is empty.");
+    machine.pc = machine.pop().unwrap_or_else(|| {
+        warn!(
+            "[execute_00ee()] Returning from 0x{:03X} with an empty stack, to 0x000.",
+            machine.pc
+        );
+        0
+    });
+}
+
+/// `instructions::execute_00cn()`
+/// Type = Display (SUPER-CHIP)
+/// Explanation = Scrolls the display down by N pixels.
+pub fn execute_00cn(machine: &mut Machine, n: u8) {
+    info!("[execute_00cn]");
+    scroll(machine, 0, n as isize);
+}
+
+/// `instructions::execute_00dn()`
+/// Type = Display (XO-CHIP)
+/// Explanation = Scrolls the display up by N pixels.
+pub fn execute_00dn(machine: &mut Machine, n: u8) {
+    info!("[execute_00dn]");
+    scroll(machine, 0, -(n as isize));
+}
+
+/// `instructions::execute_00fb()`
+/// Type = Display (SUPER-CHIP)
+/// Explanation = Scrolls the display right by 4 pixels.
+pub fn execute_00fb(machine: &mut Machine) {
+    info!("[execute_00fb]");
+    scroll(machine, HORIZONTAL_SCROLL, 0);
+}
+
+/// `instructions::execute_00fc()`
+/// Type = Display (SUPER-CHIP)
+/// Explanation = Scrolls the display left by 4 pixels.
+pub fn execute_00fc(machine: &mut Machine) {
+    info!("[execute_00fc]");
+    scroll(machine, -HORIZONTAL_SCROLL, 0);
+}
+
+/// `scroll()` moves the pixels of the selected planes by (`dx`, `dy`). The pixels moved in from outside of the
+/// display are off. In MegaChip mode, the screen of MegaChip is moved as well.
+fn scroll(machine: &mut Machine, dx: isize, dy: isize) {
+    machine.record_mega_screen();
+    if let Some(screen) = machine.vram.mega.as_mut() {
+        screen.buffer = shifted(&screen.buffer, dx, dy, 0xFF00_0000);
+    }
+    //the cells of MegaChip hold colors of the palette, not planes
+    let planes = match machine.megachip_mode() {
+        true => 0xFF,
+        false => machine.planes,
+    };
+    let moved = shifted(&machine.vram.cells, dx, dy, 0);
+    for (y, row) in moved.iter().enumerate() {
+        for (x, &moved) in row.iter().enumerate() {
+            let old = machine.vram.cells[y][x];
+            let pixel = old & !planes | moved & planes;
+            if pixel != old {
+                machine.set_pixel(x, y, pixel);
+            }
+        }
+    }
+    machine.vram.state_changed = true;
+    machine.pc += 2;
+}
+
+/// `shifted()` returns the `rows` moved by (`dx`, `dy`), filled with `blank` where nothing moved in.
+fn shifted<T: Copy>(rows: &[Vec<T>], dx: isize, dy: isize, blank: T) -> Vec<Vec<T>> {
+    let (height, width) = (rows.len() as isize, rows[0].len() as isize);
+    (0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| {
+                    let (from_x, from_y) = (x - dx, y - dy);
+                    match (0..width).contains(&from_x) && (0..height).contains(&from_y) {
+                        true => rows[from_y as usize][from_x as usize],
+                        false => blank,
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// `instructions::execute_00fd()`
+/// Type = Flow (SUPER-CHIP)
+/// Explanation = Exits the interpreter : the machine halts.
+pub fn execute_00fd(machine: &mut Machine) {
+    info!("[execute_00fd]");
+    machine.set_halted(true);
+}
+
+/// `instructions::execute_00fe()`
+/// Type = Display (SUPER-CHIP)
+/// Explanation = Switches to the 64x32 display, and clears it.
+pub fn execute_00fe(machine: &mut Machine) {
+    info!("[execute_00fe]");
+    machine.set_resolution(VRAM_WIDTH, VRAM_HEIGHT);
+    machine.pc += 2;
+}
+
+/// `instructions::execute_00ff()`
+/// Type = Display (SUPER-CHIP)
+/// Explanation = Switches to the 128x64 display, and clears it.
+pub fn execute_00ff(machine: &mut Machine) {
+    info!("[execute_00ff]");
+    machine.set_resolution(SUPER_CHIP_VRAM_WIDTH, SUPER_CHIP_VRAM_HEIGHT);
+    machine.pc += 2;
+}
+
+/// `instructions::execute_0010()`
+/// Type = Display (MegaChip)
+/// Explanation = Switches MegaChip mode off.
+pub fn execute_0010(machine: &mut Machine) {
+    info!("[execute_0010]");
+    machine.set_megachip_mode(false);
+    machine.pc += 2;
+}
+
+/// `instructions::execute_0011()`
+/// Type = Display (MegaChip)
+/// Explanation = Switches MegaChip mode on : the screen has 256 x 192 colored pixels.
+pub fn execute_0011(machine: &mut Machine) {
+    info!("[execute_0011]");
+    machine.set_megachip_mode(true);
+    machine.pc += 2;
+}
+
+/// `instructions::execute_00e0_megachip()`
+/// Type = Display (MegaChip)
+/// Explanation = Shows the screen drawn since the last time, and clears it.
+pub fn execute_00e0_megachip(machine: &mut Machine) {
+    info!("[execute_00e0_megachip]");
+    machine.record_mega_screen();
+    if let Some(screen) = machine.vram.mega.as_mut() {
+        screen.frame = screen.buffer.clone();
+        for row in screen.buffer.iter_mut() {
+            for pixel in row.iter_mut() {
+                *pixel = 0xFF00_0000;
+            }
+        }
+    }
+    for y in 0..machine.vram.cells.len() {
+        for x in 0..machine.vram.cells[y].len() {
+            if machine.vram.cells[y][x] != 0 {
+                machine.set_pixel(x, y, 0);
+            }
+        }
+    }
+    machine.vram.state_changed = true;
+    machine.pc += 2;
+}
+
+/// `instructions::execute_01nn()`
+/// Type = Memory (MegaChip)
+/// Explanation = Sets I to the 24-bit address NNNNNN : NN, followed by the 16 bits of the next opcode.
+pub fn execute_01nn(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_01nn]");
+    let pc = machine.pc as usize;
+    let cells = &machine.memory.cells;
+    let low =
+        (*cells.get(pc + 2).unwrap_or(&0) as u32) << 8 | *cells.get(pc + 3).unwrap_or(&0) as u32;
+    machine.set_index((operator.nn_const as u32) << 16 | low);
+    machine.pc += 4;
+}
+
+/// `instructions::execute_02nn()`
+/// Type = Display (MegaChip)
+/// Explanation = Loads NN colors of the palette from I, from color 1 on. A color is 4 bytes : alpha, red, green, blue.
+pub fn execute_02nn(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_02nn]");
+    machine.record_megachip();
+    let i = machine.i as usize;
+    let cells = &machine.memory.cells;
+    if let Some(megachip) = machine.megachip.as_mut() {
+        for color in 1..=operator.nn_const as usize {
+            let address = i + (color - 1) * 4;
+            let argb = (0..4).fold(0, |argb, offset| {
+                argb << 8 | *cells.get(address + offset).unwrap_or(&0) as u32
+            });
+            megachip.palette[color] = argb;
+        }
+    }
+    machine.pc += 2;
+}
+
+/// `instructions::execute_03nn()`
+/// Type = Display (MegaChip)
+/// Explanation = Sets the width of the sprites to NN pixels (256 for 0).
+pub fn execute_03nn(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_03nn]");
+    machine.record_megachip();
+    if let Some(megachip) = machine.megachip.as_mut() {
+        megachip.sprite_width = match operator.nn_const {
+            0 => 256,
+            nn => nn as usize,
+        };
+    }
+    machine.pc += 2;
+}
+
+/// `instructions::execute_04nn()`
+/// Type = Display (MegaChip)
+/// Explanation = Sets the height of the sprites to NN pixels (256 for 0).
+pub fn execute_04nn(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_04nn]");
+    machine.record_megachip();
+    if let Some(megachip) = machine.megachip.as_mut() {
+        megachip.sprite_height = match operator.nn_const {
+            0 => 256,
+            nn => nn as usize,
+        };
+    }
+    machine.pc += 2;
+}
+
+/// `instructions::execute_05nn()`
+/// Type = Display (MegaChip)
+/// Explanation = Sets the alpha of the whole screen to NN, from 0 (black) to 0xFF (opaque).
+pub fn execute_05nn(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_05nn]");
+    machine.record_mega_screen();
+    if let Some(screen) = machine.vram.mega.as_mut() {
+        screen.alpha = operator.nn_const;
+        machine.vram.state_changed = true;
+    }
+    machine.pc += 2;
+}
+
+/// `instructions::execute_060n()`
+/// Type = Sound (MegaChip)
+/// Explanation = Plays the sample at I, again and again for N = 0, once otherwise.
+pub fn execute_060n(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_060n]");
+    machine.record_megachip();
+    if let Some(megachip) = machine.megachip.as_mut() {
+        let sample = Sample::read(
+            &machine.memory.cells,
+            machine.i as usize,
+            operator.n_const == 0,
+        );
+        debug!(
+            "[execute_060n()] Playing {} samples at {} Hz.",
+            sample.data.len(),
+            sample.rate
+        );
+        megachip.sound = Some(sample);
+        megachip.sound_changed = true;
+    }
+    machine.pc += 2;
+}
+
+/// `instructions::execute_0700()`
+/// Type = Sound (MegaChip)
+/// Explanation = Stops the sample played.
+pub fn execute_0700(machine: &mut Machine) {
+    info!("[execute_0700]");
+    machine.record_megachip();
+    if let Some(megachip) = machine.megachip.as_mut() {
+        megachip.sound = None;
+        megachip.sound_changed = true;
+    }
+    machine.pc += 2;
+}
+
+/// `instructions::execute_080n()`
+/// Type = Display (MegaChip)
+/// Explanation = Sets how sprites are blended with the screen : 0 (normal), 1 (25%), 2 (50%), 3 (75%), 4 (add) or
+/// 5 (multiply).
+pub fn execute_080n(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_080n]");
+    machine.record_megachip();
+    if let Some(megachip) = machine.megachip.as_mut() {
+        megachip.blend_mode = BlendMode::from_number(operator.n_const).unwrap_or(BlendMode::Normal);
+    }
+    machine.pc += 2;
+}
+
+/// `instructions::execute_09nn()`
+/// Type = Display (MegaChip)
+/// Explanation = Sets the collision color : drawing a sprite over a pixel of color NN sets VF.
+pub fn execute_09nn(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_09nn]");
+    machine.record_megachip();
+    if let Some(megachip) = machine.megachip.as_mut() {
+        megachip.collision_color = operator.nn_const;
+    }
+    machine.pc += 2;
 }
 
 /// `instructions::execute_1nnn()`
@@ -47,7 +442,7 @@ pub fn execute_1nnn(machine: &mut Machine, operator: &Operator) {
 /// Explanation = Calls subroutine at NNN.
 pub fn execute_2nnn(machine: &mut Machine, operator: &Operator) {
     info!("[execute_2nnn]");
-    machine.stack.cells.push(machine.pc + 2);
+    machine.push(machine.pc + 2);
     machine.pc = operator.nnn_address;
 }
 
@@ -57,7 +452,7 @@ pub fn execute_2nnn(machine: &mut Machine, operator: &Operator) {
 pub fn execute_3nnn(machine: &mut Machine, operator: &Operator) {
     info!("[execute_3nnn]");
     match operator.vx == operator.nn_const {
-        true => machine.pc += 4,
+        true => skip(machine),
         false => machine.pc += 2,
     };
 }
@@ -68,7 +463,7 @@ pub fn execute_3nnn(machine: &mut Machine, operator: &Operator) {
 pub fn execute_4nnn(machine: &mut Machine, operator: &Operator) {
     info!("[execute_4nnn]");
     match operator.vx != operator.nn_const {
-        true => machine.pc += 4,
+        true => skip(machine),
         false => machine.pc += 2,
     };
 }
@@ -79,17 +474,62 @@ pub fn execute_4nnn(machine: &mut Machine, operator: &Operator) {
 pub fn execute_5xy0(machine: &mut Machine, operator: &Operator) {
     info!("[execute_5xy0]");
     match operator.vx == operator.vy {
-        true => machine.pc += 4,
+        true => skip(machine),
         false => machine.pc += 2,
     };
 }
 
+/// `instructions::execute_5xy2()`
+/// Type = Memory (XO-CHIP)
+/// Explanation = Stores VX to VY (including VY) in memory starting at address I, in that order even if X > Y.
+/// I is left unmodified.
+pub fn execute_5xy2(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_5xy2]");
+    for (offset, x) in register_range(operator.x, operator.y).enumerate() {
+        let address = (machine.i as usize + offset) % machine.memory.cells.len();
+        machine.write_memory(address, machine.registers.v[x]);
+    }
+    machine.pc += 2;
+}
+
+/// `instructions::execute_5xy3()`
+/// Type = Memory (XO-CHIP)
+/// Explanation = Fills VX to VY (including VY) with values from memory starting at address I, in that order even
+/// if X > Y. I is left unmodified.
+pub fn execute_5xy3(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_5xy3]");
+    for (offset, x) in register_range(operator.x, operator.y).enumerate() {
+        let address = (machine.i as usize + offset) % machine.memory.cells.len();
+        machine.set_register(x, machine.memory.cells[address]);
+    }
+    machine.pc += 2;
+}
+
+/// `register_range()` returns the indexes of the registers from X to Y, counting down if X > Y.
+pub fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+    match x <= y {
+        true => Box::new(x..=y),
+        false => Box::new((y..=x).rev()),
+    }
+}
+
+/// `skip()` moves the program counter past the next instruction, which is 4 bytes long if it is F000 NNNN (XO-CHIP).
+fn skip(machine: &mut Machine) {
+    let next = machine.pc as usize + 2;
+    let long = machine.platform == Platform::XoChip
+        && machine.memory.cells.get(next..next + 2) == Some(&[0xF0, 0x00][..]);
+    machine.pc += match long {
+        true => 6,
+        false => 4,
+    };
+}
+
 /// `instructions::execute_6xnn()`
 /// Type = Const
 /// Explanation = Sets VX to NN.
 pub fn execute_6xnn(machine: &mut Machine, operator: &Operator) {
     info!("[execute_6xnn]");
-    machine.registers.v[operator.x] = operator.nn_const;
+    machine.set_register(operator.x, operator.nn_const);
     machine.pc += 2;
 }
 
@@ -98,9 +538,10 @@ pub fn execute_6xnn(machine: &mut Machine, operator: &Operator) {
 /// Explanation = Adds NN to VX. (Carry flag is not changed).
 pub fn execute_7xnn(machine: &mut Machine, operator: &Operator) {
     info!("[execute_7xnn]");
-    machine.registers.v[operator.x] = machine.registers.v[operator.x]
-        .overflowing_add(operator.nn_const)
-        .0;
+    machine.set_register(
+        operator.x,
+        machine.registers.v[operator.x].wrapping_add(operator.nn_const),
+    );
     machine.pc += 2;
 }
 
@@ -109,99 +550,124 @@ pub fn execute_7xnn(machine: &mut Machine, operator: &Operator) {
 /// Explanation = Sets VX to the value of VY.
 pub fn execute_8xy0(machine: &mut Machine, operator: &Operator) {
     info!("[execute_8xy0]");
-    machine.registers.v[operator.x] = machine.registers.v[operator.y];
+    machine.set_register(operator.x, machine.registers.v[operator.y]);
     machine.pc += 2;
 }
 
 /// `instructions::execute_8xy1()`
 /// Type = Bitwise Operation
 /// Explanation = Sets VX to VX or VY. (Bitwise OR operation)
+/// With the `logic_resets_vf` quirk, VF is reset to 0.
 pub fn execute_8xy1(machine: &mut Machine, operator: &Operator) {
     info!("[execute_8xy1]");
-    machine.registers.v[operator.x] |= machine.registers.v[operator.y];
+    machine.set_register(
+        operator.x,
+        machine.registers.v[operator.x] | machine.registers.v[operator.y],
+    );
+    if machine.quirks.logic_resets_vf {
+        machine.set_register(0xF, 0);
+    }
     machine.pc += 2;
 }
 
 /// `instructions::execute_8xy2()`
 /// Type = Bitwise Operation
 /// Explanation = Sets VX to VX and VY. (Bitwise AND operation)
+/// With the `logic_resets_vf` quirk, VF is reset to 0.
 pub fn execute_8xy2(machine: &mut Machine, operator: &Operator) {
     info!("[execute_8xy2]");
-    machine.registers.v[operator.x] &= machine.registers.v[operator.y];
+    machine.set_register(
+        operator.x,
+        machine.registers.v[operator.x] & machine.registers.v[operator.y],
+    );
+    if machine.quirks.logic_resets_vf {
+        machine.set_register(0xF, 0);
+    }
     machine.pc += 2;
 }
 
 /// `instructions::execute_8xy3()`
 /// Type = Bitwise Operation
 /// Explanation = Sets VX to VX xor VY.
+/// With the `logic_resets_vf` quirk, VF is reset to 0.
 pub fn execute_8xy3(machine: &mut Machine, operator: &Operator) {
     info!("[execute_8xy3]");
-    machine.registers.v[operator.x] ^= machine.registers.v[operator.y];
+    machine.set_register(
+        operator.x,
+        machine.registers.v[operator.x] ^ machine.registers.v[operator.y],
+    );
+    if machine.quirks.logic_resets_vf {
+        machine.set_register(0xF, 0);
+    }
     machine.pc += 2;
 }
 
 /// `instructions::execute_8xy4()`
 /// Type = Math
 /// Explanation = Adds VY to VX. VF is set to 1 when there's a carry, and to 0 when there isn't.
+/// VF is written last, as on the COSMAC VIP : with X = F, VF holds the carry.
 pub fn execute_8xy4(machine: &mut Machine, operator: &Operator) {
     info!("[execute_8xy4]");
     let (result, overflow) =
         machine.registers.v[operator.x].overflowing_add(machine.registers.v[operator.y]);
-    match overflow {
-        true => machine.registers.v[0xF] = 1,
-        false => machine.registers.v[0xF] = 0,
-    };
-    machine.registers.v[operator.x] = result;
+    machine.set_register(operator.x, result);
+    machine.set_register(0xF, overflow as u8);
     machine.pc += 2;
 }
 
 /// `instructions::execute_8xy5()`
 /// Type = Math
 /// Explanation = VY is subtracted from VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
+/// VF is written last : with X = F, VF holds the flag (the pseudo comparisons of Octo rely on it).
 pub fn execute_8xy5(machine: &mut Machine, operator: &Operator) {
     info!("[execute_8xy5]");
     let (result, overflow) =
         machine.registers.v[operator.x].overflowing_sub(machine.registers.v[operator.y]);
-    match overflow {
-        true => machine.registers.v[0xF] = 0,
-        false => machine.registers.v[0xF] = 1,
-    };
-    machine.registers.v[operator.x] = result;
+    machine.set_register(operator.x, result);
+    machine.set_register(0xF, !overflow as u8);
     machine.pc += 2;
 }
 
 /// `instructions::execute_8xy6()`
 /// Type = Bitwise Operation
 /// Explanation = Stores the least significant bit of VX in VF and then shifts VX to the right by 1.
+/// With the `shift_vy` quirk, VY is shifted into VX instead. VF is written last : with X = F, VF holds the bit.
 pub fn execute_8xy6(machine: &mut Machine, operator: &Operator) {
     info!("[execute_8xy6]");
-    machine.registers.v[0xF] = machine.registers.v[operator.x] & 0x1;
-    machine.registers.v[operator.x] >>= 1;
+    let source = match machine.quirks.shift_vy {
+        true => machine.registers.v[operator.y],
+        false => machine.registers.v[operator.x],
+    };
+    machine.set_register(operator.x, source >> 1);
+    machine.set_register(0xF, source & 0x1);
     machine.pc += 2;
 }
 
 /// `instructions::execute_8xy7()`
 /// Type = Math
 /// Explanation = Sets VX to VY minus VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
+/// VF is written last : with X = F, VF holds the flag.
 pub fn execute_8xy7(machine: &mut Machine, operator: &Operator) {
     info!("[execute_8xy7]");
     let (result, overflow) =
         machine.registers.v[operator.y].overflowing_sub(machine.registers.v[operator.x]);
-    match overflow {
-        true => machine.registers.v[0xF] = 0,
-        false => machine.registers.v[0xF] = 1,
-    };
-    machine.registers.v[operator.x] = result;
+    machine.set_register(operator.x, result);
+    machine.set_register(0xF, !overflow as u8);
     machine.pc += 2;
 }
 
 /// `instructions::execute_8xye()`
 /// Type = Bitwise Operation
 /// Explanation = Stores the most significant bit of VX in VF and then shifts VX to the left by 1.
+/// With the `shift_vy` quirk, VY is shifted into VX instead. VF is written last : with X = F, VF holds the bit.
 pub fn execute_8xye(machine: &mut Machine, operator: &Operator) {
     info!("[execute_8xye]");
-    machine.registers.v[0xF] = (machine.registers.v[operator.x] & 0b10000000) >> 7;
-    machine.registers.v[operator.x] <<= 1;
+    let source = match machine.quirks.shift_vy {
+        true => machine.registers.v[operator.y],
+        false => machine.registers.v[operator.x],
+    };
+    machine.set_register(operator.x, source << 1);
+    machine.set_register(0xF, (source & 0b10000000) >> 7);
     machine.pc += 2;
 }
 
@@ -211,7 +677,7 @@ pub fn execute_8xye(machine: &mut Machine, operator: &Operator) {
 pub fn execute_9xy0(machine: &mut Machine, operator: &Operator) {
     info!("[execute_9xy0]");
     match operator.vx != operator.vy {
-        true => machine.pc += 4,
+        true => skip(machine),
         false => machine.pc += 2,
     };
 }
@@ -221,16 +687,62 @@ pub fn execute_9xy0(machine: &mut Machine, operator: &Operator) {
 /// Explanation = Sets I to the address NNN.
 pub fn execute_annn(machine: &mut Machine, operator: &Operator) {
     info!("[execute_annn]");
-    machine.i = operator.nnn_address;
+    machine.set_index(operator.nnn_address as u32);
     machine.pc += 2;
 }
 
 /// `instructions::execute_bnnn()`
 /// Type = Flow
 /// Explanation = Jumps to the address NNN plus V0.
+/// With the `jump_vx` quirk, it jumps to XNN plus VX.
 pub fn execute_bnnn(machine: &mut Machine, operator: &Operator) {
     info!("[execute_bnnn]");
-    machine.pc = operator.nnn_address + machine.registers.v[0] as u16;
+    let offset = match machine.quirks.jump_vx {
+        true => operator.vx,
+        false => machine.registers.v[0],
+    };
+    machine.pc = operator.nnn_address + offset as u16;
+}
+
+/// `instructions::execute_bxyn()`
+/// Type = Display (CHIP-8X)
+/// Explanation = Sets the color of zones of the screen to the color in VY (0 to 7).
+/// With N = 0, VX and VX+1 select blocks of 8x4 pixels : their low nibbles are the first column and row of blocks,
+/// their high nibbles the number of more columns and rows.
+/// Otherwise VX and VX+1 are the coordinates of a pixel, and the zones of its 8 pixels are colored on N rows.
+pub fn execute_bxyn(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_bxyn]");
+    let vx_1 = machine.registers.v[(operator.x + 1) % 16];
+    machine.record_colors();
+    if let Some(colors) = machine.vram.colors.as_mut() {
+        let color = operator.vy & 0x7;
+        let (columns, rows) = match operator.n_const {
+            0 => {
+                let (column, row) = ((operator.vx & 0xF) as usize, (vx_1 & 0xF) as usize);
+                (
+                    column..=column + (operator.vx >> 4) as usize,
+                    row * 4..=row * 4 + (vx_1 >> 4) as usize * 4 + 3,
+                )
+            }
+            n => {
+                let (column, row) = (operator.vx as usize / 8, vx_1 as usize);
+                (column..=column, row..=row + n as usize - 1)
+            }
+        };
+        for row in rows {
+            for column in columns.clone() {
+                if let Some(zone) = colors
+                    .zones
+                    .get_mut(row)
+                    .and_then(|zones| zones.get_mut(column))
+                {
+                    *zone = color;
+                }
+            }
+        }
+        machine.vram.state_changed = true;
+    }
+    machine.pc += 2;
 }
 
 /// `instructions::execute_cxnn()`
@@ -238,7 +750,10 @@ pub fn execute_bnnn(machine: &mut Machine, operator: &Operator) {
 /// Explanation = Sets VX to the result of a bitwise and operation on a random number (Typically: 0 to 255) and NN.
 pub fn execute_cxnn(machine: &mut Machine, operator: &Operator) {
     info!("[execute_cxnn]");
-    machine.registers.v[operator.x] = operator.nn_const & rand::thread_rng().gen::<u8>();
+    machine.set_register(
+        operator.x,
+        operator.nn_const & rand::thread_rng().gen::<u8>(),
+    );
     machine.pc += 2;
 }
 
@@ -252,25 +767,120 @@ pub fn execute_cxnn(machine: &mut Machine, operator: &Operator) {
 /// while unset sprite pixels do nothing.
 /// The carry flag (VF) is set to 1 if any screen pixels are flipped from set to unset when a sprite is drawn
 /// and set to 0 otherwise. This is used for collision detection.
+/// Sprites wrap around the edges of the screen, with the `clip_sprites` quirk they are clipped instead
+/// (the starting coordinate still wraps).
+/// From SUPER-CHIP on, DXY0 draws a sprite of 16 x 16 pixels, two bytes per row.
+/// On XO-CHIP, a sprite is drawn on each selected plane, the sprite of the second plane following the first one.
 pub fn execute_dxyn(machine: &mut Machine, operator: &Operator) {
     info!("[execute_dxyn]");
-    machine.registers.v[0xF] = 0;
+    let mut collision = 0;
 
     let vram_height = machine.vram.cells.len();
     let vram_width = machine.vram.cells[0].len();
-    for byte in 0..operator.n_const {
-        let y = (machine.registers.v[operator.y] as usize + byte as usize) % vram_height;
-        for bit in 0..8 {
-            let x = (machine.registers.v[operator.x] as usize + bit as usize) % vram_width;
-            let color = (machine.memory.cells[machine.i as usize + byte as usize] >> (7 - bit)) & 1;
-            machine.registers.v[0xF] |= color & machine.vram.cells[y][x];
-            machine.vram.cells[y][x] ^= color;
+    let top = machine.registers.v[operator.y] as usize % vram_height;
+    let left = machine.registers.v[operator.x] as usize % vram_width;
+    let clip = machine.quirks.clip_sprites;
+    let (width, height) = sprite_size(machine, operator);
+    let mut address = machine.i as usize;
+    for plane in [1, 2] {
+        if machine.planes & plane == 0 {
+            continue;
+        }
+        for row in 0..height {
+            if clip && top + row >= vram_height {
+                break;
+            }
+            let y = (top + row) % vram_height;
+            for column in 0..width {
+                if clip && left + column >= vram_width {
+                    break;
+                }
+                let x = (left + column) % vram_width;
+                let byte = address + row * width / 8 + column / 8;
+                let byte = machine.memory.cells[byte % machine.memory.cells.len()];
+                if (byte >> (7 - column % 8)) & 1 != 0 {
+                    let pixel = machine.vram.cells[y][x];
+                    if pixel & plane != 0 {
+                        collision = 1;
+                    }
+                    machine.set_pixel(x, y, pixel ^ plane);
+                }
+            }
         }
+        address += width / 8 * height;
     }
+    machine.set_register(0xF, collision);
     machine.vram.state_changed = true;
     machine.pc += 2;
 }
 
+/// `sprite_size()` returns the width and height of the sprite DXYN draws : 8 x N, or 16 x 16 for DXY0 from
+/// SUPER-CHIP on.
+pub fn sprite_size(machine: &Machine, operator: &Operator) -> (usize, usize) {
+    match (operator.n_const, machine.platform) {
+        (0, Platform::Chip8) => (8, 0),
+        (0, _) => (16, 16),
+        (n, _) => (8, n as usize),
+    }
+}
+
+/// `instructions::execute_dxyn_megachip()`
+/// Type = Display (MegaChip)
+/// Explanation = Draws the sprite at I at coordinate (VX, VY), with the width and height set by 03NN and 04NN.
+/// Each byte of the sprite is a color of the palette, color 0 is transparent. The sprite is blended with the screen,
+/// and clipped at its edges. VF is set to 1 if the sprite is drawn over a pixel of the collision color.
+/// The characters of the font are drawn as white SUPER-CHIP sprites, 8 pixels wide and N high.
+pub fn execute_dxyn_megachip(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_dxyn_megachip]");
+    machine.set_register(0xF, 0);
+    let megachip = match machine.megachip.as_ref() {
+        Some(megachip) => megachip,
+        None => return,
+    };
+    let i = machine.i as usize;
+    let font_end = machine.memory.big_font_base as usize + 16 * BIG_CHARACTER_SIZE;
+    let (width, height) = match i < font_end {
+        true => (8, operator.n_const as usize),
+        false => (megachip.sprite_width, megachip.sprite_height),
+    };
+    let (blend_mode, collision_color) = (megachip.blend_mode, megachip.collision_color);
+    //the pixels of the sprite which are not transparent : their coordinates, color and ARGB color
+    let mut pixels = vec![];
+    let (left, top) = (operator.vx as usize, operator.vy as usize);
+    for row in 0..height.min(SCREEN_HEIGHT.saturating_sub(top)) {
+        let y = top + row;
+        for column in 0..width.min(SCREEN_WIDTH.saturating_sub(left)) {
+            let x = left + column;
+            match i < font_end {
+                true => {
+                    let byte = machine.memory.cells[i + row];
+                    if (byte >> (7 - column)) & 1 != 0 {
+                        pixels.push((x, y, 0xFF, 0xFFFF_FFFF));
+                    }
+                }
+                false => match machine.memory.cells.get(i + row * width + column) {
+                    None | Some(0) => (),
+                    Some(&color) => pixels.push((x, y, color, megachip.palette[color as usize])),
+                },
+            };
+        }
+    }
+    for (x, y, color, argb) in pixels {
+        let cell = machine.vram.cells[y][x];
+        if cell != 0 && cell == collision_color {
+            machine.set_register(0xF, 1);
+        }
+        machine.set_pixel(x, y, color);
+        if let Some(old) = machine.vram.mega.as_ref().map(|screen| screen.buffer[y][x]) {
+            machine.record(Mutation::MegaPixel { x, y, old });
+            if let Some(screen) = machine.vram.mega.as_mut() {
+                screen.buffer[y][x] = blend_mode.blend(argb, old);
+            }
+        }
+    }
+    machine.pc += 2;
+}
+
 /// `instructions::execute_ex9e()`
 /// Type = KeyOp
 /// Explanation = Skips the next instruction if the key stored in VX is pressed.
@@ -281,7 +891,7 @@ pub fn execute_ex9e(machine: &mut Machine, operator: &Operator) {
         .keyboard
         .is_key_down(machine.registers.v[operator.x])
     {
-        true => machine.pc += 4,
+        true => skip(machine),
         false => machine.pc += 2,
     }
 }
@@ -297,16 +907,85 @@ pub fn execute_exa1(machine: &mut Machine, operator: &Operator) {
         .is_key_down(machine.registers.v[operator.x])
     {
         true => machine.pc += 2,
-        false => machine.pc += 4,
+        false => skip(machine),
     };
 }
 
+/// `instructions::execute_exf2()`
+/// Type = KeyOp (CHIP-8X)
+/// Explanation = Skips the next instruction if the key stored in VX is pressed on the second keypad.
+pub fn execute_exf2(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_exf2]");
+    match machine
+        .keyboard
+        .is_second_key_down(machine.registers.v[operator.x])
+    {
+        true => skip(machine),
+        false => machine.pc += 2,
+    }
+}
+
+/// `instructions::execute_exf5()`
+/// Type = KeyOp (CHIP-8X)
+/// Explanation = Skips the next instruction if the key stored in VX isn't pressed on the second keypad.
+pub fn execute_exf5(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_exf5]");
+    match machine
+        .keyboard
+        .is_second_key_down(machine.registers.v[operator.x])
+    {
+        true => machine.pc += 2,
+        false => skip(machine),
+    }
+}
+
+/// `instructions::execute_f000()`
+/// Type = Memory (XO-CHIP)
+/// Explanation = Sets I to the 16-bit address NNNN of the next opcode.
+pub fn execute_f000(machine: &mut Machine) {
+    info!("[execute_f000]");
+    let pc = machine.pc as usize;
+    let cells = &machine.memory.cells;
+    let address =
+        (*cells.get(pc + 2).unwrap_or(&0) as u32) << 8 | *cells.get(pc + 3).unwrap_or(&0) as u32;
+    machine.set_index(address);
+    machine.pc += 4;
+}
+
+/// `instructions::execute_fn01()`
+/// Type = Display (XO-CHIP)
+/// Explanation = Selects the bit planes drawn on by 00E0, DXYN and the scroll instructions : 0 (none), 1, 2 or 3 (both).
+pub fn execute_fn01(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_fn01]");
+    machine.record(Mutation::Planes {
+        old: machine.planes,
+    });
+    machine.planes = operator.x as u8 & 0x3;
+    machine.pc += 2;
+}
+
+/// `instructions::execute_f002()`
+/// Type = Sound (XO-CHIP)
+/// Explanation = Loads the 16 bytes at I as the audio pattern, played while the sound timer runs.
+pub fn execute_f002(machine: &mut Machine) {
+    info!("[execute_f002]");
+    machine.record(Mutation::Audio {
+        pattern: machine.audio_pattern,
+        pitch: machine.pitch,
+    });
+    for offset in 0..machine.audio_pattern.len() {
+        let address = (machine.i as usize + offset) % machine.memory.cells.len();
+        machine.audio_pattern[offset] = machine.memory.cells[address];
+    }
+    machine.pc += 2;
+}
+
 /// `instructions::execute_fx07()`
 /// Type = Timer
 /// Explanation = Sets VX to the value of the delay timer.
 pub fn execute_fx07(machine: &mut Machine, operator: &Operator) {
     info!("[execute_fx07]");
-    machine.registers.v[operator.x] = machine.timers.dt;
+    machine.set_register(operator.x, machine.timers.dt);
     machine.pc += 2;
 }
 
@@ -316,6 +995,12 @@ pub fn execute_fx07(machine: &mut Machine, operator: &Operator) {
 /// (Blocking Operation. All instruction halted until next key event)
 pub fn execute_fx0a(machine: &mut Machine, operator: &Operator) {
     info!("[execute_fx0a]");
+    machine.record(Mutation::KeyBoard {
+        keys: machine.keyboard.keys.clone(),
+        second_keys: machine.keyboard.second_keys.clone(),
+        keypress_awaited: machine.keyboard.keypress_awaited,
+        key_register: machine.keyboard.key_register,
+    });
     machine.keyboard.keypress_awaited = true;
     machine.keyboard.key_register = operator.x;
     machine.pc += 2;
@@ -326,7 +1011,7 @@ pub fn execute_fx0a(machine: &mut Machine, operator: &Operator) {
 /// Explanation = Sets the delay timer to VX.
 pub fn execute_fx15(machine: &mut Machine, operator: &Operator) {
     info!("[execute_fx15]");
-    machine.timers.dt = machine.registers.v[operator.x];
+    machine.set_timers(machine.registers.v[operator.x], machine.timers.st);
     machine.pc += 2;
 }
 
@@ -335,7 +1020,7 @@ pub fn execute_fx15(machine: &mut Machine, operator: &Operator) {
 /// Explanation = Sets the sound timer to VX.
 pub fn execute_fx18(machine: &mut Machine, operator: &Operator) {
     info!("[execute_fx18]");
-    machine.timers.st = machine.registers.v[operator.x];
+    machine.set_timers(machine.timers.dt, machine.registers.v[operator.x]);
     machine.pc += 2;
 }
 
@@ -344,14 +1029,14 @@ pub fn execute_fx18(machine: &mut Machine, operator: &Operator) {
 /// Explanation = Adds VX to I. VF is set to 1 when there is a range overflow (I+VX>0xFFF), and to 0 when there isn't.
 pub fn execute_fx1e(machine: &mut Machine, operator: &Operator) {
     info!("[execute_fx1e]");
-    let (result, overflow) = machine
-        .i
-        .overflowing_add(machine.registers.v[operator.x] as u16);
-    match overflow {
-        true => machine.registers.v[0xF] = 1,
-        false => machine.registers.v[0xF] = 0,
+    //I has 16 bits, or 24 bits on MegaChip
+    let limit = match machine.megachip {
+        Some(_) => 0xFF_FFFF,
+        None => 0xFFFF,
     };
-    machine.i = result;
+    let result = machine.i + machine.registers.v[operator.x] as u32;
+    machine.set_register(0xF, (result > limit) as u8);
+    machine.set_index(result & limit);
     machine.pc += 2;
 }
 
@@ -361,7 +1046,20 @@ pub fn execute_fx1e(machine: &mut Machine, operator: &Operator) {
 /// Characters 0-F (in hexadecimal) are represented by a 4x5 font.
 pub fn execute_fx29(machine: &mut Machine, operator: &Operator) {
     info!("[execute_fx29]");
-    machine.i = machine.registers.v[operator.x] as u16 * 5;
+    machine
+        .set_index((machine.memory.font_base + machine.registers.v[operator.x] as u16 * 5) as u32);
+    machine.pc += 2;
+}
+
+/// `instructions::execute_fx30()`
+/// Type = Memory (SUPER-CHIP)
+/// Explanation = Sets I to the location of the big sprite for the character in VX.
+/// Characters 0-F (in hexadecimal) are represented by a 8x10 font.
+pub fn execute_fx30(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_fx30]");
+    machine.set_index(
+        (machine.memory.big_font_base + (machine.registers.v[operator.x] & 0xF) as u16 * 10) as u32,
+    );
     machine.pc += 2;
 }
 
@@ -374,9 +1072,24 @@ pub fn execute_fx29(machine: &mut Machine, operator: &Operator) {
 /// the tens digit at location I+1, and the ones digit at location I+2.)
 pub fn execute_fx33(machine: &mut Machine, operator: &Operator) {
     info!("[execute_fx33]");
-    machine.memory.cells[machine.i as usize] = machine.registers.v[operator.x] / 100;
-    machine.memory.cells[machine.i as usize + 1] = (machine.registers.v[operator.x] % 100) / 10;
-    machine.memory.cells[machine.i as usize + 2] = machine.registers.v[operator.x] % 10;
+    let vx = machine.registers.v[operator.x];
+    for (offset, &digit) in [vx / 100, (vx % 100) / 10, vx % 10].iter().enumerate() {
+        let address = (machine.i as usize + offset) % machine.memory.cells.len();
+        machine.write_memory(address, digit);
+    }
+    machine.pc += 2;
+}
+
+/// `instructions::execute_fx3a()`
+/// Type = Sound (XO-CHIP)
+/// Explanation = Sets the pitch of the audio pattern to VX : it plays at 4000 * 2 ^ ((VX - 64) / 48) Hz.
+pub fn execute_fx3a(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_fx3a]");
+    machine.record(Mutation::Audio {
+        pattern: machine.audio_pattern,
+        pitch: machine.pitch,
+    });
+    machine.pitch = operator.vx;
     machine.pc += 2;
 }
 
@@ -384,10 +1097,16 @@ pub fn execute_fx33(machine: &mut Machine, operator: &Operator) {
 /// Type = Memory
 /// Explanation = Stores V0 to VX (including VX) in memory starting at address I.
 /// The offset from I is increased by 1 for each value written, but I itself is left unmodified.
+/// With the `load_store_increments_i` quirk, I is left pointing after the last value written.
 pub fn execute_fx55(machine: &mut Machine, operator: &Operator) {
     info!("[execute_fx55]");
-    machine.memory.cells[(machine.i as usize)..(machine.i + (operator.x + 1) as u16) as usize]
-        .copy_from_slice(&machine.registers.v[0..((operator.x + 1) as usize)]);
+    for x in 0..=operator.x {
+        let address = (machine.i as usize + x) % machine.memory.cells.len();
+        machine.write_memory(address, machine.registers.v[x]);
+    }
+    if machine.quirks.load_store_increments_i {
+        machine.set_index(machine.i + (operator.x + 1) as u32);
+    }
     machine.pc += 2;
 }
 
@@ -395,10 +1114,47 @@ pub fn execute_fx55(machine: &mut Machine, operator: &Operator) {
 /// Type = Memory
 /// Explanation = Fills V0 to VX (including VX) with values from memory starting at address I.
 /// The offset from I is increased by 1 for each value written, but I itself is left unmodified.[d]
+/// With the `load_store_increments_i` quirk, I is left pointing after the last value read.
 pub fn execute_fx65(machine: &mut Machine, operator: &Operator) {
     info!("[execute_fx65]");
-    machine.registers.v[0..((operator.x + 1) as usize)].copy_from_slice(
-        &machine.memory.cells[(machine.i as usize)..(machine.i + (operator.x + 1) as u16) as usize],
+    for x in 0..=operator.x {
+        let address = (machine.i as usize + x) % machine.memory.cells.len();
+        machine.set_register(x, machine.memory.cells[address]);
+    }
+    if machine.quirks.load_store_increments_i {
+        machine.set_index(machine.i + (operator.x + 1) as u32);
+    }
+    machine.pc += 2;
+}
+
+/// `instructions::execute_fx75()`
+/// Type = Memory (SUPER-CHIP)
+/// Explanation = Stores V0 to VX (including VX) in the user flags.
+pub fn execute_fx75(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_fx75]");
+    machine.record(Mutation::Flags { old: machine.flags });
+    machine.flags[..=operator.x].copy_from_slice(&machine.registers.v[..=operator.x]);
+    machine.pc += 2;
+}
+
+/// `instructions::execute_fx85()`
+/// Type = Memory (SUPER-CHIP)
+/// Explanation = Fills V0 to VX (including VX) with the user flags.
+pub fn execute_fx85(machine: &mut Machine, operator: &Operator) {
+    info!("[execute_fx85]");
+    for x in 0..=operator.x {
+        machine.set_register(x, machine.flags[x]);
+    }
+    machine.pc += 2;
+}
+
+/// `instructions::execute_unknown()`
+/// Type = Unknown
+/// Explanation = The opcode means nothing to this machine : it is skipped.
+pub fn execute_unknown(machine: &mut Machine, operator: &Operator) {
+    warn!(
+        "[execute_unknown()] Skipping the unknown opcode {:04X} at 0x{:03X}.",
+        operator.opcode, machine.pc
     );
     machine.pc += 2;
 }
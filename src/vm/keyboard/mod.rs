@@ -1,8 +1,11 @@
 //! CHIP-8 has a HEX based keypad (0x0-0xF). It contains 16 keys.
+//! CHIP-8X has a second keypad, with the same keys.
 
 /// `KeyBoard` : CHIP-8 has a HEX based keypad (0x0-0xF). It contains 16 keys.
 pub struct KeyBoard {
     pub keys: Vec<bool>,
+    /// `second_keys` are the keys of the second keypad of CHIP-8X.
+    pub second_keys: Vec<bool>,
     pub keypress_awaited: bool,
     pub key_register: usize,
 }
@@ -12,6 +15,7 @@ impl KeyBoard {
     pub fn new() -> KeyBoard {
         KeyBoard {
             keys: vec![false; 16],
+            second_keys: vec![false; 16],
             keypress_awaited: false,
             key_register: 0,
         }
@@ -28,4 +32,8 @@ impl KeyBoard {
     pub fn is_key_down(&self, index: u8) -> bool {
         self.keys[index as usize]
     }
+    ///`KeyBoard.is_second_key_down()` will return value at the key-index, on the second keypad
+    pub fn is_second_key_down(&self, index: u8) -> bool {
+        self.second_keys[index as usize]
+    }
 }
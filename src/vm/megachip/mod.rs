@@ -0,0 +1,130 @@
+//! MegaChip - the MEGA-CHIP8 extension of SUPER-CHIP (Revival Studios) : a screen of 256 x 192 pixels whose colors
+//! are 32-bit ARGB colors of a palette loaded from memory, sprites of any width and height blended with the screen,
+//! 16 MB of memory addressed by a 24-bit I (01NN NNNN), and sampled sounds.
+//! 0011 switches MegaChip mode on, 0010 switches it off.
+
+/// Width of the screen of MegaChip.
+pub const SCREEN_WIDTH: usize = 256;
+/// Height of the screen of MegaChip.
+pub const SCREEN_HEIGHT: usize = 192;
+
+/// `BlendMode` : how the pixels of a sprite are mixed with the screen (080N).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    /// The colors of the sprite are mixed by their alpha channel.
+    Normal,
+    /// The colors of the sprite are mixed by their alpha channel, and are 25%, 50% or 75% opaque at most.
+    Opacity25,
+    Opacity50,
+    Opacity75,
+    /// The colors of the sprite are added to the screen.
+    Add,
+    /// The colors of the screen are multiplied by the colors of the sprite.
+    Multiply,
+}
+
+impl BlendMode {
+    /// `BlendMode::from_number()` will return the blend mode numbered N by 080N : 0 to 5.
+    pub fn from_number(number: u8) -> Option<BlendMode> {
+        match number {
+            0 => Some(BlendMode::Normal),
+            1 => Some(BlendMode::Opacity25),
+            2 => Some(BlendMode::Opacity50),
+            3 => Some(BlendMode::Opacity75),
+            4 => Some(BlendMode::Add),
+            5 => Some(BlendMode::Multiply),
+            _ => None,
+        }
+    }
+
+    /// `BlendMode.blend()` returns the opaque color of the screen, once the color `source` of a sprite is drawn over
+    /// the color `destination`.
+    pub fn blend(self, source: u32, destination: u32) -> u32 {
+        let channel = |color: u32, shift: u32| (color >> shift) & 0xFF;
+        let alpha = channel(source, 24);
+        let opacity = match self {
+            BlendMode::Opacity25 => alpha / 4,
+            BlendMode::Opacity50 => alpha / 2,
+            BlendMode::Opacity75 => alpha * 3 / 4,
+            _ => alpha,
+        };
+        let mut color = 0xFF00_0000;
+        for shift in [16, 8, 0] {
+            let (source, destination) = (channel(source, shift), channel(destination, shift));
+            let mixed = match self {
+                BlendMode::Add => (destination + source * opacity / 255).min(255),
+                BlendMode::Multiply => destination * source / 255,
+                _ => (source * opacity + destination * (255 - opacity)) / 255,
+            };
+            color |= mixed << shift;
+        }
+        color
+    }
+}
+
+/// `Sample` : a sampled sound (060N), 8-bit unsigned samples played at `rate` samples per second.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub rate: u32,
+    pub data: Vec<u8>,
+    /// `looping` samples are played again and again (0600), until 0700 stops them.
+    pub looping: bool,
+}
+
+impl Sample {
+    /// `Sample::read()` will return the sample at `address` : its rate (2 bytes) and its length (3 bytes),
+    /// followed by a byte which is not used, and the samples. A sample going beyond the memory is cut.
+    pub fn read(memory: &[u8], address: usize, looping: bool) -> Sample {
+        let byte = |offset: usize| *memory.get(address + offset).unwrap_or(&0) as usize;
+        let rate = (byte(0) << 8 | byte(1)) as u32;
+        let length = byte(2) << 16 | byte(3) << 8 | byte(4);
+        let start = (address + 6).min(memory.len());
+        let end = (start + length).min(memory.len());
+        Sample {
+            rate,
+            data: memory[start..end].to_vec(),
+            looping,
+        }
+    }
+}
+
+/// `MegaChip` : the state of the MegaChip extension, besides its screen.
+#[derive(Debug, Clone)]
+pub struct MegaChip {
+    /// `enabled` is set by 0011, which switches to the screen of MegaChip, and cleared by 0010.
+    pub enabled: bool,
+    /// `palette` holds 256 ARGB colors. Color 0 is transparent, the others are loaded by 02NN.
+    pub palette: Vec<u32>,
+    /// `sprite_width` and `sprite_height` are the size of the sprites DXYN draws (03NN, 04NN).
+    pub sprite_width: usize,
+    pub sprite_height: usize,
+    pub blend_mode: BlendMode,
+    /// `collision_color` is the color of the pixels which set VF when a sprite is drawn over them (09NN).
+    pub collision_color: u8,
+    /// `sound` is the sample played (060N), until it ends or 0700 stops it.
+    pub sound: Option<Sample>,
+    /// `sound_changed` is set when a sample is started or stopped.
+    pub sound_changed: bool,
+}
+
+impl Default for MegaChip {
+    fn default() -> Self {
+        MegaChip::new()
+    }
+}
+
+impl MegaChip {
+    /// `MegaChip::new()` will return MegaChip switched off, with a transparent palette and sprites of 8 x 8 pixels.
+    pub fn new() -> MegaChip {
+        MegaChip {
+            enabled: false,
+            palette: vec![0; 256],
+            sprite_width: 8,
+            sprite_height: 8,
+            blend_mode: BlendMode::Normal,
+            collision_color: 0,
+            sound: None,
+            sound_changed: false,
+        }
+    }
+}
@@ -2,8 +2,20 @@
 //! The first 512 bytes, from 0x000 to 0x1FF, are used for the CHIP-8 interpreter itself and should not be used by programs.
 //! Most CHIP-8 programs start ad 0x2000 (512).
 
+use super::graphics::fontset::FontSet;
+
 /// MEMORY_SIZE constant (= 4096) represents size of the CHIP-8 memory (RAM).
-const MEMORY_SIZE: usize = 4096 as usize;
+pub(crate) const MEMORY_SIZE: usize = 4096;
+
+/// MEGACHIP_MEMORY_SIZE constant (= 16 MB) represents size of the memory of MegaChip, which I addresses with 24 bits.
+pub const MEGACHIP_MEMORY_SIZE: usize = 0x100_0000;
+
+/// XO_CHIP_MEMORY_SIZE constant (= 64 KB) represents size of the memory of XO-CHIP, which F000 NNNN addresses.
+pub const XO_CHIP_MEMORY_SIZE: usize = 0x1_0000;
+
+/// PROGRAM_START constant (= 0x200) is where programs are loaded and start, unless told otherwise
+/// (e.g. 0x600 on the ETI-660).
+pub const PROGRAM_START: u16 = 0x200;
 
 /// `Memory` : CHIP-8 momory size is 4,096 bytes (4-KB). Memory locations are numbered from 0x000 (0) to 0xFFF (4095).
 /// We will call each location as a cell.
@@ -11,6 +23,14 @@ pub struct Memory {
     /// Memory is an array of `u8` type of `cells`, with length = 4096 bytes. Each `cell` can be accessed by their index value,
     /// starting from 0 and ending with 4095.
     pub cells: Vec<u8>,
+    /// `loaded` marks the cells which were given a value when the machine started, by the fontset or the program.
+    pub loaded: Vec<bool>,
+    /// `font_base` is the address of the small font (FX29), 0x000 by default.
+    pub font_base: u16,
+    /// `big_font_base` is the address of the big font (FX30), right after the small font.
+    pub big_font_base: u16,
+    /// `font_end` is the address right after the big font, where the font loaded ends.
+    font_end: u16,
 }
 
 impl Memory {
@@ -19,31 +39,87 @@ impl Memory {
     pub fn new() -> Memory {
         let mut memory = Memory {
             cells: vec![0; MEMORY_SIZE],
+            loaded: vec![false; MEMORY_SIZE],
+            font_base: 0,
+            big_font_base: 0,
+            font_end: 0,
         };
-        memory.set_fonts();
+        memory.load_font(&FontSet::new(), 0);
         memory
     }
 
+    /// `Memory.load_data()` loads `data` at 0x200, where most programs are loaded.
     pub fn load_data(&mut self, data: &[u8]) {
+        self.load_data_at(PROGRAM_START, data);
+    }
+
+    /// `Memory.load_data_at()` loads `data` from `address`. What goes beyond the end of the memory is dropped.
+    pub fn load_data_at(&mut self, address: u16, data: &[u8]) {
         for (i, &byte) in data.iter().enumerate() {
-            let addr = 0x200 + i;
-            if addr < 4096 {
+            let addr = address as usize + i;
+            if addr < self.cells.len() {
                 self.cells[addr] = byte;
+                self.loaded[addr] = true;
             } else {
                 break;
             }
         }
     }
 
-    /// `Memory.set_fonts()` populates fontset in the memory.
-    /// Mainly, it loads predefined FontSet (graphics::Fontset), into its first 80 cells.
-    fn set_fonts(&mut self) {
-        //get fontset
-        use super::graphics::fontset;
-        let font_set = fontset::FontSet::new();
+    /// `Memory.resize()` changes the size of the memory (e.g. to `MEGACHIP_MEMORY_SIZE`), keeping what it holds.
+    pub fn resize(&mut self, size: usize) {
+        self.cells.resize(size, 0);
+        self.loaded.resize(size, false);
+    }
+
+    /// `Memory.font_range()` returns the addresses of the fonts, the small one followed by the big one.
+    pub fn font_range(&self) -> std::ops::Range<usize> {
+        self.font_base as usize..self.font_end as usize
+    }
+
+    /// `Memory.load_font()` populates fontset in the memory, the small font at `base` (e.g. 0x000 or 0x050) and
+    /// the big font right after it. The font loaded before is cleared.
+    pub fn load_font(&mut self, font_set: &FontSet, base: u16) {
+        //the old font may be longer than the new one
+        let old_fonts = self.font_base as usize..self.font_end as usize;
+        for i in old_fonts.filter(|&i| i < MEMORY_SIZE) {
+            self.cells[i] = 0;
+            self.loaded[i] = false;
+        }
         //update memory cells with the font set
-        for (i, &font) in font_set.fonts.iter().enumerate() {
-            self.cells[i] = font;
+        self.font_base = base;
+        self.big_font_base = base + font_set.fonts.len() as u16;
+        self.font_end = self.big_font_base + font_set.big_fonts.len() as u16;
+        let fonts = font_set.fonts.iter().chain(font_set.big_fonts.iter());
+        for (i, &font) in fonts.enumerate() {
+            self.cells[base as usize + i] = font;
+            self.loaded[base as usize + i] = true;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loading_a_font_clears_the_whole_previous_font() {
+        let mut memory = Memory::new();
+        //a font with a shorter big font, loaded where the previous one was
+        let font_set = FontSet {
+            fonts: vec![0xFF; 80],
+            big_fonts: vec![0xFF; 100],
+        };
+        memory.load_font(&font_set, 0);
+        assert!(memory.cells[..180].iter().all(|&cell| cell == 0xFF));
+        assert!(memory.cells[180..240].iter().all(|&cell| cell == 0));
+        assert!(!memory.loaded[180..240].contains(&true));
+
+        //elsewhere, nothing of the previous font is left
+        memory.load_font(&FontSet::new(), 0x050);
+        assert!(memory.cells[..0x050].iter().all(|&cell| cell == 0));
+        assert!(memory.cells[0x050 + 240..0x200]
+            .iter()
+            .all(|&cell| cell == 0));
+    }
+}
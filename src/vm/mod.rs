@@ -1,31 +1,59 @@
 //! CHIP-8 VM has following basic components :
 //! Graphics (display and font-set), Instruction set, Keyboard, Memory, Registers, Stack and Timers.
 
+mod cdp1802;
+mod decoder;
 mod graphics;
 mod instructions;
 mod keyboard;
+mod megachip;
 mod memory;
 mod operator;
+mod quirks;
 mod registers;
 mod stack;
 mod timers;
 
-use graphics::vram::VRam;
+use crate::debugger::undo_log::Mutation;
+use crate::debugger::{Coverage, Profiler, SanityChecker, SmcDetector, Tracer};
+pub use cdp1802::Cdp1802;
+pub use decoder::{Instruction, Platform};
+pub use graphics::fontset::{FontSet, FontStyle};
+pub use graphics::vram::{Colors, MegaScreen, VRam};
 use keyboard::KeyBoard;
 use log::info;
+pub use megachip::{BlendMode, MegaChip, Sample};
 use memory::Memory;
-use operator::Operator;
+pub use memory::{MEGACHIP_MEMORY_SIZE, PROGRAM_START, XO_CHIP_MEMORY_SIZE};
+pub use operator::Operator;
+pub use quirks::Quirks;
 use registers::Registers;
 use stack::Stack;
 use timers::Timers;
 
 const VRAM_WIDTH: usize = 64;
 const VRAM_HEIGHT: usize = 32;
+/// Size of the high resolution display of SUPER-CHIP and XO-CHIP (00FF).
+const SUPER_CHIP_VRAM_WIDTH: usize = 128;
+const SUPER_CHIP_VRAM_HEIGHT: usize = 64;
+/// Pitch of the audio pattern of XO-CHIP until FX3A sets it : 4000 Hz.
+const DEFAULT_PITCH: u8 = 64;
+/// Height of the display of the hi-res CHIP-8 interpreter, which has two pages of VRAM.
+const HIRES_VRAM_HEIGHT: usize = 64;
+/// CHIP-8X programs are loaded after its interpreter, which is bigger than the one of CHIP-8.
+pub const CHIP_8X_PROGRAM_START: u16 = 0x300;
+/// Hi-res CHIP-8 programs start with `1260`, a jump to the interpreter (0x260), which starts the program at 0x2C0.
+const HIRES_SIGNATURE: [u8; 2] = [0x12, 0x60];
+const HIRES_ENTRY_POINT: u16 = 0x260;
+pub const HIRES_PROGRAM_START: u16 = 0x2C0;
 
 #[derive(Debug)]
 pub struct OutputState<'a> {
     pub vram: &'a VRam,
     pub play_sound: bool,
+    /// `sound` is the sample MegaChip plays, and `sound_changed` is set when it was started or stopped.
+    pub sound: Option<&'a Sample>,
+    pub sound_changed: bool,
 }
 
 pub struct Machine {
@@ -35,10 +63,54 @@ pub struct Machine {
     pub registers: Registers,
     pub stack: Stack,
     pub timers: Timers,
-    /// `i` is a 16-bit index register. It can have value from 0x000 to 0xFFF.
-    pub i: u16,
+    /// `quirks` select how the instructions which differ between interpreters behave.
+    pub quirks: Quirks,
+    /// `i` is the index register. It can have value from 0x000 to 0xFFF, or up to 0xFFFFFF on MegaChip.
+    pub i: u32,
     /// `pc` is a 16-bit program counter. It can have value from 0x000 to 0xFFF.
     pub pc: u16,
+    /// `tracer` writes every executed instruction to a trace file, when tracing is switched on.
+    pub tracer: Option<Tracer>,
+    /// `coverage` counts executions, reads and writes of every memory cell, when coverage is switched on.
+    pub coverage: Option<Coverage>,
+    /// `profiler` counts the cycles spent in every subroutine, when profiling is switched on.
+    pub profiler: Option<Profiler>,
+    /// `sanity_checker` looks for suspicious behaviour of the program, in strict mode.
+    pub sanity_checker: Option<SanityChecker>,
+    /// `smc_detector` finds self-modifying code, when tracing or debugging.
+    pub smc_detector: Option<SmcDetector>,
+    /// `platform` is the instruction set the program is written for : DXY0 draws 16x16 sprites from SUPER-CHIP on,
+    /// and F000 NNNN is 4 bytes long on XO-CHIP.
+    pub platform: Platform,
+    /// `halted` is set when the sanity checker stopped the machine, or when the program exited (00FD).
+    /// A halted machine does not execute instructions.
+    pub halted: bool,
+    /// `hires` is set for the programs of the hi-res CHIP-8 interpreter, which have a 64x64 display.
+    pub hires: bool,
+    /// `chip8x` is set for the programs of CHIP-8X, which have colors and a second keypad.
+    pub chip8x: bool,
+    /// `megachip` is the state of MegaChip, which has 16 MB of memory and switches to its own screen.
+    pub megachip: Option<MegaChip>,
+    /// `flags` are the user flags of the HP48 (SUPER-CHIP, 8 of them) and XO-CHIP (16), saved by FX75 and
+    /// loaded by FX85.
+    pub flags: [u8; 16],
+    /// `planes` selects the bit planes XO-CHIP draws on (FN01), plane 1 by default. A pixel holds a bit per plane.
+    pub planes: u8,
+    /// `audio_pattern` is the pattern of 128 1-bit samples XO-CHIP plays while the sound timer runs (F002),
+    /// at the `pitch` set by FX3A.
+    pub audio_pattern: [u8; 16],
+    pub pitch: u8,
+    /// `cdp1802` is the CPU of the COSMAC VIP, which runs the routines of machine code called by 0NNN.
+    pub cdp1802: Cdp1802,
+    /// `journal` collects the old values of what the step being executed changes, when the debugger keeps
+    /// an undo log.
+    pub journal: Option<Vec<Mutation>>,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Machine::new()
+    }
 }
 
 impl Machine {
@@ -50,135 +122,812 @@ impl Machine {
             registers: Registers::new(),
             stack: Stack::new(),
             timers: Timers::new(),
+            quirks: Quirks::new(),
             i: 0,
-            pc: 0x200,
+            pc: PROGRAM_START,
+            tracer: None,
+            coverage: None,
+            profiler: None,
+            sanity_checker: None,
+            smc_detector: None,
+            platform: Platform::Chip8,
+            halted: false,
+            hires: false,
+            chip8x: false,
+            megachip: None,
+            flags: [0; 16],
+            planes: 1,
+            audio_pattern: [0; 16],
+            pitch: DEFAULT_PITCH,
+            cdp1802: Cdp1802::new(),
+            journal: None,
+        }
+    }
+
+    /// `Machine.load_program()` loads `data` from `load_address`, and starts the program at `entry_point`.
+    pub fn load_program(&mut self, data: &[u8], load_address: u16, entry_point: u16) {
+        info!(
+            "[Machine.load_program()] Loading {} bytes at 0x{:03X}, starting at 0x{:03X}.",
+            data.len(),
+            load_address,
+            entry_point
+        );
+        self.memory.load_data_at(load_address, data);
+        self.pc = entry_point;
+        if is_hires(data, load_address, entry_point) {
+            self.enable_hires();
+            //the interpreter at 0x260 is not run, the program starts right away
+            if entry_point == HIRES_ENTRY_POINT {
+                self.pc = HIRES_PROGRAM_START;
+            }
+        }
+    }
+
+    /// `Machine.enable_hires()` switches to the 64x64 display of the hi-res CHIP-8 interpreter.
+    pub fn enable_hires(&mut self) {
+        info!("[Machine.enable_hires()] Switching to the 64x64 display of hi-res CHIP-8.");
+        self.record(Mutation::Hires { old: self.hires });
+        self.hires = true;
+        let old = std::mem::replace(&mut self.vram, VRam::new(VRAM_WIDTH, HIRES_VRAM_HEIGHT));
+        self.record(Mutation::VRam { old: Box::new(old) });
+        if self.chip8x {
+            self.vram.colors = Some(Colors::new(VRAM_WIDTH, HIRES_VRAM_HEIGHT));
+        }
+        self.vram.state_changed = true;
+    }
+
+    /// `Machine.set_platform()` selects the instruction set of the program. XO-CHIP addresses 64 KB of memory.
+    pub fn set_platform(&mut self, platform: Platform) {
+        info!(
+            "[Machine.set_platform()] Running {} instructions.",
+            platform
+        );
+        self.platform = platform;
+        if platform == Platform::XoChip && self.memory.cells.len() < XO_CHIP_MEMORY_SIZE {
+            self.memory.resize(XO_CHIP_MEMORY_SIZE);
+        }
+    }
+
+    /// `Machine.set_resolution()` switches to a cleared display of `width` x `height` (00FE and 00FF).
+    pub fn set_resolution(&mut self, width: usize, height: usize) {
+        info!(
+            "[Machine.set_resolution()] Switching to a display of {}x{}.",
+            width, height
+        );
+        let old = std::mem::replace(&mut self.vram, VRam::new(width, height));
+        self.record(Mutation::VRam { old: Box::new(old) });
+        self.vram.state_changed = true;
+    }
+
+    /// `Machine.enable_chip8x()` adds the colors and the second keypad of CHIP-8X.
+    pub fn enable_chip8x(&mut self) {
+        info!("[Machine.enable_chip8x()] Switching to the colors of CHIP-8X.");
+        self.record(Mutation::Chip8x { old: self.chip8x });
+        self.record_colors();
+        self.chip8x = true;
+        let (width, height) = (self.vram.cells[0].len(), self.vram.cells.len());
+        self.vram.colors = Some(Colors::new(width, height));
+        self.vram.state_changed = true;
+    }
+
+    /// `Machine.enable_megachip()` adds the 16 MB of memory of MegaChip, and its instructions. The program switches
+    /// to the screen of MegaChip with 0011.
+    pub fn enable_megachip(&mut self) {
+        info!("[Machine.enable_megachip()] Adding the memory and the instructions of MegaChip.");
+        self.memory.resize(MEGACHIP_MEMORY_SIZE);
+        self.megachip = Some(MegaChip::new());
+    }
+
+    /// `Machine.set_megachip_mode()` switches to the screen of MegaChip (256 x 192 colored pixels), or back.
+    pub fn set_megachip_mode(&mut self, enabled: bool) {
+        info!("[Machine.set_megachip_mode()] MegaChip mode : {}.", enabled);
+        self.record_megachip();
+        if let Some(megachip) = self.megachip.as_mut() {
+            megachip.enabled = enabled;
         }
+        let vram = match enabled {
+            true => {
+                let mut vram = VRam::new(megachip::SCREEN_WIDTH, megachip::SCREEN_HEIGHT);
+                vram.mega = Some(MegaScreen::new(
+                    megachip::SCREEN_WIDTH,
+                    megachip::SCREEN_HEIGHT,
+                ));
+                vram
+            }
+            false if self.hires => VRam::new(VRAM_WIDTH, HIRES_VRAM_HEIGHT),
+            false => VRam::new(VRAM_WIDTH, VRAM_HEIGHT),
+        };
+        let old = std::mem::replace(&mut self.vram, vram);
+        self.record(Mutation::VRam { old: Box::new(old) });
+        if self.chip8x && !enabled {
+            self.vram.colors = Some(Colors::new(VRAM_WIDTH, self.vram.cells.len()));
+        }
+        self.vram.state_changed = true;
+    }
+
+    /// `Machine.megachip_mode()` tells whether the machine is in MegaChip mode.
+    pub fn megachip_mode(&self) -> bool {
+        self.megachip
+            .as_ref()
+            .is_some_and(|megachip| megachip.enabled)
+    }
+
+    /// `Machine.runs_machine_code()` tells whether 0NNN calls CDP1802 machine code : only CHIP-8 programs, in the
+    /// 4 KB of memory and on the 64 x 32 display of the COSMAC VIP, do. Elsewhere 0NNN is skipped.
+    pub fn runs_machine_code(&self) -> bool {
+        self.platform == Platform::Chip8
+            && self.memory.cells.len() == memory::MEMORY_SIZE
+            && self.megachip.is_none()
+            && !self.hires
+    }
+
+    /// `Machine.decode()` will return the instruction of the `opcode` on the platform of the machine. MegaChip
+    /// extends SUPER-CHIP.
+    pub fn decode(&self, opcode: u16) -> Instruction {
+        let platform = match self.megachip {
+            Some(_) => self.platform.max(Platform::SuperChip),
+            None => self.platform,
+        };
+        Instruction::decode_for(opcode, platform)
+    }
+
+    /// `Machine.record()` adds a mutation to the journal of the step, if the machine keeps one.
+    pub fn record(&mut self, mutation: Mutation) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.push(mutation);
+        }
+    }
+
+    /// `Machine.record_colors()` adds the colors of CHIP-8X to the journal, before they are changed.
+    pub fn record_colors(&mut self) {
+        if self.journal.is_some() {
+            let old = self.vram.colors.clone();
+            self.record(Mutation::Colors { old });
+        }
+    }
+
+    /// `Machine.record_megachip()` adds the state of MegaChip to the journal, before it is changed.
+    pub fn record_megachip(&mut self) {
+        if let (Some(_), Some(megachip)) = (self.journal.as_ref(), self.megachip.as_ref()) {
+            let old = Box::new(megachip.clone());
+            self.record(Mutation::MegaChip { old });
+        }
+    }
+
+    /// `Machine.record_mega_screen()` adds the screen of MegaChip to the journal, before it is changed.
+    pub fn record_mega_screen(&mut self) {
+        if let (Some(_), Some(screen)) = (self.journal.as_ref(), self.vram.mega.as_ref()) {
+            let old = Box::new(screen.clone());
+            self.record(Mutation::MegaScreen { old });
+        }
+    }
+
+    /// `Machine.set_register()` sets VX to `value`.
+    pub fn set_register(&mut self, x: usize, value: u8) {
+        self.record(Mutation::Register {
+            index: x,
+            old: self.registers.v[x],
+        });
+        self.registers.v[x] = value;
+    }
+
+    /// `Machine.write_memory()` sets the memory cell at `address` to `value`.
+    pub fn write_memory(&mut self, address: usize, value: u8) {
+        self.record(Mutation::Memory {
+            address,
+            old: self.memory.cells[address],
+        });
+        self.memory.cells[address] = value;
+    }
+
+    /// `Machine.set_pixel()` sets the pixel at (`x`, `y`) of the VRAM to `value`.
+    pub fn set_pixel(&mut self, x: usize, y: usize, value: u8) {
+        self.record(Mutation::Pixel {
+            x,
+            y,
+            old: self.vram.cells[y][x],
+        });
+        self.vram.cells[y][x] = value;
+    }
+
+    /// `Machine.set_index()` sets I to `value`.
+    pub fn set_index(&mut self, value: u32) {
+        self.record(Mutation::Index { old: self.i });
+        self.i = value;
+    }
+
+    /// `Machine.set_halted()` halts the machine, or lets it go on.
+    pub fn set_halted(&mut self, halted: bool) {
+        self.record(Mutation::Halted { old: self.halted });
+        self.halted = halted;
+    }
+
+    /// `Machine.set_timers()` sets the delay timer to `dt` and the sound timer to `st`.
+    pub fn set_timers(&mut self, dt: u8, st: u8) {
+        self.record(Mutation::Timers {
+            dt: self.timers.dt,
+            st: self.timers.st,
+        });
+        self.timers.dt = dt;
+        self.timers.st = st;
+    }
+
+    /// `Machine.push()` pushes `address` on the stack.
+    pub fn push(&mut self, address: u16) {
+        self.record(Mutation::Push);
+        self.stack.cells.push(address);
+    }
+
+    /// `Machine.pop()` pops the address on top of the stack, if any.
+    pub fn pop(&mut self) -> Option<u16> {
+        let address = self.stack.cells.pop()?;
+        self.record(Mutation::Pop { old: address });
+        Some(address)
     }
 
     pub fn get_operators(&self) -> Operator {
-        Operator::get_operators(&self)
+        Operator::get_operators(self)
     }
 
-    pub fn process_keys(&mut self, keys: Vec<bool>) -> OutputState {
+    /// `Machine.process_keys()` runs a cycle with the 16 keys of the keypad pressed or not, followed by
+    /// the 16 keys of the second keypad of CHIP-8X, if any.
+    pub fn process_keys(&mut self, mut keys: Vec<bool>) -> OutputState<'_> {
         info!("[Machine.process_keys()] The machine started processing keys.");
         self.vram.state_changed = false;
+        if let Some(megachip) = self.megachip.as_mut() {
+            megachip.sound_changed = false;
+        }
+        self.record(Mutation::KeyBoard {
+            keys: self.keyboard.keys.clone(),
+            second_keys: self.keyboard.second_keys.clone(),
+            keypress_awaited: self.keyboard.keypress_awaited,
+            key_register: self.keyboard.key_register,
+        });
+        if keys.len() > 16 {
+            self.keyboard.second_keys = keys.split_off(16);
+        }
         self.keyboard.keys = keys;
+        if self.halted {
+            return self.output_state();
+        }
 
         if self.keyboard.keypress_awaited {
-            for (i, &key) in self.keyboard.keys.iter().enumerate() {
-                if key {
-                    self.keyboard.keypress_awaited = false;
-                    self.registers.v[self.keyboard.key_register] = i as u8;
-                    break;
-                }
+            if let Some(key) = self.keyboard.keys.iter().position(|&key| key) {
+                self.keyboard.keypress_awaited = false;
+                self.set_register(self.keyboard.key_register, key as u8);
             }
         } else {
-            if self.timers.st > 0 {
-                self.timers.st -= 1;
-            }
-            if self.timers.dt > 0 {
-                self.timers.dt -= 1;
+            if self.timers.st > 0 || self.timers.dt > 0 {
+                self.set_timers(
+                    self.timers.dt.saturating_sub(1),
+                    self.timers.st.saturating_sub(1),
+                );
             }
             self.emulate_cycle();
         }
 
+        self.output_state()
+    }
+
+    /// `Machine.output_state()` returns what the drivers need to show: the VRAM and whether to play a sound.
+    pub fn output_state(&self) -> OutputState<'_> {
         OutputState {
             vram: &self.vram,
             play_sound: false,
+            sound: self
+                .megachip
+                .as_ref()
+                .and_then(|megachip| megachip.sound.as_ref()),
+            sound_changed: self
+                .megachip
+                .as_ref()
+                .is_some_and(|megachip| megachip.sound_changed),
         }
     }
 
     fn emulate_cycle(&mut self) {
         //let mach= self;
         let operator = self.get_operators();
-        match (
-            operator.nibble_1,
-            operator.nibble_2,
-            operator.nibble_3,
-            operator.nibble_4,
-        ) {
+        if let Some(mut sanity_checker) = self.sanity_checker.take() {
+            let halt = sanity_checker.check(self, &operator);
+            self.sanity_checker = Some(sanity_checker);
+            if halt {
+                self.set_halted(true);
+                return;
+            }
+        }
+        if let Some(mut smc_detector) = self.smc_detector.take() {
+            smc_detector.record(self, &operator);
+            self.smc_detector = Some(smc_detector);
+        }
+        if let Some(mut tracer) = self.tracer.take() {
+            tracer.record(self, &operator);
+            self.tracer = Some(tracer);
+        }
+        if let Some(mut coverage) = self.coverage.take() {
+            coverage.record(self, &operator);
+            self.coverage = Some(coverage);
+        }
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record(&operator);
+        }
+        //the program counter moves with every instruction, it is recorded once before the instruction
+        self.record(Mutation::ProgramCounter { old: self.pc });
+        match self.decode(operator.opcode) {
+            //switch MegaChip mode off and on
+            Instruction::Sys(0x010) if self.megachip.is_some() => instructions::execute_0010(self),
+            Instruction::Sys(0x011) if self.megachip.is_some() => instructions::execute_0011(self),
+            //show the screen of MegaChip drawn since the last time, and clear it
+            Instruction::Cls if self.megachip_mode() => instructions::execute_00e0_megachip(self),
+            //the instructions of MegaChip : 01NN NNNN sets a 24-bit I, 02NN to 09NN set the palette, the sprites,
+            //the alpha of the screen, the sound, the blend mode and the collision color
+            Instruction::Sys(nnn) if self.megachip_mode() => match nnn >> 8 {
+                0x1 => instructions::execute_01nn(self, &operator),
+                0x2 => instructions::execute_02nn(self, &operator),
+                0x3 => instructions::execute_03nn(self, &operator),
+                0x4 => instructions::execute_04nn(self, &operator),
+                0x5 => instructions::execute_05nn(self, &operator),
+                0x6 => instructions::execute_060n(self, &operator),
+                0x7 => instructions::execute_0700(self),
+                0x8 => instructions::execute_080n(self, &operator),
+                0x9 => instructions::execute_09nn(self, &operator),
+                _ => instructions::execute_unknown(self, &operator),
+            },
             //clear screen
-            (0, 0, 0xE, 0) => instructions::execute_00e0(self),
+            Instruction::Cls => instructions::execute_00e0(self),
+            //clear the 64x64 screen of hi-res CHIP-8
+            Instruction::Sys(0x230) if self.hires => instructions::execute_00e0(self),
+            //cycle the background color of CHIP-8X
+            Instruction::Sys(0x2A0) if self.chip8x => instructions::execute_02a0(self),
             //return from subroutine
-            (0, 0, 0xE, 0xE) => instructions::execute_00ee(self),
+            Instruction::Ret => instructions::execute_00ee(self),
+            //hi-res CHIP-8 starts the program at 0x2C0
+            Instruction::Jump(HIRES_ENTRY_POINT) if self.hires && self.pc == PROGRAM_START => {
+                self.pc = HIRES_PROGRAM_START
+            }
             //jump (goto) to address nnn
-            (0x1, _, _, _) => instructions::execute_1nnn(self, &operator),
+            Instruction::Jump(_) => instructions::execute_1nnn(self, &operator),
             //call subroutine at nnn
-            (0x2, _, _, _) => instructions::execute_2nnn(self, &operator),
+            Instruction::Call(_) => instructions::execute_2nnn(self, &operator),
             //Skips the next instruction if VX equals NN.
-            (0x3, _, _, _) => instructions::execute_3nnn(self, &operator),
+            Instruction::SkipEqualByte { .. } => instructions::execute_3nnn(self, &operator),
             //Skips the next instruction if VX doesn't equal NN.
-            (0x4, _, _, _) => instructions::execute_4nnn(self, &operator),
+            Instruction::SkipNotEqualByte { .. } => instructions::execute_4nnn(self, &operator),
             //Skips the next instruction if VX equals VY.
-            (0x5, _, _, 0) => instructions::execute_5xy0(self, &operator),
+            Instruction::SkipEqual { .. } => instructions::execute_5xy0(self, &operator),
             //Sets VX to NN.
-            (0x6, _, _, _) => instructions::execute_6xnn(self, &operator),
+            Instruction::LoadByte { .. } => instructions::execute_6xnn(self, &operator),
             //Adds NN to VX.
-            (0x7, _, _, _) => instructions::execute_7xnn(self, &operator),
+            Instruction::AddByte { .. } => instructions::execute_7xnn(self, &operator),
             //Sets VX to the value of VY.
-            (0x8, _, _, 0x0) => instructions::execute_8xy0(self, &operator),
+            Instruction::Load { .. } => instructions::execute_8xy0(self, &operator),
             //Sets VX to VX or VY. (Bitwise OR operation)
-            (0x8, _, _, 0x1) => instructions::execute_8xy1(self, &operator),
+            Instruction::Or { .. } => instructions::execute_8xy1(self, &operator),
             //Sets VX to VX and VY. (Bitwise AND operation)
-            (0x8, _, _, 0x2) => instructions::execute_8xy2(self, &operator),
+            Instruction::And { .. } => instructions::execute_8xy2(self, &operator),
             //Sets VX to VX xor VY.
-            (0x8, _, _, 0x3) => instructions::execute_8xy3(self, &operator),
+            Instruction::Xor { .. } => instructions::execute_8xy3(self, &operator),
             //Adds VY to VX. VF is set to 1 when there's a carry, and to 0 when there isn't.
-            (0x8, _, _, 0x4) => instructions::execute_8xy4(self, &operator),
+            Instruction::Add { .. } => instructions::execute_8xy4(self, &operator),
             //VY is subtracted from VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
-            (0x8, _, _, 0x5) => instructions::execute_8xy5(self, &operator),
+            Instruction::Sub { .. } => instructions::execute_8xy5(self, &operator),
             //Stores the least significant bit of VX in VF and then shifts VX to the right by 1.
-            (0x8, _, _, 0x6) => instructions::execute_8xy6(self, &operator),
+            Instruction::ShiftRight { .. } => instructions::execute_8xy6(self, &operator),
             //Sets VX to VY minus VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
-            (0x8, _, _, 0x7) => instructions::execute_8xy7(self, &operator),
+            Instruction::SubNegated { .. } => instructions::execute_8xy7(self, &operator),
             //Stores the most significant bit of VX in VF and then shifts VX to the left by 1.
-            (0x8, _, _, 0xE) => instructions::execute_8xye(self, &operator),
+            Instruction::ShiftLeft { .. } => instructions::execute_8xye(self, &operator),
             //Skips the next instruction if VX doesn't equal VY. (Usually the next instruction is a jump to skip a code block)
-            (0x9, _, _, 0x0) => instructions::execute_9xy0(self, &operator),
+            Instruction::SkipNotEqual { .. } => instructions::execute_9xy0(self, &operator),
             //Sets I to the address NNN.
-            (0xA, _, _, _) => instructions::execute_annn(self, &operator),
+            Instruction::LoadIndex(_) => instructions::execute_annn(self, &operator),
+            //Sets the color of zones of the screen (CHIP-8X).
+            Instruction::JumpOffset(_) if self.chip8x => {
+                instructions::execute_bxyn(self, &operator)
+            }
             //Jumps to the address NNN plus V0.
-            (0xB, _, _, _) => instructions::execute_bnnn(self, &operator),
+            Instruction::JumpOffset(_) => instructions::execute_bnnn(self, &operator),
             //Sets VX to the result of a bitwise and operation on a random number (Typically: 0 to 255) and NN.
-            (0xC, _, _, _) => instructions::execute_cxnn(self, &operator),
+            Instruction::Random { .. } => instructions::execute_cxnn(self, &operator),
             //Draws a sprite at coordinate (VX, VY) that has a width of 8 pixels and a height of N pixels.
             //Each row of 8 pixels is read as bit-coded starting from memory location I;
             //I value doesn’t change after the execution of this instruction.
             //As described above, VF is set to 1 if any screen pixels are flipped from set to unset when the sprite is drawn,
             //and to 0 if that doesn’t happen
-            (0xD, _, _, _) => instructions::execute_dxyn(self, &operator),
+            Instruction::Draw { .. } if self.megachip_mode() => {
+                instructions::execute_dxyn_megachip(self, &operator)
+            }
+            Instruction::Draw { .. } => instructions::execute_dxyn(self, &operator),
             //Skips the next instruction if the key stored in VX is pressed.
             //(Usually the next instruction is a jump to skip a code block)
-            (0xE, _, 0x9, 0xE) => instructions::execute_ex9e(self, &operator),
+            Instruction::SkipKey(_) => instructions::execute_ex9e(self, &operator),
             //Skips the next instruction if the key stored in VX isn't pressed.
             //(Usually the next instruction is a jump to skip a code block)
-            (0xE, _, 0xA, 0x1) => instructions::execute_exa1(self, &operator),
+            Instruction::SkipNotKey(_) => instructions::execute_exa1(self, &operator),
             //Sets VX to the value of the delay timer.
-            (0xF, _, 0x0, 0x7) => instructions::execute_fx07(self, &operator),
+            Instruction::LoadDelay(_) => instructions::execute_fx07(self, &operator),
             //A key press is awaited, and then stored in VX. (Blocking Operation. All instruction halted until next key event)
-            (0xF, _, 0x0, 0xA) => instructions::execute_fx0a(self, &operator),
+            Instruction::WaitKey(_) => instructions::execute_fx0a(self, &operator),
             //Sets the delay timer to VX.
-            (0xF, _, 0x1, 0x5) => instructions::execute_fx15(self, &operator),
+            Instruction::SetDelay(_) => instructions::execute_fx15(self, &operator),
             //Sets the sound timer to VX.
-            (0xF, _, 0x1, 0x8) => instructions::execute_fx18(self, &operator),
+            Instruction::SetSound(_) => instructions::execute_fx18(self, &operator),
             //Adds VX to I. VF is set to 1 when there is a range overflow (I+VX>0xFFF), and to 0 when there isn't.
-            (0xF, _, 0x1, 0xE) => instructions::execute_fx1e(self, &operator),
+            Instruction::AddIndex(_) => instructions::execute_fx1e(self, &operator),
             //Sets I to the location of the sprite for the character in VX.
             //Characters 0-F (in hexadecimal) are represented by a 4x5 font.
-            (0xF, _, 0x2, 0x9) => instructions::execute_fx29(self, &operator),
+            Instruction::LoadFont(_) => instructions::execute_fx29(self, &operator),
+            //Sets I to the location of the big sprite for the character in VX.
+            //Characters 0-F (in hexadecimal) are represented by a 8x10 font.
+            Instruction::LoadBigFont(_) => instructions::execute_fx30(self, &operator),
             //Stores the binary-coded decimal representation of VX, with the most significant of three digits at the address in I,
             //the middle digit at I plus 1, and the least significant digit at I plus 2.
             //(In other words, take the decimal representation of VX, place the hundreds digit in memory at location in I,
             //the tens digit at location I+1, and the ones digit at location I+2.)
-            (0xF, _, 0x3, 0x3) => instructions::execute_fx33(self, &operator),
+            Instruction::StoreBcd(_) => instructions::execute_fx33(self, &operator),
             //Stores V0 to VX (including VX) in memory starting at address I.
             //The offset from I is increased by 1 for each value written, but I itself is left unmodified.
-            (0xF, _, 0x5, 0x5) => instructions::execute_fx55(self, &operator),
+            Instruction::StoreRegisters(_) => instructions::execute_fx55(self, &operator),
             //Fills V0 to VX (including VX) with values from memory starting at address I.
             //The offset from I is increased by 1 for each value written, but I itself is left unmodified.[d]
-            (0xF, _, 0x6, 0x5) => instructions::execute_fx65(self, &operator),
-            //If no match found, do nothing.
-            (_, _, _, _) => (),
+            Instruction::LoadRegisters(_) => instructions::execute_fx65(self, &operator),
+            //Skips the next instruction if the key stored in VX is pressed on the second keypad (CHIP-8X).
+            Instruction::Unknown(opcode) if self.chip8x && opcode & 0xF0FF == 0xE0F2 => {
+                instructions::execute_exf2(self, &operator)
+            }
+            //Skips the next instruction if the key stored in VX isn't pressed on the second keypad (CHIP-8X).
+            Instruction::Unknown(opcode) if self.chip8x && opcode & 0xF0FF == 0xE0F5 => {
+                instructions::execute_exf5(self, &operator)
+            }
+            //Calls the routine of CDP1802 machine code at NNN.
+            Instruction::Sys(_) if self.runs_machine_code() => {
+                instructions::execute_0nnn(self, &operator)
+            }
+            Instruction::Sys(_) => instructions::execute_unknown(self, &operator),
+            //Scrolls the display down by N pixels (SUPER-CHIP).
+            Instruction::ScrollDown(n) => instructions::execute_00cn(self, n),
+            //Scrolls the display up by N pixels (XO-CHIP).
+            Instruction::ScrollUp(n) => instructions::execute_00dn(self, n),
+            //Scrolls the display right by 4 pixels (SUPER-CHIP).
+            Instruction::ScrollRight => instructions::execute_00fb(self),
+            //Scrolls the display left by 4 pixels (SUPER-CHIP).
+            Instruction::ScrollLeft => instructions::execute_00fc(self),
+            //Exits the interpreter (SUPER-CHIP).
+            Instruction::Exit => instructions::execute_00fd(self),
+            //Switches to the 64x32 display (SUPER-CHIP).
+            Instruction::LowRes => instructions::execute_00fe(self),
+            //Switches to the 128x64 display (SUPER-CHIP).
+            Instruction::HighRes => instructions::execute_00ff(self),
+            //Stores VX to VY in memory starting at address I (XO-CHIP).
+            Instruction::StoreRange { .. } => instructions::execute_5xy2(self, &operator),
+            //Fills VX to VY with values from memory starting at address I (XO-CHIP).
+            Instruction::LoadRange { .. } => instructions::execute_5xy3(self, &operator),
+            //Sets I to the 16-bit address of the next opcode (XO-CHIP).
+            Instruction::LoadIndexLong => instructions::execute_f000(self),
+            //Selects the bit planes to draw on (XO-CHIP).
+            Instruction::Plane(_) => instructions::execute_fn01(self, &operator),
+            //Loads the audio pattern from I (XO-CHIP).
+            Instruction::Audio => instructions::execute_f002(self),
+            //Sets the pitch of the audio pattern to VX (XO-CHIP).
+            Instruction::SetPitch(_) => instructions::execute_fx3a(self, &operator),
+            //Stores V0 to VX in the user flags (SUPER-CHIP).
+            Instruction::StoreFlags(_) => instructions::execute_fx75(self, &operator),
+            //Fills V0 to VX with the user flags (SUPER-CHIP).
+            Instruction::LoadFlags(_) => instructions::execute_fx85(self, &operator),
+            //Unknown opcodes are skipped.
+            _ => instructions::execute_unknown(self, &operator),
+        }
+    }
+}
+
+/// `is_hires()` tells if a program is written for the hi-res CHIP-8 interpreter : it is loaded at 0x200, starts
+/// with `1260`, and is started either there or at the interpreter (0x260).
+pub fn is_hires(data: &[u8], load_address: u16, entry_point: u16) -> bool {
+    load_address == PROGRAM_START
+        && (entry_point == PROGRAM_START || entry_point == HIRES_ENTRY_POINT)
+        && data.starts_with(&HIRES_SIGNATURE)
+}
+
+/// `code_start()` returns the address of the first instruction of a program : its entry point, or 0x2C0 for
+/// hi-res CHIP-8 programs.
+pub fn code_start(data: &[u8], load_address: u16, entry_point: u16) -> u16 {
+    match is_hires(data, load_address, entry_point) {
+        true => HIRES_PROGRAM_START,
+        false => entry_point,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `run()` executes `steps` instructions of `program`.
+    fn run(program: &[u8], steps: usize) -> Machine {
+        run_on(Platform::Chip8, program, steps)
+    }
+
+    /// `run_on()` executes `steps` instructions of `program`, written for `platform`.
+    fn run_on(platform: Platform, program: &[u8], steps: usize) -> Machine {
+        let mut machine = Machine::new();
+        machine.set_platform(platform);
+        machine.load_program(program, PROGRAM_START, PROGRAM_START);
+        for _ in 0..steps {
+            machine.process_keys(vec![false; 16]);
+        }
+        machine
+    }
+
+    #[test]
+    fn arithmetic_with_vf_as_vx_leaves_the_flag_in_vf() {
+        //VF := 3, V1 := 5, then each instruction with X = F : the flag wins over the result
+        let cases: [(u16, u8); 5] = [
+            (0x8F14, 0), //3 + 5, no carry
+            (0x8F15, 0), //3 - 5, borrow
+            (0x8F16, 1), //3 >> 1, the bit shifted out is 1
+            (0x8F17, 1), //5 - 3, no borrow
+            (0x8F1E, 0), //3 << 1, the bit shifted out is 0
+        ];
+        for (opcode, flag) in cases {
+            let [high, low] = opcode.to_be_bytes();
+            let machine = run(&[0x6F, 0x03, 0x61, 0x05, high, low], 3);
+            assert_eq!(machine.registers.v[0xF], flag, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn arithmetic_sets_vx_and_the_flag() {
+        //V0 := 0xF0, V1 := 0x20, V0 += V1 (carry), V2 := 1, V2 -= V1 (borrow)
+        let machine = run(
+            &[0x60, 0xF0, 0x61, 0x20, 0x80, 0x14, 0x62, 0x01, 0x82, 0x15],
+            5,
+        );
+        assert_eq!(machine.registers.v[0], 0x10);
+        assert_eq!(machine.registers.v[2], 0xE1);
+        assert_eq!(machine.registers.v[0xF], 0);
+    }
+
+    #[test]
+    fn hires_programs_start_at_0x2c0_from_either_entry_point() {
+        let mut program = vec![0x12, 0x60];
+        program.resize(0xC0, 0);
+        //V0 := 1
+        program.extend([0x60, 0x01]);
+        //from 0x200, the jump to the interpreter comes first
+        for (entry_point, steps) in [(PROGRAM_START, 2), (HIRES_ENTRY_POINT, 1)] {
+            let mut machine = Machine::new();
+            machine.load_program(&program, PROGRAM_START, entry_point);
+            assert!(machine.hires, "0x{:03X}", entry_point);
+            assert_eq!(machine.vram.cells.len(), HIRES_VRAM_HEIGHT);
+            for _ in 0..steps {
+                machine.process_keys(vec![false; 16]);
+            }
+            assert_eq!(machine.pc, HIRES_PROGRAM_START + 2);
+            assert_eq!(machine.registers.v[0], 1);
+        }
+        //the program is not loaded where the interpreter expects it
+        let mut machine = Machine::new();
+        machine.load_program(&program, CHIP_8X_PROGRAM_START, CHIP_8X_PROGRAM_START);
+        assert!(!machine.hires);
+    }
+
+    #[test]
+    fn super_chip_program_runs_past_its_first_instruction() {
+        //hires, V0 := 1, jump to itself
+        let machine = run_on(
+            Platform::SuperChip,
+            &[0x00, 0xFF, 0x60, 0x01, 0x12, 0x04],
+            5,
+        );
+        assert_eq!(machine.pc, 0x204);
+        assert_eq!(machine.registers.v[0], 1);
+        assert_eq!(
+            (machine.vram.cells[0].len(), machine.vram.cells.len()),
+            (128, 64)
+        );
+    }
+
+    #[test]
+    fn unknown_opcodes_are_skipped() {
+        //5XY1 means nothing, V0 := 7
+        let machine = run(&[0x50, 0x01, 0x60, 0x07], 2);
+        assert_eq!(machine.pc, 0x204);
+        assert_eq!(machine.registers.v[0], 7);
+    }
+
+    #[test]
+    fn exit_halts_the_machine() {
+        let machine = run_on(Platform::SuperChip, &[0x00, 0xFD, 0x60, 0x07], 2);
+        assert!(machine.halted);
+        assert_eq!(machine.registers.v[0], 0);
+    }
+
+    #[test]
+    fn scrolling_moves_the_display() {
+        //draw the first row of the font of 0 (0xF0) at (0, 0), scroll down 2, right 4, left 4, then right 4
+        let machine = run_on(
+            Platform::SuperChip,
+            &[0xD0, 0x01, 0x00, 0xC2, 0x00, 0xFB, 0x00, 0xFC, 0x00, 0xFB],
+            5,
+        );
+        assert!(machine.vram.cells[0].iter().all(|&pixel| pixel == 0));
+        assert_eq!(
+            &machine.vram.cells[2][..10],
+            &[0, 0, 0, 0, 1, 1, 1, 1, 0, 0]
+        );
+    }
+
+    #[test]
+    fn big_sprites_are_16_pixels_wide_from_super_chip_on() {
+        //I := 0x300 (16 x 16 bytes of 0xFF), draw twice : VF is set the second time
+        let mut program = vec![0xA3, 0x00, 0xD0, 0x00, 0xD0, 0x00];
+        program.resize(0x100, 0);
+        program.extend([0xFF; 32]);
+        let machine = run_on(Platform::SuperChip, &program, 2);
+        assert_eq!(machine.vram.cells[15][..17].iter().sum::<u8>(), 16);
+        assert_eq!(machine.registers.v[0xF], 0);
+        let machine = run_on(Platform::SuperChip, &program, 3);
+        assert!(machine.vram.cells.iter().flatten().all(|&pixel| pixel == 0));
+        assert_eq!(machine.registers.v[0xF], 1);
+        //CHIP-8 draws nothing for DXY0
+        let machine = run(&program, 2);
+        assert!(machine.vram.cells.iter().flatten().all(|&pixel| pixel == 0));
+    }
+
+    #[test]
+    fn xo_chip_draws_a_sprite_on_each_selected_plane() {
+        //I := 0x300, planes 1 and 2, draw 1 row : 0xF0 on the first plane, 0x3C on the second one
+        let mut program = vec![0xA3, 0x00, 0xF3, 0x01, 0xD0, 0x01];
+        program.resize(0x100, 0);
+        program.extend([0xF0, 0x3C]);
+        let machine = run_on(Platform::XoChip, &program, 3);
+        assert_eq!(&machine.vram.cells[0][..8], &[1, 1, 3, 3, 2, 2, 0, 0]);
+        //00E0 with plane 2 selected clears it only
+        program[6..10].copy_from_slice(&[0xF2, 0x01, 0x00, 0xE0]);
+        let machine = run_on(Platform::XoChip, &program, 5);
+        assert_eq!(&machine.vram.cells[0][..8], &[1, 1, 1, 1, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn register_ranges_are_stored_and_loaded_in_either_order() {
+        //V1 := 1, V2 := 2, V3 := 3, I := 0x300, store V1-V3, store V3-V1 after them, load V5-V4 from the start
+        let program = [
+            0x61, 0x01, 0x62, 0x02, 0x63, 0x03, 0xA3, 0x00, 0x51, 0x32, 0xA3, 0x03, 0x53, 0x12,
+            0xA3, 0x00, 0x55, 0x43,
+        ];
+        let machine = run_on(Platform::XoChip, &program, 9);
+        assert_eq!(&machine.memory.cells[0x300..0x306], &[1, 2, 3, 3, 2, 1]);
+        assert_eq!(&machine.registers.v[4..6], &[2, 1]);
+        assert_eq!(machine.i, 0x300);
+    }
+
+    #[test]
+    fn memory_accesses_through_i_wrap_around_the_memory() {
+        //I := 0xFFE, V2 := 123, BCD of V2, load V0-V2 : I is now 0x1001, load V0
+        let program = [0xAF, 0xFE, 0x62, 0x7B, 0xF2, 0x33, 0xF2, 0x65, 0xF0, 0x65];
+        let mut machine = Machine::new();
+        machine.quirks.load_store_increments_i = true;
+        machine.load_program(&program, PROGRAM_START, PROGRAM_START);
+        for _ in 0..5 {
+            machine.process_keys(vec![false; 16]);
+        }
+        assert_eq!(&machine.memory.cells[0xFFE..], &[1, 2]);
+        assert_eq!(machine.memory.cells[0], 3);
+        assert_eq!(&machine.registers.v[..3], &[machine.memory.cells[1], 2, 3]);
+        assert_eq!(machine.i, 0x1002);
+        //V0 := 0xFF, I := 0xFFF, I += V0 : I goes past the 4 KB of memory, then store and load V0
+        let machine = run_on(
+            Platform::SuperChip,
+            &[0x60, 0xFF, 0xAF, 0xFF, 0xF0, 0x1E, 0xF0, 0x55, 0xF0, 0x65],
+            5,
+        );
+        assert_eq!(machine.i, 0x10FE);
+        assert_eq!(machine.memory.cells[0x0FE], 0xFF);
+        assert_eq!(machine.registers.v[0], 0xFF);
+        assert!(!machine.halted);
+    }
+
+    #[test]
+    fn long_index_is_skipped_as_one_instruction() {
+        //I := 0x1234, V0 := 0, skip if V0 = 0 over I := 0xABCD, V1 := 1
+        let program = [
+            0xF0, 0x00, 0x12, 0x34, 0x30, 0x00, 0xF0, 0x00, 0xAB, 0xCD, 0x61, 0x01,
+        ];
+        let machine = run_on(Platform::XoChip, &program, 3);
+        assert_eq!(machine.i, 0x1234);
+        assert_eq!(machine.registers.v[1], 1);
+        assert_eq!(machine.memory.cells.len(), XO_CHIP_MEMORY_SIZE);
+    }
+
+    #[test]
+    fn user_flags_keep_the_registers() {
+        //V0 := 5, V1 := 6, save V0-V1, V0 := 0, V1 := 0, load V0-V1
+        let program = [
+            0x60, 0x05, 0x61, 0x06, 0xF1, 0x75, 0x60, 0x00, 0x61, 0x00, 0xF1, 0x85,
+        ];
+        let machine = run_on(Platform::SuperChip, &program, 6);
+        assert_eq!(&machine.registers.v[..2], &[5, 6]);
+    }
+
+    #[test]
+    fn audio_pattern_and_pitch_are_kept() {
+        //I := 0x300, load the pattern, V0 := 0x70, pitch := V0
+        let mut program = vec![0xA3, 0x00, 0xF0, 0x02, 0x60, 0x70, 0xF0, 0x3A];
+        program.resize(0x100, 0);
+        program.extend(0..16);
+        let machine = run_on(Platform::XoChip, &program, 4);
+        assert_eq!(machine.audio_pattern[15], 15);
+        assert_eq!(machine.pitch, 0x70);
+    }
+
+    #[test]
+    fn leaving_megachip_mode_keeps_the_colors_of_chip_8x() {
+        let mut machine = Machine::new();
+        machine.enable_chip8x();
+        machine.enable_megachip();
+        machine.set_megachip_mode(true);
+        machine.set_megachip_mode(false);
+        assert_eq!(machine.vram.cells.len(), VRAM_HEIGHT);
+        assert!(machine.vram.colors.is_some());
+    }
+
+    #[test]
+    fn unknown_megachip_instructions_are_skipped() {
+        //MegaChip on, then 0x0012 and 0x0A00, which mean nothing to MegaChip
+        let mut machine = Machine::new();
+        machine.enable_megachip();
+        machine.load_program(
+            &[0x00, 0x11, 0x00, 0x12, 0x0A, 0x00],
+            PROGRAM_START,
+            PROGRAM_START,
+        );
+        for _ in 0..3 {
+            machine.process_keys(vec![false; 16]);
+        }
+        assert_eq!(machine.pc, PROGRAM_START + 6);
+    }
+
+    #[test]
+    fn machine_code_routines_run_on_chip_8_only() {
+        //call the routine at 0x20A, V0 := 1, ..., V1 := 7, then the routine : VX := 0x2A, R5 := 0x208, return
+        let mut program = vec![0x02, 0x0A, 0x60, 0x01, 0x00, 0x00, 0x00, 0x00, 0x61, 0x07];
+        program.extend(&[0xF8, 0x2A, 0x56, 0xF8, 0x08, 0xA5, 0xD4]);
+        let machine = run(&program, 2);
+        assert_eq!(&machine.registers.v[..3], &[0, 7, 0x2A]);
+        assert_eq!(machine.pc, 0x20A);
+
+        for platform in [Platform::SuperChip, Platform::XoChip] {
+            let machine = run_on(platform, &program, 1);
+            assert_eq!((machine.pc, machine.registers.v[2]), (0x202, 0));
+            assert!(machine.memory.cells[0xEF0..].iter().all(|&cell| cell == 0));
+        }
+    }
+
+    #[test]
+    fn later_instructions_only_run_on_their_platform() {
+        //00FB is a call of the machine code at 0x0FB on CHIP-8 : V0 := 0x2A, return
+        let mut machine = Machine::new();
+        machine.load_program(&[0x00, 0xFB], PROGRAM_START, PROGRAM_START);
+        machine
+            .memory
+            .load_data_at(0x0FB, &[0xF8, 0x2A, 0x56, 0xD4]);
+        machine.process_keys(vec![false; 16]);
+        assert_eq!((machine.pc, machine.registers.v[0]), (0x202, 0x2A));
+
+        //it scrolls the display on SUPER-CHIP
+        let machine = run_on(Platform::SuperChip, &[0x00, 0xFB], 1);
+        assert_eq!((machine.pc, machine.registers.v[0]), (0x202, 0));
+
+        //V0 := 7, I := 0x300, 5XY2 stores V0 on XO-CHIP only, F000 NNNN is unknown before XO-CHIP
+        let program = [0x60, 0x07, 0xA3, 0x00, 0x50, 0x02, 0xF0, 0x00];
+        for (platform, stored, i) in [
+            (Platform::Chip8, 0, 0x300),
+            (Platform::SuperChip, 0, 0x300),
+            (Platform::XoChip, 7, 0x0000),
+        ] {
+            let machine = run_on(platform, &program, 4);
+            assert_eq!(machine.memory.cells[0x300], stored, "{}", platform);
+            assert_eq!(machine.i, i, "{}", platform);
+            assert!(!machine.halted);
         }
     }
 }
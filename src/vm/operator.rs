@@ -1,4 +1,5 @@
-use crate::vm::Machine;
+use crate::vm::instructions::{register_range, run_machine_code, sprite_size};
+use crate::vm::{Instruction, Machine};
 
 type OpCode = u16;
 
@@ -38,6 +39,77 @@ impl Operator {
             vy: machine.registers.v[((opcode & 0x00F0) >> 4) as usize],
         }
     }
+
+    /// `Operator.memory_reads()` returns the addresses of the memory cells the opcode is going to read through I,
+    /// when executed on the `machine`. Only DXYN (draw sprite), 5XY3 and FX65 (load registers) and F002 (audio pattern)
+    /// read memory as data. The addresses wrap around the end of the memory, as the instructions do.
+    pub fn memory_reads(&self, machine: &Machine) -> Vec<usize> {
+        let count = match (self.nibble_1, self.nibble_2, self.nibble_3, self.nibble_4) {
+            (0xD, _, _, _) if machine.megachip_mode() => self.n_const as usize,
+            (0xD, _, _, _) => {
+                //a sprite for each selected plane
+                let (width, height) = sprite_size(machine, self);
+                let planes = machine.planes.count_ones() as usize;
+                width / 8 * height * planes
+            }
+            (0x5, _, _, 0x3) => register_range(self.x, self.y).count(),
+            (0xF, _, 0x6, 0x5) => self.x + 1,
+            (0xF, 0x0, 0x0, 0x2) => machine.audio_pattern.len(),
+            _ => 0,
+        };
+        (0..count)
+            .map(|offset| wrap_index(machine, offset))
+            .collect()
+    }
+
+    /// `Operator.memory_writes()` returns the memory cells (address, value) the opcode is going to write,
+    /// when executed on the `machine`. FX33 (BCD), 5XY2 and FX55 (store registers) write through I, wrapping around
+    /// the end of the memory as the instructions do. On the COSMAC VIP, 0NNN writes what its routine of machine code
+    /// writes, which is found by running the routine on a copy of the memory.
+    pub fn memory_writes(&self, machine: &Machine) -> Vec<(usize, u8)> {
+        let values = match (self.nibble_1, self.nibble_3, self.nibble_4) {
+            (0x0, _, _) if calls_machine_code(machine, self) => {
+                let (memory, _, _) = run_machine_code(machine, self);
+                return memory
+                    .into_iter()
+                    .enumerate()
+                    .filter(|&(address, value)| machine.memory.cells[address] != value)
+                    .collect();
+            }
+            (0x5, _, 0x2) => register_range(self.x, self.y)
+                .map(|x| machine.registers.v[x])
+                .collect(),
+            (0xF, 0x3, 0x3) => vec![self.vx / 100, (self.vx % 100) / 10, self.vx % 10],
+            (0xF, 0x5, 0x5) => machine.registers.v[0..=self.x].to_vec(),
+            _ => vec![],
+        };
+        values
+            .into_iter()
+            .enumerate()
+            .map(|(offset, value)| (wrap_index(machine, offset), value))
+            .collect()
+    }
+
+    /// `Operator.mnemonic()` returns the assembly mnemonic of the opcode, e.g. `LD V1, 0x0A` for `0x610A`.
+    /// Opcodes the machine does not know are shown as `UNKNOWN`.
+    pub fn mnemonic(&self) -> String {
+        Instruction::decode(self.opcode).to_string()
+    }
+}
+
+/// `wrap_index()` returns the address `offset` cells after I, wrapped around the end of the memory.
+fn wrap_index(machine: &Machine, offset: usize) -> usize {
+    (machine.i as usize + offset) % machine.memory.cells.len()
+}
+
+/// `calls_machine_code()` tells whether the machine runs the opcode as a call of machine code (0NNN), as
+/// `Machine.emulate_cycle()` dispatches it.
+fn calls_machine_code(machine: &Machine, operator: &Operator) -> bool {
+    match machine.decode(operator.opcode) {
+        Instruction::Sys(0x2A0) if machine.chip8x => false,
+        Instruction::Sys(_) => machine.runs_machine_code(),
+        _ => false,
+    }
 }
 
 fn extract_opcode(machine: &Machine) -> OpCode {
@@ -0,0 +1,115 @@
+//! Quirks - the behaviours which differ between CHIP-8 interpreters. ROMs are written against one of them,
+//! and misbehave silently (e.g. scores going wrong, sprites vanishing) on the others.
+
+use crate::vm::Platform;
+use std::fmt;
+
+/// `Quirks` : how the machine executes the instructions whose behaviour depends on the interpreter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6 and 8XYE shift VY into VX (COSMAC VIP), instead of shifting VX in place.
+    pub shift_vy: bool,
+    /// FX55 and FX65 leave I pointing after the last register (COSMAC VIP), instead of leaving it unchanged.
+    pub load_store_increments_i: bool,
+    /// BXNN jumps to XNN plus VX (SUPER-CHIP), instead of NNN plus V0.
+    pub jump_vx: bool,
+    /// 8XY1, 8XY2 and 8XY3 reset VF to 0 (COSMAC VIP).
+    pub logic_resets_vf: bool,
+    /// Sprites are clipped at the edges of the screen, instead of wrapping around.
+    pub clip_sprites: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::new()
+    }
+}
+
+impl Quirks {
+    /// `Quirks::new()` will return the quirks this machine always had : shifts in place, FX55 and FX65 leave I
+    /// unchanged, BNNN adds V0, VF is left alone by logic instructions and sprites wrap around.
+    pub fn new() -> Quirks {
+        Quirks {
+            shift_vy: false,
+            load_store_increments_i: false,
+            jump_vx: false,
+            logic_resets_vf: false,
+            clip_sprites: false,
+        }
+    }
+
+    /// `Quirks::for_platform()` will return the quirks of the reference interpreter of a platform :
+    /// the COSMAC VIP for CHIP-8, SUPER-CHIP 1.1 and Octo for XO-CHIP.
+    pub fn for_platform(platform: Platform) -> Quirks {
+        match platform {
+            Platform::Chip8 => Quirks {
+                shift_vy: true,
+                load_store_increments_i: true,
+                jump_vx: false,
+                logic_resets_vf: true,
+                clip_sprites: true,
+            },
+            Platform::SuperChip => Quirks {
+                shift_vy: false,
+                load_store_increments_i: false,
+                jump_vx: true,
+                logic_resets_vf: false,
+                clip_sprites: true,
+            },
+            Platform::XoChip => Quirks {
+                shift_vy: true,
+                load_store_increments_i: true,
+                jump_vx: false,
+                logic_resets_vf: false,
+                clip_sprites: false,
+            },
+        }
+    }
+
+    /// `Quirks.by_name()` returns every quirk with its name, as `Quirks.set()` reads it.
+    pub fn by_name(&self) -> [(&'static str, bool); 5] {
+        [
+            ("shift_vy", self.shift_vy),
+            ("load_store_increments_i", self.load_store_increments_i),
+            ("jump_vx", self.jump_vx),
+            ("logic_resets_vf", self.logic_resets_vf),
+            ("clip_sprites", self.clip_sprites),
+        ]
+    }
+
+    /// `Quirks.set()` sets the quirk called `name` (as its field), and returns false if there is no such quirk.
+    pub fn set(&mut self, name: &str, value: bool) -> bool {
+        let quirk = match name {
+            "shift_vy" => &mut self.shift_vy,
+            "load_store_increments_i" => &mut self.load_store_increments_i,
+            "jump_vx" => &mut self.jump_vx,
+            "logic_resets_vf" => &mut self.logic_resets_vf,
+            "clip_sprites" => &mut self.clip_sprites,
+            _ => return false,
+        };
+        *quirk = value;
+        true
+    }
+}
+
+impl fmt::Display for Quirks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "shift {}, load/store {}, jump {}, logic {}, sprites {}",
+            if self.shift_vy { "VY" } else { "VX" },
+            if self.load_store_increments_i {
+                "increments I"
+            } else {
+                "keeps I"
+            },
+            if self.jump_vx { "XNN + VX" } else { "NNN + V0" },
+            if self.logic_resets_vf {
+                "resets VF"
+            } else {
+                "keeps VF"
+            },
+            if self.clip_sprites { "clip" } else { "wrap" },
+        )
+    }
+}
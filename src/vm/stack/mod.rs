@@ -10,8 +10,39 @@ pub struct Stack {
 }
 
 impl Stack {
-    /// `Stack::new()` will return new Stack with 16 cellls, all initialized to zero.
+    /// `Stack::new()` will return new empty Stack with room for 16 cells.
+    /// The number of used cells is the stack pointer (sp).
     pub fn new() -> Stack {
-        Stack { cells: vec![0; 16] }
+        Stack {
+            cells: Vec::with_capacity(16),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::{Machine, PROGRAM_START};
+
+    #[test]
+    fn the_stack_starts_empty_and_holds_the_return_addresses() {
+        //call 0x204, jump to itself, call 0x208, return, return, then return with an empty stack
+        let program = [0x22, 0x04, 0x12, 0x02, 0x22, 0x08, 0x00, 0xEE, 0x00, 0xEE];
+        let mut machine = Machine::new();
+        machine.load_program(&program, PROGRAM_START, PROGRAM_START);
+        assert!(machine.stack.cells.is_empty());
+        let mut stacks = vec![];
+        for _ in 0..4 {
+            machine.process_keys(vec![false; 16]);
+            stacks.push(machine.stack.cells.clone());
+        }
+        assert_eq!(
+            stacks,
+            vec![vec![0x202], vec![0x202, 0x206], vec![0x202], vec![]]
+        );
+        assert_eq!(machine.pc, 0x202);
+        //as when the stack held 16 zeros, returning from the top goes to 0x000
+        machine.pc = 0x206;
+        machine.process_keys(vec![false; 16]);
+        assert_eq!((machine.pc, machine.stack.cells.len()), (0x000, 0));
     }
 }